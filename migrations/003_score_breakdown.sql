@@ -0,0 +1,3 @@
+-- JSON-encoded anomaly score breakdown (rule and LLM contributions, totals and
+-- thresholds) for events decided in anomaly scoring mode.
+ALTER TABLE events ADD COLUMN score_breakdown TEXT;
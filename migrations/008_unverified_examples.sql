@@ -0,0 +1,3 @@
+-- Few-shot examples taken from the judge's own verdicts were never checked
+-- by a human: 'confirmed' becomes 'unverified', 'corrected' is unchanged.
+UPDATE few_shot_examples SET source = 'unverified' WHERE source = 'confirmed';
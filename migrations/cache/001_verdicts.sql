@@ -0,0 +1,11 @@
+-- Verdict cache of the SQLite backend (`cache.backend: sqlite`), kept in its
+-- own file (`cache.sqlite_path`). `verdict` is the JSON of the decision;
+-- `expires_at` is in unix milliseconds.
+CREATE TABLE IF NOT EXISTS verdicts (
+    cache_key TEXT PRIMARY KEY NOT NULL,
+    verdict TEXT NOT NULL,
+    path TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_verdicts_expires_at ON verdicts(expires_at);
@@ -0,0 +1,3 @@
+-- Version of the judge prompts that produced the cached verdict, so that
+-- events decided from the cache report the prompts actually used.
+ALTER TABLE verdicts ADD COLUMN prompt_version TEXT;
@@ -0,0 +1,3 @@
+-- Redacted, size-capped copy of the request (query, selected headers, body excerpt)
+-- stored as JSON so the Learner can see what was actually sent.
+ALTER TABLE events ADD COLUMN request_context TEXT;
@@ -0,0 +1,4 @@
+-- Version of the judge prompt templates in use when the event was decided
+-- (`<judge_system>+<judge>`), to compare the decisions of prompt revisions.
+ALTER TABLE events ADD COLUMN prompt_version TEXT;
+CREATE INDEX IF NOT EXISTS idx_events_prompt_version ON events(prompt_version);
@@ -0,0 +1,18 @@
+-- Past requests with a confirmed verdict, shown to the judge as few-shot
+-- examples. `minhash` is the MinHash signature of the normalized request;
+-- `source` is 'confirmed' (confident LLM verdict) or 'corrected' (human fix).
+CREATE TABLE IF NOT EXISTS few_shot_examples (
+    id BIGSERIAL PRIMARY KEY,
+    created_at BIGINT NOT NULL,
+    method TEXT NOT NULL,
+    path_template TEXT NOT NULL,
+    payload_hash TEXT NOT NULL UNIQUE,
+    request TEXT NOT NULL,
+    minhash BYTEA NOT NULL,
+    decision TEXT NOT NULL,
+    reason TEXT,
+    source TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_few_shot_examples_template
+    ON few_shot_examples(method, path_template, created_at DESC);
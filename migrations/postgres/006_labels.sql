@@ -0,0 +1,14 @@
+-- Human feedback on events: `label` is 'false_positive' (should have been
+-- allowed) or 'false_negative' (should have been blocked). The latest label
+-- of a payload_hash pins its verdict.
+CREATE TABLE IF NOT EXISTS labels (
+    id BIGSERIAL PRIMARY KEY,
+    created_at BIGINT NOT NULL,
+    event_id BIGINT NOT NULL REFERENCES events(id),
+    payload_hash TEXT NOT NULL,
+    label TEXT NOT NULL,
+    note TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_labels_payload_hash ON labels(payload_hash);
+CREATE INDEX IF NOT EXISTS idx_labels_created_at ON labels(created_at);
@@ -0,0 +1,3 @@
+-- Number of requests an event stands for: 1 for events that are always
+-- logged, 1/rate for allows logged by sampling. Counts are sums of weights.
+ALTER TABLE events ADD COLUMN weight DOUBLE PRECISION NOT NULL DEFAULT 1.0;
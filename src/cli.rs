@@ -0,0 +1,475 @@
+use crate::core::modsecurity;
+use crate::core::rulebook::Rulebook;
+use crate::core::validator::{self, Severity};
+use crate::models::label::LabelKind;
+use crate::storage::logs::LogStore;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use std::path::Path;
+
+const USAGE: &str = "Usage:
+  guardix                      Start the WAF
+  guardix rules lint <file>    Validate a rulebook file
+  guardix rules export --format modsecurity [--id-base <n>] <file>
+                               Print the rulebook as ModSecurity SecRules
+  guardix rules import <secrules> <file>
+                               Add ModSecurity/CRS rules (.conf file or
+                               directory) to a rulebook file
+  guardix label <event_id> fp|fn [--note <text>] [--url <admin-url>]
+                               Label an event as a false positive/negative
+                               through the admin API (token read from
+                               GUARDIX_ADMIN_TOKEN)
+  guardix logs vacuum <file>   Switch a SQLite logs database created by an
+                               older version to incremental auto-vacuum
+                               (one full VACUUM; stop the WAF first)";
+
+/// Admin API address used by `guardix label` without `--url`
+const DEFAULT_ADMIN_URL: &str = "http://127.0.0.1:5001";
+
+/// Environment variable holding the admin API token
+const ADMIN_TOKEN_ENV: &str = "GUARDIX_ADMIN_TOKEN";
+
+/// Runs a command-line subcommand and returns the process exit code.
+pub async fn run(args: &[String]) -> Result<i32> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["rules", "lint", file] => lint(Path::new(file)),
+        ["rules", "export", rest @ ..] => export(rest),
+        ["rules", "import", source, file] => import(Path::new(source), Path::new(file)),
+        ["label", rest @ ..] => label(rest).await,
+        ["logs", "vacuum", file] => vacuum_logs(Path::new(file)).await,
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            Ok(2)
+        }
+    }
+}
+
+fn read_rulebook(path: &Path) -> Result<Rulebook> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read rulebook file: {:?}", path))?;
+    serde_json::from_str(&content).with_context(|| "Failed to parse rulebook JSON")
+}
+
+/// Prints every issue found in the rulebook; fails (exit code 1) on errors only
+fn lint(path: &Path) -> Result<i32> {
+    let rulebook = read_rulebook(path)?;
+
+    let issues = validator::validate_rulebook(&rulebook);
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    println!(
+        "{}: {} rules, {} errors, {} warnings",
+        path.display(),
+        rulebook.rules.len(),
+        errors,
+        issues.len() - errors
+    );
+
+    Ok(if errors > 0 { 1 } else { 0 })
+}
+
+/// Prints the rulebook in another WAF's rule format
+fn export(args: &[&str]) -> Result<i32> {
+    let mut format = None;
+    let mut id_base = modsecurity::DEFAULT_ID_BASE;
+    let mut file = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--format" => format = args.next().copied(),
+            "--id-base" => {
+                let value = args.next().copied().unwrap_or_default();
+                id_base = value
+                    .parse()
+                    .with_context(|| format!("Invalid --id-base: {:?}", value))?;
+            }
+            _ if file.is_none() && !arg.starts_with("--") => file = Some(*arg),
+            _ => bail!("Unexpected argument: {}\n{}", arg, USAGE),
+        }
+    }
+
+    let Some(file) = file else {
+        bail!("Missing rulebook file\n{}", USAGE);
+    };
+    let rulebook = read_rulebook(Path::new(file))?;
+
+    match format {
+        Some("modsecurity") => {
+            print!(
+                "{}",
+                modsecurity::export_rulebook(&rulebook, id_base, Utc::now())
+            );
+            Ok(0)
+        }
+        Some(other) => bail!("Unsupported export format: {}", other),
+        None => bail!("Missing --format\n{}", USAGE),
+    }
+}
+
+/// Merges imported SecRules into the rulebook file (created if missing).
+/// Previously imported rules with the same id are replaced.
+fn import(source: &Path, path: &Path) -> Result<i32> {
+    let mut rulebook = if path.exists() {
+        read_rulebook(path)?
+    } else {
+        Rulebook::new()
+    };
+
+    let report = modsecurity::import_path(source)?;
+    for skipped in &report.skipped {
+        println!("skipped {}", skipped);
+    }
+    for warning in &report.warnings {
+        println!("warning {}", warning);
+    }
+
+    let imported = report.rules.len();
+    for rule in report.rules {
+        match rulebook.rules.iter_mut().find(|r| r.id == rule.id) {
+            Some(existing) => *existing = rule,
+            None => rulebook.rules.push(rule),
+        }
+    }
+    rulebook.touch();
+
+    let issues = validator::validate_rulebook(&rulebook);
+    if validator::has_errors(&issues) {
+        for issue in issues.iter().filter(|i| i.severity == Severity::Error) {
+            println!("{}", issue);
+        }
+        bail!("Rulebook {:?} would be invalid, not written", path);
+    }
+
+    let content =
+        serde_json::to_string_pretty(&rulebook).with_context(|| "Failed to serialize rulebook")?;
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write rulebook file: {:?}", path))?;
+
+    println!(
+        "{}: imported {} rules, skipped {}, rulebook now has {} rules",
+        path.display(),
+        imported,
+        report.skipped.len(),
+        rulebook.rules.len()
+    );
+
+    Ok(0)
+}
+
+/// Labels an event through the admin API of the running WAF, which pins
+/// the corrected verdict right away
+async fn label(args: &[&str]) -> Result<i32> {
+    let mut note = None;
+    let mut url = DEFAULT_ADMIN_URL;
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--note" => note = args.next().copied(),
+            "--url" => url = args.next().copied().unwrap_or(DEFAULT_ADMIN_URL),
+            _ if !arg.starts_with("--") => positional.push(*arg),
+            _ => bail!("Unexpected argument: {}\n{}", arg, USAGE),
+        }
+    }
+
+    let [event_id, kind] = positional.as_slice() else {
+        bail!("Expected an event id and a label\n{}", USAGE);
+    };
+    let event_id: i64 = event_id
+        .parse()
+        .with_context(|| format!("Invalid event id: {:?}", event_id))?;
+    let Some(kind) = LabelKind::parse(kind) else {
+        bail!("Unknown label {:?}, expected fp or fn", kind);
+    };
+
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/labels", url.trim_end_matches('/')))
+        .json(&serde_json::json!({
+            "event_id": event_id,
+            "label": kind.as_str(),
+            "note": note,
+        }));
+    if let Ok(token) = std::env::var(ADMIN_TOKEN_ENV) {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach the admin API at {}", url))?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+
+    if !status.is_success() {
+        eprintln!(
+            "{}: {}",
+            status,
+            body["error"].as_str().unwrap_or("request failed")
+        );
+        return Ok(1);
+    }
+
+    println!(
+        "event {} labeled {} (label {})",
+        event_id,
+        kind.as_str(),
+        body["id"]
+    );
+    Ok(0)
+}
+
+/// Converts the logs database so that retention can give space back
+async fn vacuum_logs(path: &Path) -> Result<i32> {
+    if !path.exists() {
+        bail!("No logs database at {:?}", path);
+    }
+    let store = LogStore::new(path).await?;
+
+    if store.enable_incremental_vacuum().await? {
+        println!("{}: switched to incremental auto-vacuum", path.display());
+    } else {
+        println!("{}: already uses incremental auto-vacuum", path.display());
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::rulebook::Rule;
+    use crate::models::decision::RuleAction;
+
+    fn write_rulebook(dir: &Path, patterns: &[&str]) -> String {
+        let mut rulebook = Rulebook::new();
+        for pattern in patterns {
+            rulebook.add_rule(Rule::new(
+                pattern.to_string(),
+                "sqli".to_string(),
+                0.8,
+                RuleAction::Block,
+                "test".to_string(),
+            ));
+        }
+        let path = dir.join("rulebook.json");
+        std::fs::write(&path, serde_json::to_string(&rulebook).unwrap()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_lint_exit_codes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let clean = write_rulebook(temp_dir.path(), &["union.*select"]);
+        assert_eq!(run(&args(&["rules", "lint", &clean])).await.unwrap(), 0);
+
+        // Warnings alone do not fail the lint
+        let duplicated = write_rulebook(temp_dir.path(), &["union.*select", "UNION.*SELECT"]);
+        assert_eq!(
+            run(&args(&["rules", "lint", &duplicated])).await.unwrap(),
+            0
+        );
+
+        let invalid = write_rulebook(temp_dir.path(), &["(unclosed"]);
+        assert_eq!(run(&args(&["rules", "lint", &invalid])).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lint_missing_file() {
+        assert!(run(&args(&["rules", "lint", "/nonexistent/rulebook.json"]))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_arguments() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = write_rulebook(temp_dir.path(), &["union.*select"]);
+
+        async fn run_export(extra: &[&str]) -> Result<i32> {
+            let mut all = vec!["rules", "export"];
+            all.extend_from_slice(extra);
+            run(&args(&all)).await
+        }
+
+        assert_eq!(
+            run_export(&["--format", "modsecurity", &file])
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            run_export(&[&file, "--format", "modsecurity", "--id-base", "5000"])
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(run_export(&["--format", "nginx", &file]).await.is_err());
+        assert!(run_export(&[&file]).await.is_err());
+        assert!(run_export(&["--format", "modsecurity"]).await.is_err());
+        assert!(
+            run_export(&["--format", "modsecurity", "--id-base", "x", &file])
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_merges_into_rulebook() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = write_rulebook(temp_dir.path(), &["union.*select"]);
+        let rules_dir = temp_dir.path().join("crs");
+        std::fs::create_dir(&rules_dir).unwrap();
+        std::fs::write(
+            rules_dir.join("REQUEST-930-APPLICATION-ATTACK-LFI.conf"),
+            r#"SecRule REQUEST_URI "@contains ../" "id:930100,deny,msg:'Path traversal'""#,
+        )
+        .unwrap();
+        std::fs::write(rules_dir.join("README.md"), "not rules").unwrap();
+        let source = rules_dir.to_string_lossy().into_owned();
+
+        assert_eq!(
+            run(&args(&["rules", "import", &source, &file]))
+                .await
+                .unwrap(),
+            0
+        );
+        // Importing again replaces the rule instead of duplicating it
+        assert_eq!(
+            run(&args(&["rules", "import", &source, &file]))
+                .await
+                .unwrap(),
+            0
+        );
+
+        let rulebook = read_rulebook(Path::new(&file)).unwrap();
+        assert_eq!(rulebook.rules.len(), 2);
+        assert_eq!(rulebook.rules[1].id, "modsec-930100");
+        assert_eq!(rulebook.rules[1].created_by, "import");
+
+        let created = temp_dir.path().join("new.json");
+        let created = created.to_string_lossy().into_owned();
+        assert_eq!(
+            run(&args(&["rules", "import", &source, &created]))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(read_rulebook(Path::new(&created)).unwrap().rules.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_label_through_admin_api() {
+        use crate::config::FailMode;
+        use crate::core::feedback::Feedback;
+        use crate::core::judge::Judge;
+        use crate::http::admin::{self, AdminState};
+        use crate::llm::client::mock::MockLlmProvider;
+        use crate::models::decision::JudgeDecision;
+        use crate::models::request::RequestPayload;
+        use crate::storage::logs::{EventStore, LogStore};
+        use std::collections::HashMap;
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::sync::RwLock;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("test.db"))
+                .await
+                .unwrap(),
+        );
+        let judge = Arc::new(
+            Judge::new(
+                Arc::new(MockLlmProvider::new()),
+                None,
+                Arc::new(RwLock::new(Rulebook::new())),
+                Duration::from_secs(1),
+                FailMode::Open,
+            )
+            .await,
+        );
+        let payload = RequestPayload::new(
+            "POST".to_string(),
+            "/upload".to_string(),
+            HashMap::new(),
+            Some("url=http://169.254.169.254/".to_string()),
+            HashMap::new(),
+            None,
+        );
+        let id = logs
+            .log_event(&payload, &JudgeDecision::Allow { confidence: 0.8 })
+            .await
+            .unwrap();
+
+        let app = admin::router(AdminState {
+            logs: Arc::clone(&logs),
+            feedback: Arc::new(Feedback::new(Arc::clone(&logs), Arc::clone(&judge))),
+            judge: Arc::clone(&judge),
+            retention: None,
+            writer: None,
+            exporter: None,
+            token: None,
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let id = id.to_string();
+        assert_eq!(
+            run(&args(&["label", &id, "fp", "--url", &url]))
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            run(&args(&[
+                "label", &id, "fn", "--note", "SSRF", "--url", &url
+            ]))
+            .await
+            .unwrap(),
+            0
+        );
+        assert!(judge.evaluate(payload).await.is_block());
+
+        assert!(run(&args(&["label", "x", "fn"])).await.is_err());
+        assert!(run(&args(&["label", &id, "maybe"])).await.is_err());
+        assert!(run(&args(&["label", &id])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_logs_vacuum() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("logs.db");
+        let file = file.to_string_lossy();
+
+        // Never creates a database
+        assert!(run(&args(&["logs", "vacuum", &file])).await.is_err());
+
+        LogStore::new(file.as_ref()).await.unwrap();
+        assert_eq!(run(&args(&["logs", "vacuum", &file])).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_command() {
+        assert_eq!(run(&args(&["rules"])).await.unwrap(), 2);
+        assert_eq!(run(&args(&["--help"])).await.unwrap(), 0);
+    }
+}
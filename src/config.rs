@@ -1,3 +1,7 @@
+use crate::http::client_ip::TrustedProxies;
+use crate::models::canonical::{self, DEFAULT_HASH_HEADERS};
+use crate::models::decision::ThreatLevel;
+use crate::storage::cache::VerdictTtls;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -12,6 +16,18 @@ pub struct Config {
     pub storage: StorageConfig,
     pub learner: LearnerConfig,
     pub observability: ObservabilityConfig,
+    #[serde(default)]
+    pub scoring: ScoringConfig,
+    #[serde(default)]
+    pub fewshot: FewShotConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub escalation: EscalationConfig,
+    #[serde(default)]
+    pub async_judge: AsyncJudgeConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
 }
 
 impl Config {
@@ -42,6 +58,9 @@ impl Config {
         if self.waf.request_timeout_ms == 0 {
             anyhow::bail!("waf.request_timeout_ms must be greater than 0");
         }
+        if let Err(e) = TrustedProxies::parse(&self.waf.trusted_proxies) {
+            anyhow::bail!("waf.trusted_proxies: {:#}", e);
+        }
 
         if self.llm.judge_timeout_ms == 0 {
             anyhow::bail!("llm.judge_timeout_ms must be greater than 0");
@@ -57,39 +76,258 @@ impl Config {
         }
 
         // Validate cache
-        if self.cache.enabled && self.cache.redis_url.is_empty() {
+        if self.cache.enabled
+            && self.cache.backend == CacheBackend::Redis
+            && self.cache.redis_url.is_empty()
+        {
             anyhow::bail!("cache.redis_url cannot be empty when cache is enabled");
         }
 
+        if self.cache.enabled
+            && self.cache.backend == CacheBackend::Sqlite
+            && self.cache.sqlite_path.is_empty()
+        {
+            anyhow::bail!("cache.sqlite_path cannot be empty with the sqlite backend");
+        }
+
+        if self.cache.enabled
+            && self.cache.backend == CacheBackend::Memory
+            && self.cache.memory.capacity == 0
+        {
+            anyhow::bail!("cache.memory.capacity must be > 0 with the memory backend");
+        }
+
+        if self.cache.hash_headers.iter().any(|h| h.trim().is_empty()) {
+            anyhow::bail!("cache.hash_headers cannot contain empty names");
+        }
+
+        if !(0.0..=1.0).contains(&self.cache.confident_threshold) {
+            anyhow::bail!("cache.confident_threshold must be between 0 and 1");
+        }
+
+        if self.cache.memory_tier_enabled() && self.cache.memory.ttl_seconds == 0 {
+            anyhow::bail!("cache.memory.ttl_seconds must be > 0 when the memory tier is enabled");
+        }
+
         // Validate storage paths
         if self.storage.logs_db_path.is_empty() {
             anyhow::bail!("storage.logs_db_path cannot be empty");
         }
 
+        let postgres_url = self.storage.postgres_url.as_deref().unwrap_or_default();
+        if self.storage.backend == LogBackend::Postgres && postgres_url.is_empty() {
+            anyhow::bail!("storage.postgres_url is required by the postgres backend");
+        }
+
         if self.storage.rulebook_path.is_empty() {
             anyhow::bail!("storage.rulebook_path cannot be empty");
         }
 
+        if self.storage.writer.queue_capacity == 0 || self.storage.writer.batch_size == 0 {
+            anyhow::bail!("storage.writer.queue_capacity and batch_size must be greater than 0");
+        }
+
+        let sampling = &self.storage.sampling;
+        if !(0.0..=1.0).contains(&sampling.allow_rate) {
+            anyhow::bail!("storage.sampling.allow_rate must be between 0 and 1");
+        }
+        for route in &sampling.routes {
+            if !route.path_prefix.starts_with('/') {
+                anyhow::bail!(
+                    "storage.sampling.routes path_prefix must start with '/': {:?}",
+                    route.path_prefix
+                );
+            }
+            if !(0.0..=1.0).contains(&route.allow_rate) {
+                anyhow::bail!(
+                    "storage.sampling.routes[{}] allow_rate must be between 0 and 1",
+                    route.path_prefix
+                );
+            }
+        }
+        if !(0.0..=1.0).contains(&sampling.always_log_below_confidence) {
+            anyhow::bail!("storage.sampling.always_log_below_confidence must be between 0 and 1");
+        }
+
+        let retention = &self.storage.retention;
+        if retention.enabled {
+            if retention.interval_minutes == 0 {
+                anyhow::bail!("storage.retention.interval_minutes must be greater than 0");
+            }
+            if retention.batch_size == 0 {
+                anyhow::bail!("storage.retention.batch_size must be greater than 0");
+            }
+            if retention.archive_dir.as_deref() == Some("") {
+                anyhow::bail!("storage.retention.archive_dir cannot be empty");
+            }
+        }
+
+        // Validate learner
+        if self.learner.max_clusters_per_batch == 0 {
+            anyhow::bail!("learner.max_clusters_per_batch must be greater than 0");
+        }
+
+        // Validate scoring
+        validate_thresholds(
+            "scoring",
+            self.scoring.block_threshold,
+            self.scoring.flag_threshold,
+        )?;
+        for route in &self.scoring.routes {
+            if !route.path_prefix.starts_with('/') {
+                anyhow::bail!(
+                    "scoring.routes path_prefix must start with '/': {:?}",
+                    route.path_prefix
+                );
+            }
+            validate_thresholds(
+                &format!("scoring.routes[{}]", route.path_prefix),
+                route.block_threshold,
+                route.flag_threshold,
+            )?;
+        }
+        if self.scoring.llm_weight < 0.0 {
+            anyhow::bail!("scoring.llm_weight cannot be negative");
+        }
+
+        // Validate few-shot examples
+        if !(0.0..=1.0).contains(&self.fewshot.min_similarity) {
+            anyhow::bail!("fewshot.min_similarity must be between 0 and 1");
+        }
+        if !(0.0..=1.0).contains(&self.fewshot.min_confidence) {
+            anyhow::bail!("fewshot.min_confidence must be between 0 and 1");
+        }
+        if self.fewshot.enabled && self.fewshot.max_age_days == 0 {
+            anyhow::bail!("fewshot.max_age_days must be greater than 0");
+        }
+
+        // Validate escalation
+        if self.escalation.enabled {
+            let escalation = &self.escalation;
+            if escalation.judges.is_empty() {
+                anyhow::bail!("escalation.judges cannot be empty when escalation is enabled");
+            }
+            if !(0.0..=1.0).contains(&escalation.min_confidence)
+                || !(0.0..=1.0).contains(&escalation.max_confidence)
+                || escalation.min_confidence > escalation.max_confidence
+            {
+                anyhow::bail!(
+                    "escalation.min_confidence and max_confidence must be between 0 and 1, min first"
+                );
+            }
+            if escalation.timeout_ms == 0 {
+                anyhow::bail!("escalation.timeout_ms must be greater than 0");
+            }
+            for (i, judge) in escalation.judges.iter().enumerate() {
+                if judge.name.is_empty() {
+                    anyhow::bail!("escalation.judges[{}].name cannot be empty", i);
+                }
+                if escalation.judges[..i].iter().any(|j| j.name == judge.name) {
+                    anyhow::bail!("Duplicate escalation judge name: {}", judge.name);
+                }
+            }
+        }
+
+        // Validate asynchronous judging
+        for route in &self.async_judge.routes {
+            if !route.starts_with('/') {
+                anyhow::bail!("async_judge.routes must start with '/': {:?}", route);
+            }
+        }
+        if !(0.0..=1.0).contains(&self.async_judge.ban_min_confidence) {
+            anyhow::bail!("async_judge.ban_min_confidence must be between 0 and 1");
+        }
+        if self.async_judge.session_cookie.as_deref() == Some("") {
+            anyhow::bail!("async_judge.session_cookie cannot be empty");
+        }
+
+        // Validate admin API
+        if self.admin.enabled && self.admin.listen_addr.is_empty() {
+            anyhow::bail!("admin.listen_addr cannot be empty when the admin API is enabled");
+        }
+        if self.admin.token.as_deref() == Some("") {
+            anyhow::bail!("admin.token cannot be empty");
+        }
+
+        // Validate event export
+        let sinks = &self.export.sinks;
+        if !sinks.is_empty() && self.export.buffer_dir.is_empty() {
+            anyhow::bail!("export.buffer_dir cannot be empty");
+        }
+        for (i, sink) in sinks.iter().enumerate() {
+            // The name is also the file name of the sink's disk buffer
+            if sink.name.is_empty()
+                || !sink
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                anyhow::bail!(
+                    "export.sinks[{}].name must be letters, digits, '-' or '_': {:?}",
+                    i,
+                    sink.name
+                );
+            }
+            if sinks[..i].iter().any(|s| s.name == sink.name) {
+                anyhow::bail!("Duplicate export sink name: {}", sink.name);
+            }
+            let known_target = sink.target == "stdout"
+                || SINK_TARGET_SCHEMES.iter().any(|scheme| {
+                    sink.target.len() > scheme.len() && sink.target.starts_with(scheme)
+                });
+            if !known_target {
+                anyhow::bail!(
+                    "export.sinks[{}].target must be stdout, file://, udp://, tcp:// or unix://: {:?}",
+                    i,
+                    sink.target
+                );
+            }
+            if let Some(decision) = sink
+                .decisions
+                .iter()
+                .find(|d| !matches!(d.as_str(), "allow" | "flag" | "block"))
+            {
+                anyhow::bail!("export.sinks[{}] unknown decision: {:?}", i, decision);
+            }
+            if sink.facility > 23 {
+                anyhow::bail!("export.sinks[{}].facility must be between 0 and 23", i);
+            }
+            if sink.queue_capacity == 0 || sink.retry_interval_seconds == 0 {
+                anyhow::bail!(
+                    "export.sinks[{}].queue_capacity and retry_interval_seconds must be greater than 0",
+                    i
+                );
+            }
+        }
+
         Ok(())
     }
 }
 
+fn validate_thresholds(name: &str, block: f32, flag: f32) -> Result<()> {
+    if flag <= 0.0 || block <= 0.0 {
+        anyhow::bail!("{} thresholds must be greater than 0", name);
+    }
+    if flag > block {
+        anyhow::bail!(
+            "{}.flag_threshold must not be greater than block_threshold",
+            name
+        );
+    }
+    Ok(())
+}
+
 /// Defines the behavior when LLM evaluation fails
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum FailMode {
     /// Allow the request through (fail open - less secure but more available)
+    #[default]
     Open,
     /// Block the request with 403 Forbidden (fail closed - more secure but less available)
     Closed,
 }
 
-impl Default for FailMode {
-    fn default() -> Self {
-        FailMode::Open
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WafConfig {
     pub listen_addr: String,
@@ -97,6 +335,10 @@ pub struct WafConfig {
     pub request_timeout_ms: u64,
     #[serde(default)]
     pub fail_mode: FailMode,
+    /// Proxies (addresses or CIDR networks) whose `X-Forwarded-For` header
+    /// gives the client address; other peers are taken as the client
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
 }
 
 impl WafConfig {
@@ -115,6 +357,10 @@ pub struct LlmConfig {
     pub judge_temperature: f32,
     pub learner_max_tokens: u32,
     pub learner_temperature: f32,
+    /// Directory of `<name>.tmpl` prompt templates overriding the built-in
+    /// ones, hot-reloaded on change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts_dir: Option<String>,
 }
 
 impl LlmConfig {
@@ -123,23 +369,321 @@ impl LlmConfig {
     }
 }
 
+/// Shared verdict cache backend
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    #[default]
+    Redis,
+    /// SQLite file (`cache.sqlite_path`): persistent, single instance
+    Sqlite,
+    /// In-process LRU sized by `cache.memory.capacity`, not persistent
+    Memory,
+    None,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheConfig {
     pub redis_url: String,
+    /// TTL of confident allow and block verdicts
     pub ttl_seconds: u64,
+    /// Enables verdict caching: the backend and the in-process tier
     pub enabled: bool,
+    #[serde(default)]
+    pub backend: CacheBackend,
+    #[serde(default = "default_cache_sqlite_path")]
+    pub sqlite_path: String,
+    /// TTL of flags and of allows and blocks below `confident_threshold`
+    #[serde(default = "default_short_ttl_seconds")]
+    pub short_ttl_seconds: u64,
+    #[serde(default = "default_confident_threshold")]
+    pub confident_threshold: f32,
+    /// Headers covered by the request hash (the verdict cache key)
+    #[serde(default = "default_hash_headers")]
+    pub hash_headers: Vec<String>,
+    /// In-process tier, checked before the backend (works with `backend: none`)
+    #[serde(default)]
+    pub memory: MemoryCacheConfig,
+}
+
+fn default_cache_sqlite_path() -> String {
+    "./data/cache.db".to_string()
+}
+
+fn default_short_ttl_seconds() -> u64 {
+    60
+}
+
+fn default_confident_threshold() -> f32 {
+    0.8
+}
+
+fn default_hash_headers() -> Vec<String> {
+    DEFAULT_HASH_HEADERS.iter().map(|h| h.to_string()).collect()
 }
 
 impl CacheConfig {
+    /// Whether verdicts are kept in process in front of the backend (not
+    /// in front of the memory backend, which is the same LRU)
+    pub fn memory_tier_enabled(&self) -> bool {
+        self.enabled && self.memory.capacity > 0 && self.backend != CacheBackend::Memory
+    }
+
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_seconds)
+    }
+
+    pub fn verdict_ttls(&self) -> VerdictTtls {
+        VerdictTtls {
+            confident: self.ttl(),
+            short: Duration::from_secs(self.short_ttl_seconds),
+            confident_threshold: self.confident_threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryCacheConfig {
+    /// Verdicts kept, least recently used evicted first (0 disables the tier)
+    #[serde(default = "default_memory_cache_capacity")]
+    pub capacity: usize,
+    /// Kept short: verdicts invalidated on another instance stay served
+    /// here until they expire
+    #[serde(default = "default_memory_cache_ttl_seconds")]
+    pub ttl_seconds: u64,
+}
+
+fn default_memory_cache_capacity() -> usize {
+    10_000
+}
+
+fn default_memory_cache_ttl_seconds() -> u64 {
+    60
+}
+
+impl Default for MemoryCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_memory_cache_capacity(),
+            ttl_seconds: default_memory_cache_ttl_seconds(),
+        }
+    }
+}
+
+impl MemoryCacheConfig {
     pub fn ttl(&self) -> Duration {
         Duration::from_secs(self.ttl_seconds)
     }
 }
 
+/// Event log backend
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogBackend {
+    /// SQLite file (`storage.logs_db_path`), single instance
+    #[default]
+    Sqlite,
+    /// PostgreSQL database (`storage.postgres_url`), shared by replicas
+    Postgres,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: LogBackend,
     pub logs_db_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postgres_url: Option<String>,
     pub rulebook_path: String,
+    /// ModSecurity/CRS `.conf` file or directory imported when no rulebook exists yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed_rules_path: Option<String>,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub writer: EventWriterConfig,
+    #[serde(default)]
+    pub sampling: SamplingConfig,
+}
+
+/// Which allowed requests are logged; blocks and flags always are
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    /// Share of allows logged (0.0 - 1.0)
+    #[serde(default = "default_allow_rate")]
+    pub allow_rate: f32,
+    /// Per-route rates; the longest matching path prefix wins
+    #[serde(default)]
+    pub routes: Vec<RouteSampling>,
+    /// Allows below this confidence are always logged
+    #[serde(default = "default_always_log_below_confidence")]
+    pub always_log_below_confidence: f32,
+    /// Request hashes remembered to always log the first allow of a new one
+    /// (0: no special case for new hashes)
+    #[serde(default = "default_seen_hashes_capacity")]
+    pub seen_hashes_capacity: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSampling {
+    pub path_prefix: String,
+    pub allow_rate: f32,
+}
+
+fn default_allow_rate() -> f32 {
+    1.0
+}
+
+fn default_always_log_below_confidence() -> f32 {
+    0.8
+}
+
+fn default_seen_hashes_capacity() -> usize {
+    100_000
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            allow_rate: default_allow_rate(),
+            routes: Vec::new(),
+            always_log_below_confidence: default_always_log_below_confidence(),
+            seen_hashes_capacity: default_seen_hashes_capacity(),
+        }
+    }
+}
+
+impl SamplingConfig {
+    /// Whether some allows are left out of the logs
+    pub fn is_enabled(&self) -> bool {
+        self.allow_rate < 1.0 || self.routes.iter().any(|route| route.allow_rate < 1.0)
+    }
+}
+
+/// Which events are dropped when the event log queue is full. Blocks are
+/// never dropped: logging them waits for room instead.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    #[default]
+    DropAllows,
+    DropAllowsAndFlags,
+    /// Nothing is dropped, every request waits for room
+    Wait,
+}
+
+impl OverflowPolicy {
+    /// Whether events with this decision type are dropped on overflow
+    pub fn drops(&self, decision: &str) -> bool {
+        match self {
+            OverflowPolicy::DropAllows => decision == "allow",
+            OverflowPolicy::DropAllowsAndFlags => decision == "allow" || decision == "flag",
+            OverflowPolicy::Wait => false,
+        }
+    }
+}
+
+/// Queue between request handling and the single event log writer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventWriterConfig {
+    /// Events waiting to be written
+    #[serde(default = "default_writer_queue_capacity")]
+    pub queue_capacity: usize,
+    /// Events written per transaction, at most
+    #[serde(default = "default_writer_batch_size")]
+    pub batch_size: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+fn default_writer_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_writer_batch_size() -> usize {
+    500
+}
+
+impl Default for EventWriterConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: default_writer_queue_capacity(),
+            batch_size: default_writer_batch_size(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// How long events are kept in the logs database, by decision type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_retention_interval_minutes")]
+    pub interval_minutes: u64,
+    /// Maximum age per decision type, in hours (0 keeps them forever)
+    #[serde(default = "default_allow_max_age_hours")]
+    pub allow_max_age_hours: u64,
+    #[serde(default = "default_flagged_max_age_hours")]
+    pub flag_max_age_hours: u64,
+    #[serde(default = "default_flagged_max_age_hours")]
+    pub block_max_age_hours: u64,
+    /// Events deleted per transaction
+    #[serde(default = "default_retention_batch_size")]
+    pub batch_size: usize,
+    /// Directory receiving expired events as gzip-compressed JSONL, one file
+    /// per run; expired events are dropped when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_dir: Option<String>,
+}
+
+fn default_retention_interval_minutes() -> u64 {
+    60
+}
+
+fn default_allow_max_age_hours() -> u64 {
+    24
+}
+
+fn default_flagged_max_age_hours() -> u64 {
+    90 * 24
+}
+
+fn default_retention_batch_size() -> usize {
+    5000
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: default_retention_interval_minutes(),
+            allow_max_age_hours: default_allow_max_age_hours(),
+            flag_max_age_hours: default_flagged_max_age_hours(),
+            block_max_age_hours: default_flagged_max_age_hours(),
+            batch_size: default_retention_batch_size(),
+            archive_dir: None,
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_minutes * 60)
+    }
+
+    /// Maximum age of each decision type that isn't kept forever
+    pub fn max_ages(&self) -> Vec<(&'static str, Duration)> {
+        [
+            ("allow", self.allow_max_age_hours),
+            ("flag", self.flag_max_age_hours),
+            ("block", self.block_max_age_hours),
+        ]
+        .into_iter()
+        .filter(|(_, hours)| *hours > 0)
+        .map(|(decision, hours)| (decision, Duration::from_secs(hours * 3600)))
+        .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -147,6 +691,13 @@ pub struct LearnerConfig {
     pub batch_interval_minutes: u64,
     pub min_flagged_requests: usize,
     pub enabled: bool,
+    /// Maximum number of flagged-request clusters sent to the LLM per batch
+    #[serde(default = "default_max_clusters_per_batch")]
+    pub max_clusters_per_batch: usize,
+}
+
+fn default_max_clusters_per_batch() -> usize {
+    10
 }
 
 impl LearnerConfig {
@@ -155,6 +706,434 @@ impl LearnerConfig {
     }
 }
 
+/// How the Judge turns local rule hits and the LLM verdict into a decision
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DecisionMode {
+    /// The first matching local rule decides, otherwise the LLM verdict does
+    #[default]
+    FirstMatch,
+    /// Rule hits and the LLM verdict add up to an anomaly score compared to
+    /// block and flag thresholds (OWASP CRS style)
+    Anomaly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringConfig {
+    #[serde(default)]
+    pub mode: DecisionMode,
+    /// Score at or above which a request is blocked
+    #[serde(default = "default_block_threshold")]
+    pub block_threshold: f32,
+    /// Score at or above which a request is flagged
+    #[serde(default = "default_flag_threshold")]
+    pub flag_threshold: f32,
+    /// Multiplier applied to the LLM verdict score
+    #[serde(default = "default_llm_weight")]
+    pub llm_weight: f32,
+    /// Per-route thresholds; the longest matching path prefix wins
+    #[serde(default)]
+    pub routes: Vec<RouteThresholds>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteThresholds {
+    pub path_prefix: String,
+    pub block_threshold: f32,
+    pub flag_threshold: f32,
+}
+
+fn default_block_threshold() -> f32 {
+    3.5
+}
+
+fn default_flag_threshold() -> f32 {
+    2.0
+}
+
+fn default_llm_weight() -> f32 {
+    1.0
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            mode: DecisionMode::default(),
+            block_threshold: default_block_threshold(),
+            flag_threshold: default_flag_threshold(),
+            llm_weight: default_llm_weight(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// Block and flag thresholds for a request path, matched in canonical form
+    /// so `//admin` or `/%61dmin` can't dodge the `/admin` thresholds (and on
+    /// segment boundaries, so `/administrator` isn't under them)
+    pub fn thresholds_for(&self, path: &str) -> (f32, f32) {
+        let path = canonical::canonical_path(path);
+        self.routes
+            .iter()
+            .filter(|route| canonical::has_route_prefix(&path, &route.path_prefix))
+            .max_by_key(|route| route.path_prefix.len())
+            .map(|route| (route.block_threshold, route.flag_threshold))
+            .unwrap_or((self.block_threshold, self.flag_threshold))
+    }
+}
+
+/// Similar past requests with a confirmed verdict shown to the judge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FewShotConfig {
+    #[serde(default = "default_fewshot_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_fewshot_max_examples")]
+    pub max_examples: usize,
+    /// Minimum estimated Jaccard similarity of an example to the request
+    #[serde(default = "default_fewshot_min_similarity")]
+    pub min_similarity: f32,
+    /// Minimum confidence of an LLM verdict to become an example
+    #[serde(default = "default_fewshot_min_confidence")]
+    pub min_confidence: f32,
+    /// Also show confident LLM blocks nobody has checked, not just corrected
+    /// verdicts. Off by default: the LLM would learn from its own mistakes.
+    #[serde(default)]
+    pub include_unverified: bool,
+    /// How long picking the examples of a request may take before the judge
+    /// goes on without them
+    #[serde(default = "default_fewshot_select_timeout_ms")]
+    pub select_timeout_ms: u64,
+    /// Prompt tokens the examples may use
+    #[serde(default = "default_fewshot_token_budget")]
+    pub token_budget: usize,
+    /// How long the examples of an endpoint are cached
+    #[serde(default = "default_fewshot_cache_ttl_seconds")]
+    pub cache_ttl_seconds: u64,
+    /// Days unverified examples (LLM verdicts) are kept; corrected ones are
+    /// kept for good
+    #[serde(default = "default_fewshot_max_age_days")]
+    pub max_age_days: u64,
+}
+
+fn default_fewshot_enabled() -> bool {
+    true
+}
+
+fn default_fewshot_max_examples() -> usize {
+    4
+}
+
+fn default_fewshot_min_similarity() -> f32 {
+    0.3
+}
+
+fn default_fewshot_min_confidence() -> f32 {
+    0.9
+}
+
+fn default_fewshot_select_timeout_ms() -> u64 {
+    50
+}
+
+fn default_fewshot_token_budget() -> usize {
+    250
+}
+
+fn default_fewshot_cache_ttl_seconds() -> u64 {
+    300
+}
+
+fn default_fewshot_max_age_days() -> u64 {
+    30
+}
+
+impl Default for FewShotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_fewshot_enabled(),
+            max_examples: default_fewshot_max_examples(),
+            min_similarity: default_fewshot_min_similarity(),
+            min_confidence: default_fewshot_min_confidence(),
+            include_unverified: false,
+            select_timeout_ms: default_fewshot_select_timeout_ms(),
+            token_budget: default_fewshot_token_budget(),
+            cache_ttl_seconds: default_fewshot_cache_ttl_seconds(),
+            max_age_days: default_fewshot_max_age_days(),
+        }
+    }
+}
+
+/// How many second opinions must confirm an escalated block
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AgreementPolicy {
+    /// At least one
+    Any,
+    /// Every judge that answered
+    All,
+    /// More than half of the judges that answered
+    #[default]
+    Majority,
+}
+
+/// Second opinions asked before issuing a mid-confidence LLM block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscalationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// LLM blocks with a confidence from `min_confidence` up to (excluding)
+    /// `max_confidence` are escalated
+    #[serde(default = "default_escalation_min_confidence")]
+    pub min_confidence: f32,
+    #[serde(default = "default_escalation_max_confidence")]
+    pub max_confidence: f32,
+    /// Latency budget of all second opinions together
+    #[serde(default = "default_escalation_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub policy: AgreementPolicy,
+    #[serde(default)]
+    pub judges: Vec<SecondOpinionConfig>,
+}
+
+/// A second-opinion judge: the `llm` settings with these overrides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecondOpinionConfig {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Prompt templates of this judge (loaded at startup)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts_dir: Option<String>,
+}
+
+impl SecondOpinionConfig {
+    /// LLM settings of this judge, answering within `timeout_ms`
+    pub fn llm_config(&self, base: &LlmConfig, timeout_ms: u64) -> LlmConfig {
+        LlmConfig {
+            base_url: self
+                .base_url
+                .clone()
+                .unwrap_or_else(|| base.base_url.clone()),
+            model: self.model.clone().unwrap_or_else(|| base.model.clone()),
+            judge_timeout_ms: timeout_ms,
+            prompts_dir: self.prompts_dir.clone(),
+            ..base.clone()
+        }
+    }
+}
+
+fn default_escalation_min_confidence() -> f32 {
+    0.5
+}
+
+fn default_escalation_max_confidence() -> f32 {
+    0.9
+}
+
+fn default_escalation_timeout_ms() -> u64 {
+    5000
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_confidence: default_escalation_min_confidence(),
+            max_confidence: default_escalation_max_confidence(),
+            timeout_ms: default_escalation_timeout_ms(),
+            policy: AgreementPolicy::default(),
+            judges: Vec::new(),
+        }
+    }
+}
+
+impl EscalationConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// Admin API (event labeling), served on its own listener
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_admin_listen_addr")]
+    pub listen_addr: String,
+    /// Bearer token required by the admin API, when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+fn default_admin_listen_addr() -> String {
+    "127.0.0.1:5001".to_string()
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen_addr: default_admin_listen_addr(),
+            token: None,
+        }
+    }
+}
+
+/// Routes forwarded before the judge's verdict, and the bans that verdict feeds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AsyncJudgeConfig {
+    /// Path prefixes forwarded right away, the request being judged in the background
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// How long a client judged malicious in the background is banned (0: no bans)
+    #[serde(default = "default_ban_ttl_seconds")]
+    pub ban_ttl_seconds: u64,
+    /// Minimum confidence of a background block to ban its client
+    #[serde(default = "default_ban_min_confidence")]
+    pub ban_min_confidence: f32,
+    /// Cookie identifying a session, banned along with the client IP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_cookie: Option<String>,
+}
+
+fn default_ban_ttl_seconds() -> u64 {
+    3600
+}
+
+fn default_ban_min_confidence() -> f32 {
+    0.8
+}
+
+impl Default for AsyncJudgeConfig {
+    fn default() -> Self {
+        Self {
+            routes: Vec::new(),
+            ban_ttl_seconds: default_ban_ttl_seconds(),
+            ban_min_confidence: default_ban_min_confidence(),
+            session_cookie: None,
+        }
+    }
+}
+
+impl AsyncJudgeConfig {
+    /// Whether requests to `path` (matched in canonical form, on segment
+    /// boundaries) are forwarded before being judged
+    pub fn is_async(&self, path: &str) -> bool {
+        let path = canonical::canonical_path(path);
+        self.routes
+            .iter()
+            .any(|route| canonical::has_route_prefix(&path, route))
+    }
+
+    pub fn ban_ttl(&self) -> Duration {
+        Duration::from_secs(self.ban_ttl_seconds)
+    }
+}
+
+/// Targets a sink can deliver to, besides `stdout`
+const SINK_TARGET_SCHEMES: &[&str] = &["file://", "udp://", "tcp://", "unix://"];
+
+/// Event export to SIEMs: each sink gets the events its filters match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportConfig {
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    /// Directory of the disk buffers holding events a sink couldn't deliver yet
+    #[serde(default = "default_export_buffer_dir")]
+    pub buffer_dir: String,
+}
+
+fn default_export_buffer_dir() -> String {
+    "./data/export".to_string()
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            sinks: Vec::new(),
+            buffer_dir: default_export_buffer_dir(),
+        }
+    }
+}
+
+/// Event line format
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// ArcSight Common Event Format
+    Cef,
+    /// Syslog (RFC 5424) with the event as structured data
+    Syslog,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkConfig {
+    pub name: String,
+    pub format: ExportFormat,
+    /// `stdout`, `file:///path`, `udp://host:port`, `tcp://host:port` or
+    /// `unix:///path` (datagram socket, like `/dev/log`)
+    pub target: String,
+    /// Decision types exported
+    #[serde(default = "default_sink_decisions")]
+    pub decisions: Vec<String>,
+    /// Blocks below this threat level aren't exported (allows and flags have none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_threat_level: Option<ThreatLevel>,
+    /// Syslog facility (default: 16, local0)
+    #[serde(default = "default_sink_facility")]
+    pub facility: u8,
+    /// Events waiting to be delivered
+    #[serde(default = "default_sink_queue_capacity")]
+    pub queue_capacity: usize,
+    /// Events dropped while the queue is full, as for the event log; blocks
+    /// are never dropped
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// Pause between redeliveries of the disk buffer after a failure,
+    /// doubled after each failed redelivery (up to 5 minutes)
+    #[serde(default = "default_sink_retry_interval_seconds")]
+    pub retry_interval_seconds: u64,
+    /// Disk buffer size above which undelivered events are dropped, and
+    /// bound on the dead letters
+    #[serde(default = "default_sink_max_buffer_mb")]
+    pub max_buffer_mb: u64,
+}
+
+fn default_sink_decisions() -> Vec<String> {
+    vec!["flag".to_string(), "block".to_string()]
+}
+
+fn default_sink_facility() -> u8 {
+    16
+}
+
+fn default_sink_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_sink_retry_interval_seconds() -> u64 {
+    10
+}
+
+fn default_sink_max_buffer_mb() -> u64 {
+    100
+}
+
+impl SinkConfig {
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_secs(self.retry_interval_seconds)
+    }
+
+    pub fn max_buffer_bytes(&self) -> u64 {
+        self.max_buffer_mb * 1024 * 1024
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObservabilityConfig {
     pub log_level: String,
@@ -165,6 +1144,66 @@ pub struct ObservabilityConfig {
 mod tests {
     use super::*;
 
+    fn valid_config() -> Config {
+        Config {
+            waf: WafConfig {
+                listen_addr: "0.0.0.0:8080".to_string(),
+                upstream_url: "http://backend:3000".to_string(),
+                request_timeout_ms: 30000,
+                fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
+            },
+            llm: LlmConfig {
+                provider: "ollama".to_string(),
+                base_url: "http://localhost:11434".to_string(),
+                model: "llama3.2".to_string(),
+                judge_timeout_ms: 200,
+                judge_max_tokens: 128,
+                judge_temperature: 0.0,
+                learner_max_tokens: 2048,
+                learner_temperature: 0.3,
+                prompts_dir: None,
+            },
+            cache: CacheConfig {
+                redis_url: "redis://localhost:6379".to_string(),
+                ttl_seconds: 900,
+                enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
+            },
+            storage: StorageConfig {
+                backend: LogBackend::Sqlite,
+                logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
+                rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
+            },
+            learner: LearnerConfig {
+                batch_interval_minutes: 60,
+                min_flagged_requests: 10,
+                enabled: true,
+                max_clusters_per_batch: 10,
+            },
+            observability: ObservabilityConfig {
+                log_level: "info".to_string(),
+                metrics_enabled: true,
+            },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
+        }
+    }
+
     #[test]
     fn test_config_validation() {
         let config = Config {
@@ -173,6 +1212,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -183,25 +1223,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         assert!(config.validate().is_ok());
@@ -215,6 +1275,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -225,25 +1286,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -259,6 +1340,7 @@ mod tests {
                 upstream_url: "".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -269,25 +1351,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -303,6 +1405,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 0,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -313,25 +1416,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -347,6 +1470,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -357,25 +1481,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -391,6 +1535,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -401,25 +1546,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -435,6 +1600,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -445,25 +1611,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -479,6 +1665,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -489,25 +1676,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "./data/logs.db".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -523,6 +1730,7 @@ mod tests {
                 upstream_url: "http://backend:3000".to_string(),
                 request_timeout_ms: 30000,
                 fail_mode: FailMode::Open,
+                trusted_proxies: Vec::new(),
             },
             llm: LlmConfig {
                 provider: "ollama".to_string(),
@@ -533,25 +1741,45 @@ mod tests {
                 judge_temperature: 0.0,
                 learner_max_tokens: 2048,
                 learner_temperature: 0.3,
+                prompts_dir: None,
             },
             cache: CacheConfig {
                 redis_url: "redis://localhost:6379".to_string(),
                 ttl_seconds: 900,
                 enabled: true,
+                backend: CacheBackend::Redis,
+                sqlite_path: default_cache_sqlite_path(),
+                short_ttl_seconds: default_short_ttl_seconds(),
+                confident_threshold: default_confident_threshold(),
+                hash_headers: default_hash_headers(),
+                memory: MemoryCacheConfig::default(),
             },
             storage: StorageConfig {
+                backend: LogBackend::Sqlite,
                 logs_db_path: "".to_string(),
+                postgres_url: None,
                 rulebook_path: "./data/rulebook.json".to_string(),
+                seed_rules_path: None,
+                retention: RetentionConfig::default(),
+                writer: EventWriterConfig::default(),
+                sampling: SamplingConfig::default(),
             },
             learner: LearnerConfig {
                 batch_interval_minutes: 60,
                 min_flagged_requests: 10,
                 enabled: true,
+                max_clusters_per_batch: 10,
             },
             observability: ObservabilityConfig {
                 log_level: "info".to_string(),
                 metrics_enabled: true,
             },
+            scoring: ScoringConfig::default(),
+            fewshot: FewShotConfig::default(),
+            admin: AdminConfig::default(),
+            escalation: EscalationConfig::default(),
+            async_judge: AsyncJudgeConfig::default(),
+            export: ExportConfig::default(),
         };
 
         let result = config.validate();
@@ -566,6 +1794,182 @@ mod tests {
         assert!(result.unwrap_err().to_string().contains("rulebook_path"));
     }
 
+    #[test]
+    fn test_config_validation_zero_max_clusters() {
+        let mut config = valid_config();
+        config.learner.max_clusters_per_batch = 0;
+
+        let result = config.validate();
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("max_clusters_per_batch"));
+    }
+
+    #[test]
+    fn test_config_validation_scoring_thresholds() {
+        let mut config = valid_config();
+        config.scoring.flag_threshold = 6.0;
+
+        let result = config.validate();
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("flag_threshold"));
+
+        let mut config = valid_config();
+        config.scoring.routes.push(RouteThresholds {
+            path_prefix: "admin".to_string(),
+            block_threshold: 3.0,
+            flag_threshold: 2.0,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_validation_fewshot() {
+        let mut config = valid_config();
+        config.fewshot.min_similarity = 1.5;
+
+        let result = config.validate();
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("min_similarity"));
+    }
+
+    #[test]
+    fn test_config_validation_admin() {
+        let mut config = valid_config();
+        config.admin.enabled = true;
+        assert!(config.validate().is_ok());
+
+        config.admin.token = Some(String::new());
+        assert!(config.validate().is_err());
+
+        config.admin.token = None;
+        config.admin.listen_addr = String::new();
+        let result = config.validate();
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("admin.listen_addr"));
+    }
+
+    #[test]
+    fn test_config_validation_escalation() {
+        let mut config = valid_config();
+        config.escalation.enabled = true;
+        assert!(config.validate().is_err());
+
+        let judge = SecondOpinionConfig {
+            name: "large".to_string(),
+            base_url: None,
+            model: Some("llama3.1:70b".to_string()),
+            prompts_dir: None,
+        };
+        config.escalation.judges.push(judge.clone());
+        assert!(config.validate().is_ok());
+
+        config.escalation.judges.push(judge);
+        assert!(config.validate().is_err());
+
+        config.escalation.judges.pop();
+        config.escalation.min_confidence = 0.95;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_second_opinion_llm_config() {
+        let base = valid_config().llm;
+        let judge = SecondOpinionConfig {
+            name: "large".to_string(),
+            base_url: None,
+            model: Some("llama3.1:70b".to_string()),
+            prompts_dir: None,
+        };
+
+        let llm = judge.llm_config(&base, 1500);
+        assert_eq!(llm.model, "llama3.1:70b");
+        assert_eq!(llm.base_url, base.base_url);
+        assert_eq!(llm.judge_timeout_ms, 1500);
+    }
+
+    #[test]
+    fn test_async_judge_config() {
+        let mut config = valid_config();
+        config.async_judge.routes = vec!["/api/feed".to_string()];
+        assert!(config.validate().is_ok());
+        assert!(config.async_judge.is_async("/api/feed/latest"));
+        assert!(!config.async_judge.is_async("/api/users"));
+        assert!(config.async_judge.is_async("/api//feed"));
+        assert!(config.async_judge.is_async("/api/%66eed"));
+        assert!(!config.async_judge.is_async("/api/feedback"));
+
+        config.async_judge.routes.push("api".to_string());
+        assert!(config.validate().is_err());
+
+        config.async_judge.routes.pop();
+        config.async_judge.session_cookie = Some(String::new());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_export_config() {
+        let mut config = valid_config();
+        let yaml = "sinks:\n  - name: soc\n    format: syslog\n    target: udp://siem:514\n    min_threat_level: high\n";
+        config.export = serde_yaml_ng::from_str(yaml).unwrap();
+        let sink = &config.export.sinks[0];
+        assert_eq!(sink.format, ExportFormat::Syslog);
+        assert_eq!(sink.decisions, vec!["flag", "block"]);
+        assert_eq!(sink.min_threat_level, Some(ThreatLevel::High));
+        assert_eq!(config.export.buffer_dir, "./data/export");
+        assert!(config.validate().is_ok());
+
+        config.export.sinks[0].target = "http://siem".to_string();
+        assert!(config.validate().is_err());
+
+        config.export.sinks[0].target = "stdout".to_string();
+        config.export.sinks[0].decisions.push("deny".to_string());
+        assert!(config.validate().is_err());
+
+        config.export.sinks[0].decisions.pop();
+        config.export.sinks[0].name = "../soc".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_scoring_thresholds_for_route() {
+        let mut scoring = ScoringConfig::default();
+        scoring.routes.push(RouteThresholds {
+            path_prefix: "/api".to_string(),
+            block_threshold: 8.0,
+            flag_threshold: 4.0,
+        });
+        scoring.routes.push(RouteThresholds {
+            path_prefix: "/api/admin".to_string(),
+            block_threshold: 3.0,
+            flag_threshold: 1.0,
+        });
+
+        assert_eq!(scoring.thresholds_for("/login"), (3.5, 2.0));
+        assert_eq!(scoring.thresholds_for("/api/users"), (8.0, 4.0));
+        assert_eq!(scoring.thresholds_for("/api/admin/users"), (3.0, 1.0));
+
+        // Matched on the canonical path, as the hash and the rules see it
+        scoring.routes.push(RouteThresholds {
+            path_prefix: "/admin".to_string(),
+            block_threshold: 2.0,
+            flag_threshold: 1.0,
+        });
+        assert_eq!(scoring.thresholds_for("/admin"), (2.0, 1.0));
+        assert_eq!(scoring.thresholds_for("//admin"), (2.0, 1.0));
+        assert_eq!(scoring.thresholds_for("/%61dmin"), (2.0, 1.0));
+        assert_eq!(scoring.thresholds_for("/./admin"), (2.0, 1.0));
+        assert_eq!(scoring.thresholds_for("/api//admin/users"), (3.0, 1.0));
+        assert_eq!(scoring.thresholds_for("//api"), (8.0, 4.0));
+        assert_eq!(scoring.thresholds_for("/administrator"), (3.5, 2.0));
+        assert_eq!(scoring.thresholds_for("/apiary"), (3.5, 2.0));
+    }
+
     #[test]
     fn test_waf_config_request_timeout() {
         let config = WafConfig {
@@ -573,6 +1977,7 @@ mod tests {
             upstream_url: "http://backend:3000".to_string(),
             request_timeout_ms: 5000,
             fail_mode: FailMode::Open,
+            trusted_proxies: Vec::new(),
         };
 
         let timeout = config.request_timeout();
@@ -590,6 +1995,7 @@ mod tests {
             judge_temperature: 0.0,
             learner_max_tokens: 2048,
             learner_temperature: 0.3,
+            prompts_dir: None,
         };
 
         let timeout = config.judge_timeout();
@@ -602,10 +2008,133 @@ mod tests {
             redis_url: "redis://localhost:6379".to_string(),
             ttl_seconds: 600,
             enabled: true,
+            backend: CacheBackend::Redis,
+            sqlite_path: default_cache_sqlite_path(),
+            short_ttl_seconds: default_short_ttl_seconds(),
+            confident_threshold: default_confident_threshold(),
+            hash_headers: default_hash_headers(),
+            memory: MemoryCacheConfig::default(),
         };
 
         let ttl = config.ttl();
         assert_eq!(ttl.as_secs(), 600);
+
+        let ttls = config.verdict_ttls();
+        assert_eq!(ttls.confident.as_secs(), 600);
+        assert_eq!(ttls.short.as_secs(), 60);
+    }
+
+    #[test]
+    fn test_memory_cache_config() {
+        let mut config = valid_config();
+        assert_eq!(config.cache.memory.capacity, 10_000);
+        assert_eq!(config.cache.memory.ttl().as_secs(), 60);
+
+        config.cache.memory.ttl_seconds = 0;
+        assert!(config.validate().is_err());
+
+        // A disabled tier needs no TTL
+        config.cache.memory.capacity = 0;
+        assert!(config.validate().is_ok());
+
+        // Nor does a disabled cache
+        config.cache.memory.capacity = 10_000;
+        config.cache.enabled = false;
+        assert!(!config.cache.memory_tier_enabled());
+        assert!(config.validate().is_ok());
+        config.cache.enabled = true;
+        assert!(config.cache.memory_tier_enabled());
+
+        // The memory backend is the tier already
+        config.cache.backend = CacheBackend::Memory;
+        assert!(!config.cache.memory_tier_enabled());
+    }
+
+    #[test]
+    fn test_cache_backend_config() {
+        let mut config = valid_config();
+        let yaml = serde_yaml_ng::to_string(&config.cache).unwrap();
+        assert!(yaml.contains("backend: redis"));
+
+        // The Redis URL only matters to the Redis backend
+        config.cache.redis_url = String::new();
+        config.cache.backend = CacheBackend::Sqlite;
+        assert!(config.validate().is_ok());
+        config.cache.sqlite_path = String::new();
+        assert!(config.validate().is_err());
+
+        config.cache.backend = CacheBackend::Memory;
+        config.cache.memory.capacity = 0;
+        assert!(config.validate().is_err());
+
+        config.cache.backend = CacheBackend::None;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_retention_config() {
+        let mut config = valid_config();
+        assert!(!config.storage.retention.enabled);
+
+        let yaml = "enabled: true\nallow_max_age_hours: 12\nflag_max_age_hours: 0\n";
+        config.storage.retention = serde_yaml_ng::from_str(yaml).unwrap();
+        assert_eq!(config.storage.retention.interval().as_secs(), 3600);
+        assert_eq!(
+            config.storage.retention.max_ages(),
+            vec![
+                ("allow", Duration::from_secs(12 * 3600)),
+                ("block", Duration::from_secs(90 * 24 * 3600)),
+            ]
+        );
+        assert!(config.validate().is_ok());
+
+        config.storage.retention.batch_size = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_event_writer_config() {
+        let mut config = valid_config();
+        assert_eq!(config.storage.writer.overflow, OverflowPolicy::DropAllows);
+
+        config.storage.writer = serde_yaml_ng::from_str("overflow: drop_allows_and_flags").unwrap();
+        assert_eq!(config.storage.writer.queue_capacity, 10_000);
+        assert!(config.storage.writer.overflow.drops("flag"));
+        assert!(!config.storage.writer.overflow.drops("block"));
+        assert!(!OverflowPolicy::Wait.drops("allow"));
+
+        config.storage.writer.batch_size = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_sampling_config() {
+        let mut config = valid_config();
+        assert!(!config.storage.sampling.is_enabled());
+
+        let yaml = "routes:\n  - path_prefix: /health\n    allow_rate: 0.01\n";
+        config.storage.sampling = serde_yaml_ng::from_str(yaml).unwrap();
+        assert!(config.storage.sampling.is_enabled());
+        assert!(config.validate().is_ok());
+
+        config.storage.sampling.routes[0].path_prefix = "health".to_string();
+        assert!(config.validate().is_err());
+
+        config.storage.sampling.routes.clear();
+        config.storage.sampling.allow_rate = 1.5;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_log_backend_config() {
+        let mut config = valid_config();
+        assert_eq!(config.storage.backend, LogBackend::Sqlite);
+
+        config.storage.backend = serde_yaml_ng::from_str("postgres").unwrap();
+        assert!(config.validate().is_err());
+
+        config.storage.postgres_url = Some("postgres://guardix@db/guardix".to_string());
+        assert!(config.validate().is_ok());
     }
 
     #[test]
@@ -614,6 +2143,7 @@ mod tests {
             batch_interval_minutes: 30,
             min_flagged_requests: 5,
             enabled: true,
+            max_clusters_per_batch: 10,
         };
 
         let interval = config.batch_interval();
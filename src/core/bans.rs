@@ -0,0 +1,209 @@
+use crate::config::AsyncJudgeConfig;
+use crate::models::request::RequestPayload;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// What a ban applies to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BanKey {
+    Ip(String),
+    Session(String),
+}
+
+#[derive(Debug, Clone)]
+struct Ban {
+    reason: String,
+    until: Instant,
+    /// Hash of the request that got the client banned
+    payload_hash: String,
+}
+
+/// Clients (IP, and session when a session cookie is configured) banned
+/// after a background verdict found one of their requests malicious, so
+/// their follow-up requests are refused without being judged.
+/// Bans are kept in memory and expire after the configured TTL, or are
+/// lifted when the triggering request is labeled a false positive.
+pub struct BanList {
+    ttl: Duration,
+    session_cookie: Option<String>,
+    bans: RwLock<HashMap<BanKey, Ban>>,
+    hits: AtomicU64,
+}
+
+impl BanList {
+    pub fn new(ttl: Duration, session_cookie: Option<String>) -> Self {
+        Self {
+            ttl,
+            session_cookie,
+            bans: RwLock::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    /// Ban list of the background-judged routes, if they ban clients
+    pub fn for_async_judge(config: &AsyncJudgeConfig) -> Option<Arc<Self>> {
+        (!config.routes.is_empty() && config.ban_ttl_seconds > 0)
+            .then(|| Arc::new(Self::new(config.ban_ttl(), config.session_cookie.clone())))
+    }
+
+    /// Keys identifying the client of `payload`
+    fn keys(&self, payload: &RequestPayload) -> Vec<BanKey> {
+        let mut keys = Vec::new();
+        if let Some(ref ip) = payload.ip_addr {
+            keys.push(BanKey::Ip(ip.clone()));
+        }
+        if let Some(session) = self
+            .session_cookie
+            .as_deref()
+            .and_then(|name| session_id(payload, name))
+        {
+            keys.push(BanKey::Session(session));
+        }
+        keys
+    }
+
+    /// Bans the client of `payload`, returning the keys banned (none when the
+    /// client can't be identified)
+    pub fn ban(&self, payload: &RequestPayload, reason: &str) -> Vec<BanKey> {
+        let keys = self.keys(payload);
+        let until = Instant::now() + self.ttl;
+
+        let mut bans = self.bans.write().unwrap();
+        bans.retain(|_, ban| ban.until > Instant::now());
+        for key in &keys {
+            bans.insert(
+                key.clone(),
+                Ban {
+                    reason: reason.to_string(),
+                    until,
+                    payload_hash: payload.normalized_hash.clone(),
+                },
+            );
+        }
+        keys
+    }
+
+    /// Lifts the bans triggered by requests hashed `payload_hash`, returning
+    /// the keys unbanned
+    pub fn lift(&self, payload_hash: &str) -> Vec<BanKey> {
+        let mut bans = self.bans.write().unwrap();
+        let keys: Vec<BanKey> = bans
+            .iter()
+            .filter(|(_, ban)| ban.payload_hash == payload_hash)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            bans.remove(key);
+        }
+        keys
+    }
+
+    /// Reason of the ban on the client of `payload`, if banned
+    pub fn check(&self, payload: &RequestPayload) -> Option<String> {
+        let keys = self.keys(payload);
+        if keys.is_empty() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let bans = self.bans.read().unwrap();
+        let reason = keys
+            .iter()
+            .filter_map(|key| bans.get(key))
+            .find(|ban| ban.until > now)
+            .map(|ban| ban.reason.clone())?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(reason)
+    }
+
+    /// Requests refused because of a ban so far
+    #[allow(dead_code)] // Used in tests
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+/// Value of the cookie `name` in the request's `Cookie` header
+fn session_id(payload: &RequestPayload, name: &str) -> Option<String> {
+    payload
+        .headers
+        .iter()
+        .filter(|(header, _)| header.eq_ignore_ascii_case("cookie"))
+        .flat_map(|(_, value)| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(key, value)| *key == name && !value.is_empty())
+        .map(|(_, value)| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(ip: Option<&str>, cookie: Option<&str>) -> RequestPayload {
+        let mut headers = HashMap::new();
+        if let Some(cookie) = cookie {
+            headers.insert("cookie".to_string(), cookie.to_string());
+        }
+        RequestPayload::new(
+            "GET".to_string(),
+            "/feed".to_string(),
+            headers,
+            None,
+            HashMap::new(),
+            ip.map(str::to_string),
+        )
+    }
+
+    #[test]
+    fn test_ban_ip_and_session() {
+        let bans = BanList::new(Duration::from_secs(60), Some("sid".to_string()));
+        let attack = payload(Some("10.0.0.1"), Some("theme=dark; sid=abc"));
+
+        assert_eq!(
+            bans.ban(&attack, "SQLi"),
+            vec![
+                BanKey::Ip("10.0.0.1".to_string()),
+                BanKey::Session("abc".to_string())
+            ]
+        );
+        assert_eq!(bans.check(&attack), Some("SQLi".to_string()));
+        // Same session from another IP, same IP without the session
+        assert!(bans
+            .check(&payload(Some("10.0.0.2"), Some("sid=abc")))
+            .is_some());
+        assert!(bans.check(&payload(Some("10.0.0.1"), None)).is_some());
+        assert!(bans
+            .check(&payload(Some("10.0.0.2"), Some("sid=xyz")))
+            .is_none());
+        assert!(bans.check(&payload(None, None)).is_none());
+        assert_eq!(bans.hits(), 3);
+
+        // Anonymous clients can't be banned
+        assert!(bans.ban(&payload(None, Some("other=1")), "XSS").is_empty());
+    }
+
+    #[test]
+    fn test_lift_bans_of_a_request() {
+        let bans = BanList::new(Duration::from_secs(60), Some("sid".to_string()));
+        let attack = payload(Some("10.0.0.1"), Some("sid=abc"));
+        let other = payload(Some("10.0.0.2"), None);
+        bans.ban(&attack, "SQLi");
+        bans.ban(&other, "XSS");
+
+        assert_eq!(bans.lift(&attack.normalized_hash).len(), 2);
+        assert!(bans.check(&attack).is_none());
+        assert!(bans.check(&other).is_some());
+        assert!(bans.lift(&attack.normalized_hash).is_empty());
+    }
+
+    #[test]
+    fn test_ban_expires() {
+        let bans = BanList::new(Duration::ZERO, None);
+        let attack = payload(Some("10.0.0.1"), None);
+
+        bans.ban(&attack, "SQLi");
+        assert!(bans.check(&attack).is_none());
+    }
+}
@@ -0,0 +1,436 @@
+use crate::models::request::LogEntry;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Minimum Jaccard similarity of value shingles for two events to share a cluster
+const SIMILARITY_THRESHOLD: f32 = 0.4;
+
+/// Cap on cluster leaders per structural bucket, keeps clustering O(n) on large batches
+const MAX_LEADERS_PER_BUCKET: usize = 64;
+
+/// Number of representative samples kept per cluster
+const MAX_REPRESENTATIVES: usize = 8;
+
+/// Well-known scanners and clients, matched against the lowercased user-agent
+const USER_AGENT_FAMILIES: &[(&str, &str)] = &[
+    ("sqlmap", "sqlmap"),
+    ("nikto", "nikto"),
+    ("nmap", "nmap"),
+    ("masscan", "masscan"),
+    ("zgrab", "zgrab"),
+    ("nuclei", "nuclei"),
+    ("wpscan", "wpscan"),
+    ("curl", "curl"),
+    ("wget", "wget"),
+    ("python", "python"),
+    ("go-http-client", "go"),
+    ("java", "java"),
+    ("postman", "postman"),
+    ("headless", "headless-browser"),
+    ("mozilla", "browser"),
+];
+
+/// A group of structurally similar flagged requests, sent to the Learner as one unit
+#[derive(Debug, Clone)]
+pub struct FlaggedCluster {
+    pub method: String,
+    /// Path with identifiers replaced by placeholders (e.g. `/users/{id}`)
+    pub path_template: String,
+    pub param_names: Vec<String>,
+    pub user_agent_family: String,
+    /// Total number of flagged events in the cluster
+    pub size: usize,
+    /// Ids of all flagged events in the cluster
+    pub event_ids: Vec<i64>,
+    /// Newest distinct events of the cluster, used as prompt examples
+    pub samples: Vec<LogEntry>,
+}
+
+/// Groups flagged events by structural similarity.
+///
+/// Events are first bucketed by method, path template, parameter names and
+/// user-agent family, then split inside each bucket by leader clustering on
+/// token shingles of their parameter values. Clusters are returned largest first.
+pub fn cluster_flagged(logs: &[LogEntry]) -> Vec<FlaggedCluster> {
+    let mut buckets: HashMap<StructuralKey, Vec<Leader>> = HashMap::new();
+
+    for log in logs {
+        let (key, shingles) = signature(log);
+        let leaders = buckets.entry(key).or_default();
+
+        let best = leaders
+            .iter()
+            .enumerate()
+            .map(|(idx, leader)| (idx, jaccard(&leader.shingles, &shingles)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((idx, similarity))
+                if similarity >= SIMILARITY_THRESHOLD
+                    || leaders.len() >= MAX_LEADERS_PER_BUCKET =>
+            {
+                leaders[idx].members.push(log);
+            }
+            _ => leaders.push(Leader {
+                shingles,
+                members: vec![log],
+            }),
+        }
+    }
+
+    let mut clusters: Vec<FlaggedCluster> = buckets
+        .into_iter()
+        .flat_map(|(key, leaders)| {
+            leaders
+                .into_iter()
+                .map(move |leader| build_cluster(&key, leader.members))
+        })
+        .collect();
+
+    clusters.sort_by(|a, b| {
+        b.size
+            .cmp(&a.size)
+            .then_with(|| a.path_template.cmp(&b.path_template))
+    });
+    clusters
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct StructuralKey {
+    method: String,
+    path_template: String,
+    param_names: Vec<String>,
+    user_agent_family: String,
+}
+
+struct Leader<'a> {
+    shingles: HashSet<u64>,
+    members: Vec<&'a LogEntry>,
+}
+
+fn build_cluster(key: &StructuralKey, mut members: Vec<&LogEntry>) -> FlaggedCluster {
+    let size = members.len();
+    let event_ids = members.iter().map(|log| log.id).collect();
+    members.sort_by_key(|log| std::cmp::Reverse(log.timestamp));
+
+    let mut seen = HashSet::new();
+    let samples = members
+        .into_iter()
+        .filter(|log| seen.insert(log.payload_hash.as_str()))
+        .take(MAX_REPRESENTATIVES)
+        .cloned()
+        .collect();
+
+    FlaggedCluster {
+        method: key.method.clone(),
+        path_template: key.path_template.clone(),
+        param_names: key.param_names.clone(),
+        user_agent_family: key.user_agent_family.clone(),
+        size,
+        event_ids,
+        samples,
+    }
+}
+
+fn signature(log: &LogEntry) -> (StructuralKey, HashSet<u64>) {
+    let context = log.context().unwrap_or_default();
+
+    let mut param_names: BTreeSet<String> = context.query.iter().map(|(k, _)| k.clone()).collect();
+    let mut values: Vec<&str> = context.query.iter().map(|(_, v)| v.as_str()).collect();
+
+    let body_json = context
+        .body_excerpt
+        .as_deref()
+        .and_then(|b| serde_json::from_str::<serde_json::Value>(b).ok());
+    let body_values: Vec<String> = match &body_json {
+        Some(serde_json::Value::Object(map)) => {
+            param_names.extend(map.keys().map(|k| format!("body.{}", k)));
+            map.values().flat_map(json_leaf_strings).collect()
+        }
+        _ => context.body_excerpt.iter().cloned().collect(),
+    };
+    values.extend(body_values.iter().map(|s| s.as_str()));
+
+    let user_agent = log
+        .user_agent
+        .as_deref()
+        .or_else(|| context.headers.get("user-agent").map(|s| s.as_str()));
+
+    let key = StructuralKey {
+        method: log.method.clone(),
+        path_template: path_template(&log.path),
+        param_names: param_names.into_iter().collect(),
+        user_agent_family: user_agent_family(user_agent).to_string(),
+    };
+
+    (key, shingles(&values))
+}
+
+fn json_leaf_strings(value: &serde_json::Value) -> Vec<String> {
+    match value {
+        serde_json::Value::Object(map) => map.values().flat_map(json_leaf_strings).collect(),
+        serde_json::Value::Array(items) => items.iter().flat_map(json_leaf_strings).collect(),
+        serde_json::Value::String(s) => vec![s.clone()],
+        serde_json::Value::Null => Vec::new(),
+        other => vec![other.to_string()],
+    }
+}
+
+/// Replaces identifier-like path segments with placeholders
+pub fn path_template(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.is_empty() {
+                segment
+            } else if segment.chars().all(|c| c.is_ascii_digit()) {
+                "{id}"
+            } else if is_uuid(segment) {
+                "{uuid}"
+            } else if segment.len() >= 16 && segment.chars().all(|c| c.is_ascii_hexdigit()) {
+                "{hash}"
+            } else if segment.len() > 40 {
+                "{token}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_uuid(segment: &str) -> bool {
+    segment.len() == 36
+        && segment.chars().enumerate().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Maps a user-agent string to a coarse family (scanner, client library, browser)
+pub fn user_agent_family(user_agent: Option<&str>) -> &'static str {
+    let Some(user_agent) = user_agent.filter(|ua| !ua.trim().is_empty()) else {
+        return "none";
+    };
+    let lowered = user_agent.to_ascii_lowercase();
+
+    USER_AGENT_FAMILIES
+        .iter()
+        .find(|(marker, _)| lowered.contains(marker))
+        .map(|(_, family)| *family)
+        .unwrap_or("other")
+}
+
+/// Tokenizes values into lowercase words and single punctuation marks (digit runs
+/// collapse to `0`) and hashes every pair of adjacent tokens.
+fn shingles(values: &[&str]) -> HashSet<u64> {
+    let mut tokens: Vec<String> = Vec::new();
+
+    for value in values {
+        let mut word = String::new();
+        for c in value.chars() {
+            if c.is_alphanumeric() {
+                word.extend(c.to_lowercase());
+            } else {
+                flush_word(&mut word, &mut tokens);
+                if !c.is_whitespace() {
+                    tokens.push(c.to_string());
+                }
+            }
+        }
+        flush_word(&mut word, &mut tokens);
+    }
+
+    if tokens.len() < 2 {
+        return tokens.iter().map(|t| hash_of([t])).collect();
+    }
+    tokens.windows(2).map(hash_of).collect()
+}
+
+fn flush_word(word: &mut String, tokens: &mut Vec<String>) {
+    if word.is_empty() {
+        return;
+    }
+    if word.chars().all(|c| c.is_ascii_digit()) {
+        tokens.push("0".to_string());
+        word.clear();
+    } else {
+        tokens.push(std::mem::take(word));
+    }
+}
+
+fn hash_of<T: Hash>(value: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn jaccard(a: &HashSet<u64>, b: &HashSet<u64>) -> f32 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let intersection = a.intersection(b).count();
+    let union = a.len() + b.len() - intersection;
+    intersection as f32 / union as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::request::{RequestContext, RequestPayload};
+    use std::collections::HashMap;
+
+    fn flagged(id: i64, path: &str, query: &[(&str, &str)], user_agent: &str) -> LogEntry {
+        let mut headers = HashMap::new();
+        headers.insert("user-agent".to_string(), user_agent.to_string());
+        let query_params = query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            path.to_string(),
+            headers,
+            None,
+            query_params,
+            None,
+        );
+
+        LogEntry {
+            id,
+            timestamp: id,
+            method: "GET".to_string(),
+            path: path.to_string(),
+            payload_hash: format!("{}-{}", payload.normalized_hash, id),
+            decision: "flag".to_string(),
+            confidence: 0.6,
+            reason: Some("Suspicious".to_string()),
+            ip_addr: None,
+            user_agent: Some(user_agent.to_string()),
+            request_context: Some(
+                serde_json::to_string(&RequestContext::from_payload(&payload)).unwrap(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_path_template() {
+        assert_eq!(path_template("/users/42/orders"), "/users/{id}/orders");
+        assert_eq!(
+            path_template("/items/550e8400-e29b-41d4-a716-446655440000"),
+            "/items/{uuid}"
+        );
+        assert_eq!(
+            path_template("/files/d41d8cd98f00b204e9800998ecf8427e"),
+            "/files/{hash}"
+        );
+        assert_eq!(path_template("/api/search"), "/api/search");
+        assert_eq!(path_template("/"), "/");
+    }
+
+    #[test]
+    fn test_user_agent_family() {
+        assert_eq!(user_agent_family(Some("sqlmap/1.7.2#stable")), "sqlmap");
+        assert_eq!(
+            user_agent_family(Some("Mozilla/5.0 (X11; Linux x86_64)")),
+            "browser"
+        );
+        assert_eq!(user_agent_family(Some("python-requests/2.31")), "python");
+        assert_eq!(user_agent_family(Some("custom-bot")), "other");
+        assert_eq!(user_agent_family(None), "none");
+    }
+
+    #[test]
+    fn test_similar_payloads_share_a_cluster() {
+        let logs = vec![
+            flagged(1, "/users/1", &[("q", "' OR 1=1--")], "sqlmap/1.7"),
+            flagged(2, "/users/2", &[("q", "' OR 2=2--")], "sqlmap/1.7"),
+            flagged(3, "/users/3", &[("q", "' or 7=7--")], "sqlmap/1.8"),
+        ];
+
+        let clusters = cluster_flagged(&logs);
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].size, 3);
+        assert_eq!(clusters[0].path_template, "/users/{id}");
+        assert_eq!(clusters[0].param_names, vec!["q".to_string()]);
+        assert_eq!(clusters[0].user_agent_family, "sqlmap");
+        // Newest first
+        assert_eq!(clusters[0].samples[0].id, 3);
+    }
+
+    #[test]
+    fn test_different_structures_split() {
+        let logs = vec![
+            flagged(
+                1,
+                "/search",
+                &[("q", "<script>alert(1)</script>")],
+                "curl/8.0",
+            ),
+            flagged(
+                2,
+                "/search",
+                &[("q", "<script>alert(2)</script>")],
+                "curl/8.0",
+            ),
+            flagged(3, "/files", &[("name", "../../etc/passwd")], "curl/8.0"),
+            flagged(
+                4,
+                "/search",
+                &[("q", "' UNION SELECT password FROM users")],
+                "curl/8.0",
+            ),
+        ];
+
+        let clusters = cluster_flagged(&logs);
+
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0].size, 2);
+        assert!(clusters[0].samples[0].path.starts_with("/search"));
+        assert_eq!(clusters.iter().map(|c| c.size).sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_representatives_are_capped_and_distinct() {
+        let mut logs: Vec<LogEntry> = (0..30)
+            .map(|i| flagged(i, "/login", &[("user", "admin'--")], "python-requests/2.31"))
+            .collect();
+        // Same request hash logged twice must only appear once
+        logs.push(logs[29].clone());
+
+        let clusters = cluster_flagged(&logs);
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].size, 31);
+        assert_eq!(clusters[0].samples.len(), MAX_REPRESENTATIVES);
+        let hashes: HashSet<_> = clusters[0]
+            .samples
+            .iter()
+            .map(|l| &l.payload_hash)
+            .collect();
+        assert_eq!(hashes.len(), MAX_REPRESENTATIVES);
+    }
+
+    #[test]
+    fn test_clustering_scales_to_large_batches() {
+        let logs: Vec<LogEntry> = (0..5000)
+            .map(|i| {
+                let value = format!("payload-{}-{}", i % 97, "x".repeat((i % 13) as usize));
+                flagged(
+                    i,
+                    &format!("/api/v{}/items/{}", i % 3, i),
+                    &[("v", &value)],
+                    "curl/8.0",
+                )
+            })
+            .collect();
+
+        let start = std::time::Instant::now();
+        let clusters = cluster_flagged(&logs);
+
+        assert_eq!(clusters.iter().map(|c| c.size).sum::<usize>(), 5000);
+        assert!(clusters.len() <= 3 * MAX_LEADERS_PER_BUCKET);
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+    }
+}
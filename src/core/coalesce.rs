@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Single-flight: concurrent calls for the same key share the result of the
+/// first one (the leader) instead of each doing the work. When the leader is
+/// cancelled before finishing, one of its followers takes over and the
+/// others follow it.
+pub struct Coalescer<T> {
+    inflight: Mutex<HashMap<String, watch::Receiver<Option<T>>>>,
+}
+
+impl<T: Clone> Default for Coalescer<T> {
+    fn default() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Removes the leader's entry once it is done or cancelled
+struct Flight<'a, T> {
+    coalescer: &'a Coalescer<T>,
+    key: &'a str,
+}
+
+impl<T> Drop for Flight<'_, T> {
+    fn drop(&mut self) {
+        self.coalescer.inflight.lock().unwrap().remove(self.key);
+    }
+}
+
+impl<T: Clone> Coalescer<T> {
+    /// Result of `work` for `key`, or of the call already in flight for it;
+    /// `true` when the result was shared
+    pub async fn run<F>(&self, key: &str, work: F) -> (T, bool)
+    where
+        F: Future<Output = T>,
+    {
+        loop {
+            let follower = {
+                let mut inflight = self.inflight.lock().unwrap();
+                match inflight.get(key) {
+                    Some(rx) => Err(rx.clone()),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        inflight.insert(key.to_string(), rx);
+                        Ok(tx)
+                    }
+                }
+            };
+
+            match follower {
+                Ok(tx) => {
+                    // Dropped before `tx`: followers woken by a cancellation
+                    // find the key free
+                    let _flight = Flight {
+                        coalescer: self,
+                        key,
+                    };
+                    let value = work.await;
+                    tx.send_replace(Some(value.clone()));
+                    return (value, false);
+                }
+                Err(mut rx) => {
+                    let shared = rx
+                        .wait_for(Option::is_some)
+                        .await
+                        .ok()
+                        .and_then(|value| value.clone());
+                    if let Some(value) = shared {
+                        return (value, true);
+                    }
+                    // Leader cancelled: the first follower back leads
+                }
+            }
+        }
+    }
+
+    /// Keys with a call in flight
+    #[allow(dead_code)] // Used in tests
+    pub fn in_flight(&self) -> usize {
+        self.inflight.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_result() {
+        let coalescer = Arc::new(Coalescer::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..50 {
+            let coalescer = Arc::clone(&coalescer);
+            let calls = Arc::clone(&calls);
+            tasks.spawn(async move {
+                coalescer
+                    .run("key", async {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        42
+                    })
+                    .await
+            });
+        }
+
+        let mut shared = 0;
+        while let Some(result) = tasks.join_next().await {
+            let (value, was_shared) = result.unwrap();
+            assert_eq!(value, 42);
+            shared += was_shared as usize;
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(shared, 49);
+        assert_eq!(coalescer.in_flight(), 0);
+
+        // Done flights aren't reused
+        assert_eq!(coalescer.run("key", async { 7 }).await, (7, false));
+    }
+
+    #[tokio::test]
+    async fn test_follower_takes_over_cancelled_leader() {
+        let coalescer = Arc::new(Coalescer::default());
+
+        let leader = {
+            let coalescer = Arc::clone(&coalescer);
+            tokio::spawn(async move {
+                coalescer
+                    .run("key", async {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        1
+                    })
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let followers: Vec<_> = (0..5)
+            .map(|_| {
+                let coalescer = Arc::clone(&coalescer);
+                let calls = Arc::clone(&calls);
+                tokio::spawn(async move {
+                    coalescer
+                        .run("key", async {
+                            calls.fetch_add(1, Ordering::Relaxed);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            2
+                        })
+                        .await
+                })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        leader.abort();
+
+        // One follower redoes the work, the others share its result
+        let mut shared = 0;
+        for follower in followers {
+            let (value, was_shared) = follower.await.unwrap();
+            assert_eq!(value, 2);
+            shared += was_shared as usize;
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(shared, 4);
+        assert_eq!(coalescer.in_flight(), 0);
+    }
+}
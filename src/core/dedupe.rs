@@ -0,0 +1,372 @@
+use crate::core::rulebook::{MatchInput, Rule, RuleProvenance, RuleTarget, Rulebook};
+use crate::core::transform::Transform;
+use crate::core::validator::normalized_pattern;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+/// Minimum number of backtest samples two rules must both match before their
+/// identical match sets are taken as evidence that they overlap
+pub const MIN_SHARED_MATCHES: usize = 3;
+
+/// Recent requests rules are backtested against, with the fingerprint of each
+/// rule matcher computed once: a pattern is compiled and run over the samples
+/// the first time it is seen, then reused by every later comparison
+#[derive(Default)]
+pub struct Backtest {
+    samples: Vec<MatchInput>,
+    fingerprints: HashMap<MatcherKey, Arc<RuleFingerprint>>,
+}
+
+/// What a rule's fingerprint depends on (rules merged or reweighted keep theirs)
+#[derive(PartialEq, Eq, Hash)]
+struct MatcherKey {
+    pattern: String,
+    targets: Vec<RuleTarget>,
+    transforms: Vec<Transform>,
+}
+
+impl Backtest {
+    pub fn new(samples: Vec<MatchInput>) -> Self {
+        Self {
+            samples,
+            fingerprints: HashMap::new(),
+        }
+    }
+
+    fn fingerprint(&mut self, rule: &Rule) -> Arc<RuleFingerprint> {
+        let key = MatcherKey {
+            pattern: rule.pattern.clone(),
+            targets: rule.targets.clone(),
+            transforms: rule.transforms.clone(),
+        };
+        let samples = &self.samples;
+        Arc::clone(
+            self.fingerprints
+                .entry(key)
+                .or_insert_with(|| Arc::new(RuleFingerprint::new(rule, samples))),
+        )
+    }
+}
+
+/// Precomputed facts used to compare two rules
+struct RuleFingerprint {
+    normalized: Option<String>,
+    targets: BTreeSet<String>,
+    /// Indexes of the backtest samples the rule matches
+    matches: Vec<usize>,
+}
+
+impl RuleFingerprint {
+    fn new(rule: &Rule, samples: &[MatchInput]) -> Self {
+        let matches = match rule.compile_pattern() {
+            Ok(regex) => samples
+                .iter()
+                .enumerate()
+                .filter(|(_, sample)| regex.is_match(rule.match_text(sample).as_str()))
+                .map(|(idx, _)| idx)
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        Self {
+            normalized: normalized_pattern(&rule.pattern),
+            targets: rule
+                .targets
+                .iter()
+                .map(RuleTarget::as_str)
+                .map(String::from)
+                .collect(),
+            matches,
+        }
+    }
+
+    /// Same normalized pattern, or the same threat type matching exactly the
+    /// same (large enough) set of backtest samples
+    fn duplicates(&self, rule: &Rule, other: &Self, other_rule: &Rule) -> bool {
+        if self.targets != other.targets
+            || rule.transforms != other_rule.transforms
+            || !same_schedule(rule, other_rule)
+        {
+            return false;
+        }
+        if self.normalized.is_some() && self.normalized == other.normalized {
+            return true;
+        }
+
+        rule.threat_type == other_rule.threat_type
+            && self.matches.len() >= MIN_SHARED_MATCHES
+            && self.matches == other.matches
+    }
+}
+
+/// Rules with different activation windows are never merged (e.g. a temporary
+/// virtual patch and a permanent rule with the same pattern)
+fn same_schedule(rule: &Rule, other: &Rule) -> bool {
+    rule.not_before == other.not_before
+        && rule.expires_at == other.expires_at
+        && rule.active_hours == other.active_hours
+}
+
+/// Returns the index of the rule in `rulebook` that `rule` duplicates, if any
+pub fn find_duplicate(rulebook: &Rulebook, rule: &Rule, backtest: &mut Backtest) -> Option<usize> {
+    let fingerprint = backtest.fingerprint(rule);
+
+    rulebook.rules.iter().position(|existing| {
+        let existing_fingerprint = backtest.fingerprint(existing);
+        fingerprint.duplicates(rule, &existing_fingerprint, existing)
+    })
+}
+
+/// Merges `other` into `target`: the higher confidence wins (together with its
+/// action), descriptions are combined and `other` is recorded as provenance.
+pub fn merge_into(target: &mut Rule, other: Rule) {
+    if other.confidence > target.confidence {
+        target.confidence = other.confidence;
+        target.action = other.action;
+    }
+
+    if let Some(description) = other.description {
+        let mut parts: Vec<&str> = target
+            .description
+            .as_deref()
+            .map(|d| d.split("; ").collect())
+            .unwrap_or_default();
+        for part in description.split("; ") {
+            if !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+        target.description = Some(parts.join("; "));
+    }
+
+    target.merged_from.push(RuleProvenance {
+        id: other.id,
+        created_by: other.created_by,
+        created_at: other.created_at,
+    });
+    target.merged_from.extend(other.merged_from);
+}
+
+/// Merges every duplicate rule into the earliest rule it duplicates.
+///
+/// Returns the number of rules merged away; the rulebook version is bumped
+/// once if any were.
+pub fn merge_duplicates(rulebook: &mut Rulebook, backtest: &mut Backtest) -> usize {
+    let fingerprints: Vec<Arc<RuleFingerprint>> = rulebook
+        .rules
+        .iter()
+        .map(|rule| backtest.fingerprint(rule))
+        .collect();
+
+    // Index of the rule each rule is merged into (itself when it survives)
+    let mut survivor_of: Vec<usize> = (0..rulebook.rules.len()).collect();
+    for idx in 0..rulebook.rules.len() {
+        if let Some(survivor) = (0..idx).find(|&earlier| {
+            survivor_of[earlier] == earlier
+                && fingerprints[idx].duplicates(
+                    &rulebook.rules[idx],
+                    &fingerprints[earlier],
+                    &rulebook.rules[earlier],
+                )
+        }) {
+            survivor_of[idx] = survivor;
+        }
+    }
+
+    let merged = survivor_of
+        .iter()
+        .enumerate()
+        .filter(|(idx, survivor)| idx != *survivor)
+        .count();
+    if merged == 0 {
+        return 0;
+    }
+
+    let rules = std::mem::take(&mut rulebook.rules);
+    let mut kept: Vec<Option<Rule>> = Vec::with_capacity(rules.len());
+    for (idx, rule) in rules.into_iter().enumerate() {
+        let survivor = survivor_of[idx];
+        if survivor == idx {
+            kept.push(Some(rule));
+        } else {
+            if let Some(target) = kept[survivor].as_mut() {
+                tracing::info!("Merging duplicate rule {} into {}", rule.id, target.id);
+                merge_into(target, rule);
+            }
+            kept.push(None);
+        }
+    }
+
+    rulebook.rules = kept.into_iter().flatten().collect();
+    rulebook.touch();
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::decision::RuleAction;
+
+    fn rule(pattern: &str, confidence: f32, description: &str) -> Rule {
+        Rule::new(
+            pattern.to_string(),
+            "sqli".to_string(),
+            confidence,
+            RuleAction::Flag,
+            "llm".to_string(),
+        )
+        .with_description(description.to_string())
+    }
+
+    fn samples(queries: &[&str]) -> Vec<MatchInput> {
+        queries
+            .iter()
+            .map(|q| MatchInput {
+                path: "/users".to_string(),
+                query: q.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_normalized_duplicates() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("or 1=1", 0.6, "SQL injection tautology"));
+        rulebook.add_rule(rule("(?:OR 1=1)", 0.9, "Tautology in query"));
+        rulebook.add_rule(rule("<script", 0.8, "XSS"));
+        let first_id = rulebook.rules[0].id.clone();
+        let second_id = rulebook.rules[1].id.clone();
+        let version = rulebook.version;
+
+        let merged = merge_duplicates(&mut rulebook, &mut Backtest::default());
+
+        assert_eq!(merged, 1);
+        assert_eq!(rulebook.rules.len(), 2);
+        assert_eq!(rulebook.version, version + 1);
+
+        let survivor = &rulebook.rules[0];
+        assert_eq!(survivor.id, first_id);
+        assert_eq!(survivor.confidence, 0.9);
+        assert_eq!(
+            survivor.description.as_deref(),
+            Some("SQL injection tautology; Tautology in query")
+        );
+        assert_eq!(survivor.merged_from.len(), 1);
+        assert_eq!(survivor.merged_from[0].id, second_id);
+        assert_eq!(survivor.merged_from[0].created_by, "llm");
+    }
+
+    #[test]
+    fn test_merge_same_backtest_matches() {
+        let samples = samples(&[
+            "id=1' or '1'='1",
+            "id=2' or '2'='2",
+            "id=3' or 'a'='a",
+            "id=4",
+            "name=bob",
+        ]);
+
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule(r"'\s*or\s*'", 0.7, "Quote tautology"));
+        rulebook.add_rule(rule(r"'[^']*'\s*=\s*'", 0.8, "String comparison tautology"));
+
+        assert_eq!(
+            merge_duplicates(&mut rulebook, &mut Backtest::new(samples)),
+            1
+        );
+        assert_eq!(rulebook.rules.len(), 1);
+        assert_eq!(rulebook.rules[0].confidence, 0.8);
+    }
+
+    #[test]
+    fn test_no_merge_when_matches_differ_or_too_few() {
+        let samples = samples(&["id=1' or '1'='1", "id=2' or '2'='2", "id=3 union select"]);
+
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule(r"'\s*or\s*'", 0.7, "Quote tautology"));
+        rulebook.add_rule(rule(r"'[^']*'\s*=\s*'", 0.8, "String comparison"));
+        rulebook.add_rule(rule(r"union\s+select", 0.9, "Union"));
+        let version = rulebook.version;
+
+        // The first two rules match the same two samples, below MIN_SHARED_MATCHES
+        assert_eq!(
+            merge_duplicates(&mut rulebook, &mut Backtest::new(samples)),
+            0
+        );
+        assert_eq!(rulebook.rules.len(), 3);
+        assert_eq!(rulebook.version, version);
+    }
+
+    #[test]
+    fn test_no_merge_across_targets_schedules_or_threat_types() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("select", 0.7, "a"));
+        rulebook.add_rule(rule("select", 0.7, "b").with_targets(vec![RuleTarget::Body]));
+
+        assert_eq!(merge_duplicates(&mut rulebook, &mut Backtest::default()), 0);
+
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("select", 0.7, "a"));
+        let mut patch = rule("select", 0.9, "b");
+        patch.expires_at = Some(chrono::Utc::now() + chrono::Duration::days(1));
+        rulebook.add_rule(patch);
+
+        assert_eq!(merge_duplicates(&mut rulebook, &mut Backtest::default()), 0);
+
+        let samples = samples(&["x=<a>", "x=<b>", "x=<c>"]);
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("<", 0.7, "a"));
+        let mut xss = rule(r"<\w>", 0.7, "b");
+        xss.threat_type = "xss".to_string();
+        rulebook.add_rule(xss);
+
+        assert_eq!(
+            merge_duplicates(&mut rulebook, &mut Backtest::new(samples)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_find_duplicate() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("<script", 0.8, "XSS"));
+        rulebook.add_rule(rule("union.*select", 0.8, "Union"));
+
+        let mut backtest = Backtest::new(samples(&["id=1 union select", "q=<script>"]));
+        assert_eq!(
+            find_duplicate(
+                &rulebook,
+                &rule("UNION.*SELECT", 0.5, "Union"),
+                &mut backtest
+            ),
+            Some(1)
+        );
+        assert_eq!(
+            find_duplicate(&rulebook, &rule("sleep\\(", 0.5, "Timing"), &mut backtest),
+            None
+        );
+
+        // Existing rules were fingerprinted once and reused by the second lookup
+        assert_eq!(backtest.fingerprints.len(), 4);
+    }
+
+    #[test]
+    fn test_merge_into_keeps_provenance_chain() {
+        let mut target = rule("a", 0.9, "first");
+        target.action = RuleAction::Block;
+        let mut middle = rule("a", 0.5, "first");
+        let inner = rule("a", 0.4, "second");
+        let inner_id = inner.id.clone();
+        merge_into(&mut middle, inner);
+
+        merge_into(&mut target, middle);
+
+        assert_eq!(target.confidence, 0.9);
+        assert_eq!(target.action, RuleAction::Block);
+        assert_eq!(target.description.as_deref(), Some("first; second"));
+        assert_eq!(target.merged_from.len(), 2);
+        assert_eq!(target.merged_from[1].id, inner_id);
+    }
+}
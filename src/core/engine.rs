@@ -0,0 +1,210 @@
+use crate::core::rulebook::{MatchInput, Rule, RuleTarget, Rulebook};
+use crate::models::request::RequestPayload;
+use chrono::{DateTime, Utc};
+use regex_automata::meta::Regex;
+
+/// Local rule engine: the rulebook's patterns compiled once and matched
+/// against each request before the LLM is consulted.
+pub struct RuleEngine {
+    rules: Vec<(Rule, Regex)>,
+    /// Whether a rule targets named inputs (`args`)
+    uses_args: bool,
+}
+
+impl RuleEngine {
+    /// Compiles every rule of the rulebook. Rules whose pattern does not compile
+    /// are skipped (validation normally rejects them before they get here).
+    pub fn new(rulebook: &Rulebook) -> Self {
+        let rules = rulebook
+            .rules
+            .iter()
+            .filter_map(|rule| match rule.compile_pattern() {
+                Ok(regex) => Some((rule.clone(), regex)),
+                Err(e) => {
+                    tracing::warn!(rule_id = %rule.id, error = %e, "Skipping rule in local engine");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let uses_args = rules
+            .iter()
+            .any(|(rule, _)| rule.targets.contains(&RuleTarget::Args));
+
+        Self { rules, uses_args }
+    }
+
+    /// Text of `payload` the rules are matched against; named inputs are
+    /// only extracted when a rule targets them
+    pub fn input(&self, payload: &RequestPayload) -> MatchInput {
+        let input = MatchInput::from_payload(payload);
+        if self.uses_args {
+            input.with_args(payload)
+        } else {
+            input
+        }
+    }
+
+    /// Active rules matching the request, in rulebook order
+    pub fn matching_rules<'a>(
+        &'a self,
+        input: &'a MatchInput,
+        now: DateTime<Utc>,
+    ) -> impl Iterator<Item = &'a Rule> + 'a {
+        self.rules
+            .iter()
+            .filter(move |(rule, regex)| Self::is_match(rule, regex, input, now))
+            .map(|(rule, _)| rule)
+    }
+
+    /// First active rule matching the request, if any
+    #[allow(dead_code)] // Used in tests
+    pub fn first_match(&self, input: &MatchInput, now: DateTime<Utc>) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|(rule, regex)| Self::is_match(rule, regex, input, now))
+            .map(|(rule, _)| rule)
+    }
+
+    fn is_match(rule: &Rule, regex: &Regex, input: &MatchInput, now: DateTime<Utc>) -> bool {
+        rule.is_active_at(now) && regex.is_match(rule.match_text(input).as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transform::Transform;
+    use crate::models::decision::RuleAction;
+
+    fn rule(pattern: &str, action: RuleAction) -> Rule {
+        Rule::new(
+            pattern.to_string(),
+            "sqli".to_string(),
+            0.9,
+            action,
+            "test".to_string(),
+        )
+    }
+
+    fn input(path: &str, query: &str) -> MatchInput {
+        MatchInput {
+            path: path.to_string(),
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_first_match_in_rulebook_order() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("union", RuleAction::Flag));
+        rulebook.add_rule(rule("select", RuleAction::Block));
+        let engine = RuleEngine::new(&rulebook);
+
+        let request = input("/users", "id=1 UNION SELECT 1");
+        let matched = engine.first_match(&request, Utc::now()).unwrap();
+        assert_eq!(matched.pattern, "union");
+        assert_eq!(engine.matching_rules(&request, Utc::now()).count(), 2);
+
+        assert!(engine
+            .first_match(&input("/users", "id=1"), Utc::now())
+            .is_none());
+    }
+
+    #[test]
+    fn test_targets_restrict_matching() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("admin", RuleAction::Block).with_targets(vec![RuleTarget::Query]));
+        let engine = RuleEngine::new(&rulebook);
+
+        assert!(engine
+            .first_match(&input("/admin", ""), Utc::now())
+            .is_none());
+        assert!(engine
+            .first_match(&input("/", "next=/admin"), Utc::now())
+            .is_some());
+    }
+
+    #[test]
+    fn test_args_extracted_only_when_targeted() {
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/users".to_string(),
+            std::collections::HashMap::new(),
+            None,
+            std::collections::HashMap::new(),
+            None,
+        )
+        .with_raw_query(Some("role=admin".to_string()));
+
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("admin", RuleAction::Block));
+        assert_eq!(RuleEngine::new(&rulebook).input(&payload).args, "");
+
+        rulebook.add_rule(rule("root", RuleAction::Block).with_targets(vec![RuleTarget::Args]));
+        assert_eq!(
+            RuleEngine::new(&rulebook).input(&payload).args,
+            "role=admin"
+        );
+    }
+
+    #[test]
+    fn test_transforms_apply_before_matching() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(
+            rule("union select", RuleAction::Block).with_transforms(vec![
+                Transform::UrlDecode,
+                Transform::ReplaceComments,
+                Transform::CompressWhitespace,
+            ]),
+        );
+        let engine = RuleEngine::new(&rulebook);
+
+        let evasive = input("/users", "id=1%20UNION/**/%0aSELECT");
+        assert!(engine.first_match(&evasive, Utc::now()).is_some());
+
+        let mut plain = Rulebook::new();
+        plain.add_rule(rule("union select", RuleAction::Block));
+        assert!(RuleEngine::new(&plain)
+            .first_match(&evasive, Utc::now())
+            .is_none());
+    }
+
+    #[test]
+    fn test_inactive_rules_are_ignored() {
+        let now = Utc::now();
+        let mut expired = rule("select", RuleAction::Block);
+        expired.expires_at = Some(now - chrono::Duration::hours(1));
+        let mut pending = rule("select", RuleAction::Block);
+        pending.not_before = Some(now + chrono::Duration::hours(1));
+
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(expired);
+        rulebook.add_rule(pending);
+        let engine = RuleEngine::new(&rulebook);
+
+        let request = input("/users", "q=select");
+        assert!(engine.first_match(&request, now).is_none());
+        assert!(engine
+            .first_match(&request, now + chrono::Duration::hours(2))
+            .is_some());
+    }
+
+    #[test]
+    fn test_invalid_rules_are_skipped() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("(unclosed", RuleAction::Block));
+        rulebook.add_rule(rule("select", RuleAction::Block));
+
+        let engine = RuleEngine::new(&rulebook);
+        assert_eq!(engine.len(), 1);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::config::{AgreementPolicy, EscalationConfig};
+use crate::core::fewshot::FewShotExample;
+use crate::core::rulebook::Rulebook;
+use crate::llm::client::LlmProvider;
+use crate::models::decision::JudgeDecision;
+use crate::models::request::RequestPayload;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Call count and latency of one LLM provider
+#[derive(Debug, Default, Clone)]
+pub struct ProviderMetrics {
+    pub calls: Arc<AtomicU64>,
+    pub errors: Arc<AtomicU64>,
+    pub timeouts: Arc<AtomicU64>,
+    pub total_latency_ms: Arc<AtomicU64>,
+    pub max_latency_ms: Arc<AtomicU64>,
+}
+
+impl ProviderMetrics {
+    /// Records a call that answered (successfully or not) after `latency`
+    pub fn record(&self, latency: Duration, ok: bool) {
+        let latency_ms = latency.as_millis() as u64;
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_latency_ms
+            .fetch_add(latency_ms, Ordering::Relaxed);
+        self.max_latency_ms.fetch_max(latency_ms, Ordering::Relaxed);
+    }
+
+    /// Records a call abandoned after its latency budget
+    pub fn record_timeout(&self, budget: Duration) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+        self.record(budget, true);
+    }
+
+    pub fn avg_latency_ms(&self) -> f64 {
+        match self.calls.load(Ordering::Relaxed) {
+            0 => 0.0,
+            calls => self.total_latency_ms.load(Ordering::Relaxed) as f64 / calls as f64,
+        }
+    }
+}
+
+/// A second-opinion judge
+struct SecondOpinion {
+    name: String,
+    llm: Arc<dyn LlmProvider>,
+    metrics: ProviderMetrics,
+}
+
+/// Asks second-opinion judges to confirm mid-confidence LLM blocks before a
+/// 403 is issued. The judges run concurrently within their own latency
+/// budget; a block they don't confirm (per the agreement policy) is
+/// downgraded to a flag. Judges that fail or time out don't vote, and a block
+/// nobody voted on stands.
+pub struct Escalation {
+    config: EscalationConfig,
+    judges: Vec<SecondOpinion>,
+}
+
+impl Escalation {
+    pub fn new(config: EscalationConfig) -> Self {
+        Self {
+            config,
+            judges: Vec::new(),
+        }
+    }
+
+    pub fn with_judge(mut self, name: &str, llm: Arc<dyn LlmProvider>) -> Self {
+        self.judges.push(SecondOpinion {
+            name: name.to_string(),
+            llm,
+            metrics: ProviderMetrics::default(),
+        });
+        self
+    }
+
+    /// Latency metrics of each second-opinion judge, by name
+    pub fn judge_metrics(&self) -> impl Iterator<Item = (&str, &ProviderMetrics)> {
+        self.judges.iter().map(|j| (j.name.as_str(), &j.metrics))
+    }
+
+    /// Whether `verdict` is a block in the escalated confidence range
+    pub fn should_escalate(&self, verdict: &JudgeDecision) -> bool {
+        matches!(verdict, JudgeDecision::Block { .. })
+            && !self.judges.is_empty()
+            && verdict.confidence() >= self.config.min_confidence
+            && verdict.confidence() < self.config.max_confidence
+    }
+
+    /// The block `verdict` if the second opinions confirm it, else a flag.
+    /// `rules` is the snapshot the primary judge was given, shared as is.
+    pub async fn review(
+        &self,
+        payload: &RequestPayload,
+        rules: &Arc<Rulebook>,
+        examples: &[FewShotExample],
+        verdict: JudgeDecision,
+    ) -> Review {
+        let budget = self.config.timeout();
+        let deadline = tokio::time::Instant::now() + budget;
+        let payload = Arc::new(payload.clone());
+        let examples: Arc<[FewShotExample]> = examples.into();
+
+        let mut calls = JoinSet::new();
+        for (idx, judge) in self.judges.iter().enumerate() {
+            let llm = Arc::clone(&judge.llm);
+            let (payload, rules, examples) = (
+                Arc::clone(&payload),
+                Arc::clone(rules),
+                Arc::clone(&examples),
+            );
+            calls.spawn(async move {
+                let start = Instant::now();
+                let result = llm.judge_request(&payload, &rules, &examples).await;
+                (idx, start.elapsed(), result)
+            });
+        }
+
+        let mut answered = vec![false; self.judges.len()];
+        let (mut votes, mut confirmations) = (0, 0);
+        while let Ok(Some(joined)) = tokio::time::timeout_at(deadline, calls.join_next()).await {
+            let Ok((idx, latency, result)) = joined else {
+                continue;
+            };
+            let judge = &self.judges[idx];
+            answered[idx] = true;
+            judge.metrics.record(latency, result.is_ok());
+
+            match result {
+                Ok(opinion) => {
+                    votes += 1;
+                    if matches!(opinion, JudgeDecision::Block { .. }) {
+                        confirmations += 1;
+                    }
+                    tracing::debug!(
+                        judge = %judge.name,
+                        decision = ?opinion.decision_type(),
+                        confidence = opinion.confidence(),
+                        latency_ms = latency.as_millis() as u64,
+                        "Second opinion"
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(judge = %judge.name, error = %e, "Second opinion failed");
+                }
+            }
+        }
+        calls.abort_all();
+        for (judge, _) in self.judges.iter().zip(&answered).filter(|(_, a)| !**a) {
+            judge.metrics.record_timeout(budget);
+            tracing::warn!(judge = %judge.name, "Second opinion timed out after {:?}", budget);
+        }
+
+        let confirmed = votes == 0 || agrees(self.config.policy, confirmations, votes);
+        let decision = match verdict {
+            JudgeDecision::Block {
+                confidence, reason, ..
+            } if !confirmed => JudgeDecision::Flag {
+                confidence,
+                reason: format!(
+                    "Block not confirmed by second opinion ({}/{} agreed): {}",
+                    confirmations, votes, reason
+                ),
+                suggested_rule: None,
+            },
+            verdict => verdict,
+        };
+
+        Review {
+            decision,
+            votes,
+            confirmations,
+        }
+    }
+}
+
+/// Outcome of an escalation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Review {
+    pub decision: JudgeDecision,
+    /// Judges that answered
+    pub votes: usize,
+    /// Judges that answered block
+    pub confirmations: usize,
+}
+
+/// Whether `confirmations` out of `votes` confirm a block under `policy`
+fn agrees(policy: AgreementPolicy, confirmations: usize, votes: usize) -> bool {
+    match policy {
+        AgreementPolicy::Any => confirmations >= 1,
+        AgreementPolicy::All => confirmations == votes,
+        AgreementPolicy::Majority => confirmations * 2 > votes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::mock::MockLlmProvider;
+    use crate::models::decision::ThreatLevel;
+    use std::collections::HashMap;
+
+    fn config(policy: AgreementPolicy) -> EscalationConfig {
+        EscalationConfig {
+            enabled: true,
+            policy,
+            timeout_ms: 200,
+            ..EscalationConfig::default()
+        }
+    }
+
+    fn block(confidence: f32) -> JudgeDecision {
+        JudgeDecision::Block {
+            confidence,
+            reason: "SQLi".to_string(),
+            threat_level: ThreatLevel::High,
+        }
+    }
+
+    fn payload() -> RequestPayload {
+        RequestPayload::new(
+            "GET".to_string(),
+            "/search".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::from([("q".to_string(), "select plans".to_string())]),
+            None,
+        )
+    }
+
+    fn escalation(policy: AgreementPolicy, judges: Vec<MockLlmProvider>) -> Escalation {
+        judges
+            .into_iter()
+            .enumerate()
+            .fold(Escalation::new(config(policy)), |e, (i, llm)| {
+                e.with_judge(&format!("judge-{}", i), Arc::new(llm))
+            })
+    }
+
+    #[test]
+    fn test_agreement_policies() {
+        assert!(agrees(AgreementPolicy::Any, 1, 3));
+        assert!(!agrees(AgreementPolicy::Any, 0, 3));
+        assert!(agrees(AgreementPolicy::All, 3, 3));
+        assert!(!agrees(AgreementPolicy::All, 2, 3));
+        assert!(agrees(AgreementPolicy::Majority, 2, 3));
+        assert!(!agrees(AgreementPolicy::Majority, 1, 2));
+    }
+
+    #[test]
+    fn test_should_escalate() {
+        let escalation = escalation(AgreementPolicy::Majority, vec![MockLlmProvider::new()]);
+
+        assert!(escalation.should_escalate(&block(0.7)));
+        assert!(!escalation.should_escalate(&block(0.95)));
+        assert!(!escalation.should_escalate(&block(0.3)));
+        assert!(!escalation.should_escalate(&JudgeDecision::Allow { confidence: 0.7 }));
+        assert!(!Escalation::new(config(AgreementPolicy::Any)).should_escalate(&block(0.7)));
+    }
+
+    #[tokio::test]
+    async fn test_review_policies() {
+        let judges = || {
+            vec![
+                MockLlmProvider::new().with_block(),
+                MockLlmProvider::new(),
+                MockLlmProvider::new(),
+            ]
+        };
+        let rules = Arc::new(Rulebook::new());
+
+        let review = escalation(AgreementPolicy::Any, judges())
+            .review(&payload(), &rules, &[], block(0.7))
+            .await;
+        assert_eq!(review.decision, block(0.7));
+        assert_eq!((review.confirmations, review.votes), (1, 3));
+
+        let review = escalation(AgreementPolicy::Majority, judges())
+            .review(&payload(), &rules, &[], block(0.7))
+            .await;
+        match review.decision {
+            JudgeDecision::Flag {
+                confidence, reason, ..
+            } => {
+                assert_eq!(confidence, 0.7);
+                assert!(reason.starts_with("Block not confirmed by second opinion (1/3 agreed)"));
+            }
+            other => panic!("expected flag, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_review_failed_and_slow_judges_do_not_vote() {
+        let escalation = escalation(
+            AgreementPolicy::All,
+            vec![
+                MockLlmProvider::new().with_error(),
+                MockLlmProvider::new().with_delay(Duration::from_secs(5)),
+                MockLlmProvider::new().with_block(),
+            ],
+        );
+
+        let start = Instant::now();
+        let review = escalation
+            .review(&payload(), &Arc::new(Rulebook::new()), &[], block(0.7))
+            .await;
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(review.decision, block(0.7));
+        assert_eq!((review.confirmations, review.votes), (1, 1));
+
+        let metrics: Vec<_> = escalation.judge_metrics().collect();
+        assert_eq!(metrics[0].1.errors.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics[1].1.timeouts.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics[1].1.avg_latency_ms(), 200.0);
+        assert_eq!(metrics[2].1.calls.load(Ordering::Relaxed), 1);
+
+        // Nobody answered: the block stands
+        let escalation = self::escalation(
+            AgreementPolicy::All,
+            vec![MockLlmProvider::new().with_error()],
+        );
+        let review = escalation
+            .review(&payload(), &Arc::new(Rulebook::new()), &[], block(0.7))
+            .await;
+        assert_eq!(review.decision, block(0.7));
+        assert_eq!(review.votes, 0);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::core::bans::BanList;
+use crate::core::fewshot::{ExampleIndex, ExampleSource};
+use crate::core::judge::Judge;
+use crate::models::decision::JudgeDecision;
+use crate::models::label::{EventLabel, LabelKind};
+use crate::models::request::LogEntry;
+use crate::storage::logs::EventStore;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Applies human labels on events: the label is stored (for the Learner),
+/// the corrected verdict is pinned for identical requests and, when few-shot
+/// examples are enabled, recorded as a corrected example. Pinning a request
+/// as harmless lifts the bans it triggered.
+pub struct Feedback {
+    logs: Arc<dyn EventStore>,
+    judge: Arc<Judge>,
+    examples: Option<Arc<ExampleIndex>>,
+    bans: Option<Arc<BanList>>,
+}
+
+impl Feedback {
+    pub fn new(logs: Arc<dyn EventStore>, judge: Arc<Judge>) -> Self {
+        Self {
+            logs,
+            judge,
+            examples: None,
+            bans: None,
+        }
+    }
+
+    pub fn with_examples(mut self, examples: Arc<ExampleIndex>) -> Self {
+        self.examples = Some(examples);
+        self
+    }
+
+    pub fn with_bans(mut self, bans: Arc<BanList>) -> Self {
+        self.bans = Some(bans);
+        self
+    }
+
+    /// Labels `event`, failing if the label contradicts its decision
+    /// (a false positive must have been blocked or flagged, a false negative
+    /// allowed or flagged)
+    pub async fn label(
+        &self,
+        event: &LogEntry,
+        kind: LabelKind,
+        note: Option<&str>,
+    ) -> Result<EventLabel> {
+        kind.check(&event.decision)?;
+
+        let label = self.logs.add_label(event, kind, note).await?;
+        let verdict = kind.pinned_verdict(note);
+        self.judge
+            .pin_verdict(&event.payload_hash, verdict.clone())
+            .await;
+
+        let mut unbanned = Vec::new();
+        if let Some(ref bans) = self.bans {
+            if !verdict.is_block() {
+                unbanned = bans.lift(&event.payload_hash);
+            }
+        }
+
+        if let Some(ref examples) = self.examples {
+            if let Err(e) = examples
+                .record(&event.to_payload(), &verdict, ExampleSource::Corrected)
+                .await
+            {
+                tracing::warn!(error = %e, event_id = event.id, "Failed to record corrected example");
+            }
+        }
+
+        tracing::info!(
+            event_id = event.id,
+            label = kind.as_str(),
+            method = %event.method,
+            path = %event.path,
+            pinned = ?verdict.decision_type(),
+            unbanned = ?unbanned,
+            "Event labeled"
+        );
+        Ok(label)
+    }
+}
+
+/// Verdicts pinned by the latest label of each payload hash
+pub async fn pinned_verdicts(logs: &dyn EventStore) -> Result<Vec<(String, JudgeDecision)>> {
+    Ok(logs
+        .latest_labels()
+        .await?
+        .into_iter()
+        .filter_map(|label| {
+            let verdict = label.kind()?.pinned_verdict(label.note.as_deref());
+            Some((label.payload_hash, verdict))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FailMode, FewShotConfig};
+    use crate::core::rulebook::{Rule, Rulebook};
+    use crate::llm::client::mock::MockLlmProvider;
+    use crate::models::decision::RuleAction;
+    use crate::models::request::RequestPayload;
+    use crate::storage::logs::LogStore;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_label_pins_verdict() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("test.db"))
+                .await
+                .unwrap(),
+        );
+
+        // A rule blocks a legitimate search
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(Rule::new(
+            "select".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "test".to_string(),
+        ));
+        let judge = Arc::new(
+            Judge::new(
+                Arc::new(MockLlmProvider::new()),
+                None,
+                Arc::new(RwLock::new(rulebook)),
+                Duration::from_secs(1),
+                FailMode::Open,
+            )
+            .await,
+        );
+        let examples = Arc::new(ExampleIndex::new(
+            Arc::clone(&logs),
+            FewShotConfig::default(),
+        ));
+        let bans = Arc::new(BanList::new(Duration::from_secs(60), None));
+        let feedback = Feedback::new(Arc::clone(&logs), Arc::clone(&judge))
+            .with_examples(Arc::clone(&examples))
+            .with_bans(Arc::clone(&bans));
+
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/search".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::from([("q".to_string(), "select a plan".to_string())]),
+            Some("203.0.113.7".to_string()),
+        );
+        let decision = judge.evaluate(payload.clone()).await;
+        assert!(decision.is_block());
+        bans.ban(&payload, "sqli");
+        assert!(bans.check(&payload).is_some());
+        let id = logs.log_event(&payload, &decision).await.unwrap();
+        let event = logs.get_event(id).await.unwrap().unwrap();
+
+        assert!(feedback
+            .label(&event, LabelKind::FalseNegative, None)
+            .await
+            .is_err());
+        let label = feedback
+            .label(&event, LabelKind::FalsePositive, Some("plan search"))
+            .await
+            .unwrap();
+        assert_eq!(label.event_id, id);
+        assert_eq!(label.label, "false_positive");
+
+        // The client banned for it is let back in
+        assert_eq!(bans.check(&payload), None);
+
+        // The pinned verdict wins over the rule
+        let decision = judge.evaluate(payload.clone()).await;
+        assert_eq!(decision, JudgeDecision::Allow { confidence: 1.0 });
+
+        // ...also after a restart
+        let pins = pinned_verdicts(logs.as_ref()).await.unwrap();
+        assert_eq!(
+            pins,
+            vec![(
+                payload.normalized_hash.clone(),
+                JudgeDecision::Allow { confidence: 1.0 }
+            )]
+        );
+
+        // ...and similar requests are shown the correction
+        let similar = RequestPayload::new(
+            "GET".to_string(),
+            "/search".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::from([("q".to_string(), "select a plan now".to_string())]),
+            None,
+        );
+        let shown = examples.select(&similar).await.unwrap();
+        assert_eq!(shown.len(), 1);
+        assert!(shown[0].corrected);
+        assert_eq!(shown[0].decision, "allow");
+    }
+}
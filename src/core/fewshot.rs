@@ -0,0 +1,687 @@
+use crate::config::FewShotConfig;
+use crate::core::clustering::path_template;
+use crate::core::transform::{self, Transform};
+use crate::llm::sampler::estimate_tokens;
+use crate::llm::summarizer::truncate_chars;
+use crate::models::canonical;
+use crate::models::decision::JudgeDecision;
+use crate::models::request::RequestPayload;
+use crate::storage::logs::EventStore;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of hash functions in a MinHash signature
+const MINHASH_SIZE: usize = 32;
+
+/// Characters per shingle of the normalized request
+const SHINGLE_CHARS: usize = 3;
+
+/// Characters of the normalized request kept in an example
+const EXAMPLE_REQUEST_CHARS: usize = 160;
+
+/// Characters of the verdict reason kept in an example
+const EXAMPLE_REASON_CHARS: usize = 80;
+
+/// Characters of the body taken into the normalized request
+const NORMALIZED_BODY_CHARS: usize = 1024;
+
+/// Newest examples of a path template considered for similarity
+const CANDIDATES_PER_TEMPLATE: i64 = 256;
+
+/// Similarity bonus of human-corrected examples, so they win close calls
+const CORRECTED_BONUS: f32 = 0.1;
+
+/// Expired unverified examples are pruned every this many recorded examples
+const PRUNE_EVERY_RECORDS: u64 = 1000;
+
+const NORMALIZE_TRANSFORMS: &[Transform] = &[
+    Transform::UrlDecode,
+    Transform::Lowercase,
+    Transform::CompressWhitespace,
+];
+
+/// Where an example's verdict comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExampleSource {
+    /// A confident, fresh LLM verdict nobody has checked
+    Unverified,
+    /// A verdict fixed by a human (false positive or false negative)
+    Corrected,
+}
+
+impl ExampleSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExampleSource::Unverified => "unverified",
+            ExampleSource::Corrected => "corrected",
+        }
+    }
+}
+
+/// A past request with its verdict, as stored in `few_shot_examples`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredExample {
+    pub method: String,
+    pub path_template: String,
+    pub payload_hash: String,
+    /// Normalized request excerpt shown to the LLM
+    pub request: String,
+    /// MinHash signature of the normalized request (little-endian u64s)
+    pub minhash: Vec<u8>,
+    pub decision: String,
+    pub reason: Option<String>,
+    pub source: String,
+    pub created_at: i64,
+}
+
+/// An example as given to the judge prompt
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FewShotExample {
+    pub request: String,
+    pub decision: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Verdict fixed by a human
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub corrected: bool,
+    #[serde(skip)]
+    pub similarity: f32,
+}
+
+struct Candidate {
+    example: StoredExample,
+    signature: Vec<u64>,
+}
+
+struct CachedTemplate {
+    loaded_at: Instant,
+    candidates: Arc<Vec<Candidate>>,
+}
+
+/// Picks similar past requests with their verdict for the judge prompt.
+///
+/// Requests are normalized (decoded, lowercased, digits collapsed) and hashed
+/// into a MinHash signature of their character shingles, stored in SQLite with
+/// the verdict. Candidates share the request's method and path template; they
+/// are loaded once per template and cached for `cache_ttl_seconds`.
+/// Unverified examples are pruned after `max_age_days`.
+pub struct ExampleIndex {
+    logs: Arc<dyn EventStore>,
+    config: FewShotConfig,
+    cache: Mutex<HashMap<(String, String), CachedTemplate>>,
+    records: AtomicU64,
+}
+
+impl ExampleIndex {
+    pub fn new(logs: Arc<dyn EventStore>, config: FewShotConfig) -> Self {
+        Self {
+            logs,
+            config,
+            cache: Mutex::new(HashMap::new()),
+            records: AtomicU64::new(0),
+        }
+    }
+
+    /// Up to `max_examples` examples at least `min_similarity` similar to the
+    /// request, most similar first, within `token_budget`
+    pub async fn select(&self, payload: &RequestPayload) -> Result<Vec<FewShotExample>> {
+        let candidates = self
+            .candidates(&payload.method, &path_template(&payload.path))
+            .await?;
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let signature = minhash(&normalize(payload));
+        let mut scored: Vec<(f32, &Candidate)> = candidates
+            .iter()
+            .filter(|c| c.example.payload_hash != payload.normalized_hash)
+            .filter(|c| self.usable(&c.example))
+            .map(|c| (similarity(&signature, &c.signature), c))
+            .filter(|(similarity, _)| *similarity >= self.config.min_similarity)
+            .collect();
+        scored.sort_by(|a, b| rank(b).total_cmp(&rank(a)));
+
+        let mut examples = Vec::new();
+        let mut used_tokens = 0;
+        for (similarity, candidate) in scored {
+            if examples.len() >= self.config.max_examples {
+                break;
+            }
+            let example = FewShotExample {
+                request: candidate.example.request.clone(),
+                decision: candidate.example.decision.clone(),
+                reason: candidate.example.reason.clone(),
+                corrected: candidate.example.source == ExampleSource::Corrected.as_str(),
+                similarity,
+            };
+            let cost = estimate_tokens(&serde_json::to_string(&example)?);
+            if used_tokens + cost > self.config.token_budget {
+                continue;
+            }
+            used_tokens += cost;
+            examples.push(example);
+        }
+
+        Ok(examples)
+    }
+
+    /// How long `select` may take before the judge goes on without examples
+    pub fn select_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.select_timeout_ms)
+    }
+
+    /// Stores the request and its verdict as an example. Unverified verdicts
+    /// are only kept with `include_unverified`, and must be blocks with at
+    /// least `min_confidence`; corrected ones replace any example of the same
+    /// request and are never replaced by an unverified one.
+    pub async fn record(
+        &self,
+        payload: &RequestPayload,
+        decision: &JudgeDecision,
+        source: ExampleSource,
+    ) -> Result<()> {
+        if source == ExampleSource::Unverified
+            && (!self.config.include_unverified
+                || !decision.is_block()
+                || decision.confidence() < self.config.min_confidence)
+        {
+            return Ok(());
+        }
+        let (decision_type, reason) = match decision {
+            JudgeDecision::Allow { .. } => (decision.decision_type(), None),
+            JudgeDecision::Flag { reason, .. } | JudgeDecision::Block { reason, .. } => {
+                (decision.decision_type(), Some(reason.as_str()))
+            }
+        };
+
+        let normalized = normalize(payload);
+        let example = StoredExample {
+            method: payload.method.clone(),
+            path_template: path_template(&payload.path),
+            payload_hash: payload.normalized_hash.clone(),
+            request: truncate_chars(&normalized, EXAMPLE_REQUEST_CHARS).to_string(),
+            minhash: encode_signature(&minhash(&normalized)),
+            decision: decision_type.to_string(),
+            reason: reason.map(|r| truncate_chars(r, EXAMPLE_REASON_CHARS).to_string()),
+            source: source.as_str().to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        self.logs.upsert_example(&example).await?;
+
+        // New examples count right away, corrections especially
+        self.cache
+            .lock()
+            .unwrap()
+            .remove(&(example.method, example.path_template));
+
+        let records = self.records.fetch_add(1, Ordering::Relaxed) + 1;
+        if records.is_multiple_of(PRUNE_EVERY_RECORDS) {
+            self.prune().await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes unverified examples older than `max_age_days`, returning how
+    /// many
+    pub async fn prune(&self) -> Result<u64> {
+        let max_age = Duration::from_secs(self.config.max_age_days * 24 * 3600);
+        let before = chrono::Utc::now().timestamp() - max_age.as_secs() as i64;
+        let pruned = self.logs.delete_unverified_examples(before).await?;
+        if pruned > 0 {
+            self.cache.lock().unwrap().clear();
+            tracing::info!(pruned, "Expired few-shot examples pruned");
+        }
+        Ok(pruned)
+    }
+
+    /// Corrected examples always count; unverified ones only as blocks with
+    /// `include_unverified`, which also leaves out those stored before
+    fn usable(&self, example: &StoredExample) -> bool {
+        example.source == ExampleSource::Corrected.as_str()
+            || (self.config.include_unverified && example.decision == "block")
+    }
+
+    async fn candidates(&self, method: &str, template: &str) -> Result<Arc<Vec<Candidate>>> {
+        let key = (method.to_string(), template.to_string());
+        let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
+        if let Some(cached) = self.cache.lock().unwrap().get(&key) {
+            if cached.loaded_at.elapsed() < ttl {
+                return Ok(Arc::clone(&cached.candidates));
+            }
+        }
+
+        let examples = self
+            .logs
+            .examples_for_template(method, template, CANDIDATES_PER_TEMPLATE)
+            .await?;
+        let candidates: Arc<Vec<Candidate>> = Arc::new(
+            examples
+                .into_iter()
+                .map(|example| Candidate {
+                    signature: decode_signature(&example.minhash),
+                    example,
+                })
+                .collect(),
+        );
+
+        self.cache.lock().unwrap().insert(
+            key,
+            CachedTemplate {
+                loaded_at: Instant::now(),
+                candidates: Arc::clone(&candidates),
+            },
+        );
+        Ok(candidates)
+    }
+}
+
+fn rank((similarity, candidate): &(f32, &Candidate)) -> f32 {
+    if candidate.example.source == ExampleSource::Corrected.as_str() {
+        similarity + CORRECTED_BONUS
+    } else {
+        *similarity
+    }
+}
+
+/// Request text compared between requests: path, sorted query and body
+/// excerpt, URL-decoded, lowercased, with digit runs collapsed to `0`
+pub fn normalize(payload: &RequestPayload) -> String {
+    let query: Vec<String> = canonical::query_pairs(payload)
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+
+    let mut text = payload.path.clone();
+    if !query.is_empty() {
+        text.push('?');
+        text.push_str(&query.join("&"));
+    }
+    if let Some(body) = payload.body.as_deref().filter(|b| !b.is_empty()) {
+        text.push(' ');
+        text.push_str(truncate_chars(body, NORMALIZED_BODY_CHARS));
+    }
+
+    let text = transform::apply_all(&text, NORMALIZE_TRANSFORMS);
+    let mut normalized = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            if !normalized.ends_with('0') {
+                normalized.push('0');
+            }
+        } else {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+/// MinHash signature of the character shingles of `text`
+pub fn minhash(text: &str) -> Vec<u64> {
+    let chars: Vec<char> = text.chars().collect();
+    let shingles: Vec<u64> = if chars.len() <= SHINGLE_CHARS {
+        vec![fnv1a(&chars)]
+    } else {
+        chars.windows(SHINGLE_CHARS).map(fnv1a).collect()
+    };
+
+    (0..MINHASH_SIZE as u64)
+        .map(|i| {
+            let seed = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            shingles
+                .iter()
+                .map(|h| splitmix64(h ^ seed))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+/// Estimated Jaccard similarity of two signatures
+pub fn similarity(a: &[u64], b: &[u64]) -> f32 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    let equal = a.iter().zip(b).filter(|(x, y)| x == y).count();
+    equal as f32 / a.len() as f32
+}
+
+fn encode_signature(signature: &[u64]) -> Vec<u8> {
+    signature.iter().flat_map(|h| h.to_le_bytes()).collect()
+}
+
+fn decode_signature(bytes: &[u8]) -> Vec<u64> {
+    let (chunks, _) = bytes.as_chunks::<8>();
+    chunks
+        .iter()
+        .map(|chunk| u64::from_le_bytes(*chunk))
+        .collect()
+}
+
+/// FNV-1a: stable across builds, unlike `DefaultHasher`, since signatures
+/// are persisted
+fn fnv1a(chars: &[char]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for c in chars {
+        for byte in (*c as u32).to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::decision::ThreatLevel;
+    use crate::storage::logs::LogStore;
+
+    fn get(path: &str, query: &[(&str, &str)]) -> RequestPayload {
+        RequestPayload::new(
+            "GET".to_string(),
+            path.to_string(),
+            HashMap::new(),
+            None,
+            query
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            None,
+        )
+    }
+
+    fn block(reason: &str) -> JudgeDecision {
+        JudgeDecision::Block {
+            confidence: 0.95,
+            reason: reason.to_string(),
+            threat_level: ThreatLevel::High,
+        }
+    }
+
+    fn with_unverified() -> FewShotConfig {
+        FewShotConfig {
+            include_unverified: true,
+            ..FewShotConfig::default()
+        }
+    }
+
+    async fn index(config: FewShotConfig) -> (tempfile::TempDir, ExampleIndex) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = LogStore::new(temp_dir.path().join("test.db"))
+            .await
+            .unwrap();
+        (temp_dir, ExampleIndex::new(Arc::new(logs), config))
+    }
+
+    #[test]
+    fn test_normalize() {
+        let payload = get("/Items/42", &[("q", "Union%20SELECT  1"), ("a", "x")]);
+        assert_eq!(normalize(&payload), "/items/0?a=x&q=union select 0");
+    }
+
+    #[test]
+    fn test_normalize_keeps_every_value_of_a_repeated_key() {
+        let payload =
+            get("/items", &[]).with_raw_query(Some("id=1&a=x&id=2%20OR%201=1".to_string()));
+        assert_eq!(normalize(&payload), "/items?a=x&id=0&id=0 or 0=0");
+    }
+
+    #[test]
+    fn test_minhash_similarity() {
+        let a = minhash("/search?q=1' or '1'='1");
+        let b = minhash("/search?q=2' or '2'='2");
+        let c = minhash("/search?q=running shoes");
+
+        assert_eq!(similarity(&a, &a), 1.0);
+        assert!(similarity(&a, &b) > similarity(&a, &c));
+        assert_eq!(decode_signature(&encode_signature(&a)), a);
+    }
+
+    #[tokio::test]
+    async fn test_select_similar_examples() {
+        let (_dir, index) = index(with_unverified()).await;
+
+        let sqli = get("/search", &[("q", "1' OR '1'='1' --")]);
+        index
+            .record(&sqli, &block("SQL tautology"), ExampleSource::Unverified)
+            .await
+            .unwrap();
+        let benign = get("/search", &[("q", "running shoes size 42")]);
+        index
+            .record(
+                &benign,
+                &JudgeDecision::Allow { confidence: 0.95 },
+                ExampleSource::Unverified,
+            )
+            .await
+            .unwrap();
+        // An allow, not confident enough, and on another template
+        index
+            .record(
+                &get("/search", &[("q", "1' OR 'a'='a")]),
+                &JudgeDecision::Block {
+                    confidence: 0.6,
+                    reason: "maybe".to_string(),
+                    threat_level: ThreatLevel::Low,
+                },
+                ExampleSource::Unverified,
+            )
+            .await
+            .unwrap();
+        index
+            .record(
+                &get("/login", &[("q", "1' OR '1'='1' --")]),
+                &block("SQL tautology"),
+                ExampleSource::Unverified,
+            )
+            .await
+            .unwrap();
+
+        let examples = index
+            .select(&get("/search", &[("q", "7' OR '7'='7' --")]))
+            .await
+            .unwrap();
+
+        assert!(!examples.is_empty());
+        assert_eq!(examples[0].decision, "block");
+        assert_eq!(examples[0].reason.as_deref(), Some("SQL tautology"));
+        assert_eq!(examples[0].request, "/search?q=0' or '0'='0' --");
+        assert_eq!(examples.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_corrected_examples_win_and_stick() {
+        let (_dir, index) = index(with_unverified()).await;
+
+        let payload = get("/comments", &[("text", "I'd like <b>bold</b> text")]);
+        index
+            .record(&payload, &block("XSS"), ExampleSource::Unverified)
+            .await
+            .unwrap();
+        index
+            .record(
+                &payload,
+                &JudgeDecision::Allow { confidence: 1.0 },
+                ExampleSource::Corrected,
+            )
+            .await
+            .unwrap();
+        // A later unverified verdict doesn't undo the correction
+        index
+            .record(&payload, &block("XSS"), ExampleSource::Unverified)
+            .await
+            .unwrap();
+
+        let examples = index
+            .select(&get(
+                "/comments",
+                &[("text", "I'd like <i>italic</i> text")],
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(examples.len(), 1);
+        assert_eq!(examples[0].decision, "allow");
+        assert!(examples[0].corrected);
+    }
+
+    #[tokio::test]
+    async fn test_unverified_examples_are_opt_in() {
+        let (_dir, index) = index(FewShotConfig::default()).await;
+
+        let sqli = get("/search", &[("q", "1' OR '1'='1' --")]);
+        index
+            .record(&sqli, &block("SQL tautology"), ExampleSource::Unverified)
+            .await
+            .unwrap();
+        assert!(index
+            .logs
+            .examples_for_template("GET", "/search", 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Stored while they were on, or as an allow: not shown either
+        for (q, decision) in [("1' OR 'a'='a' --", "block"), ("1' OR 'b'='b' --", "allow")] {
+            let payload = get("/search", &[("q", q)]);
+            let normalized = normalize(&payload);
+            index
+                .logs
+                .upsert_example(&StoredExample {
+                    method: payload.method.clone(),
+                    path_template: path_template(&payload.path),
+                    payload_hash: payload.normalized_hash.clone(),
+                    request: normalized.clone(),
+                    minhash: encode_signature(&minhash(&normalized)),
+                    decision: decision.to_string(),
+                    reason: None,
+                    source: ExampleSource::Unverified.as_str().to_string(),
+                    created_at: chrono::Utc::now().timestamp(),
+                })
+                .await
+                .unwrap();
+        }
+        let query = get("/search", &[("q", "7' OR '7'='7' --")]);
+        assert!(index.select(&query).await.unwrap().is_empty());
+
+        index
+            .record(&sqli, &block("SQL tautology"), ExampleSource::Corrected)
+            .await
+            .unwrap();
+        let examples = index.select(&query).await.unwrap();
+        assert_eq!(examples.len(), 1);
+        assert!(examples[0].corrected);
+    }
+
+    #[tokio::test]
+    async fn test_unverified_allows_are_never_examples() {
+        let (_dir, index) = index(with_unverified()).await;
+
+        let payload = get("/search", &[("q", "running shoes size 42")]);
+        index
+            .record(
+                &payload,
+                &JudgeDecision::Allow { confidence: 1.0 },
+                ExampleSource::Unverified,
+            )
+            .await
+            .unwrap();
+
+        assert!(index
+            .select(&get("/search", &[("q", "running shoes size 43")]))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prune_drops_old_unverified_examples() {
+        let (_dir, index) = index(with_unverified()).await;
+        let old = chrono::Utc::now().timestamp() - 31 * 24 * 3600;
+
+        for (path, source) in [
+            ("/old-unverified", ExampleSource::Unverified),
+            ("/old-corrected", ExampleSource::Corrected),
+        ] {
+            let payload = get(path, &[]);
+            let normalized = normalize(&payload);
+            index
+                .logs
+                .upsert_example(&StoredExample {
+                    method: payload.method.clone(),
+                    path_template: path_template(&payload.path),
+                    payload_hash: payload.normalized_hash.clone(),
+                    request: normalized.clone(),
+                    minhash: encode_signature(&minhash(&normalized)),
+                    decision: "block".to_string(),
+                    reason: None,
+                    source: source.as_str().to_string(),
+                    created_at: old,
+                })
+                .await
+                .unwrap();
+        }
+        let recent = get("/recent", &[]);
+        index
+            .record(&recent, &block("test"), ExampleSource::Unverified)
+            .await
+            .unwrap();
+
+        assert_eq!(index.prune().await.unwrap(), 1);
+        for (path, kept) in [
+            ("/old-unverified", false),
+            ("/old-corrected", true),
+            ("/recent", true),
+        ] {
+            let examples = index
+                .logs
+                .examples_for_template("GET", path, 10)
+                .await
+                .unwrap();
+            assert_eq!(examples.len(), kept as usize, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_select_respects_limits() {
+        let config = FewShotConfig {
+            max_examples: 3,
+            token_budget: 60,
+            min_similarity: 0.0,
+            ..with_unverified()
+        };
+        let (_dir, index) = index(config).await;
+
+        for i in 0..10 {
+            let payload = get("/items", &[("name", &format!("item-{}", "x".repeat(i)))]);
+            index
+                .record(&payload, &block("test"), ExampleSource::Unverified)
+                .await
+                .unwrap();
+        }
+
+        let examples = index
+            .select(&get("/items", &[("name", "item")]))
+            .await
+            .unwrap();
+
+        assert!(!examples.is_empty() && examples.len() <= 3);
+        let tokens: usize = examples
+            .iter()
+            .map(|e| estimate_tokens(&serde_json::to_string(e).unwrap()))
+            .sum();
+        assert!(tokens <= 60);
+    }
+}
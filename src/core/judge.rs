@@ -1,177 +1,867 @@
-use crate::config::FailMode;
-use crate::core::rulebook::Rulebook;
+use crate::config::{DecisionMode, FailMode, ScoringConfig};
+use crate::core::coalesce::Coalescer;
+use crate::core::engine::RuleEngine;
+use crate::core::escalation::{Escalation, ProviderMetrics};
+use crate::core::fewshot::{ExampleIndex, ExampleSource, FewShotExample};
+use crate::core::rulebook::{MatchInput, Rulebook};
+use crate::core::scoring::ScoreBreakdown;
+use crate::core::steering::{SteeringDetector, SteeringMatch};
 use crate::llm::client::LlmProvider;
-use crate::models::decision::{JudgeDecision, ThreatLevel};
+use crate::models::canonical;
+use crate::models::decision::{JudgeDecision, RuleAction, ThreatLevel};
 use crate::models::request::RequestPayload;
-use crate::storage::cache::RedisCache;
+use crate::storage::cache::{CacheFlush, CachedVerdict, VerdictCache, VerdictTtls};
+use crate::storage::memory::MemoryCache;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::time::timeout;
 
 /// The Judge service is responsible for real-time request evaluation.
-/// It uses a cache-aside pattern with Redis and falls back to LLM evaluation.
+/// Active local rules are checked first; otherwise it uses a cache-aside
+/// pattern (in-process tier, then the shared `VerdictCache` backend) and
+/// falls back to LLM evaluation, with identical concurrent requests sharing
+/// one LLM call.
 /// In case of errors or timeouts, behavior depends on the configured fail_mode (open or closed).
+/// In anomaly mode, rule hits and the LLM verdict are scored instead (see `scoring`).
+/// Verdicts pinned by a human label override all of the above.
+/// Mid-confidence LLM blocks can be escalated to second opinions (see `escalation`).
 pub struct Judge {
     llm: Arc<dyn LlmProvider>,
-    cache: Option<Arc<RedisCache>>,
+    cache: Option<Arc<dyn VerdictCache>>,
+    memory_cache: Option<MemoryCache>,
+    verdict_ttls: VerdictTtls,
+    /// Fingerprint of the rules the LLM judges against; cache keys are
+    /// namespaced by it so that verdicts given under other rules aren't
+    /// served, after a restart or by another replica either
+    rulebook_fingerprint: std::sync::RwLock<RulesFingerprint>,
+    /// LLM verdicts in flight, by payload hash
+    inflight: Coalescer<Result<LlmVerdict, String>>,
     rulebook: Arc<RwLock<Rulebook>>,
+    /// Snapshot of the rulebook given to the LLM, so that no lock is held
+    /// while it (or a second opinion) answers and a reload never waits on it
+    rules_snapshot: std::sync::RwLock<Arc<Rulebook>>,
+    engine: std::sync::RwLock<Arc<RuleEngine>>,
+    steering: SteeringDetector,
     timeout_duration: Duration,
     fail_mode: FailMode,
+    scoring: ScoringConfig,
+    examples: Option<Arc<ExampleIndex>>,
+    escalation: Option<Escalation>,
+    /// Verdicts fixed by a human, by payload hash
+    pins: std::sync::RwLock<HashMap<String, JudgeDecision>>,
     metrics: JudgeMetrics,
 }
 
+/// A decision with the anomaly score it was derived from (anomaly mode only)
+/// and the version of the judge prompts behind its LLM verdict (none when
+/// the LLM wasn't consulted)
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub decision: JudgeDecision,
+    pub score: Option<ScoreBreakdown>,
+    pub prompt_version: Option<String>,
+}
+
+/// Fingerprint of the rules and of which of them are active, valid until the
+/// next activation change (forever if no rule has one ahead)
+struct RulesFingerprint {
+    fingerprint: String,
+    valid_until: Option<DateTime<Utc>>,
+}
+
+impl RulesFingerprint {
+    fn of(rulebook: &Rulebook, now: DateTime<Utc>) -> Self {
+        Self {
+            fingerprint: rulebook.fingerprint(now),
+            valid_until: rulebook.next_activation_change(now),
+        }
+    }
+
+    fn is_valid_at(&self, now: DateTime<Utc>) -> bool {
+        self.valid_until.is_none_or(|t| now < t)
+    }
+}
+
+/// An LLM verdict, fresh or cached, with the version of the prompts that
+/// produced it
+type LlmVerdict = (JudgeDecision, Option<String>);
+
 #[derive(Default, Clone)]
 pub struct JudgeMetrics {
     pub total_requests: Arc<std::sync::atomic::AtomicU64>,
+    pub pinned_hits: Arc<std::sync::atomic::AtomicU64>,
+    pub local_rule_hits: Arc<std::sync::atomic::AtomicU64>,
+    pub steering_detections: Arc<std::sync::atomic::AtomicU64>,
     pub cache_hits: Arc<std::sync::atomic::AtomicU64>,
     pub cache_misses: Arc<std::sync::atomic::AtomicU64>,
+    /// Cache hits per tier (`cache_hits` is their sum)
+    pub memory_cache_hits: Arc<std::sync::atomic::AtomicU64>,
+    pub backend_cache_hits: Arc<std::sync::atomic::AtomicU64>,
+    /// Requests that waited for an identical request's LLM verdict
+    pub coalesced_requests: Arc<std::sync::atomic::AtomicU64>,
     pub llm_timeouts: Arc<std::sync::atomic::AtomicU64>,
     pub llm_errors: Arc<std::sync::atomic::AtomicU64>,
     pub fail_open_count: Arc<std::sync::atomic::AtomicU64>,
     pub fail_closed_count: Arc<std::sync::atomic::AtomicU64>,
+    /// Requests judged after being forwarded
+    pub background_evaluations: Arc<std::sync::atomic::AtomicU64>,
+    /// Calls to the primary LLM
+    pub llm: ProviderMetrics,
+    pub escalations: Arc<std::sync::atomic::AtomicU64>,
+    pub escalations_confirmed: Arc<std::sync::atomic::AtomicU64>,
+    pub escalations_overturned: Arc<std::sync::atomic::AtomicU64>,
 }
 
 impl Judge {
-    pub fn new(
+    pub async fn new(
         llm: Arc<dyn LlmProvider>,
-        cache: Option<Arc<RedisCache>>,
+        cache: Option<Arc<dyn VerdictCache>>,
         rulebook: Arc<RwLock<Rulebook>>,
         timeout_duration: Duration,
         fail_mode: FailMode,
     ) -> Self {
+        let (engine, fingerprint, snapshot) = {
+            let rulebook = rulebook.read().await;
+            (
+                RuleEngine::new(&rulebook),
+                RulesFingerprint::of(&rulebook, Utc::now()),
+                Arc::new(rulebook.clone()),
+            )
+        };
+
         Self {
             llm,
             cache,
+            memory_cache: None,
+            verdict_ttls: VerdictTtls::default(),
+            rulebook_fingerprint: std::sync::RwLock::new(fingerprint),
+            inflight: Coalescer::default(),
             rulebook,
+            rules_snapshot: std::sync::RwLock::new(snapshot),
+            engine: std::sync::RwLock::new(Arc::new(engine)),
+            steering: SteeringDetector::new(),
             timeout_duration,
             fail_mode,
+            scoring: ScoringConfig::default(),
+            examples: None,
+            escalation: None,
+            pins: std::sync::RwLock::new(HashMap::new()),
             metrics: JudgeMetrics::default(),
         }
     }
 
+    pub fn with_scoring(mut self, scoring: ScoringConfig) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// Adds an in-process cache tier in front of the backend (or alone without it)
+    pub fn with_memory_cache(mut self, memory_cache: MemoryCache) -> Self {
+        self.memory_cache = Some(memory_cache);
+        self
+    }
+
+    /// How long verdicts are cached, by decision type
+    pub fn with_verdict_ttls(mut self, verdict_ttls: VerdictTtls) -> Self {
+        self.verdict_ttls = verdict_ttls;
+        self
+    }
+
+    /// Shows the LLM similar past requests with their verdict, and records
+    /// its confident verdicts as new examples
+    pub fn with_examples(mut self, examples: Arc<ExampleIndex>) -> Self {
+        self.examples = Some(examples);
+        self
+    }
+
+    /// Has mid-confidence LLM blocks confirmed by second-opinion judges
+    pub fn with_escalation(mut self, escalation: Escalation) -> Self {
+        self.escalation = Some(escalation);
+        self
+    }
+
+    /// Verdicts pinned by earlier labels, by payload hash
+    pub fn with_pinned_verdicts(
+        self,
+        pins: impl IntoIterator<Item = (String, JudgeDecision)>,
+    ) -> Self {
+        self.pins.write().unwrap().extend(pins);
+        self
+    }
+
+    /// Pins the verdict of a payload hash and drops its cached verdict, so
+    /// the next identical request gets the pinned verdict right away
+    pub async fn pin_verdict(&self, hash: &str, decision: JudgeDecision) {
+        self.pins
+            .write()
+            .unwrap()
+            .insert(hash.to_string(), decision);
+
+        let key = self.cache_key(hash);
+        if let Some(ref memory_cache) = self.memory_cache {
+            memory_cache.invalidate(&key);
+        }
+        if let Some(ref cache) = self.cache {
+            if let Err(e) = cache.invalidate(&key).await {
+                tracing::warn!(error = %e, hash = %hash, "Failed to invalidate cached verdict");
+            }
+        }
+    }
+
     /// Evaluate a request and return a decision.
     /// This is the main entry point for request evaluation.
-    ///
-    /// Flow:
-    /// 1. Check cache for existing verdict
-    /// 2. If cache miss, call LLM with timeout
-    /// 3. Cache the result (if cache enabled)
-    /// 4. On error/timeout: behavior depends on fail_mode (open: allow, closed: block)
+    #[allow(dead_code)] // Used in tests
     pub async fn evaluate(&self, payload: RequestPayload) -> JudgeDecision {
+        self.evaluate_scored(payload).await.decision
+    }
+
+    /// Evaluate a request off the request path: the returned task resolves
+    /// to the evaluation, cached and counted like an inline one. Used on
+    /// routes forwarded before their verdict (`async_judge.routes`).
+    pub fn evaluate_in_background(
+        self: &Arc<Self>,
+        payload: RequestPayload,
+    ) -> tokio::task::JoinHandle<Evaluation> {
+        use std::sync::atomic::Ordering;
+
+        self.metrics
+            .background_evaluations
+            .fetch_add(1, Ordering::Relaxed);
+        let judge = Arc::clone(self);
+        tokio::spawn(async move { judge.evaluate_scored(payload).await })
+    }
+
+    /// Decision of the local rules alone when they block the request: a
+    /// block rule in first-match mode, rule hits and steering crossing the
+    /// block threshold in anomaly mode. Used on routes forwarded before their
+    /// LLM verdict, so that known attacks are still stopped inline.
+    pub fn evaluate_local(&self, payload: &RequestPayload) -> Option<Evaluation> {
+        use std::sync::atomic::Ordering;
+
+        // A pinned allow overrides the rules here too; a pinned block stops
+        // the request like a rule would
+        if let Some(decision) = self.pinned_verdict(payload) {
+            if !decision.is_block() {
+                return None;
+            }
+            self.metrics.total_requests.fetch_add(1, Ordering::Relaxed);
+            self.metrics.pinned_hits.fetch_add(1, Ordering::Relaxed);
+            return Some(Evaluation {
+                decision,
+                score: None,
+                prompt_version: None,
+            });
+        }
+
+        let evaluation = match self.scoring.mode {
+            DecisionMode::FirstMatch => Evaluation {
+                decision: self.match_local_rules(payload)?,
+                score: None,
+                prompt_version: None,
+            },
+            DecisionMode::Anomaly => {
+                let (block_threshold, flag_threshold) = self.scoring.thresholds_for(&payload.path);
+                let mut score = ScoreBreakdown::new(block_threshold, flag_threshold);
+                let engine = Arc::clone(&self.engine.read().unwrap());
+                let input = engine.input(payload);
+                for rule in engine.matching_rules(&input, Utc::now()) {
+                    score.add_rule(rule);
+                }
+                if let Some(steering) = self.steering.detect(&input) {
+                    score.add_steering(&steering);
+                }
+                Evaluation {
+                    decision: score.decision(),
+                    score: Some(score),
+                    prompt_version: None,
+                }
+            }
+        };
+        if !evaluation.decision.is_block() {
+            return None;
+        }
+
+        self.metrics.total_requests.fetch_add(1, Ordering::Relaxed);
+        self.metrics.local_rule_hits.fetch_add(1, Ordering::Relaxed);
+        tracing::info!(
+            method = %payload.method,
+            path = %payload.path,
+            decision = ?evaluation.decision.decision_type(),
+            "Request blocked by local rules"
+        );
+        Some(evaluation)
+    }
+
+    /// Evaluate a request, returning the decision with its score breakdown.
+    /// A verdict pinned for the request's hash is returned as is.
+    ///
+    /// First-match flow:
+    /// 1. Check local rules: the first active matching block rule decides
+    /// 2. Check cache for existing verdict
+    /// 3. If cache miss, call LLM with timeout
+    /// 4. Cache the result (if cache enabled)
+    /// 5. On error/timeout: behavior depends on fail_mode (open: allow, closed: block)
+    /// 6. Requests trying to steer the LLM are flagged at least
+    /// 7. Requests matching a flag rule are flagged at least
+    ///
+    /// Anomaly flow: every matching rule and any steering attempt add to the
+    /// score; unless that already crosses the block threshold, the (cached)
+    /// LLM verdict is added too and the total is compared to the route's
+    /// thresholds.
+    pub async fn evaluate_scored(&self, payload: RequestPayload) -> Evaluation {
         use std::sync::atomic::Ordering;
 
         self.metrics.total_requests.fetch_add(1, Ordering::Relaxed);
 
-        // Step 1: Check cache
-        if let Some(ref cache) = self.cache {
-            match cache.get_verdict(&payload.normalized_hash).await {
-                Ok(Some(cached_decision)) => {
-                    self.metrics.cache_hits.fetch_add(1, Ordering::Relaxed);
-                    tracing::debug!(
-                        hash = %payload.normalized_hash,
-                        decision = ?cached_decision.decision_type(),
-                        "Cache hit"
-                    );
-                    return cached_decision;
+        if let Some(decision) = self.pinned_verdict(&payload) {
+            self.metrics.pinned_hits.fetch_add(1, Ordering::Relaxed);
+            return Evaluation {
+                decision,
+                score: None,
+                prompt_version: None,
+            };
+        }
+
+        match self.scoring.mode {
+            DecisionMode::FirstMatch => self.evaluate_first_match(&payload).await,
+            DecisionMode::Anomaly => self.evaluate_anomaly(&payload).await,
+        }
+    }
+
+    /// Verdict pinned by a label for the request's hash, if any
+    fn pinned_verdict(&self, payload: &RequestPayload) -> Option<JudgeDecision> {
+        let decision = self
+            .pins
+            .read()
+            .unwrap()
+            .get(&payload.normalized_hash)
+            .cloned()?;
+        tracing::info!(
+            method = %payload.method,
+            path = %payload.path,
+            decision = ?decision.decision_type(),
+            "Request matched pinned verdict"
+        );
+        Some(decision)
+    }
+
+    async fn evaluate_first_match(&self, payload: &RequestPayload) -> Evaluation {
+        use std::sync::atomic::Ordering;
+
+        // Step 1: Check local rules
+        let local = self.match_local_rules(payload);
+        if let Some(decision) = &local {
+            self.metrics.local_rule_hits.fetch_add(1, Ordering::Relaxed);
+            tracing::info!(
+                method = %payload.method,
+                path = %payload.path,
+                decision = ?decision.decision_type(),
+                confidence = decision.confidence(),
+                "Request matched local rule"
+            );
+            if decision.is_block() {
+                return Evaluation {
+                    decision: decision.clone(),
+                    score: None,
+                    prompt_version: None,
+                };
+            }
+        }
+
+        // Steps 2-4: Cached or fresh LLM verdict
+        let (decision, prompt_version) = match self.llm_verdict(payload).await {
+            Ok(verdict) => verdict,
+            // Step 5: Apply fail mode
+            Err(e) => (self.fail_mode_decision(payload, e), None),
+        };
+
+        // Step 6: Don't let a steering attempt through on an allow verdict
+        let decision = match (self.detect_steering(payload), decision) {
+            (Some(steering), JudgeDecision::Allow { .. }) => JudgeDecision::Flag {
+                confidence: 1.0,
+                reason: steering.reason(),
+                suggested_rule: None,
+            },
+            (_, decision) => decision,
+        };
+
+        // Step 7: A matching flag rule isn't overridden by an allow verdict
+        let decision = match (local, decision) {
+            (Some(flag), JudgeDecision::Allow { .. }) => flag,
+            (_, decision) => decision,
+        };
+
+        Evaluation {
+            decision,
+            score: None,
+            prompt_version,
+        }
+    }
+
+    /// Steering phrase in the request, if any (logged and counted)
+    fn detect_steering(&self, payload: &RequestPayload) -> Option<SteeringMatch> {
+        use std::sync::atomic::Ordering;
+
+        let steering = self.steering.detect(&MatchInput::from_payload(payload))?;
+        self.metrics
+            .steering_detections
+            .fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            method = %payload.method,
+            path = %payload.path,
+            kind = steering.kind,
+            excerpt = %steering.excerpt,
+            "LLM steering attempt detected"
+        );
+        Some(steering)
+    }
+
+    async fn evaluate_anomaly(&self, payload: &RequestPayload) -> Evaluation {
+        use std::sync::atomic::Ordering;
+
+        let (block_threshold, flag_threshold) = self.scoring.thresholds_for(&payload.path);
+        let mut score = ScoreBreakdown::new(block_threshold, flag_threshold);
+
+        let engine = Arc::clone(&self.engine.read().unwrap());
+        let input = engine.input(payload);
+        for rule in engine.matching_rules(&input, Utc::now()) {
+            score.add_rule(rule);
+        }
+        if !score.contributions.is_empty() {
+            self.metrics.local_rule_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(steering) = self.detect_steering(payload) {
+            score.add_steering(&steering);
+        }
+
+        // The LLM can only add to the score, so it is not needed once rule
+        // hits alone cross the block threshold
+        let mut prompt_version = None;
+        if !score.blocks() {
+            match self.llm_verdict(payload).await {
+                Ok((verdict, version)) => {
+                    score.add_verdict(&verdict, self.scoring.llm_weight);
+                    prompt_version = version;
                 }
-                Ok(None) => {
-                    self.metrics.cache_misses.fetch_add(1, Ordering::Relaxed);
+                Err(e) if self.fail_mode == FailMode::Open => {
+                    tracing::warn!(
+                        error = %e,
+                        method = %payload.method,
+                        path = %payload.path,
+                        "LLM evaluation failed, scoring local rules only"
+                    );
+                    self.metrics.fail_open_count.fetch_add(1, Ordering::Relaxed);
                 }
                 Err(e) => {
-                    tracing::warn!(error = %e, "Cache lookup failed");
+                    return Evaluation {
+                        decision: self.fail_mode_decision(payload, e),
+                        score: Some(score),
+                        prompt_version: None,
+                    }
                 }
             }
         }
 
-        // Step 2: Call LLM with timeout
-        let decision = self.call_llm_with_timeout(&payload).await;
+        let decision = score.decision();
+        tracing::info!(
+            method = %payload.method,
+            path = %payload.path,
+            decision = ?decision.decision_type(),
+            score = score.total,
+            block_threshold = score.block_threshold,
+            flag_threshold = score.flag_threshold,
+            breakdown = %score,
+            "Request scored"
+        );
 
-        // Step 3: Cache the result
-        if let Some(ref cache) = self.cache {
-            if let Ok(ref dec) = decision {
-                if let Err(e) = cache.set_verdict(&payload.normalized_hash, dec).await {
-                    tracing::warn!(error = %e, "Failed to cache verdict");
+        Evaluation {
+            decision,
+            score: Some(score),
+            prompt_version,
+        }
+    }
+
+    /// Cached LLM verdict, or a fresh one (cached for next time). Identical
+    /// requests arriving while a verdict is computed wait for it.
+    async fn llm_verdict(&self, payload: &RequestPayload) -> Result<LlmVerdict> {
+        use std::sync::atomic::Ordering;
+
+        // Keyed before the LLM is called, so a verdict is never stored under
+        // a newer rulebook than the one it was given under
+        let key = self.cache_key(&payload.normalized_hash);
+        if let Some(cached) = self.cached_verdict(&key).await {
+            self.metrics.cache_hits.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!(
+                hash = %payload.normalized_hash,
+                decision = ?cached.decision.decision_type(),
+                "Cache hit"
+            );
+            return Ok((cached.decision, cached.prompt_version));
+        }
+        if self.memory_cache.is_some() || self.cache.is_some() {
+            self.metrics.cache_misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // anyhow errors can't be shared, their message is
+        let (decision, shared) = self
+            .inflight
+            .run(&key, async {
+                self.fresh_verdict(payload, &key)
+                    .await
+                    .map_err(|e| format!("{:#}", e))
+            })
+            .await;
+        if shared {
+            self.metrics
+                .coalesced_requests
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        decision.map_err(anyhow::Error::msg)
+    }
+
+    /// Cache key of a payload hash under the rules active now. The
+    /// fingerprint is recomputed once a rule activates or expires, so a
+    /// verdict given before is no longer served.
+    fn cache_key(&self, hash: &str) -> String {
+        let now = Utc::now();
+        {
+            let current = self.rulebook_fingerprint.read().unwrap();
+            if current.is_valid_at(now) {
+                return format!("r{}:{}", current.fingerprint, hash);
+            }
+        }
+
+        // The snapshot is read under the write lock, so a reload (which swaps
+        // the snapshot first) is never overwritten with an older fingerprint
+        let mut current = self.rulebook_fingerprint.write().unwrap();
+        if !current.is_valid_at(now) {
+            *current = RulesFingerprint::of(&self.rules_snapshot.read().unwrap(), now);
+        }
+        format!("r{}:{}", current.fingerprint, hash)
+    }
+
+    /// Verdict from the in-process tier, else from the backend (then kept in-process)
+    async fn cached_verdict(&self, key: &str) -> Option<CachedVerdict> {
+        use std::sync::atomic::Ordering;
+
+        if let Some(verdict) = self.memory_cache.as_ref().and_then(|m| m.get(key)) {
+            self.metrics
+                .memory_cache_hits
+                .fetch_add(1, Ordering::Relaxed);
+            return Some(verdict);
+        }
+
+        let cache = self.cache.as_ref()?;
+        match cache.get_verdict(key).await {
+            Ok(Some(verdict)) => {
+                self.metrics
+                    .backend_cache_hits
+                    .fetch_add(1, Ordering::Relaxed);
+                if let (Some(memory_cache), Some(ttl)) = (
+                    &self.memory_cache,
+                    self.verdict_ttls.ttl_for(&verdict.decision),
+                ) {
+                    memory_cache.set(key, &verdict, ttl);
                 }
+                Some(verdict)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!(error = %e, "Cache lookup failed");
+                None
             }
         }
+    }
+
+    /// Caches `decision` under `key` in every tier, for as long as its type
+    /// allows
+    async fn store_verdict(
+        &self,
+        payload: &RequestPayload,
+        key: &str,
+        decision: &JudgeDecision,
+        prompt_version: Option<&str>,
+    ) {
+        let Some(ttl) = self.verdict_ttls.ttl_for(decision) else {
+            return;
+        };
+        let verdict = CachedVerdict {
+            decision: decision.clone(),
+            path: canonical::canonical_path(&payload.path),
+            prompt_version: prompt_version.map(str::to_string),
+        };
 
-        // Step 4: Handle result or apply fail mode
-        match decision {
-            Ok(dec) => {
-                tracing::info!(
+        if let Some(ref memory_cache) = self.memory_cache {
+            memory_cache.set(key, &verdict, ttl);
+        }
+        if let Some(ref cache) = self.cache {
+            if let Err(e) = cache.set_verdict(key, &verdict, ttl).await {
+                tracing::warn!(error = %e, "Failed to cache verdict");
+            }
+        }
+    }
+
+    /// Drops the cached verdicts matching `flush` from every tier, returning
+    /// how many were dropped
+    pub async fn flush_cache(&self, flush: &CacheFlush) -> Result<usize> {
+        let mut flushed = self
+            .memory_cache
+            .as_ref()
+            .map(|m| m.flush(flush))
+            .unwrap_or(0);
+        if let Some(ref cache) = self.cache {
+            flushed += cache.flush(flush).await?;
+        }
+        tracing::info!(
+            path_prefix = ?flush.path_prefix,
+            decision = ?flush.decision,
+            flushed,
+            "Verdict cache flushed"
+        );
+        Ok(flushed)
+    }
+
+    /// Verdict of the LLM, cached in every tier under `key`
+    async fn fresh_verdict(&self, payload: &RequestPayload, key: &str) -> Result<LlmVerdict> {
+        let prompt_version = self.llm.prompt_version().await;
+
+        // Call LLM with timeout
+        let decision = self.call_llm_with_timeout(payload).await;
+
+        // Cache the result
+        if let Ok(ref dec) = decision {
+            self.store_verdict(payload, key, dec, prompt_version.as_deref())
+                .await;
+        }
+
+        if let Ok(ref dec) = decision {
+            tracing::info!(
+                method = %payload.method,
+                path = %payload.path,
+                decision = ?dec.decision_type(),
+                confidence = dec.confidence(),
+                "Request evaluated"
+            );
+            self.record_example(payload, dec);
+        }
+        decision.map(|decision| (decision, prompt_version))
+    }
+
+    /// Records a fresh LLM verdict as an unverified few-shot example in the
+    /// background, if the index takes them. Steering attempts are left out:
+    /// their verdict can't be trusted.
+    fn record_example(&self, payload: &RequestPayload, decision: &JudgeDecision) {
+        let Some(examples) = &self.examples else {
+            return;
+        };
+        if self
+            .steering
+            .detect(&MatchInput::from_payload(payload))
+            .is_some()
+        {
+            return;
+        }
+
+        let examples = Arc::clone(examples);
+        let payload = payload.clone();
+        let decision = decision.clone();
+        tokio::spawn(async move {
+            if let Err(e) = examples
+                .record(&payload, &decision, ExampleSource::Unverified)
+                .await
+            {
+                tracing::warn!(error = %e, "Failed to record few-shot example");
+            }
+        });
+    }
+
+    /// Decision when the LLM could not be consulted (open: allow, closed: block)
+    fn fail_mode_decision(&self, payload: &RequestPayload, e: anyhow::Error) -> JudgeDecision {
+        use std::sync::atomic::Ordering;
+
+        match self.fail_mode {
+            FailMode::Open => {
+                tracing::warn!(
+                    error = %e,
                     method = %payload.method,
                     path = %payload.path,
-                    decision = ?dec.decision_type(),
-                    confidence = dec.confidence(),
-                    "Request evaluated"
+                    "LLM evaluation failed, failing open (allowing request)"
                 );
-                dec
+                self.metrics.fail_open_count.fetch_add(1, Ordering::Relaxed);
+                JudgeDecision::Allow { confidence: 0.0 }
             }
-            Err(e) => {
-                match self.fail_mode {
-                    FailMode::Open => {
-                        tracing::warn!(
-                            error = %e,
-                            method = %payload.method,
-                            path = %payload.path,
-                            "LLM evaluation failed, failing open (allowing request)"
-                        );
-                        self.metrics.fail_open_count.fetch_add(1, Ordering::Relaxed);
-                        JudgeDecision::Allow { confidence: 0.0 }
-                    }
-                    FailMode::Closed => {
-                        tracing::warn!(
-                            error = %e,
-                            method = %payload.method,
-                            path = %payload.path,
-                            "LLM evaluation failed, failing closed (blocking request)"
-                        );
-                        self.metrics.fail_closed_count.fetch_add(1, Ordering::Relaxed);
-                        JudgeDecision::Block {
-                            confidence: 0.0,
-                            reason: "LLM evaluation failed".to_string(),
-                            threat_level: ThreatLevel::Medium,
-                        }
-                    }
+            FailMode::Closed => {
+                tracing::warn!(
+                    error = %e,
+                    method = %payload.method,
+                    path = %payload.path,
+                    "LLM evaluation failed, failing closed (blocking request)"
+                );
+                self.metrics
+                    .fail_closed_count
+                    .fetch_add(1, Ordering::Relaxed);
+                JudgeDecision::Block {
+                    confidence: 0.0,
+                    reason: "LLM evaluation failed".to_string(),
+                    threat_level: ThreatLevel::Medium,
                 }
             }
         }
     }
 
+    /// Decision of the first active block rule matching the request, else of
+    /// the first matching flag rule
+    fn match_local_rules(&self, payload: &RequestPayload) -> Option<JudgeDecision> {
+        let engine = Arc::clone(&self.engine.read().unwrap());
+        if engine.is_empty() {
+            return None;
+        }
+
+        let input = engine.input(payload);
+        let mut matching = engine.matching_rules(&input, Utc::now());
+        let first = matching.next()?;
+        let rule = match first.action {
+            RuleAction::Block => first,
+            RuleAction::Flag => matching
+                .find(|rule| rule.action == RuleAction::Block)
+                .unwrap_or(first),
+        };
+        let reason = format!(
+            "Matched rule {} ({}): {}",
+            rule.id,
+            rule.threat_type,
+            rule.description.as_deref().unwrap_or(&rule.pattern)
+        );
+
+        Some(match rule.action {
+            RuleAction::Block => JudgeDecision::Block {
+                confidence: rule.confidence,
+                reason,
+                threat_level: rule.threat_level.unwrap_or(ThreatLevel::High),
+            },
+            RuleAction::Flag => JudgeDecision::Flag {
+                confidence: rule.confidence,
+                reason,
+                suggested_rule: None,
+            },
+        })
+    }
+
     async fn call_llm_with_timeout(&self, payload: &RequestPayload) -> Result<JudgeDecision> {
         use std::sync::atomic::Ordering;
 
-        let rulebook = self.rulebook.read().await;
+        let rulebook = Arc::clone(&self.rules_snapshot.read().unwrap());
+        let examples = match &self.examples {
+            Some(index) => match timeout(index.select_timeout(), index.select(payload)).await {
+                Ok(Ok(examples)) => examples,
+                Ok(Err(e)) => {
+                    tracing::warn!(error = %e, "Failed to select few-shot examples");
+                    Vec::new()
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        timeout = ?index.select_timeout(),
+                        "Few-shot example selection timed out, judging without examples"
+                    );
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
 
-        let result = timeout(self.timeout_duration, async {
-            self.llm.judge_request(payload, &rulebook).await
-        })
+        let start = std::time::Instant::now();
+        let result = timeout(
+            self.timeout_duration,
+            self.llm.judge_request(payload, &rulebook, &examples),
+        )
         .await;
 
         match result {
-            Ok(Ok(decision)) => Ok(decision),
+            Ok(Ok(decision)) => {
+                self.metrics.llm.record(start.elapsed(), true);
+                match &self.escalation {
+                    Some(escalation) if escalation.should_escalate(&decision) => Ok(self
+                        .escalate(escalation, payload, &rulebook, &examples, decision)
+                        .await),
+                    _ => Ok(decision),
+                }
+            }
             Ok(Err(e)) => {
+                self.metrics.llm.record(start.elapsed(), false);
                 self.metrics.llm_errors.fetch_add(1, Ordering::Relaxed);
                 Err(e)
             }
             Err(_) => {
+                self.metrics.llm.record_timeout(self.timeout_duration);
                 self.metrics.llm_timeouts.fetch_add(1, Ordering::Relaxed);
                 anyhow::bail!("LLM timeout after {:?}", self.timeout_duration)
             }
         }
     }
 
+    /// Has a mid-confidence block reviewed by the second-opinion judges
+    async fn escalate(
+        &self,
+        escalation: &Escalation,
+        payload: &RequestPayload,
+        rulebook: &Arc<Rulebook>,
+        examples: &[FewShotExample],
+        decision: JudgeDecision,
+    ) -> JudgeDecision {
+        use std::sync::atomic::Ordering;
+
+        self.metrics.escalations.fetch_add(1, Ordering::Relaxed);
+        let review = escalation
+            .review(payload, rulebook, examples, decision)
+            .await;
+        let counter = if review.decision.is_block() {
+            &self.metrics.escalations_confirmed
+        } else {
+            &self.metrics.escalations_overturned
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        tracing::info!(
+            method = %payload.method,
+            path = %payload.path,
+            decision = ?review.decision.decision_type(),
+            confirmations = review.confirmations,
+            votes = review.votes,
+            "Block escalated to second opinion"
+        );
+        review.decision
+    }
+
     /// Returns metrics for monitoring and observability endpoints
-    #[allow(dead_code)]
     pub fn metrics(&self) -> &JudgeMetrics {
         &self.metrics
     }
 
-    /// Update the rulebook reference (used by hot-reload)
-    #[allow(dead_code)]
+    /// Latency metrics of each second-opinion judge, by name
+    pub fn second_opinion_metrics(&self) -> Vec<(&str, &ProviderMetrics)> {
+        self.escalation
+            .iter()
+            .flat_map(|e| e.judge_metrics())
+            .collect()
+    }
+
+    /// Update the rulebook reference and recompile the local rules (used by hot-reload)
     pub fn update_rulebook(&self, new_rulebook: Rulebook) -> tokio::task::JoinHandle<()> {
+        let engine = RuleEngine::new(&new_rulebook);
+        tracing::info!("Local rule engine compiled {} rules", engine.len());
+        *self.engine.write().unwrap() = Arc::new(engine);
+
+        // The LLM sees the new rules before cache keys move to their
+        // fingerprint, so a verdict given under the previous rules is never
+        // stored under the new ones (keys are taken before the rules)
+        let fingerprint = RulesFingerprint::of(&new_rulebook, Utc::now());
+        *self.rules_snapshot.write().unwrap() = Arc::new(new_rulebook.clone());
+        *self.rulebook_fingerprint.write().unwrap() = fingerprint;
+
         let rulebook = Arc::clone(&self.rulebook);
         tokio::spawn(async move {
             let mut rb = rulebook.write().await;
@@ -185,13 +875,14 @@ impl Judge {
 mod tests {
     use super::*;
     use crate::llm::client::mock::MockLlmProvider;
+    use crate::llm::prompts::PromptTemplates;
     use std::collections::HashMap;
 
     #[tokio::test]
     async fn test_judge_with_mock_llm() {
         let llm = Arc::new(MockLlmProvider::new());
         let rulebook = Arc::new(RwLock::new(Rulebook::new()));
-        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open);
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open).await;
 
         let payload = RequestPayload::new(
             "GET".to_string(),
@@ -210,7 +901,7 @@ mod tests {
     async fn test_judge_block_decision() {
         let llm = Arc::new(MockLlmProvider::new().with_block());
         let rulebook = Arc::new(RwLock::new(Rulebook::new()));
-        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open);
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open).await;
 
         let payload = RequestPayload::new(
             "GET".to_string(),
@@ -225,13 +916,703 @@ mod tests {
         assert!(decision.is_block());
     }
 
+    #[tokio::test]
+    async fn test_local_rule_decides_before_llm() {
+        use crate::core::rulebook::Rule;
+        use std::sync::atomic::Ordering;
+
+        let llm = Arc::new(MockLlmProvider::new());
+        let mut rules = Rulebook::new();
+        rules.add_rule(
+            Rule::new(
+                r"drop\s+table".to_string(),
+                "sqli".to_string(),
+                0.95,
+                RuleAction::Block,
+                "test".to_string(),
+            )
+            .with_threat_level(ThreatLevel::Critical),
+        );
+        let mut expired = Rule::new(
+            "/admin".to_string(),
+            "access".to_string(),
+            0.9,
+            RuleAction::Block,
+            "test".to_string(),
+        );
+        expired.expires_at = Some(Utc::now() - chrono::Duration::minutes(1));
+        rules.add_rule(expired);
+
+        let rulebook = Arc::new(RwLock::new(rules));
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open).await;
+
+        let payload = RequestPayload::new(
+            "POST".to_string(),
+            "/admin".to_string(),
+            HashMap::new(),
+            Some("'; DROP TABLE users--".to_string()),
+            HashMap::new(),
+            None,
+        );
+        let decision = judge.evaluate(payload).await;
+        assert!(matches!(
+            decision,
+            JudgeDecision::Block {
+                confidence: 0.95,
+                threat_level: ThreatLevel::Critical,
+                ..
+            }
+        ));
+
+        // The expired rule no longer matches, so the (allowing) mock LLM decides
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/admin".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+        let decision = judge.evaluate(payload).await;
+        assert!(matches!(decision, JudgeDecision::Allow { .. }));
+
+        assert_eq!(judge.metrics().local_rule_hits.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_local_flag_rule_still_asks_llm() {
+        use crate::core::rulebook::Rule;
+
+        let rules = || {
+            let mut rules = Rulebook::new();
+            rules.add_rule(Rule::new(
+                "/export".to_string(),
+                "exfiltration".to_string(),
+                0.6,
+                RuleAction::Flag,
+                "test".to_string(),
+            ));
+            Arc::new(RwLock::new(rules))
+        };
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/export".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        // The LLM allows: the flag rule keeps the request flagged
+        let judge = Judge::new(
+            Arc::new(MockLlmProvider::new()),
+            None,
+            rules(),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await;
+        let decision = judge.evaluate(payload.clone()).await;
+        assert!(matches!(decision, JudgeDecision::Flag { confidence, .. } if confidence == 0.6));
+
+        // The LLM blocks: a flag rule doesn't stand in its way
+        let judge = Judge::new(
+            Arc::new(MockLlmProvider::new().with_block()),
+            None,
+            rules(),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await;
+        assert!(judge.evaluate(payload).await.is_block());
+    }
+
+    fn anomaly_scoring() -> ScoringConfig {
+        ScoringConfig {
+            mode: DecisionMode::Anomaly,
+            routes: vec![crate::config::RouteThresholds {
+                path_prefix: "/admin".to_string(),
+                block_threshold: 3.0,
+                flag_threshold: 1.0,
+            }],
+            ..ScoringConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_anomaly_mode_adds_up_rule_hits() {
+        use crate::core::rulebook::Rule;
+
+        let llm = Arc::new(MockLlmProvider::new());
+        let mut rules = Rulebook::new();
+        for pattern in ["union", "select"] {
+            rules.add_rule(Rule::new(
+                pattern.to_string(),
+                "sqli".to_string(),
+                0.6,
+                RuleAction::Block,
+                "test".to_string(),
+            ));
+        }
+        let rulebook = Arc::new(RwLock::new(rules));
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open)
+            .await
+            .with_scoring(anomaly_scoring());
+
+        let request = |path: &str, body: &str| {
+            RequestPayload::new(
+                "POST".to_string(),
+                path.to_string(),
+                HashMap::new(),
+                Some(body.to_string()),
+                HashMap::new(),
+                None,
+            )
+        };
+
+        // One hit (3.0) flags, two hits (6.0) cross the default block threshold
+        let evaluation = judge.evaluate_scored(request("/users", "union")).await;
+        assert!(matches!(evaluation.decision, JudgeDecision::Flag { .. }));
+        let score = evaluation.score.unwrap();
+        assert_eq!(score.total, 3.0);
+        assert_eq!(score.contributions.len(), 1);
+
+        let evaluation = judge
+            .evaluate_scored(request("/users", "union select"))
+            .await;
+        assert!(evaluation.decision.is_block());
+        assert_eq!(evaluation.score.unwrap().total, 6.0);
+
+        // Stricter per-route threshold
+        let evaluation = judge
+            .evaluate_scored(request("/admin/users", "union"))
+            .await;
+        assert!(evaluation.decision.is_block());
+        assert_eq!(evaluation.score.unwrap().block_threshold, 3.0);
+
+        let evaluation = judge.evaluate_scored(request("/users", "hello")).await;
+        assert!(matches!(evaluation.decision, JudgeDecision::Allow { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_anomaly_mode_adds_llm_verdict() {
+        let llm = Arc::new(MockLlmProvider::new().with_block());
+        let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open)
+            .await
+            .with_scoring(anomaly_scoring());
+
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/search".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        // The mock blocks with confidence 0.9 at high threat: 3.6 blocks
+        let evaluation = judge.evaluate_scored(payload).await;
+        assert!(evaluation.decision.is_block());
+        let score = evaluation.score.unwrap();
+        assert_eq!(score.contributions[0].source, "llm");
+        assert!((score.total - 3.6).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_anomaly_mode_default_thresholds() {
+        use crate::core::rulebook::Rule;
+
+        let mut rules = Rulebook::new();
+        rules.add_rule(Rule::new(
+            "union select".to_string(),
+            "sqli".to_string(),
+            0.95,
+            RuleAction::Block,
+            "test".to_string(),
+        ));
+        let mut flag = Rule::new(
+            "<script".to_string(),
+            "xss".to_string(),
+            0.9,
+            RuleAction::Flag,
+            "test".to_string(),
+        );
+        flag.threat_level = Some(ThreatLevel::Medium);
+        rules.add_rule(flag);
+        let scoring = ScoringConfig {
+            mode: DecisionMode::Anomaly,
+            ..ScoringConfig::default()
+        };
+        let judge = Judge::new(
+            Arc::new(MockLlmProvider::new()),
+            None,
+            Arc::new(RwLock::new(rules)),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await
+        .with_scoring(scoring.clone());
+
+        let request = |q: &str| {
+            RequestPayload::new(
+                "GET".to_string(),
+                "/search".to_string(),
+                HashMap::new(),
+                None,
+                HashMap::from([("q".to_string(), q.to_string())]),
+                None,
+            )
+        };
+
+        // A confident critical rule hit blocks on its own
+        let evaluation = judge.evaluate_scored(request("1 union select 2")).await;
+        assert!(evaluation.decision.is_block());
+        assert!((evaluation.score.unwrap().total - 4.75).abs() < 1e-6);
+
+        // A medium one only flags
+        let evaluation = judge.evaluate_scored(request("<script>")).await;
+        assert!(matches!(evaluation.decision, JudgeDecision::Flag { .. }));
+
+        // A high-threat LLM block also blocks without any rule hit
+        let judge = Judge::new(
+            Arc::new(MockLlmProvider::new().with_block()),
+            None,
+            Arc::new(RwLock::new(Rulebook::new())),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await
+        .with_scoring(scoring);
+        assert!(judge.evaluate(request("hello")).await.is_block());
+    }
+
+    #[tokio::test]
+    async fn test_anomaly_mode_fail_open_keeps_rule_score() {
+        use crate::core::rulebook::Rule;
+
+        let llm = Arc::new(MockLlmProvider::new().with_error());
+        let mut rules = Rulebook::new();
+        rules.add_rule(Rule::new(
+            "<script".to_string(),
+            "xss".to_string(),
+            1.0,
+            RuleAction::Flag,
+            "test".to_string(),
+        ));
+        let rulebook = Arc::new(RwLock::new(rules));
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open)
+            .await
+            .with_scoring(anomaly_scoring());
+
+        let mut query = HashMap::new();
+        query.insert("q".to_string(), "<script>".to_string());
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/search".to_string(),
+            HashMap::new(),
+            None,
+            query,
+            None,
+        );
+
+        let decision = judge.evaluate(payload).await;
+        assert!(matches!(decision, JudgeDecision::Flag { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_local_honors_pinned_verdicts() {
+        use crate::core::rulebook::Rule;
+
+        let mut rules = Rulebook::new();
+        rules.add_rule(Rule::new(
+            "select".to_string(),
+            "sqli".to_string(),
+            0.95,
+            RuleAction::Block,
+            "test".to_string(),
+        ));
+        let judge = Judge::new(
+            Arc::new(MockLlmProvider::new()),
+            None,
+            Arc::new(RwLock::new(rules)),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await;
+        let request = |q: &str| {
+            RequestPayload::new(
+                "GET".to_string(),
+                "/feed".to_string(),
+                HashMap::new(),
+                None,
+                HashMap::from([("q".to_string(), q.to_string())]),
+                None,
+            )
+        };
+
+        // An allow pinned by a false-positive label wins over the block rule
+        let labeled = request("select a plan");
+        assert!(judge.evaluate_local(&labeled).is_some());
+        judge
+            .pin_verdict(
+                &labeled.normalized_hash,
+                JudgeDecision::Allow { confidence: 1.0 },
+            )
+            .await;
+        assert!(judge.evaluate_local(&labeled).is_none());
+
+        // A pinned block stops the request without any rule hit
+        let missed = request("hello");
+        assert!(judge.evaluate_local(&missed).is_none());
+        judge
+            .pin_verdict(
+                &missed.normalized_hash,
+                JudgeDecision::Block {
+                    confidence: 1.0,
+                    reason: "labeled".to_string(),
+                    threat_level: ThreatLevel::High,
+                },
+            )
+            .await;
+        assert!(judge.evaluate_local(&missed).unwrap().decision.is_block());
+    }
+
+    #[tokio::test]
+    async fn test_evaluation_records_prompt_version() {
+        let llm = Arc::new(MockLlmProvider::new().with_prompt_version("3+5"));
+        let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open).await;
+
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/api/users".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        let evaluation = judge.evaluate_scored(payload).await;
+        assert_eq!(evaluation.prompt_version.as_deref(), Some("3+5"));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_version_only_for_llm_verdicts() {
+        let cache: Arc<dyn VerdictCache> = Arc::new(MemoryCache::new(100, Duration::from_secs(60)));
+        let judge_with = |llm: MockLlmProvider| {
+            let cache = Arc::clone(&cache);
+            async move {
+                let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+                Judge::new(
+                    Arc::new(llm),
+                    Some(cache),
+                    rulebook,
+                    Duration::from_secs(1),
+                    FailMode::Closed,
+                )
+                .await
+            }
+        };
+        let payload = |path: &str| {
+            RequestPayload::new(
+                "GET".to_string(),
+                path.to_string(),
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                None,
+            )
+        };
+
+        // A cached verdict keeps the version of the prompts that produced it
+        let old = judge_with(MockLlmProvider::new().with_prompt_version("3+5")).await;
+        old.evaluate_scored(payload("/feed")).await;
+        let new = judge_with(MockLlmProvider::new().with_prompt_version("4+5")).await;
+        let evaluation = new.evaluate_scored(payload("/feed")).await;
+        assert_eq!(evaluation.prompt_version.as_deref(), Some("3+5"));
+
+        // Pinned and fail-mode decisions didn't come from the LLM
+        let pinned = payload("/pinned");
+        new.pin_verdict(
+            &pinned.normalized_hash,
+            JudgeDecision::Allow { confidence: 1.0 },
+        )
+        .await;
+        assert_eq!(new.evaluate_scored(pinned).await.prompt_version, None);
+
+        let failing = judge_with(
+            MockLlmProvider::new()
+                .with_error()
+                .with_prompt_version("4+5"),
+        )
+        .await;
+        let evaluation = failing.evaluate_scored(payload("/other")).await;
+        assert!(evaluation.decision.is_block());
+        assert_eq!(evaluation.prompt_version, None);
+    }
+
+    #[tokio::test]
+    async fn test_judge_learns_and_shows_examples() {
+        use crate::config::FewShotConfig;
+        use crate::storage::logs::{EventStore, LogStore};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("test.db"))
+                .await
+                .unwrap(),
+        );
+        let examples = Arc::new(ExampleIndex::new(
+            Arc::clone(&logs),
+            FewShotConfig {
+                include_unverified: true,
+                ..FewShotConfig::default()
+            },
+        ));
+        let llm = Arc::new(MockLlmProvider::new().with_block());
+        let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+        let judge = Judge::new(
+            llm.clone(),
+            None,
+            rulebook,
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await
+        .with_examples(Arc::clone(&examples));
+
+        let request = |id: &str| {
+            RequestPayload::new(
+                "GET".to_string(),
+                format!("/api/users/{}", id),
+                HashMap::new(),
+                None,
+                HashMap::from([("sort".to_string(), "name; DROP TABLE users".to_string())]),
+                None,
+            )
+        };
+
+        judge.evaluate(request("1")).await;
+        assert!(llm.last_examples().is_empty());
+
+        // The verdict is recorded in the background
+        for _ in 0..50 {
+            let stored = logs
+                .examples_for_template("GET", "/api/users/{id}", 10)
+                .await
+                .unwrap();
+            if !stored.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        judge.evaluate(request("2")).await;
+        let shown = llm.last_examples();
+        assert_eq!(shown.len(), 1);
+        assert_eq!(shown[0].decision, "block");
+        assert_eq!(shown[0].reason.as_deref(), Some("Mock block"));
+    }
+
+    #[tokio::test]
+    async fn test_judge_goes_on_without_examples_when_selection_is_slow() {
+        use crate::config::FewShotConfig;
+        use crate::storage::logs::{EventStore, LogStore};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("test.db"))
+                .await
+                .unwrap(),
+        );
+        // Any database round trip takes longer than this
+        let examples = Arc::new(ExampleIndex::new(
+            Arc::clone(&logs),
+            FewShotConfig {
+                select_timeout_ms: 0,
+                ..FewShotConfig::default()
+            },
+        ));
+        let request = || {
+            RequestPayload::new(
+                "GET".to_string(),
+                "/api/users/1".to_string(),
+                HashMap::new(),
+                None,
+                HashMap::from([("sort".to_string(), "name; DROP TABLE users".to_string())]),
+                None,
+            )
+        };
+        examples
+            .record(
+                &request(),
+                &JudgeDecision::Block {
+                    confidence: 1.0,
+                    reason: "SQL injection".to_string(),
+                    threat_level: ThreatLevel::High,
+                },
+                ExampleSource::Corrected,
+            )
+            .await
+            .unwrap();
+
+        let llm = Arc::new(MockLlmProvider::new().with_block());
+        let judge = Judge::new(
+            llm.clone(),
+            None,
+            Arc::new(RwLock::new(Rulebook::new())),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await
+        .with_examples(examples);
+
+        let decision = judge.evaluate(request()).await;
+        assert!(decision.is_block());
+        assert!(llm.last_examples().is_empty());
+    }
+
+    /// Regression corpus of prompt-injection attempts
+    const INJECTION_CORPUS: &str = include_str!("../../tests/fixtures/prompt_injection.txt");
+
+    #[tokio::test]
+    async fn test_prompt_injection_corpus_is_never_allowed() {
+        use std::sync::atomic::Ordering;
+
+        // The mock LLM allows everything, like a judge that has been steered
+        let llm = Arc::new(MockLlmProvider::new());
+        let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open).await;
+        let anomaly = Judge::new(
+            Arc::new(MockLlmProvider::new()),
+            None,
+            Arc::new(RwLock::new(Rulebook::new())),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await
+        .with_scoring(ScoringConfig {
+            mode: DecisionMode::Anomaly,
+            ..ScoringConfig::default()
+        });
+
+        let attempts: Vec<&str> = INJECTION_CORPUS
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .collect();
+        assert!(attempts.len() >= 20);
+
+        for attempt in &attempts {
+            let mut headers = HashMap::new();
+            headers.insert("x-comment".to_string(), attempt.to_string());
+            let mut query = HashMap::new();
+            query.insert("q".to_string(), attempt.to_string());
+
+            for payload in [
+                RequestPayload::new(
+                    "POST".to_string(),
+                    "/comments".to_string(),
+                    HashMap::new(),
+                    Some(attempt.to_string()),
+                    HashMap::new(),
+                    None,
+                ),
+                RequestPayload::new(
+                    "GET".to_string(),
+                    "/search".to_string(),
+                    HashMap::new(),
+                    None,
+                    query.clone(),
+                    None,
+                ),
+                RequestPayload::new(
+                    "GET".to_string(),
+                    "/".to_string(),
+                    headers.clone(),
+                    None,
+                    HashMap::new(),
+                    None,
+                ),
+            ] {
+                let prompt = PromptTemplates::builtin()
+                    .judge_prompt(&payload, &Rulebook::new(), &[])
+                    .unwrap();
+                assert_eq!(
+                    prompt.matches("</request_data>").count(),
+                    1,
+                    "attempt escaped the data block: {}",
+                    attempt
+                );
+
+                let decision = judge.evaluate(payload.clone()).await;
+                assert!(
+                    matches!(decision, JudgeDecision::Flag { .. }),
+                    "attempt was not flagged: {}",
+                    attempt
+                );
+                let decision = anomaly.evaluate(payload).await;
+                assert!(
+                    !matches!(decision, JudgeDecision::Allow { .. }),
+                    "attempt was allowed in anomaly mode: {}",
+                    attempt
+                );
+            }
+        }
+
+        assert_eq!(
+            judge.metrics().steering_detections.load(Ordering::Relaxed),
+            attempts.len() as u64 * 3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_rulebook_recompiles_local_rules() {
+        use crate::core::rulebook::Rule;
+
+        let llm = Arc::new(MockLlmProvider::new());
+        let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+        let judge = Judge::new(
+            llm,
+            None,
+            Arc::clone(&rulebook),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await;
+
+        let mut rules = Rulebook::new();
+        rules.add_rule(Rule::new(
+            "<script".to_string(),
+            "xss".to_string(),
+            0.7,
+            RuleAction::Flag,
+            "test".to_string(),
+        ));
+        judge.update_rulebook(rules).await.unwrap();
+        assert_eq!(rulebook.read().await.rules.len(), 1);
+
+        let mut query = HashMap::new();
+        query.insert("q".to_string(), "<script>alert(1)</script>".to_string());
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/search".to_string(),
+            HashMap::new(),
+            None,
+            query,
+            None,
+        );
+        let decision = judge.evaluate(payload).await;
+        assert!(matches!(decision, JudgeDecision::Flag { .. }));
+    }
+
     #[tokio::test]
     async fn test_metrics_tracking() {
         use std::sync::atomic::Ordering;
 
         let llm = Arc::new(MockLlmProvider::new());
         let rulebook = Arc::new(RwLock::new(Rulebook::new()));
-        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open);
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open).await;
 
         let payload = RequestPayload::new(
             "GET".to_string(),
@@ -247,6 +1628,241 @@ mod tests {
         assert_eq!(judge.metrics().total_requests.load(Ordering::Relaxed), 1);
     }
 
+    #[tokio::test]
+    async fn test_identical_requests_share_one_llm_call() {
+        use std::sync::atomic::Ordering;
+
+        let llm = Arc::new(MockLlmProvider::new().with_delay(Duration::from_millis(100)));
+        let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+        let judge = Arc::new(
+            Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open)
+                .await
+                .with_memory_cache(MemoryCache::new(100, Duration::from_secs(60))),
+        );
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/feed".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..20 {
+            let judge = Arc::clone(&judge);
+            let payload = payload.clone();
+            tasks.spawn(async move { judge.evaluate(payload).await });
+        }
+        while let Some(decision) = tasks.join_next().await {
+            assert!(matches!(decision.unwrap(), JudgeDecision::Allow { .. }));
+        }
+
+        let metrics = judge.metrics();
+        assert_eq!(metrics.llm.calls.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.coalesced_requests.load(Ordering::Relaxed), 19);
+
+        // Later identical requests are served by the in-process tier
+        judge.evaluate(payload).await;
+        assert_eq!(metrics.llm.calls.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.memory_cache_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.cache_hits.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reload_does_not_wait_for_llm_in_flight() {
+        let llm = MockLlmProvider::new().with_delay(Duration::from_secs(2));
+        let rulebook = Arc::new(RwLock::new(Rulebook::new()));
+        let judge = Arc::new(
+            Judge::new(
+                Arc::new(llm),
+                None,
+                Arc::clone(&rulebook),
+                Duration::from_secs(5),
+                FailMode::Open,
+            )
+            .await,
+        );
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/feed".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        let in_flight = judge.evaluate_in_background(payload);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut rules = Rulebook::new();
+        rules.version = 2;
+        tokio::time::timeout(Duration::from_millis(500), judge.update_rulebook(rules))
+            .await
+            .expect("reload waited for the LLM")
+            .unwrap();
+        let reloaded = tokio::time::timeout(Duration::from_millis(500), rulebook.read())
+            .await
+            .expect("readers queued behind the reload");
+        assert_eq!(reloaded.version, 2);
+        in_flight.abort();
+    }
+
+    #[tokio::test]
+    async fn test_verdict_in_flight_during_reload_is_not_served() {
+        use crate::core::rulebook::Rule;
+        use std::sync::atomic::Ordering;
+
+        let llm = MockLlmProvider::new().with_delay(Duration::from_millis(200));
+        let judge = Arc::new(
+            Judge::new(
+                Arc::new(llm),
+                None,
+                Arc::new(RwLock::new(Rulebook::new())),
+                Duration::from_secs(1),
+                FailMode::Open,
+            )
+            .await
+            .with_memory_cache(MemoryCache::new(100, Duration::from_secs(60))),
+        );
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/feed".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        // Reloaded while the LLM judges under the old rules
+        let in_flight = judge.evaluate_in_background(payload.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut changed = Rulebook::new();
+        changed.add_rule(Rule::new(
+            "union select".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "test".to_string(),
+        ));
+        let reload = judge.update_rulebook(changed);
+        in_flight.await.unwrap();
+        reload.await.unwrap();
+
+        judge.evaluate(payload).await;
+        assert_eq!(judge.metrics().llm.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rule_activation_retires_cached_verdicts() {
+        use crate::core::rulebook::Rule;
+        use std::sync::atomic::Ordering;
+
+        let mut rule = Rule::new(
+            "union select".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Flag,
+            "test".to_string(),
+        );
+        rule.not_before = Some(Utc::now() + chrono::Duration::milliseconds(200));
+        let mut rules = Rulebook::new();
+        rules.add_rule(rule);
+        let judge = Judge::new(
+            Arc::new(MockLlmProvider::new()),
+            None,
+            Arc::new(RwLock::new(rules)),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await
+        .with_memory_cache(MemoryCache::new(100, Duration::from_secs(60)));
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/feed".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        judge.evaluate(payload.clone()).await;
+        judge.evaluate(payload.clone()).await;
+        assert_eq!(judge.metrics().llm.calls.load(Ordering::Relaxed), 1);
+
+        // The rule activates: the verdict was given without it
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        judge.evaluate(payload).await;
+        assert_eq!(judge.metrics().llm.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rulebook_reload_retires_cached_verdicts() {
+        use crate::core::rulebook::Rule;
+        use std::sync::atomic::Ordering;
+
+        let cache: Arc<dyn VerdictCache> = Arc::new(MemoryCache::new(100, Duration::from_secs(60)));
+        let judge_with = |rules: Rulebook| {
+            let cache = Arc::clone(&cache);
+            async move {
+                Judge::new(
+                    Arc::new(MockLlmProvider::new()),
+                    Some(cache),
+                    Arc::new(RwLock::new(rules)),
+                    Duration::from_secs(1),
+                    FailMode::Open,
+                )
+                .await
+            }
+        };
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/feed".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+        let rule = Rule::new(
+            "union select".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "test".to_string(),
+        );
+
+        let judge = judge_with(Rulebook::new()).await;
+        judge.evaluate(payload.clone()).await;
+        judge.evaluate(payload.clone()).await;
+        assert_eq!(judge.metrics().llm.calls.load(Ordering::Relaxed), 1);
+
+        // A restarted or other replica with the same rules shares the verdict
+        let replica = judge_with(Rulebook::new()).await;
+        replica.evaluate(payload.clone()).await;
+        assert_eq!(replica.metrics().llm.calls.load(Ordering::Relaxed), 0);
+
+        // Reloading the same rules keeps it, whatever their version
+        let mut same = Rulebook::new();
+        same.version = 7;
+        judge.update_rulebook(same).await.unwrap();
+        judge.evaluate(payload.clone()).await;
+        assert_eq!(judge.metrics().llm.calls.load(Ordering::Relaxed), 1);
+
+        // Changed rules retire it, even when the file keeps its version
+        let mut changed = Rulebook::new();
+        changed.rules.push(rule.clone());
+        judge.update_rulebook(changed).await.unwrap();
+        judge.evaluate(payload.clone()).await;
+        assert_eq!(judge.metrics().llm.calls.load(Ordering::Relaxed), 2);
+
+        // ...also for a replica starting with them
+        let mut changed = Rulebook::new();
+        changed.rules.push(rule);
+        let replica = judge_with(changed).await;
+        replica.evaluate(payload).await;
+        assert_eq!(replica.metrics().llm.calls.load(Ordering::Relaxed), 0);
+    }
+
     #[tokio::test]
     async fn test_fail_mode_open() {
         use std::sync::atomic::Ordering;
@@ -254,7 +1870,7 @@ mod tests {
         // Mock LLM that always fails
         let llm = Arc::new(MockLlmProvider::new().with_error());
         let rulebook = Arc::new(RwLock::new(Rulebook::new()));
-        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open);
+        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Open).await;
 
         let payload = RequestPayload::new(
             "GET".to_string(),
@@ -277,7 +1893,14 @@ mod tests {
         // Mock LLM that always fails
         let llm = Arc::new(MockLlmProvider::new().with_error());
         let rulebook = Arc::new(RwLock::new(Rulebook::new()));
-        let judge = Judge::new(llm, None, rulebook, Duration::from_secs(1), FailMode::Closed);
+        let judge = Judge::new(
+            llm,
+            None,
+            rulebook,
+            Duration::from_secs(1),
+            FailMode::Closed,
+        )
+        .await;
 
         let payload = RequestPayload::new(
             "GET".to_string(),
@@ -292,4 +1915,73 @@ mod tests {
         assert!(decision.is_block());
         assert_eq!(judge.metrics().fail_closed_count.load(Ordering::Relaxed), 1);
     }
+
+    #[tokio::test]
+    async fn test_escalation_downgrades_unconfirmed_block() {
+        use crate::config::{AgreementPolicy, EscalationConfig};
+        use std::sync::atomic::Ordering;
+
+        let escalation = |judges: Vec<MockLlmProvider>| {
+            let config = EscalationConfig {
+                enabled: true,
+                max_confidence: 1.0,
+                policy: AgreementPolicy::Majority,
+                ..EscalationConfig::default()
+            };
+            judges
+                .into_iter()
+                .enumerate()
+                .fold(Escalation::new(config), |e, (i, llm)| {
+                    e.with_judge(&format!("second-{}", i), Arc::new(llm))
+                })
+        };
+        let judge = |judges| {
+            let escalation = escalation(judges);
+            async move {
+                Judge::new(
+                    Arc::new(MockLlmProvider::new().with_block()),
+                    None,
+                    Arc::new(RwLock::new(Rulebook::new())),
+                    Duration::from_secs(1),
+                    FailMode::Open,
+                )
+                .await
+                .with_escalation(escalation)
+            }
+        };
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/admin".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+
+        let confirmed = judge(vec![
+            MockLlmProvider::new().with_block(),
+            MockLlmProvider::new().with_block(),
+        ])
+        .await;
+        assert!(confirmed.evaluate(payload.clone()).await.is_block());
+        let metrics = confirmed.metrics();
+        assert_eq!(metrics.escalations_confirmed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.llm.calls.load(Ordering::Relaxed), 1);
+        assert_eq!(confirmed.second_opinion_metrics().len(), 2);
+
+        let overturned = judge(vec![
+            MockLlmProvider::new(),
+            MockLlmProvider::new().with_block(),
+        ])
+        .await;
+        let decision = overturned.evaluate(payload).await;
+        assert!(matches!(decision, JudgeDecision::Flag { .. }));
+        assert_eq!(
+            overturned
+                .metrics()
+                .escalations_overturned
+                .load(Ordering::Relaxed),
+            1
+        );
+    }
 }
@@ -1,31 +1,58 @@
-use crate::core::rulebook::{Rule, Rulebook};
+use crate::core::clustering::cluster_flagged;
+use crate::core::dedupe::{self, Backtest};
+use crate::core::engine::RuleEngine;
+use crate::core::rulebook::{MatchInput, Rule, Rulebook};
+use crate::core::validator;
 use crate::llm::client::LlmProvider;
 use crate::models::decision::LearnerOutput;
-use crate::storage::logs::LogStore;
+use crate::models::label::LabelKind;
+use crate::models::request::LogEntry;
+use crate::storage::logs::EventStore;
 use crate::storage::rules::RulebookStore;
 use anyhow::{Context, Result};
+use chrono::Utc;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::time::interval;
 
+/// Maximum number of recent events used to backtest rules for duplicates
+const BACKTEST_SAMPLE_LIMIT: i64 = 1000;
+
+/// Maximum number of flagged events carried over to the next batch when
+/// there are more clusters than `max_clusters_per_batch`; the oldest go first
+const MAX_CARRIED_OVER_EVENTS: usize = 5000;
+
+/// Confidence a weakened rule never goes below; rules already there are
+/// removed when a false positive is reported against them
+const MIN_WEAKENED_CONFIDENCE: f32 = 0.3;
+
 /// The Learner service runs periodically in batch mode to analyze flagged requests
 /// and generate new rules or modify existing ones based on observed patterns.
+/// Flagged requests are clustered first and each cluster gets its own LLM call.
+/// Human labels are taken into account: false negatives are learned from like
+/// flagged requests, and the rules matching false positives are weakened.
+/// Clusters left over by the per-batch cap are carried to the next batch.
 pub struct Learner {
     llm: Arc<dyn LlmProvider>,
-    logs: Arc<LogStore>,
+    logs: Arc<dyn EventStore>,
     rules_store: Arc<RulebookStore>,
     batch_interval: Duration,
     min_flagged_requests: usize,
+    max_clusters_per_batch: usize,
     last_run_timestamp: std::sync::Arc<std::sync::RwLock<i64>>,
+    /// Flagged events of the clusters the last batch had no room for
+    carried_over: std::sync::Arc<std::sync::Mutex<Vec<LogEntry>>>,
 }
 
 impl Learner {
     pub fn new(
         llm: Arc<dyn LlmProvider>,
-        logs: Arc<LogStore>,
+        logs: Arc<dyn EventStore>,
         rules_store: Arc<RulebookStore>,
         batch_interval: Duration,
         min_flagged_requests: usize,
+        max_clusters_per_batch: usize,
     ) -> Self {
         let current_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -38,7 +65,9 @@ impl Learner {
             rules_store,
             batch_interval,
             min_flagged_requests,
+            max_clusters_per_batch,
             last_run_timestamp: Arc::new(std::sync::RwLock::new(current_time)),
+            carried_over: Arc::new(std::sync::Mutex::new(Vec::new())),
         }
     }
 
@@ -46,23 +75,52 @@ impl Learner {
     pub async fn run_batch(&self) -> Result<()> {
         tracing::info!("Starting learner batch");
 
-        // Step 1: Get the timestamp of last run
+        // Step 1: Get the timestamp of last run. Events logged while the
+        // batch runs are left for the next one.
         let last_run = {
             let timestamp = self.last_run_timestamp.read().unwrap();
             *timestamp
         };
+        let batch_started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
 
-        // Step 2: Fetch flagged events since last run
-        let flagged = self
+        // Step 2: Fetch flagged events since last run, after the ones carried
+        // over from the last batch
+        let mut flagged = self.carried_over.lock().unwrap().clone();
+        let mut flagged_ids: HashSet<i64> = flagged.iter().map(|event| event.id).collect();
+        for event in self
             .logs
             .get_flagged_since(last_run)
             .await
-            .with_context(|| "Failed to fetch flagged events")?;
+            .with_context(|| "Failed to fetch flagged events")?
+        {
+            if flagged_ids.insert(event.id) {
+                flagged.push(event);
+            }
+        }
 
-        tracing::info!("Found {} flagged requests since last run", flagged.len());
+        // Step 3: Apply labels since last run. Labeled events replace their
+        // flagged copy: false negatives are learned from, false positives not.
+        let (false_positives, false_negatives) = self.labeled_events(last_run).await?;
+        let labeled_ids: HashSet<i64> = false_positives
+            .iter()
+            .chain(&false_negatives)
+            .map(|event| event.id)
+            .collect();
+        flagged.retain(|event| !labeled_ids.contains(&event.id));
+        flagged.extend(false_negatives);
 
-        // Step 3: Check if we have enough data
-        if flagged.len() < self.min_flagged_requests {
+        tracing::info!(
+            "Found {} flagged requests and {} false positives since last run",
+            flagged.len(),
+            false_positives.len()
+        );
+
+        // Step 4: Check if we have enough data
+        let enough_flagged = flagged.len() >= self.min_flagged_requests;
+        if !enough_flagged && false_positives.is_empty() {
             tracing::info!(
                 "Not enough flagged requests ({} < {}), skipping batch",
                 flagged.len(),
@@ -71,63 +129,196 @@ impl Learner {
             return Ok(());
         }
 
-        // Step 4: Load current rulebook
+        // Step 5: Load current rulebook
+        // Strictly: saving a rulebook with its invalid rules skipped would
+        // delete them from the file
         let current_rules = self
             .rules_store
-            .load()
+            .load_strict()
             .await
             .with_context(|| "Failed to load rulebook")?;
 
         tracing::info!("Current rulebook has {} rules", current_rules.rules.len());
 
-        // Step 5: Call LLM learner
-        let output = self
-            .llm
-            .learn_rules(flagged, &current_rules)
-            .await
-            .with_context(|| "Failed to learn rules from LLM")?;
+        // Step 6: Weaken or remove the rules matching false positives
+        let mut new_rulebook = current_rules.clone();
+        if !false_positives.is_empty() {
+            let output = false_positive_changes(&new_rulebook, &false_positives);
+            for rationale in &output.rationales {
+                tracing::info!("Learner rationale: {}", rationale);
+            }
+            new_rulebook = self.apply_changes(&new_rulebook, &output, &mut Backtest::default())?;
+        }
+
+        // Step 7: Group flagged requests into clusters of similar requests
+        let clusters = if enough_flagged {
+            cluster_flagged(&flagged)
+        } else {
+            Vec::new()
+        };
 
         tracing::info!(
-            "LLM suggested {} new rules, {} rules to weaken, {} rules to remove",
-            output.new_rules.len(),
-            output.weaken_rules.len(),
-            output.remove_rules.len()
+            "Grouped {} flagged requests into {} clusters (analyzing up to {})",
+            flagged.len(),
+            clusters.len(),
+            self.max_clusters_per_batch
         );
 
-        // Step 6: Apply changes to rulebook
-        let new_rulebook = self.apply_changes(&current_rules, &output)?;
+        // Recent events (all decisions) used to detect rules that match the same requests
+        let mut backtest = Backtest::new(
+            self.logs
+                .get_events_since(last_run, BACKTEST_SAMPLE_LIMIT)
+                .await
+                .with_context(|| "Failed to fetch backtest samples")?
+                .iter()
+                .map(MatchInput::from_log)
+                .collect(),
+        );
 
-        // Step 7: Save updated rulebook
-        self.rules_store
-            .save(&new_rulebook)
-            .await
-            .with_context(|| "Failed to save rulebook")?;
+        // Step 8: Call LLM learner once per cluster, largest first, applying changes as we go
+        let mut failures = 0;
+        let analyzed = clusters.len().min(self.max_clusters_per_batch);
+
+        let overflow_ids: HashSet<i64> = clusters
+            .iter()
+            .skip(self.max_clusters_per_batch)
+            .flat_map(|cluster| cluster.event_ids.iter().copied())
+            .collect();
+        let mut carried_over: Vec<LogEntry> = flagged
+            .iter()
+            .filter(|event| overflow_ids.contains(&event.id))
+            .cloned()
+            .collect();
+        if carried_over.len() > MAX_CARRIED_OVER_EVENTS {
+            carried_over.sort_by_key(|event| std::cmp::Reverse(event.timestamp));
+            tracing::warn!(
+                "Dropping {} flagged requests left over by the cluster cap",
+                carried_over.len() - MAX_CARRIED_OVER_EVENTS
+            );
+            carried_over.truncate(MAX_CARRIED_OVER_EVENTS);
+        }
+        if !carried_over.is_empty() {
+            tracing::info!(
+                "Carrying {} flagged requests from {} clusters over to the next batch",
+                carried_over.len(),
+                clusters.len() - analyzed
+            );
+        }
 
-        tracing::info!(
-            "Rulebook updated: {} rules (was {})",
-            new_rulebook.rules.len(),
-            current_rules.rules.len()
-        );
+        for cluster in clusters.iter().take(self.max_clusters_per_batch) {
+            let output = match self.llm.learn_rules(cluster, &new_rulebook).await {
+                Ok(output) => output,
+                Err(e) => {
+                    failures += 1;
+                    tracing::warn!(
+                        error = %e,
+                        path_template = %cluster.path_template,
+                        size = cluster.size,
+                        "Learner call failed for cluster"
+                    );
+                    continue;
+                }
+            };
+
+            tracing::info!(
+                "Cluster {} {} ({} requests): LLM suggested {} new rules, {} rules to weaken, {} rules to remove",
+                cluster.method,
+                cluster.path_template,
+                cluster.size,
+                output.new_rules.len(),
+                output.weaken_rules.len(),
+                output.remove_rules.len()
+            );
+
+            new_rulebook = self.apply_changes(&new_rulebook, &output, &mut backtest)?;
 
-        // Log rationales
-        for rationale in &output.rationales {
-            tracing::info!("Learner rationale: {}", rationale);
+            // Log rationales
+            for rationale in &output.rationales {
+                tracing::info!("Learner rationale: {}", rationale);
+            }
+        }
+
+        if analyzed > 0 && failures == analyzed {
+            anyhow::bail!(
+                "Failed to learn rules from LLM for all {} clusters",
+                analyzed
+            );
+        }
+
+        // Step 9: Merge duplicate rules, including ones accumulated by earlier batches
+        let merged = dedupe::merge_duplicates(&mut new_rulebook, &mut backtest);
+        if merged > 0 {
+            tracing::info!("Merged {} duplicate rules", merged);
+        }
+
+        // Step 10: Save updated rulebook
+        if new_rulebook.version != current_rules.version {
+            self.rules_store
+                .save(&new_rulebook)
+                .await
+                .with_context(|| "Failed to save rulebook")?;
+
+            tracing::info!(
+                "Rulebook updated: {} rules (was {})",
+                new_rulebook.rules.len(),
+                current_rules.rules.len()
+            );
+        } else {
+            tracing::info!("No rulebook changes from this batch");
         }
 
-        // Step 8: Update last run timestamp
+        // Step 11: Update last run timestamp and keep the left-over clusters
         {
             let mut timestamp = self.last_run_timestamp.write().unwrap();
-            *timestamp = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64;
+            *timestamp = batch_started;
         }
+        *self.carried_over.lock().unwrap() = carried_over;
 
         Ok(())
     }
 
-    /// Apply learner output to current rulebook
-    fn apply_changes(&self, current_rules: &Rulebook, output: &LearnerOutput) -> Result<Rulebook> {
+    /// Events labeled since `since_timestamp`, as (false positives, false
+    /// negatives). False negatives are turned into flagged events carrying
+    /// the label note, so they are clustered with the other flagged requests.
+    async fn labeled_events(&self, since_timestamp: i64) -> Result<(Vec<LogEntry>, Vec<LogEntry>)> {
+        let labeled = self
+            .logs
+            .get_labeled_events_since(since_timestamp)
+            .await
+            .with_context(|| "Failed to fetch labeled events")?;
+
+        let mut false_positives = Vec::new();
+        let mut false_negatives: Vec<LogEntry> = Vec::new();
+        for (label, mut event) in labeled {
+            // Only the latest label of an event counts
+            false_positives.retain(|e: &LogEntry| e.id != event.id);
+            false_negatives.retain(|e| e.id != event.id);
+
+            match label.kind() {
+                Some(LabelKind::FalsePositive) => false_positives.push(event),
+                Some(LabelKind::FalseNegative) => {
+                    event.decision = "flag".to_string();
+                    event.reason = Some(match label.note {
+                        Some(note) => format!("Labeled false negative: {}", note),
+                        None => "Labeled false negative".to_string(),
+                    });
+                    false_negatives.push(event);
+                }
+                None => {}
+            }
+        }
+
+        Ok((false_positives, false_negatives))
+    }
+
+    /// Apply learner output to current rulebook. Suggestions duplicating an
+    /// existing rule (on the `backtest` samples) are merged into it.
+    fn apply_changes(
+        &self,
+        current_rules: &Rulebook,
+        output: &LearnerOutput,
+        backtest: &mut Backtest,
+    ) -> Result<Rulebook> {
         let mut new_rulebook = current_rules.clone();
 
         // Remove rules
@@ -141,7 +332,7 @@ impl Learner {
         for rule_id in &output.weaken_rules {
             if let Some(rule) = new_rulebook.rules.iter_mut().find(|r| r.id == *rule_id) {
                 let old_confidence = rule.confidence;
-                rule.confidence = (rule.confidence * 0.8).max(0.3); // Reduce by 20%, min 0.3
+                rule.confidence = (rule.confidence * 0.8).max(MIN_WEAKENED_CONFIDENCE); // Reduce by 20%
                 tracing::info!(
                     "Weakened rule {}: confidence {} -> {}",
                     rule_id,
@@ -162,6 +353,34 @@ impl Learner {
             )
             .with_description(suggestion.description.clone());
 
+            let issues = validator::validate_rule(&rule);
+            if validator::has_errors(&issues) {
+                for issue in &issues {
+                    tracing::warn!("Rejected suggested rule ({}): {}", rule.pattern, issue);
+                }
+                continue;
+            }
+
+            if let Some(idx) = dedupe::find_duplicate(&new_rulebook, &rule, backtest) {
+                let existing = &mut new_rulebook.rules[idx];
+                tracing::info!(
+                    "Merging suggested rule ({}) into duplicate rule {}",
+                    rule.pattern,
+                    existing.id
+                );
+                dedupe::merge_into(existing, rule);
+                new_rulebook.touch();
+                continue;
+            }
+
+            let issues = validator::validate_addition(&new_rulebook, &rule);
+            if !issues.is_empty() {
+                for issue in &issues {
+                    tracing::warn!("Rejected suggested rule ({}): {}", rule.pattern, issue);
+                }
+                continue;
+            }
+
             tracing::info!(
                 "Adding new rule: {} ({}) - action: {}",
                 rule.threat_type,
@@ -196,11 +415,45 @@ impl Learner {
     }
 }
 
+/// Changes for requests labeled false positives: every active rule matching
+/// one is weakened, or removed if already at `MIN_WEAKENED_CONFIDENCE`
+fn false_positive_changes(rulebook: &Rulebook, events: &[LogEntry]) -> LearnerOutput {
+    let engine = RuleEngine::new(rulebook);
+    let now = Utc::now();
+    let mut output = LearnerOutput {
+        new_rules: Vec::new(),
+        weaken_rules: Vec::new(),
+        remove_rules: Vec::new(),
+        rationales: Vec::new(),
+    };
+
+    for event in events {
+        let input = MatchInput::from_log(event);
+        for rule in engine.matching_rules(&input, now) {
+            if output.remove_rules.contains(&rule.id) {
+                continue;
+            }
+            if rule.confidence <= MIN_WEAKENED_CONFIDENCE {
+                output.remove_rules.push(rule.id.clone());
+            } else {
+                output.weaken_rules.push(rule.id.clone());
+            }
+            output.rationales.push(format!(
+                "Rule {} matched event {} ({} {}) labeled a false positive",
+                rule.id, event.id, event.method, event.path
+            ));
+        }
+    }
+
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::llm::client::mock::MockLlmProvider;
     use crate::models::decision::{RuleAction, RuleSuggestion};
+    use crate::storage::logs::LogStore;
 
     #[tokio::test]
     async fn test_learner_applies_changes() {
@@ -212,7 +465,14 @@ mod tests {
         let rules_store = Arc::new(RulebookStore::new(&rulebook_path).unwrap());
         let llm = Arc::new(MockLlmProvider::new());
 
-        let learner = Learner::new(llm, logs, rules_store.clone(), Duration::from_secs(60), 1);
+        let learner = Learner::new(
+            llm,
+            logs,
+            rules_store.clone(),
+            Duration::from_secs(60),
+            1,
+            10,
+        );
 
         // Create initial rulebook
         let mut initial_rulebook = Rulebook::new();
@@ -238,9 +498,328 @@ mod tests {
             rationales: vec!["Added SQLi rule".to_string()],
         };
 
-        let new_rulebook = learner.apply_changes(&initial_rulebook, &output).unwrap();
+        let new_rulebook = learner
+            .apply_changes(&initial_rulebook, &output, &mut Backtest::default())
+            .unwrap();
 
         assert_eq!(new_rulebook.rules.len(), 2);
         assert!(new_rulebook.rules.iter().any(|r| r.threat_type == "sqli"));
     }
+
+    #[tokio::test]
+    async fn test_apply_changes_rejects_invalid_and_merges_duplicate_suggestions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let rules_store =
+            Arc::new(RulebookStore::new(temp_dir.path().join("rulebook.json")).unwrap());
+        let learner = Learner::new(
+            Arc::new(MockLlmProvider::new()),
+            logs,
+            rules_store,
+            Duration::from_secs(60),
+            1,
+            10,
+        );
+
+        let mut initial_rulebook = Rulebook::new();
+        initial_rulebook.add_rule(Rule::new(
+            "union.*select".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "manual".to_string(),
+        ));
+
+        let suggestion = |pattern: &str| RuleSuggestion {
+            pattern: pattern.to_string(),
+            threat_type: "sqli".to_string(),
+            description: "test".to_string(),
+            confidence: 0.8,
+            action: RuleAction::Block,
+        };
+        let output = LearnerOutput {
+            new_rules: vec![
+                suggestion("(unclosed"),
+                suggestion(".*"),
+                suggestion(".+"),
+                suggestion("UNION.*SELECT"),
+                suggestion(r"sleep\(\d+\)"),
+            ],
+            weaken_rules: vec![],
+            remove_rules: vec![],
+            rationales: vec![],
+        };
+
+        let new_rulebook = learner
+            .apply_changes(&initial_rulebook, &output, &mut Backtest::default())
+            .unwrap();
+
+        assert_eq!(new_rulebook.rules.len(), 2);
+        assert_eq!(new_rulebook.rules[1].pattern, r"sleep\(\d+\)");
+
+        // The UNION.*SELECT suggestion was merged into the existing rule
+        let existing = &new_rulebook.rules[0];
+        assert_eq!(existing.confidence, 0.9);
+        assert_eq!(existing.merged_from.len(), 1);
+        assert_eq!(existing.merged_from[0].created_by, "llm");
+    }
+
+    /// Logs flagged requests forming two clusters (4 SQLi, 3 path traversal)
+    async fn log_two_clusters(logs: &LogStore) {
+        use crate::models::decision::JudgeDecision;
+        use crate::models::request::RequestPayload;
+        use std::collections::HashMap;
+
+        let flag = JudgeDecision::Flag {
+            confidence: 0.6,
+            reason: "Suspicious".to_string(),
+            suggested_rule: None,
+        };
+        for i in 0..4 {
+            let mut query = HashMap::new();
+            query.insert("q".to_string(), format!("' OR {}={}--", i, i));
+            let payload = RequestPayload::new(
+                "GET".to_string(),
+                format!("/users/{}", i),
+                HashMap::new(),
+                None,
+                query,
+                None,
+            );
+            logs.log_event(&payload, &flag).await.unwrap();
+        }
+        for i in 0..3 {
+            let mut query = HashMap::new();
+            query.insert("file".to_string(), format!("{}../../etc/passwd", i));
+            let payload = RequestPayload::new(
+                "GET".to_string(),
+                "/download".to_string(),
+                HashMap::new(),
+                None,
+                query,
+                None,
+            );
+            logs.log_event(&payload, &flag).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_calls_llm_once_per_cluster() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let rules_store =
+            Arc::new(RulebookStore::new(temp_dir.path().join("rulebook.json")).unwrap());
+        let llm = Arc::new(MockLlmProvider::new());
+        log_two_clusters(&logs).await;
+
+        let learner = Learner::new(
+            llm.clone(),
+            logs,
+            rules_store,
+            Duration::from_secs(60),
+            1,
+            10,
+        );
+        *learner.last_run_timestamp.write().unwrap() = 0;
+
+        learner.run_batch().await.unwrap();
+
+        assert_eq!(llm.learn_call_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_carries_over_clusters_beyond_cap() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let rules_store =
+            Arc::new(RulebookStore::new(temp_dir.path().join("rulebook.json")).unwrap());
+        let llm = Arc::new(MockLlmProvider::new());
+        log_two_clusters(&logs).await;
+
+        let learner = Learner::new(
+            llm.clone(),
+            logs,
+            rules_store,
+            Duration::from_secs(60),
+            1,
+            1,
+        );
+        *learner.last_run_timestamp.write().unwrap() = 0;
+
+        learner.run_batch().await.unwrap();
+        assert_eq!(llm.learn_call_count(), 1);
+        assert_eq!(learner.carried_over.lock().unwrap().len(), 3);
+
+        // The cursor moved past both clusters, the smaller one comes from the carry-over
+        *learner.last_run_timestamp.write().unwrap() = i64::MAX;
+        learner.run_batch().await.unwrap();
+        assert_eq!(llm.learn_call_count(), 2);
+        assert!(learner.carried_over.lock().unwrap().is_empty());
+
+        *learner.last_run_timestamp.write().unwrap() = i64::MAX;
+        learner.run_batch().await.unwrap();
+        assert_eq!(llm.learn_call_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_applies_labels() {
+        use crate::models::decision::{JudgeDecision, ThreatLevel};
+        use crate::models::request::RequestPayload;
+        use std::collections::HashMap;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let rules_store =
+            Arc::new(RulebookStore::new(temp_dir.path().join("rulebook.json")).unwrap());
+
+        let mut rulebook = Rulebook::new();
+        for (pattern, confidence) in [("select", 0.9), ("drop", 0.3), ("union", 0.9)] {
+            rulebook.add_rule(Rule::new(
+                pattern.to_string(),
+                "sqli".to_string(),
+                confidence,
+                RuleAction::Block,
+                "manual".to_string(),
+            ));
+        }
+        rules_store.save(&rulebook).await.unwrap();
+
+        let request = |path: &str, q: &str| {
+            RequestPayload::new(
+                "GET".to_string(),
+                path.to_string(),
+                HashMap::new(),
+                None,
+                HashMap::from([("q".to_string(), q.to_string())]),
+                None,
+            )
+        };
+        let block = JudgeDecision::Block {
+            confidence: 0.9,
+            reason: "Matched rule".to_string(),
+            threat_level: ThreatLevel::High,
+        };
+        let benign = logs
+            .log_event(&request("/search", "select drop-down"), &block)
+            .await
+            .unwrap();
+        let missed = logs
+            .log_event(
+                &request("/fetch", "http://169.254.169.254/"),
+                &JudgeDecision::Allow { confidence: 0.9 },
+            )
+            .await
+            .unwrap();
+        for (id, kind) in [
+            (benign, LabelKind::FalsePositive),
+            (missed, LabelKind::FalseNegative),
+        ] {
+            let event = logs.get_event(id).await.unwrap().unwrap();
+            logs.add_label(&event, kind, Some("checked")).await.unwrap();
+        }
+
+        let llm = Arc::new(MockLlmProvider::new());
+        let learner = Learner::new(
+            llm.clone(),
+            logs,
+            rules_store.clone(),
+            Duration::from_secs(60),
+            1,
+            10,
+        );
+        *learner.last_run_timestamp.write().unwrap() = 0;
+
+        learner.run_batch().await.unwrap();
+
+        // The false negative is learned from like a flagged request
+        assert_eq!(llm.learn_call_count(), 1);
+
+        // The rules matching the false positive are weakened or removed
+        let rules = rules_store.load().await.unwrap().rules;
+        let patterns: Vec<_> = rules
+            .iter()
+            .map(|r| (r.pattern.as_str(), r.confidence))
+            .collect();
+        assert_eq!(patterns, vec![("select", 0.9 * 0.8), ("union", 0.9)]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_keeps_rulebook_with_invalid_rules_untouched() {
+        use crate::models::decision::JudgeDecision;
+        use crate::models::request::RequestPayload;
+        use std::collections::HashMap;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let rulebook_path = temp_dir.path().join("rulebook.json");
+        let rules_store = Arc::new(RulebookStore::new(&rulebook_path).unwrap());
+
+        let mut rulebook = Rulebook::new();
+        for pattern in ["(unclosed", "union.*select"] {
+            rulebook.add_rule(Rule::new(
+                pattern.to_string(),
+                "sqli".to_string(),
+                0.9,
+                RuleAction::Block,
+                "manual".to_string(),
+            ));
+        }
+        rules_store.save(&rulebook).await.unwrap();
+        let before = std::fs::read_to_string(&rulebook_path).unwrap();
+
+        let flagged = RequestPayload::new(
+            "GET".to_string(),
+            "/search".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::from([("q".to_string(), "1 or 1=1".to_string())]),
+            None,
+        );
+        logs.log_event(
+            &flagged,
+            &JudgeDecision::Flag {
+                confidence: 0.6,
+                reason: "Suspicious".to_string(),
+                suggested_rule: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        let llm = Arc::new(MockLlmProvider::new());
+        let learner = Learner::new(
+            llm.clone(),
+            logs,
+            rules_store,
+            Duration::from_secs(60),
+            1,
+            10,
+        );
+        *learner.last_run_timestamp.write().unwrap() = 0;
+
+        // Saving the learned rulebook would delete the invalid rule
+        assert!(learner.run_batch().await.is_err());
+        assert_eq!(llm.learn_call_count(), 0);
+        assert_eq!(std::fs::read_to_string(&rulebook_path).unwrap(), before);
+    }
 }
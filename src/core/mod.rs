@@ -1,3 +1,16 @@
+pub mod bans;
+pub mod clustering;
+pub mod coalesce;
+pub mod dedupe;
+pub mod engine;
+pub mod escalation;
+pub mod feedback;
+pub mod fewshot;
 pub mod judge;
 pub mod learner;
+pub mod modsecurity;
 pub mod rulebook;
+pub mod scoring;
+pub mod steering;
+pub mod transform;
+pub mod validator;
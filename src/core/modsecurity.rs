@@ -0,0 +1,1112 @@
+use crate::core::rulebook::{Rule, RuleTarget, Rulebook};
+use crate::core::transform::Transform;
+use crate::core::validator;
+use crate::models::decision::{RuleAction, ThreatLevel};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// First SecRule id used for exported rules (the 9,000,000 range is left
+/// free for local rules by the OWASP id reservations)
+pub const DEFAULT_ID_BASE: u64 = 9_100_000;
+
+/// Number of SecRule ids exported rules are spread over, from the id base
+const ID_RANGE: u64 = 100_000;
+
+/// Transforms applied to every exported rule, matching the decoded values
+/// Guardix itself inspects
+const EXPORT_TRANSFORMS: &[&str] = &["none", "urlDecodeUni"];
+
+/// `created_by` of rules converted from SecRules
+pub const IMPORT_CREATED_BY: &str = "import";
+
+/// Threat type of imported rules without an `attack-*` tag
+const IMPORT_DEFAULT_THREAT: &str = "imported";
+
+/// A parsed `SecRule` directive, with any chained rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecRule {
+    pub variables: Vec<String>,
+    /// Operator name without the `@` (`rx` when omitted)
+    pub operator: String,
+    pub argument: String,
+    pub negated: bool,
+    pub actions: Vec<(String, Option<String>)>,
+    pub chain: Vec<SecRule>,
+}
+
+impl SecRule {
+    /// Value of the first action named `name`
+    pub fn action(&self, name: &str) -> Option<&str> {
+        self.actions
+            .iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    pub fn has_action(&self, name: &str) -> bool {
+        self.actions.iter().any(|(n, _)| n == name)
+    }
+
+    /// Values of every action named `name` (e.g. all `t:` transforms)
+    pub fn actions_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.actions
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .filter_map(|(_, v)| v.as_deref())
+    }
+}
+
+/// Translates the rulebook into ModSecurity `SecRule` directives.
+///
+/// Rules with validation errors, rules expired at `now` and rules with an
+/// `active_hours` schedule are skipped with a comment. Validity periods are
+/// kept as chained `TIME_EPOCH` conditions. SecRule ids are derived from the
+/// rule ids (see `secrule_id`), so they survive rules being added or removed.
+pub fn export_rulebook(rulebook: &Rulebook, id_base: u64, now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# Generated by Guardix from rulebook version {} ({} rules) at {}",
+        rulebook.version,
+        rulebook.rules.len(),
+        now.to_rfc3339()
+    );
+
+    let mut used_ids = HashSet::new();
+    for rule in &rulebook.rules {
+        out.push('\n');
+
+        let issues = validator::validate_rule(rule);
+        if validator::has_errors(&issues) {
+            let _ = writeln!(out, "# Skipped rule {}: failed validation", rule.id);
+            continue;
+        }
+        if rule.expires_at.is_some_and(|t| t <= now) {
+            let _ = writeln!(out, "# Skipped rule {}: expired", rule.id);
+            continue;
+        }
+        if rule.active_hours.is_some() {
+            let _ = writeln!(
+                out,
+                "# Skipped rule {}: active_hours schedules are not exported",
+                rule.id
+            );
+            continue;
+        }
+
+        let mut id = secrule_id(&rule.id, id_base);
+        while !used_ids.insert(id) {
+            id = id_base + (id - id_base + 1) % ID_RANGE;
+        }
+        out.push_str(&export_rule(rule, id));
+    }
+
+    out
+}
+
+/// SecRule id of a rule: `id_base` plus a hash of the rule id within
+/// `ID_RANGE`. On a collision the exporter takes the next free id.
+fn secrule_id(rule_id: &str, id_base: u64) -> u64 {
+    let digest = Sha256::digest(rule_id.as_bytes());
+    let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    id_base + hash % ID_RANGE
+}
+
+/// Translates a single rule into a `SecRule` directive with the given id
+pub fn export_rule(rule: &Rule, id: u64) -> String {
+    let phase = if rule.targets.is_empty()
+        || rule.targets.contains(&RuleTarget::Body)
+        || rule.targets.contains(&RuleTarget::Args)
+    {
+        2
+    } else {
+        1
+    };
+
+    let mut actions = vec![format!("id:{}", id), format!("phase:{}", phase)];
+    match rule.action {
+        RuleAction::Block => {
+            actions.push("deny".to_string());
+            actions.push("status:403".to_string());
+        }
+        RuleAction::Flag => actions.push("pass".to_string()),
+    }
+    actions.push("log".to_string());
+    actions.extend(EXPORT_TRANSFORMS.iter().map(|t| format!("t:{}", t)));
+    actions.extend(
+        rule.transforms
+            .iter()
+            .filter(|t| !EXPORT_TRANSFORMS.contains(&t.as_str()))
+            .map(|t| format!("t:{}", t.as_str())),
+    );
+    let msg = rule.description.as_deref().unwrap_or(&rule.threat_type);
+    actions.push(format!("msg:'{}'", quote_action(msg)));
+    actions.push("tag:'guardix'".to_string());
+    actions.push(format!("tag:'attack-{}'", quote_action(&rule.threat_type)));
+    actions.push(format!("tag:'guardix-id/{}'", quote_action(&rule.id)));
+    actions.push(format!(
+        "severity:'{}'",
+        match (rule.threat_level, rule.action) {
+            (Some(ThreatLevel::Critical), _) | (None, RuleAction::Block) => "CRITICAL",
+            (Some(ThreatLevel::High), _) => "ERROR",
+            (Some(ThreatLevel::Medium), _) | (None, RuleAction::Flag) => "WARNING",
+            (Some(ThreatLevel::Low), _) => "NOTICE",
+        }
+    ));
+
+    let mut conditions = Vec::new();
+    if let Some(not_before) = rule.not_before {
+        conditions.push(format!("@ge {}", not_before.timestamp()));
+    }
+    if let Some(expires_at) = rule.expires_at {
+        conditions.push(format!("@lt {}", expires_at.timestamp()));
+    }
+    if !conditions.is_empty() {
+        actions.push("chain".to_string());
+    }
+
+    let mut out = format!(
+        "SecRule {} \"@rx {}\" \\\n    \"{}\"\n",
+        variables_for(&rule.targets),
+        quote_operator(&format!("(?i){}", rule.pattern)),
+        actions.join(",")
+    );
+
+    let count = conditions.len();
+    for (i, condition) in conditions.into_iter().enumerate() {
+        let chain = if i + 1 < count { ",chain" } else { "" };
+        let _ = writeln!(
+            out,
+            "    SecRule TIME_EPOCH \"{}\" \"t:none{}\"",
+            condition, chain
+        );
+    }
+
+    out
+}
+
+fn variables_for(targets: &[RuleTarget]) -> String {
+    let all = [
+        RuleTarget::Path,
+        RuleTarget::Query,
+        RuleTarget::Headers,
+        RuleTarget::Body,
+    ];
+    let targets = if targets.is_empty() {
+        &all[..]
+    } else {
+        targets
+    };
+
+    targets
+        .iter()
+        .filter_map(|target| match target {
+            RuleTarget::Path => Some("REQUEST_FILENAME"),
+            RuleTarget::Query => Some("QUERY_STRING"),
+            RuleTarget::Headers => Some("REQUEST_HEADERS"),
+            RuleTarget::Body => Some("REQUEST_BODY"),
+            RuleTarget::Args => Some("ARGS|REQUEST_COOKIES|FILES"),
+            RuleTarget::Unknown(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Rewrites a regex for a double-quoted operator: quotes, escaped
+/// backslashes and `%` before `{` become `\xNN` escapes, so the argument has
+/// no quote to escape, no backslash before the closing quote and no `%{`
+/// sequence ModSecurity would expand as a macro
+fn quote_operator(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('"' | '\\' | '%')) => escaped,
+                Some(escaped) => {
+                    out.push('\\');
+                    out.push(escaped);
+                    continue;
+                }
+                None => {
+                    out.push_str("\\x5c");
+                    continue;
+                }
+            },
+            c => c,
+        };
+        match c {
+            '"' => out.push_str("\\x22"),
+            '\\' => out.push_str("\\x5c"),
+            '%' if chars.peek() == Some(&'{') => out.push_str("\\x25"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Escapes a single-quoted action value inside the double-quoted action list;
+/// `%{` is split up since ModSecurity expands macros in `msg`
+fn quote_action(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('"', "\\\"")
+        .replace("%{", "% {")
+        .replace(['\n', '\r'], " ")
+}
+
+/// Parses `SecRule` directives (with line continuations and chains).
+///
+/// Comments and other directives are ignored.
+pub fn parse_secrules(input: &str) -> Result<Vec<SecRule>> {
+    let mut rules: Vec<SecRule> = Vec::new();
+    let mut chain_open = false;
+
+    for (line_no, line) in logical_lines(input) {
+        let tokens = tokenize(&line).map_err(|e| anyhow::anyhow!("line {}: {}", line_no, e))?;
+        let Some((directive, args)) = tokens.split_first() else {
+            continue;
+        };
+        if !directive.eq_ignore_ascii_case("SecRule") {
+            continue;
+        }
+        if !(2..=3).contains(&args.len()) {
+            bail!(
+                "line {}: SecRule expects variables, operator and actions",
+                line_no
+            );
+        }
+
+        let (negated, operator, argument) = parse_operator(&args[1]);
+        let rule = SecRule {
+            variables: args[0].split('|').map(|v| v.trim().to_string()).collect(),
+            operator,
+            argument,
+            negated,
+            actions: args.get(2).map(|a| parse_actions(a)).unwrap_or_default(),
+            chain: Vec::new(),
+        };
+
+        let chains = rule.has_action("chain");
+        if chain_open {
+            match rules.last_mut() {
+                Some(parent) => parent.chain.push(rule),
+                None => bail!("line {}: chained rule without a parent", line_no),
+            }
+        } else {
+            rules.push(rule);
+        }
+        chain_open = chains;
+    }
+
+    if chain_open {
+        bail!("Rule chain is not terminated");
+    }
+
+    Ok(rules)
+}
+
+/// Rules converted by [`import_rules`], with a note for each rule (or part of
+/// a rule) that could not be converted
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub rules: Vec<Rule>,
+    /// Rules left out entirely, with the reason
+    pub skipped: Vec<String>,
+    /// Imported rules that lost a variable or transform in the conversion
+    pub warnings: Vec<String>,
+}
+
+/// Converts ModSecurity / OWASP CRS `SecRule`s into Guardix rules.
+///
+/// Supported: `@rx`, `@pm` and `@contains` on REQUEST_URI, REQUEST_FILENAME,
+/// QUERY_STRING, ARGS(_GET/_POST), REQUEST_HEADERS, REQUEST_COOKIES and
+/// REQUEST_BODY (selectors such as `ARGS:id` widen to the whole collection),
+/// plus the `t:` transforms Guardix implements. `deny` and `drop` rules
+/// block; other rules (including CRS `block`, which only adds to the anomaly
+/// score) flag, with a threat level from their `severity` that weighs them
+/// in anomaly scoring. Chained and negated rules,
+/// other operators, rules without a `msg` (flow control rather than
+/// detection) and patterns Guardix cannot compile are skipped.
+pub fn import_rules(input: &str) -> Result<ImportReport> {
+    let mut report = ImportReport::default();
+
+    for secrule in parse_secrules(input)? {
+        let label = match secrule.action("id") {
+            Some(id) => format!("rule {}", id),
+            None => "rule without id".to_string(),
+        };
+        match import_rule(&secrule, &mut report.warnings, &label) {
+            Ok(rule) => report.rules.push(rule),
+            Err(reason) => report.skipped.push(format!("{}: {}", label, reason)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Imports a `.conf` file, or every `.conf` file of a directory in name order
+pub fn import_path(path: &Path) -> Result<ImportReport> {
+    let files = if path.is_dir() {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+            .with_context(|| format!("Failed to read rules directory: {:?}", path))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "conf"))
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut report = ImportReport::default();
+    for file in files {
+        let content = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read rules file: {:?}", file))?;
+        let imported =
+            import_rules(&content).with_context(|| format!("Failed to parse {:?}", file))?;
+        report.rules.extend(imported.rules);
+        report.skipped.extend(imported.skipped);
+        report.warnings.extend(imported.warnings);
+    }
+
+    Ok(report)
+}
+
+fn case_sensitive(pattern: &str) -> String {
+    format!("(?-i:{})", pattern)
+}
+
+fn import_rule(
+    secrule: &SecRule,
+    warnings: &mut Vec<String>,
+    label: &str,
+) -> std::result::Result<Rule, String> {
+    if !secrule.chain.is_empty() {
+        return Err("chained rules are not supported".to_string());
+    }
+    if secrule.negated {
+        return Err(format!("negated @{} is not supported", secrule.operator));
+    }
+    let Some(msg) = secrule.action("msg") else {
+        return Err("no msg, not a detection rule".to_string());
+    };
+
+    // Rule patterns match case-insensitively; `@rx` without `(?i)` and
+    // `@contains` match case-sensitively in ModSecurity, so they say so inline
+    let pattern = match secrule.operator.as_str() {
+        "rx" => match secrule.argument.strip_prefix("(?i)") {
+            Some(pattern) => pattern.to_string(),
+            None => case_sensitive(&secrule.argument),
+        },
+        "pm" => secrule
+            .argument
+            .split_whitespace()
+            .map(regex_syntax::escape)
+            .collect::<Vec<_>>()
+            .join("|"),
+        "contains" => case_sensitive(&regex_syntax::escape(&secrule.argument)),
+        other => return Err(format!("unsupported operator @{}", other)),
+    };
+
+    let mut targets = Vec::new();
+    for variable in &secrule.variables {
+        // Exclusions (`!ARGS:foo`) only narrow the rule, so dropping them is safe
+        if variable.starts_with('!') {
+            continue;
+        }
+        match targets_for_variable(variable) {
+            Some(mapped) => {
+                for target in mapped {
+                    if !targets.contains(&target) {
+                        targets.push(target);
+                    }
+                }
+            }
+            None => warnings.push(format!("{}: dropped variable {}", label, variable)),
+        }
+    }
+    if targets.is_empty() {
+        return Err(format!(
+            "no supported variable in {}",
+            secrule.variables.join("|")
+        ));
+    }
+
+    let mut transforms = Vec::new();
+    for name in secrule.actions_named("t") {
+        // `t:none` discards the transforms inherited or listed before it
+        if name.eq_ignore_ascii_case("none") {
+            transforms.clear();
+            continue;
+        }
+        match Transform::from(name.to_string()) {
+            Transform::Unknown(name) => {
+                warnings.push(format!("{}: dropped transform {}", label, name))
+            }
+            transform if !transforms.contains(&transform) => transforms.push(transform),
+            _ => {}
+        }
+    }
+
+    // `block` defers to SecDefaultAction, which in CRS only adds to the
+    // anomaly score: such rules flag, and score by their severity
+    let action = if ["deny", "drop"].iter().any(|a| secrule.has_action(a)) {
+        RuleAction::Block
+    } else {
+        RuleAction::Flag
+    };
+    let threat_type = secrule
+        .actions_named("tag")
+        .find_map(|tag| tag.strip_prefix("attack-"))
+        .unwrap_or(IMPORT_DEFAULT_THREAT);
+
+    let mut rule = Rule::new(
+        pattern,
+        threat_type.to_string(),
+        confidence_for_severity(secrule.action("severity")),
+        action,
+        IMPORT_CREATED_BY.to_string(),
+    )
+    .with_description(msg.to_string())
+    .with_targets(targets)
+    .with_transforms(transforms);
+    if let Some(threat_level) = threat_level_for_severity(secrule.action("severity")) {
+        rule = rule.with_threat_level(threat_level);
+    }
+    if let Some(id) = secrule.action("id") {
+        rule.id = format!("modsec-{}", id);
+    }
+
+    let issues = validator::validate_rule(&rule);
+    if let Some(error) = issues
+        .iter()
+        .find(|i| i.severity == validator::Severity::Error)
+    {
+        return Err(error.message.clone());
+    }
+
+    Ok(rule)
+}
+
+/// Request parts a SecRule variable (collection, optionally with a selector)
+/// reads, or `None` when Guardix has no equivalent
+fn targets_for_variable(variable: &str) -> Option<Vec<RuleTarget>> {
+    let name = variable
+        .split(':')
+        .next()
+        .unwrap_or(variable)
+        .to_ascii_uppercase();
+
+    let targets = match name.as_str() {
+        "REQUEST_URI" | "REQUEST_URI_RAW" | "REQUEST_LINE" => {
+            vec![RuleTarget::Path, RuleTarget::Query]
+        }
+        "REQUEST_FILENAME" | "REQUEST_BASENAME" => vec![RuleTarget::Path],
+        "QUERY_STRING" | "ARGS_GET" | "ARGS_GET_NAMES" => vec![RuleTarget::Query],
+        "ARGS" | "ARGS_NAMES" => vec![RuleTarget::Query, RuleTarget::Body],
+        "ARGS_POST" | "ARGS_POST_NAMES" | "REQUEST_BODY" => vec![RuleTarget::Body],
+        "REQUEST_HEADERS"
+        | "REQUEST_HEADERS_NAMES"
+        | "REQUEST_COOKIES"
+        | "REQUEST_COOKIES_NAMES" => vec![RuleTarget::Headers],
+        _ => return None,
+    };
+
+    Some(targets)
+}
+
+/// Maps a ModSecurity severity (name or 0-7 number) to a threat level, as
+/// CRS maps it to an anomaly score (critical 5, error 4, warning 3, notice 2)
+fn threat_level_for_severity(severity: Option<&str>) -> Option<ThreatLevel> {
+    match severity?.to_ascii_uppercase().as_str() {
+        "EMERGENCY" | "ALERT" | "CRITICAL" | "0" | "1" | "2" => Some(ThreatLevel::Critical),
+        "ERROR" | "3" => Some(ThreatLevel::High),
+        "WARNING" | "4" => Some(ThreatLevel::Medium),
+        "NOTICE" | "5" => Some(ThreatLevel::Low),
+        _ => None,
+    }
+}
+
+/// Maps a ModSecurity severity (name or 0-7 number) to a rule confidence
+fn confidence_for_severity(severity: Option<&str>) -> f32 {
+    match severity.map(|s| s.to_ascii_uppercase()).as_deref() {
+        Some("EMERGENCY" | "ALERT" | "CRITICAL" | "0" | "1" | "2") => 0.9,
+        Some("ERROR" | "3") => 0.8,
+        Some("WARNING" | "4") => 0.6,
+        Some("NOTICE" | "5") => 0.5,
+        _ => 0.7,
+    }
+}
+
+/// Joins `\`-continued lines, dropping blank lines and comments. Yields the
+/// 1-based number of the first physical line of each logical line.
+fn logical_lines(input: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+
+    for (idx, raw) in input.lines().enumerate() {
+        let trimmed = raw.trim();
+        if current.is_empty() {
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            start = idx + 1;
+        }
+
+        match trimmed.strip_suffix('\\') {
+            Some(head) => {
+                current.push_str(head);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(trimmed);
+                lines.push((start, std::mem::take(&mut current)));
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push((start, current));
+    }
+
+    lines
+}
+
+/// Splits a directive into whitespace-separated tokens; double-quoted tokens
+/// may contain spaces and `\"` escapes
+fn tokenize(line: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut token = String::new();
+        if c == '"' {
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' if chars.peek() == Some(&'"') => {
+                        token.push('"');
+                        chars.next();
+                    }
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    _ => token.push(c),
+                }
+            }
+            if !closed {
+                bail!("unterminated quoted string");
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+        }
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn parse_operator(raw: &str) -> (bool, String, String) {
+    let (negated, rest) = match raw.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, raw),
+    };
+
+    match rest.strip_prefix('@') {
+        Some(op) => {
+            let (name, argument) = op.split_once(' ').unwrap_or((op, ""));
+            (negated, name.to_string(), argument.to_string())
+        }
+        None => (negated, "rx".to_string(), rest.to_string()),
+    }
+}
+
+/// Splits `a,b:c,msg:'x, y'` into name/value pairs, honouring single quotes
+fn parse_actions(raw: &str) -> Vec<(String, Option<String>)> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_quotes => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            '\'' => in_quotes = !in_quotes,
+            ',' if !in_quotes => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+
+    parts
+        .into_iter()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.split_once(':') {
+            Some((name, value)) => (name.trim().to_string(), Some(value.trim().to_string())),
+            None => (part, None),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, action: RuleAction) -> Rule {
+        Rule::new(
+            pattern.to_string(),
+            "sqli".to_string(),
+            0.9,
+            action,
+            "llm".to_string(),
+        )
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2025-11-06T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_export_parses_back() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(
+            rule(r#"union\s+select\s+"?\w"#, RuleAction::Block)
+                .with_description("Union-based SQLi, it's common".to_string())
+                .with_targets(vec![RuleTarget::Query, RuleTarget::Body]),
+        );
+        rulebook.add_rule(rule(r"\.\./", RuleAction::Flag).with_targets(vec![RuleTarget::Path]));
+
+        let output = export_rulebook(&rulebook, DEFAULT_ID_BASE, now());
+        let parsed = parse_secrules(&output).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+
+        let block = &parsed[0];
+        assert_eq!(block.variables, vec!["QUERY_STRING", "REQUEST_BODY"]);
+        assert_eq!(block.operator, "rx");
+        assert_eq!(block.argument, r#"(?i)union\s+select\s+\x22?\w"#);
+        assert_eq!(
+            block.action("id"),
+            Some(
+                secrule_id(&rulebook.rules[0].id, DEFAULT_ID_BASE)
+                    .to_string()
+                    .as_str()
+            )
+        );
+        assert_eq!(block.action("phase"), Some("2"));
+        assert!(block.has_action("deny"));
+        assert_eq!(block.action("status"), Some("403"));
+        assert_eq!(
+            block.actions_named("t").collect::<Vec<_>>(),
+            vec!["none", "urlDecodeUni"]
+        );
+        assert_eq!(block.action("msg"), Some("Union-based SQLi, it's common"));
+        let tags: Vec<_> = block.actions_named("tag").collect();
+        assert!(tags.contains(&"attack-sqli"));
+        assert!(tags.contains(&format!("guardix-id/{}", rulebook.rules[0].id).as_str()));
+
+        let flag = &parsed[1];
+        assert_eq!(flag.variables, vec!["REQUEST_FILENAME"]);
+        assert_eq!(
+            flag.action("id"),
+            Some(
+                secrule_id(&rulebook.rules[1].id, DEFAULT_ID_BASE)
+                    .to_string()
+                    .as_str()
+            )
+        );
+        assert_eq!(flag.action("phase"), Some("1"));
+        assert!(flag.has_action("pass"));
+        assert!(!flag.has_action("deny"));
+        assert_eq!(flag.action("msg"), Some("sqli"));
+    }
+
+    #[test]
+    fn test_export_ids_survive_rulebook_changes() {
+        let mut rulebook = Rulebook::new();
+        for pattern in ["select", "union", "sleep\\(", "<script"] {
+            rulebook.add_rule(rule(pattern, RuleAction::Block));
+        }
+        let ids = |rulebook: &Rulebook| -> Vec<(String, String)> {
+            let output = export_rulebook(rulebook, DEFAULT_ID_BASE, now());
+            parse_secrules(&output)
+                .unwrap()
+                .iter()
+                .map(|r| {
+                    let tag = r
+                        .actions_named("tag")
+                        .find(|t| t.starts_with("guardix-id/"));
+                    (
+                        tag.unwrap().to_string(),
+                        r.action("id").unwrap().to_string(),
+                    )
+                })
+                .collect()
+        };
+
+        let before = ids(&rulebook);
+        assert!(before.iter().all(|(_, id)| {
+            let id: u64 = id.parse().unwrap();
+            (DEFAULT_ID_BASE..DEFAULT_ID_BASE + ID_RANGE).contains(&id)
+        }));
+
+        // Removing a rule and adding another leaves the remaining ids unchanged
+        rulebook.rules.remove(0);
+        rulebook.add_rule(rule("drop", RuleAction::Flag));
+        let after = ids(&rulebook);
+        assert_eq!(after[..3], before[1..]);
+    }
+
+    #[test]
+    fn test_export_quotes_operator_and_actions() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(
+            rule(r#"c:\\windows|\"x"|%{2}|\%\{"#, RuleAction::Block)
+                .with_description(r#"Path "%{REQUEST_URI}" \ it's"#.to_string()),
+        );
+
+        let output = export_rulebook(&rulebook, DEFAULT_ID_BASE, now());
+        let operator_line = output.lines().find(|l| l.starts_with("SecRule")).unwrap();
+        assert!(!operator_line.contains("%{"));
+        assert!(!operator_line.contains("\\\\"));
+        assert!(!output.contains("%{REQUEST_URI}"));
+
+        let parsed = parse_secrules(&output).unwrap();
+        assert_eq!(
+            parsed[0].argument,
+            r#"(?i)c:\x5cwindows|\x22x\x22|\x25{2}|%\{"#
+        );
+        assert_eq!(
+            parsed[0].action("msg"),
+            Some(r#"Path "% {REQUEST_URI}" \ it's"#)
+        );
+
+        // The rewritten pattern matches what the original did
+        let original = &rulebook.rules[0];
+        let exported = Rule::new(
+            parsed[0].argument.clone(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "test".to_string(),
+        );
+        for text in [r"C:\Windows", r#""x""#, "%%", "%{"] {
+            assert!(
+                original.compile_pattern().unwrap().is_match(text),
+                "{}",
+                text
+            );
+            assert!(
+                exported.compile_pattern().unwrap().is_match(text),
+                "{}",
+                text
+            );
+        }
+        assert!(!exported.compile_pattern().unwrap().is_match("%"));
+    }
+
+    #[test]
+    fn test_export_patterns_compile_back() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule(r#"<script[^>]*>|javascript:"#, RuleAction::Block));
+
+        let output = export_rulebook(&rulebook, DEFAULT_ID_BASE, now());
+        let parsed = parse_secrules(&output).unwrap();
+
+        let pattern = Rule::new(
+            parsed[0].argument.clone(),
+            "xss".to_string(),
+            0.9,
+            RuleAction::Block,
+            "test".to_string(),
+        );
+        let regex = pattern.compile_pattern().unwrap();
+        assert!(regex.is_match("<SCRIPT src=x>"));
+        assert_eq!(
+            parsed[0].variables,
+            vec![
+                "REQUEST_FILENAME",
+                "QUERY_STRING",
+                "REQUEST_HEADERS",
+                "REQUEST_BODY"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_validity_period_as_chain() {
+        let mut patch = rule("cve-2025-1234", RuleAction::Block);
+        patch.not_before = Some("2025-11-01T00:00:00Z".parse().unwrap());
+        patch.expires_at = Some("2025-12-01T00:00:00Z".parse().unwrap());
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(patch);
+
+        let output = export_rulebook(&rulebook, 100, now());
+        let parsed = parse_secrules(&output).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert!(parsed[0].has_action("chain"));
+        assert_eq!(parsed[0].chain.len(), 2);
+        assert_eq!(parsed[0].chain[0].variables, vec!["TIME_EPOCH"]);
+        assert_eq!(parsed[0].chain[0].operator, "ge");
+        assert_eq!(parsed[0].chain[0].argument, "1761955200");
+        assert_eq!(parsed[0].chain[1].operator, "lt");
+        assert_eq!(parsed[0].chain[1].argument, "1764547200");
+    }
+
+    #[test]
+    fn test_export_skips_unexportable_rules() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule(".+", RuleAction::Block));
+        let mut expired = rule("old", RuleAction::Block);
+        expired.expires_at = Some("2025-01-01T00:00:00Z".parse().unwrap());
+        rulebook.add_rule(expired);
+        let mut scheduled = rule("promo", RuleAction::Flag);
+        scheduled.active_hours = Some(crate::core::rulebook::ActiveHours {
+            start: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: chrono::NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            days: vec![],
+        });
+        rulebook.add_rule(scheduled);
+        rulebook.add_rule(rule("select", RuleAction::Block));
+
+        let output = export_rulebook(&rulebook, DEFAULT_ID_BASE, now());
+        let parsed = parse_secrules(&output).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert!(output.contains("failed validation"));
+        assert!(output.contains("expired"));
+        assert!(output.contains("active_hours"));
+    }
+
+    #[test]
+    fn test_parse_crs_style_rules() {
+        let input = r#"
+# Comment
+SecRuleEngine On
+SecRule REQUEST_HEADERS:User-Agent "@pm sqlmap nikto" \
+    "id:913100,\
+    phase:1,\
+    block,\
+    t:none,t:lowercase,\
+    msg:'Found User-Agent associated with security scanner',\
+    tag:'attack-reputation-scanner'"
+SecRule ARGS "!@contains safe" "id:1,pass"
+"#;
+
+        let parsed = parse_secrules(input).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].variables, vec!["REQUEST_HEADERS:User-Agent"]);
+        assert_eq!(parsed[0].operator, "pm");
+        assert_eq!(parsed[0].argument, "sqlmap nikto");
+        assert_eq!(
+            parsed[0].actions_named("t").collect::<Vec<_>>(),
+            vec!["none", "lowercase"]
+        );
+        assert_eq!(
+            parsed[0].action("msg"),
+            Some("Found User-Agent associated with security scanner")
+        );
+        assert!(parsed[1].negated);
+        assert_eq!(parsed[1].operator, "contains");
+    }
+
+    #[test]
+    fn test_export_includes_rule_transforms() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(
+            rule("union select", RuleAction::Block).with_transforms(vec![
+                Transform::UrlDecode,
+                Transform::ReplaceComments,
+                Transform::CompressWhitespace,
+            ]),
+        );
+
+        let output = export_rulebook(&rulebook, DEFAULT_ID_BASE, now());
+        let parsed = parse_secrules(&output).unwrap();
+
+        assert_eq!(
+            parsed[0].actions_named("t").collect::<Vec<_>>(),
+            vec![
+                "none",
+                "urlDecodeUni",
+                "replaceComments",
+                "compressWhitespace"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_crs_style_rules() {
+        let input = r#"
+SecRule REQUEST_COOKIES|!REQUEST_COOKIES:/__utm/|ARGS_NAMES|ARGS|XML:/* \
+    "@rx (?i)union[\s/*]+select" \
+    "id:942190,phase:2,block,capture,\
+    t:none,t:urlDecodeUni,t:replaceComments,t:utf8toUnicode,\
+    msg:'Detects MSSQL code execution and information gathering attempts',\
+    tag:'attack-sqli',tag:'OWASP_CRS',\
+    severity:'CRITICAL'"
+SecRule REQUEST_HEADERS:User-Agent "@pm sqlmap nikto+" \
+    "id:913100,phase:1,pass,t:none,t:lowercase,msg:'Security scanner',severity:'NOTICE'"
+SecRule REQUEST_URI "@contains /etc/passwd" "id:930120,deny,msg:'OS file access'"
+SecRule REQUEST_HEADERS:Content-Type "@rx ^application/json" \
+    "id:200001,phase:1,pass,nolog,ctl:requestBodyProcessor=JSON"
+SecRule ARGS "@detectSQLi" "id:942100,block,msg:'SQLi detected via libinjection'"
+SecRule ARGS "!@rx ^[0-9]+$" "id:100,deny,msg:'Not numeric'"
+SecRule ARGS "@rx (?<!\w)alert\(" "id:941100,deny,msg:'XSS lookbehind'"
+SecRule ARGS "@rx x" "id:101,deny,msg:'Chained',chain"
+    SecRule REQUEST_METHOD "@streq POST" "t:none"
+SecRule FILES_NAMES "@rx \.php$" "id:933110,deny,msg:'PHP upload'"
+"#;
+
+        let report = import_rules(input).unwrap();
+
+        assert_eq!(report.rules.len(), 3);
+        assert_eq!(report.skipped.len(), 6);
+
+        let sqli = &report.rules[0];
+        assert_eq!(sqli.id, "modsec-942190");
+        assert_eq!(sqli.pattern, r"union[\s/*]+select");
+        assert_eq!(sqli.threat_type, "sqli");
+        assert_eq!(sqli.confidence, 0.9);
+        assert_eq!(sqli.action, RuleAction::Flag);
+        assert_eq!(sqli.threat_level, Some(ThreatLevel::Critical));
+        assert_eq!(sqli.created_by, IMPORT_CREATED_BY);
+        assert_eq!(
+            sqli.targets,
+            vec![RuleTarget::Headers, RuleTarget::Query, RuleTarget::Body]
+        );
+        assert_eq!(
+            sqli.transforms,
+            vec![Transform::UrlDecode, Transform::ReplaceComments]
+        );
+        assert!(report
+            .warnings
+            .iter()
+            .any(|w| w.contains("942190") && w.contains("utf8toUnicode")));
+        assert!(report
+            .warnings
+            .iter()
+            .any(|w| w.contains("942190") && w.contains("XML:/*")));
+
+        let scanner = &report.rules[1];
+        assert_eq!(scanner.pattern, r"sqlmap|nikto\+");
+        assert_eq!(scanner.action, RuleAction::Flag);
+        assert_eq!(scanner.threat_type, IMPORT_DEFAULT_THREAT);
+        assert_eq!(scanner.confidence, 0.5);
+        assert_eq!(scanner.threat_level, Some(ThreatLevel::Low));
+        assert_eq!(scanner.targets, vec![RuleTarget::Headers]);
+
+        let lfi = &report.rules[2];
+        assert_eq!(lfi.pattern, r"(?-i:/etc/passwd)");
+        assert_eq!(lfi.action, RuleAction::Block);
+        assert_eq!(lfi.threat_level, None);
+        assert_eq!(lfi.targets, vec![RuleTarget::Path, RuleTarget::Query]);
+
+        for (id, reason) in [
+            ("200001", "no msg"),
+            ("942100", "@detectSQLi"),
+            ("100", "negated"),
+            ("941100", "Invalid pattern"),
+            ("101", "chained"),
+            ("933110", "no supported variable"),
+        ] {
+            assert!(
+                report
+                    .skipped
+                    .iter()
+                    .any(|s| s.starts_with(&format!("rule {}:", id)) && s.contains(reason)),
+                "rule {} not skipped for {}: {:?}",
+                id,
+                reason,
+                report.skipped
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_keeps_case_sensitivity() {
+        let input = r#"
+SecRule ARGS "@rx (?i)select" "id:1,deny,msg:'Insensitive'"
+SecRule ARGS "@rx SELECT" "id:2,deny,msg:'Sensitive'"
+SecRule ARGS "@contains Passwd" "id:3,deny,msg:'Contains'"
+SecRule ARGS "@pm sqlmap" "id:4,deny,msg:'Phrase'"
+"#;
+        let report = import_rules(input).unwrap();
+        let matches = |index: usize, input: &str| {
+            report.rules[index]
+                .compile_pattern()
+                .unwrap()
+                .is_match(input)
+        };
+
+        assert!(matches(0, "SeLeCt"));
+        assert!(matches(1, "SELECT"));
+        assert!(!matches(1, "select"));
+        assert!(matches(2, "/etc/Passwd"));
+        assert!(!matches(2, "/etc/passwd"));
+        // @pm ignores case in ModSecurity too
+        assert!(matches(3, "SQLMAP"));
+    }
+
+    #[test]
+    fn test_import_round_trips_export() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(
+            rule(r"\.\./", RuleAction::Flag)
+                .with_threat_level(ThreatLevel::High)
+                .with_description("Path traversal".to_string())
+                .with_targets(vec![RuleTarget::Path])
+                .with_transforms(vec![Transform::NormalizePath]),
+        );
+
+        let output = export_rulebook(&rulebook, DEFAULT_ID_BASE, now());
+        let report = import_rules(&output).unwrap();
+
+        assert_eq!(report.rules.len(), 1);
+        let imported = &report.rules[0];
+        assert_eq!(imported.pattern, r"\.\./");
+        assert_eq!(imported.threat_type, "sqli");
+        assert_eq!(imported.action, RuleAction::Flag);
+        assert_eq!(imported.threat_level, Some(ThreatLevel::High));
+        assert_eq!(imported.targets, vec![RuleTarget::Path]);
+        assert_eq!(
+            imported.transforms,
+            vec![Transform::UrlDecode, Transform::NormalizePath]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_secrules(r#"SecRule ARGS "@rx x"#).is_err());
+        assert!(parse_secrules(r#"SecRule ARGS "@rx x" "id:1,chain""#).is_err());
+        assert!(parse_secrules("SecRule ARGS").is_err());
+    }
+}
@@ -1,8 +1,20 @@
-use crate::models::decision::RuleAction;
-use chrono::{DateTime, Utc};
+use crate::core::transform::{self, Transform};
+use crate::models::canonical;
+use crate::models::decision::{RuleAction, ThreatLevel};
+use crate::models::params::Param;
+use crate::models::request::{LogEntry, RequestPayload};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use regex_automata::meta::{self, Regex};
+use regex_automata::util::syntax;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use uuid::Uuid;
 
+/// Upper bound on the compiled size of a single rule pattern
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rulebook {
     pub version: u64,
@@ -29,8 +41,7 @@ impl Rulebook {
 
     pub fn add_rule(&mut self, rule: Rule) {
         self.rules.push(rule);
-        self.version += 1;
-        self.updated_at = Utc::now();
+        self.touch();
     }
 
     pub fn remove_rule(&mut self, rule_id: &str) -> bool {
@@ -38,18 +49,56 @@ impl Rulebook {
         self.rules.retain(|r| r.id != rule_id);
         let removed = self.rules.len() < initial_len;
         if removed {
-            self.version += 1;
-            self.updated_at = Utc::now();
+            self.touch();
         }
         removed
     }
 
+    /// Bumps the version after rules were modified in place
+    pub fn touch(&mut self) {
+        self.version += 1;
+        self.updated_at = Utc::now();
+    }
+
+    /// Hash of the rules' content and of which of them are active at `now`:
+    /// the same on every restart and replica loading these rules, changed by
+    /// any edit to them (but not by a bare version bump) and by a rule
+    /// activating or expiring
+    pub fn fingerprint(&self, now: DateTime<Utc>) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(&self.rules).unwrap_or_default());
+        for rule in self.active_rules(now) {
+            hasher.update(b"\0");
+            hasher.update(rule.id.as_bytes());
+        }
+        let digest = hasher.finalize();
+        format!(
+            "{:016x}",
+            u64::from_be_bytes(digest[..8].try_into().unwrap())
+        )
+    }
+
     /// Retrieves a specific rule by ID - used for rule inspection/debugging
     #[allow(dead_code)]
     pub fn get_rule(&self, rule_id: &str) -> Option<&Rule> {
         self.rules.iter().find(|r| r.id == rule_id)
     }
 
+    /// Earliest time after `now` at which a rule may activate or expire: a
+    /// validity period starting or ending, or an active-hours window opening
+    /// or closing (`None` if no rule has one ahead)
+    pub fn next_activation_change(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.next_activation_change(now))
+            .min()
+    }
+
+    /// Rules whose activation window contains `now`
+    pub fn active_rules(&self, now: DateTime<Utc>) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().filter(move |r| r.is_active_at(now))
+    }
+
     /// Filters rules by threat type - used for analytics and reporting
     #[allow(dead_code)]
     pub fn get_rules_by_type(&self, threat_type: &str) -> Vec<&Rule> {
@@ -67,10 +116,237 @@ pub struct Rule {
     pub threat_type: String,
     pub confidence: f32,
     pub action: RuleAction,
+    /// Threat level of a block by this rule (and its weight in anomaly
+    /// scoring); derived from the action when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threat_level: Option<ThreatLevel>,
     pub created_by: String,
     pub created_at: DateTime<Utc>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Request parts the pattern applies to (empty = whole request)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<RuleTarget>,
+    /// Normalizations applied, in order, to the target text before matching
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<Transform>,
+    /// Rules that were merged into this one as duplicates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merged_from: Vec<RuleProvenance>,
+    /// The rule is inactive before this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    /// The rule is inactive from this time on (e.g. a virtual patch until the backend is fixed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Daily window (UTC) outside of which the rule is inactive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_hours: Option<ActiveHours>,
+}
+
+/// Daily UTC activation window. `start` > `end` wraps past midnight; the
+/// window belongs to the day it starts on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Days the window applies to (empty = every day)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+}
+
+impl ActiveHours {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = now.time();
+        let day = now.weekday();
+        let on_day = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+
+        if self.start <= self.end {
+            on_day(day) && time >= self.start && time < self.end
+        } else {
+            (on_day(day) && time >= self.start) || (on_day(day.pred()) && time < self.end)
+        }
+    }
+
+    /// Next start or end of the window after `now`, on any day (the window
+    /// may not change there when `days` skips that day)
+    pub fn next_boundary(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.date_naive();
+        [today, today + chrono::Days::new(1)]
+            .into_iter()
+            .flat_map(|date| [date.and_time(self.start), date.and_time(self.end)])
+            .map(|t| t.and_utc())
+            .filter(|t| *t > now)
+            .min()
+            .expect("a window boundary is less than a day ahead")
+    }
+}
+
+/// Origin of a rule that was merged into another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleProvenance {
+    pub id: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Part of the request a rule pattern is matched against
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum RuleTarget {
+    Path,
+    Query,
+    Headers,
+    Body,
+    /// Named inputs (query, form, multipart, JSON paths, cookies), one
+    /// `name=value` line each; only matched when targeted explicitly
+    Args,
+    /// Unrecognized target name, kept so validation can report it
+    Unknown(String),
+}
+
+impl RuleTarget {
+    pub fn as_str(&self) -> &str {
+        match self {
+            RuleTarget::Path => "path",
+            RuleTarget::Query => "query",
+            RuleTarget::Headers => "headers",
+            RuleTarget::Body => "body",
+            RuleTarget::Args => "args",
+            RuleTarget::Unknown(name) => name,
+        }
+    }
+}
+
+impl From<String> for RuleTarget {
+    fn from(value: String) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "path" => RuleTarget::Path,
+            "query" => RuleTarget::Query,
+            "headers" => RuleTarget::Headers,
+            "body" => RuleTarget::Body,
+            "args" => RuleTarget::Args,
+            _ => RuleTarget::Unknown(value),
+        }
+    }
+}
+
+impl From<RuleTarget> for String {
+    fn from(value: RuleTarget) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+/// Request text rule patterns are matched against, split by target
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchInput {
+    pub path: String,
+    /// `key=value` pairs joined with `&`
+    pub query: String,
+    /// One `name: value` line per header
+    pub headers: String,
+    pub body: String,
+    /// One `name=value` line per named input (`Param`); files add a
+    /// `name.filename=...` line
+    pub args: String,
+}
+
+impl MatchInput {
+    /// Builds the input from a logged event and its stored (redacted) context
+    pub fn from_log(log: &LogEntry) -> Self {
+        let context = log.context().unwrap_or_default();
+
+        Self {
+            path: log.path.clone(),
+            query: context
+                .query
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join("&"),
+            headers: context
+                .headers
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, v))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            body: context.body_excerpt.unwrap_or_default(),
+            args: args_text(&context.params),
+        }
+    }
+
+    /// Builds the input from a live request. Named inputs are left out
+    /// (see `with_args`).
+    pub fn from_payload(payload: &RequestPayload) -> Self {
+        let query = canonical::query_pairs(payload);
+        let mut headers: Vec<_> = payload.headers.iter().collect();
+        headers.sort();
+
+        Self {
+            path: payload.path.clone(),
+            query: query
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join("&"),
+            headers: headers
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k.to_ascii_lowercase(), v))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            body: payload.body.clone().unwrap_or_default(),
+            args: String::new(),
+        }
+    }
+
+    /// Adds the named inputs of `payload`, only worth extracting when a rule
+    /// targets them
+    pub fn with_args(mut self, payload: &RequestPayload) -> Self {
+        self.args = args_text(&payload.params());
+        self
+    }
+
+    /// Returns the text for the given targets (empty = whole request)
+    pub fn text_for(&self, targets: &[RuleTarget]) -> String {
+        let all = [
+            RuleTarget::Path,
+            RuleTarget::Query,
+            RuleTarget::Headers,
+            RuleTarget::Body,
+        ];
+        let targets = if targets.is_empty() {
+            &all[..]
+        } else {
+            targets
+        };
+
+        targets
+            .iter()
+            .filter_map(|target| match target {
+                RuleTarget::Path => Some(self.path.as_str()),
+                RuleTarget::Query => Some(self.query.as_str()),
+                RuleTarget::Headers => Some(self.headers.as_str()),
+                RuleTarget::Body => Some(self.body.as_str()),
+                RuleTarget::Args => Some(self.args.as_str()),
+                RuleTarget::Unknown(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn args_text(params: &[Param]) -> String {
+    params
+        .iter()
+        .flat_map(|param| {
+            let file = param
+                .filename
+                .as_ref()
+                .map(|filename| format!("{}.filename={}", param.name, filename));
+            std::iter::once(format!("{}={}", param.name, param.value)).chain(file)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 impl Rule {
@@ -87,9 +363,16 @@ impl Rule {
             threat_type,
             confidence,
             action,
+            threat_level: None,
             created_by,
             created_at: Utc::now(),
             description: None,
+            targets: Vec::new(),
+            transforms: Vec::new(),
+            merged_from: Vec::new(),
+            not_before: None,
+            expires_at: None,
+            active_hours: None,
         }
     }
 
@@ -97,6 +380,93 @@ impl Rule {
         self.description = Some(description);
         self
     }
+
+    #[allow(dead_code)] // Used by rule importers and tests
+    pub fn with_threat_level(mut self, threat_level: ThreatLevel) -> Self {
+        self.threat_level = Some(threat_level);
+        self
+    }
+
+    #[allow(dead_code)] // Used by rule importers and tests
+    pub fn with_targets(mut self, targets: Vec<RuleTarget>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    #[allow(dead_code)] // Used by rule importers and tests
+    pub fn with_transforms(mut self, transforms: Vec<Transform>) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Text of `input` the pattern is matched against: the rule's targets with
+    /// its transforms applied
+    pub fn match_text(&self, input: &MatchInput) -> String {
+        let text = input.text_for(&self.targets);
+        transform::apply_all(&text, &self.transforms).into_owned()
+    }
+
+    /// Whether the rule applies at `now` given its validity period and schedule
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> bool {
+        self.not_before.is_none_or(|t| now >= t)
+            && self.expires_at.is_none_or(|t| now < t)
+            && self.active_hours.as_ref().is_none_or(|h| h.contains(now))
+    }
+
+    /// Earliest time after `now` at which the rule may activate or expire
+    pub fn next_activation_change(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        [self.not_before, self.expires_at]
+            .into_iter()
+            .flatten()
+            .filter(|t| *t > now)
+            .chain(self.active_hours.as_ref().map(|h| h.next_boundary(now)))
+            .min()
+    }
+
+    /// Compiles the rule pattern. Patterns match case-insensitively anywhere in
+    /// the target, with a linear-time engine (no backtracking).
+    pub fn compile_pattern(&self) -> Result<Regex> {
+        Regex::builder()
+            .syntax(syntax::Config::new().case_insensitive(true))
+            .configure(meta::Config::new().nfa_size_limit(Some(PATTERN_SIZE_LIMIT)))
+            .build(&self.pattern)
+            .with_context(|| format!("Invalid pattern: {}", self.pattern))
+    }
+}
+
+/// Remembers which rules were active so schedule transitions can be reported
+/// without waiting for a rulebook file change
+#[derive(Debug, Default)]
+pub struct ActivationTracker {
+    active: HashSet<String>,
+}
+
+impl ActivationTracker {
+    /// Re-evaluates activation at `now`, returning the ids of rules that became
+    /// active and of rules that became inactive since the previous call
+    pub fn update(
+        &mut self,
+        rulebook: &Rulebook,
+        now: DateTime<Utc>,
+    ) -> (Vec<String>, Vec<String>) {
+        let active: HashSet<String> = rulebook.active_rules(now).map(|r| r.id.clone()).collect();
+
+        let activated = rulebook
+            .rules
+            .iter()
+            .filter(|r| active.contains(&r.id) && !self.active.contains(&r.id))
+            .map(|r| r.id.clone())
+            .collect();
+        let deactivated = rulebook
+            .rules
+            .iter()
+            .filter(|r| !active.contains(&r.id) && self.active.contains(&r.id))
+            .map(|r| r.id.clone())
+            .collect();
+
+        self.active = active;
+        (activated, deactivated)
+    }
 }
 
 #[cfg(test)]
@@ -177,6 +547,56 @@ mod tests {
         assert_eq!(rulebook.rules.len(), 0);
     }
 
+    #[test]
+    fn test_fingerprint_follows_rule_content() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(Rule::new(
+            "test".to_string(),
+            "xss".to_string(),
+            0.7,
+            RuleAction::Block,
+            "admin".to_string(),
+        ));
+        let now = Utc::now();
+        let fingerprint = rulebook.fingerprint(now);
+        assert_eq!(fingerprint.len(), 16);
+
+        // Same rules loaded elsewhere, with another version and timestamp
+        let mut copy: Rulebook =
+            serde_json::from_str(&serde_json::to_string(&rulebook).unwrap()).unwrap();
+        copy.touch();
+        assert_eq!(copy.fingerprint(now), fingerprint);
+
+        copy.rules[0].confidence = 0.8;
+        assert_ne!(copy.fingerprint(now), fingerprint);
+    }
+
+    #[test]
+    fn test_fingerprint_follows_rule_activation() {
+        let now = Utc::now();
+        let mut rulebook = Rulebook::new();
+        let mut rule = Rule::new(
+            "test".to_string(),
+            "xss".to_string(),
+            0.7,
+            RuleAction::Block,
+            "admin".to_string(),
+        );
+        rule.not_before = Some(now + chrono::Duration::hours(1));
+        rule.expires_at = Some(now + chrono::Duration::hours(2));
+        rulebook.add_rule(rule);
+
+        let pending = rulebook.fingerprint(now);
+        let active = rulebook.fingerprint(now + chrono::Duration::minutes(90));
+        let expired = rulebook.fingerprint(now + chrono::Duration::hours(3));
+        assert_ne!(pending, active);
+        assert_ne!(active, expired);
+        assert_eq!(
+            pending,
+            rulebook.fingerprint(now + chrono::Duration::minutes(30))
+        );
+    }
+
     #[test]
     fn test_remove_nonexistent_rule() {
         let mut rulebook = Rulebook::new();
@@ -272,6 +692,266 @@ mod tests {
         assert_eq!(rule.action, RuleAction::Flag);
         assert_eq!(rule.created_by, "tester");
         assert!(rule.description.is_none());
+        assert!(rule.targets.is_empty());
+    }
+
+    #[test]
+    fn test_rule_target_serialization() {
+        let rule = Rule::new(
+            "UNION.*SELECT".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "admin".to_string(),
+        )
+        .with_targets(vec![RuleTarget::Query, RuleTarget::Body]);
+
+        let json = serde_json::to_string(&rule).unwrap();
+        assert!(json.contains(r#""targets":["query","body"]"#));
+
+        let json = json.replace(r#""body""#, r#""cookies""#);
+        let deserialized: Rule = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            deserialized.targets,
+            vec![
+                RuleTarget::Query,
+                RuleTarget::Unknown("cookies".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rule_without_targets_deserializes() {
+        let json = r#"{
+            "id": "r1",
+            "pattern": "<script",
+            "threat_type": "xss",
+            "confidence": 0.8,
+            "action": "flag",
+            "created_by": "llm",
+            "created_at": "2025-11-06T12:00:00Z"
+        }"#;
+
+        let rule: Rule = serde_json::from_str(json).unwrap();
+        assert!(rule.targets.is_empty());
+    }
+
+    #[test]
+    fn test_compile_pattern_is_case_insensitive() {
+        let rule = Rule::new(
+            "SELECT.*FROM".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "admin".to_string(),
+        );
+
+        let regex = rule.compile_pattern().unwrap();
+        assert!(regex.is_match("id=1 union select password from users"));
+        assert!(!regex.is_match("id=1"));
+    }
+
+    #[test]
+    fn test_compile_pattern_invalid() {
+        let rule = Rule::new(
+            "(unclosed".to_string(),
+            "sqli".to_string(),
+            0.9,
+            RuleAction::Block,
+            "admin".to_string(),
+        );
+
+        assert!(rule.compile_pattern().is_err());
+    }
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    fn flag_rule(pattern: &str) -> Rule {
+        Rule::new(
+            pattern.to_string(),
+            "sqli".to_string(),
+            0.8,
+            RuleAction::Flag,
+            "admin".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_rule_validity_period() {
+        let mut rule = flag_rule("cve-2025-1234");
+        rule.not_before = Some(at("2025-11-01T00:00:00Z"));
+        rule.expires_at = Some(at("2025-11-08T00:00:00Z"));
+
+        assert!(!rule.is_active_at(at("2025-10-31T23:59:59Z")));
+        assert!(rule.is_active_at(at("2025-11-01T00:00:00Z")));
+        assert!(rule.is_active_at(at("2025-11-07T23:59:59Z")));
+        assert!(!rule.is_active_at(at("2025-11-08T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_active_hours() {
+        // 2025-11-06 is a Thursday
+        let office = ActiveHours {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            days: vec![Weekday::Mon, Weekday::Thu],
+        };
+        assert!(office.contains(at("2025-11-06T09:00:00Z")));
+        assert!(!office.contains(at("2025-11-06T17:00:00Z")));
+        assert!(!office.contains(at("2025-11-07T10:00:00Z")));
+
+        // Friday 22:00 to Saturday 06:00
+        let overnight = ActiveHours {
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            days: vec![Weekday::Fri],
+        };
+        assert!(overnight.contains(at("2025-11-07T23:00:00Z")));
+        assert!(overnight.contains(at("2025-11-08T05:59:00Z")));
+        assert!(!overnight.contains(at("2025-11-08T23:00:00Z")));
+        assert!(!overnight.contains(at("2025-11-07T05:00:00Z")));
+    }
+
+    #[test]
+    fn test_next_activation_change() {
+        let mut rulebook = Rulebook::new();
+        let now = at("2025-11-06T12:00:00Z");
+        assert_eq!(rulebook.next_activation_change(now), None);
+
+        let mut campaign = flag_rule("cve-2025-1234");
+        campaign.not_before = Some(at("2025-11-07T00:00:00Z"));
+        campaign.expires_at = Some(at("2025-11-08T00:00:00Z"));
+        rulebook.add_rule(campaign);
+        let mut overnight = flag_rule("/backup");
+        overnight.active_hours = Some(ActiveHours {
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            days: vec![],
+        });
+        rulebook.add_rule(overnight);
+
+        for (now, next) in [
+            ("2025-11-06T12:00:00Z", "2025-11-06T22:00:00Z"),
+            ("2025-11-06T22:00:00Z", "2025-11-07T00:00:00Z"),
+            ("2025-11-07T00:00:00Z", "2025-11-07T06:00:00Z"),
+            ("2025-11-07T23:00:00Z", "2025-11-08T00:00:00Z"),
+            ("2025-11-08T00:00:00Z", "2025-11-08T06:00:00Z"),
+        ] {
+            assert_eq!(rulebook.next_activation_change(at(now)), Some(at(next)));
+        }
+    }
+
+    #[test]
+    fn test_schedule_deserialization() {
+        let json = r#"{
+            "id": "r1",
+            "pattern": "/wp-admin",
+            "threat_type": "scanner",
+            "confidence": 0.9,
+            "action": "block",
+            "created_by": "admin",
+            "created_at": "2025-11-06T12:00:00Z",
+            "expires_at": "2025-12-01T00:00:00Z",
+            "active_hours": { "start": "08:30", "end": "18:00", "days": ["mon", "Tue"] }
+        }"#;
+
+        let rule: Rule = serde_json::from_str(json).unwrap();
+        assert_eq!(rule.expires_at, Some(at("2025-12-01T00:00:00Z")));
+        let hours = rule.active_hours.unwrap();
+        assert_eq!(hours.start, NaiveTime::from_hms_opt(8, 30, 0).unwrap());
+        assert_eq!(hours.days, vec![Weekday::Mon, Weekday::Tue]);
+    }
+
+    #[test]
+    fn test_activation_tracker() {
+        let mut rulebook = Rulebook::new();
+        let mut patch = flag_rule("cve-2025-1234");
+        patch.expires_at = Some(at("2025-11-08T00:00:00Z"));
+        let mut campaign = flag_rule("promo");
+        campaign.not_before = Some(at("2025-11-07T00:00:00Z"));
+        rulebook.add_rule(patch.clone());
+        rulebook.add_rule(campaign.clone());
+
+        let mut tracker = ActivationTracker::default();
+
+        let (activated, deactivated) = tracker.update(&rulebook, at("2025-11-06T00:00:00Z"));
+        assert_eq!(activated, vec![patch.id.clone()]);
+        assert!(deactivated.is_empty());
+
+        let (activated, deactivated) = tracker.update(&rulebook, at("2025-11-07T12:00:00Z"));
+        assert_eq!(activated, vec![campaign.id.clone()]);
+        assert!(deactivated.is_empty());
+
+        let (activated, deactivated) = tracker.update(&rulebook, at("2025-11-08T00:00:00Z"));
+        assert!(activated.is_empty());
+        assert_eq!(deactivated, vec![patch.id.clone()]);
+
+        let active: Vec<_> = rulebook.active_rules(at("2025-11-08T00:00:00Z")).collect();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id, campaign.id);
+    }
+
+    #[test]
+    fn test_match_input_text_for_targets() {
+        let log = LogEntry {
+            id: 1,
+            timestamp: 0,
+            method: "POST".to_string(),
+            path: "/login".to_string(),
+            payload_hash: "abc".to_string(),
+            decision: "flag".to_string(),
+            confidence: 0.6,
+            reason: None,
+            ip_addr: None,
+            user_agent: None,
+            request_context: Some(
+                r#"{"query":[["next","/admin"]],"headers":{"user-agent":"curl"},"body_excerpt":"user=admin'--","params":[{"source":"form","name":"user","value":"admin'--"}]}"#
+                    .to_string(),
+            ),
+        };
+
+        let input = MatchInput::from_log(&log);
+
+        assert_eq!(input.text_for(&[RuleTarget::Path]), "/login");
+        assert_eq!(input.text_for(&[RuleTarget::Query]), "next=/admin");
+        assert_eq!(
+            input.text_for(&[RuleTarget::Headers, RuleTarget::Body]),
+            "user-agent: curl\nuser=admin'--"
+        );
+        assert_eq!(
+            input.text_for(&[]),
+            "/login\nnext=/admin\nuser-agent: curl\nuser=admin'--"
+        );
+        // Named inputs come from the stored params, not the body excerpt
+        assert_eq!(input.text_for(&[RuleTarget::Args]), "user=admin'--");
+    }
+
+    #[test]
+    fn test_match_input_args() {
+        let mut headers = std::collections::HashMap::new();
+        headers.insert("content-type".to_string(), "application/json".to_string());
+        headers.insert("cookie".to_string(), "role=admin".to_string());
+        let payload = RequestPayload::new(
+            "POST".to_string(),
+            "/api/users".to_string(),
+            headers,
+            Some(r#"{"user":{"name":"x' OR 1=1--"}}"#.to_string()),
+            std::collections::HashMap::new(),
+            None,
+        )
+        .with_raw_query(Some("id=1&id=2&debug".to_string()));
+
+        let input = MatchInput::from_payload(&payload).with_args(&payload);
+
+        assert_eq!(
+            input.text_for(&[RuleTarget::Args]),
+            "id=1\nid=2\ndebug=\nuser.name=x' OR 1=1--\nrole=admin"
+        );
+        // Not part of the whole-request text
+        assert!(!input.text_for(&[]).contains("user.name"));
+        assert_eq!(RuleTarget::from("ARGS".to_string()), RuleTarget::Args);
     }
 
     #[test]
@@ -0,0 +1,252 @@
+use crate::core::rulebook::Rule;
+use crate::core::steering::SteeringMatch;
+use crate::models::decision::{JudgeDecision, RuleAction, ThreatLevel};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Score of a signal at full confidence, by threat level (the OWASP CRS
+/// critical/error/warning/notice anomaly scores)
+pub fn severity_score(level: ThreatLevel) -> f32 {
+    match level {
+        ThreatLevel::Critical => 5.0,
+        ThreatLevel::High => 4.0,
+        ThreatLevel::Medium => 3.0,
+        ThreatLevel::Low => 2.0,
+    }
+}
+
+/// Threat level a local rule hit counts as: the rule's own, else derived
+/// from its action
+fn rule_threat_level(rule: &Rule) -> ThreatLevel {
+    rule.threat_level.unwrap_or(match rule.action {
+        RuleAction::Block => ThreatLevel::Critical,
+        RuleAction::Flag => ThreatLevel::Medium,
+    })
+}
+
+/// One signal added to the anomaly score
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreContribution {
+    /// `rule:<id>` or `llm`
+    pub source: String,
+    pub threat_level: ThreatLevel,
+    pub confidence: f32,
+    pub score: f32,
+    pub reason: String,
+}
+
+/// Anomaly score of a request with every contribution, kept with the event
+/// so thresholds can be tuned from real traffic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreBreakdown {
+    pub contributions: Vec<ScoreContribution>,
+    pub total: f32,
+    pub block_threshold: f32,
+    pub flag_threshold: f32,
+}
+
+impl ScoreBreakdown {
+    pub fn new(block_threshold: f32, flag_threshold: f32) -> Self {
+        Self {
+            contributions: Vec::new(),
+            total: 0.0,
+            block_threshold,
+            flag_threshold,
+        }
+    }
+
+    /// Adds a local rule hit: `confidence` × the score of its threat level
+    pub fn add_rule(&mut self, rule: &Rule) {
+        let threat_level = rule_threat_level(rule);
+        self.push(ScoreContribution {
+            source: format!("rule:{}", rule.id),
+            threat_level,
+            confidence: rule.confidence,
+            score: rule.confidence * severity_score(threat_level),
+            reason: format!(
+                "{}: {}",
+                rule.threat_type,
+                rule.description.as_deref().unwrap_or(&rule.pattern)
+            ),
+        });
+    }
+
+    /// Adds the LLM verdict, weighted by `weight`. Allow verdicts add nothing.
+    pub fn add_verdict(&mut self, verdict: &JudgeDecision, weight: f32) {
+        let (threat_level, reason) = match verdict {
+            JudgeDecision::Allow { .. } => return,
+            JudgeDecision::Flag { reason, .. } => (ThreatLevel::Medium, reason),
+            JudgeDecision::Block {
+                threat_level,
+                reason,
+                ..
+            } => (*threat_level, reason),
+        };
+
+        self.push(ScoreContribution {
+            source: "llm".to_string(),
+            threat_level,
+            confidence: verdict.confidence(),
+            score: verdict.confidence() * severity_score(threat_level) * weight,
+            reason: reason.clone(),
+        });
+    }
+
+    /// Adds an LLM steering attempt, scored as a certain medium-level signal
+    pub fn add_steering(&mut self, steering: &SteeringMatch) {
+        self.push(ScoreContribution {
+            source: "steering".to_string(),
+            threat_level: ThreatLevel::Medium,
+            confidence: 1.0,
+            score: severity_score(ThreatLevel::Medium),
+            reason: steering.reason(),
+        });
+    }
+
+    fn push(&mut self, contribution: ScoreContribution) {
+        self.total += contribution.score;
+        self.contributions.push(contribution);
+    }
+
+    pub fn blocks(&self) -> bool {
+        self.total >= self.block_threshold
+    }
+
+    pub fn flags(&self) -> bool {
+        self.total >= self.flag_threshold
+    }
+
+    /// Decision for the current total. Block and flag decisions carry the
+    /// highest contributing confidence and threat level; allow decisions are
+    /// as confident as the score is far below the flag threshold.
+    pub fn decision(&self) -> JudgeDecision {
+        let confidence = self
+            .contributions
+            .iter()
+            .map(|c| c.confidence)
+            .fold(0.0, f32::max);
+
+        if self.blocks() {
+            let threat_level = self
+                .contributions
+                .iter()
+                .map(|c| c.threat_level)
+                .max_by_key(|level| severity_score(*level) as u32)
+                .unwrap_or(ThreatLevel::Medium);
+            JudgeDecision::Block {
+                confidence,
+                reason: self.reason(),
+                threat_level,
+            }
+        } else if self.flags() {
+            JudgeDecision::Flag {
+                confidence,
+                reason: self.reason(),
+                suggested_rule: None,
+            }
+        } else {
+            JudgeDecision::Allow {
+                confidence: (1.0 - self.total / self.flag_threshold).clamp(0.0, 1.0),
+            }
+        }
+    }
+
+    fn reason(&self) -> String {
+        let reasons: Vec<&str> = self
+            .contributions
+            .iter()
+            .map(|c| c.reason.as_str())
+            .collect();
+        format!("Anomaly score {} ({})", self, reasons.join("; "))
+    }
+}
+
+/// `4.5/5 [rule:abc=4.5 llm=0.0]`
+impl fmt::Display for ScoreBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .contributions
+            .iter()
+            .map(|c| format!("{}={:.1}", c.source, c.score))
+            .collect();
+        write!(
+            f,
+            "{:.1}/{:.1} [{}]",
+            self.total,
+            self.block_threshold,
+            parts.join(" ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(action: RuleAction, confidence: f32) -> Rule {
+        Rule::new(
+            "union.*select".to_string(),
+            "sqli".to_string(),
+            confidence,
+            action,
+            "test".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_rule_hits_are_weighted() {
+        let mut score = ScoreBreakdown::new(5.0, 3.0);
+        score.add_rule(&rule(RuleAction::Block, 0.8));
+        assert_eq!(score.total, 4.0);
+        assert!(matches!(score.decision(), JudgeDecision::Flag { .. }));
+
+        score.add_rule(&rule(RuleAction::Flag, 0.5));
+        assert_eq!(score.total, 5.5);
+
+        // A configured threat level overrides the one derived from the action
+        let mut low = ScoreBreakdown::new(5.0, 3.0);
+        low.add_rule(&rule(RuleAction::Block, 1.0).with_threat_level(ThreatLevel::Low));
+        assert_eq!(low.total, 2.0);
+        assert_eq!(low.contributions[0].threat_level, ThreatLevel::Low);
+        let decision = score.decision();
+        assert!(decision.is_block());
+        assert_eq!(decision.confidence(), 0.8);
+        assert!(matches!(
+            decision,
+            JudgeDecision::Block {
+                threat_level: ThreatLevel::Critical,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_llm_verdict_is_weighted() {
+        let mut score = ScoreBreakdown::new(5.0, 3.0);
+        score.add_verdict(&JudgeDecision::Allow { confidence: 0.9 }, 2.0);
+        assert!(score.contributions.is_empty());
+        assert_eq!(score.decision(), JudgeDecision::Allow { confidence: 1.0 });
+
+        score.add_verdict(
+            &JudgeDecision::Block {
+                confidence: 0.5,
+                reason: "xss".to_string(),
+                threat_level: ThreatLevel::High,
+            },
+            2.0,
+        );
+        assert_eq!(score.total, 4.0);
+        assert_eq!(score.contributions[0].source, "llm");
+        assert!(matches!(score.decision(), JudgeDecision::Flag { .. }));
+    }
+
+    #[test]
+    fn test_breakdown_display() {
+        let mut score = ScoreBreakdown::new(5.0, 3.0);
+        let hit = rule(RuleAction::Flag, 0.5);
+        score.add_rule(&hit);
+
+        assert_eq!(score.to_string(), format!("1.5/5.0 [rule:{}=1.5]", hit.id));
+        assert_eq!(score.decision(), JudgeDecision::Allow { confidence: 0.5 });
+    }
+}
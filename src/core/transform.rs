@@ -0,0 +1,281 @@
+use crate::models::canonical;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Normalization applied to the request text before a rule pattern is matched,
+/// named after the equivalent ModSecurity `t:` transform
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Transform {
+    Lowercase,
+    UrlDecode,
+    HtmlEntityDecode,
+    CompressWhitespace,
+    RemoveWhitespace,
+    RemoveNulls,
+    ReplaceComments,
+    NormalizePath,
+    Trim,
+    /// Unrecognized transform name, kept so validation can report it
+    Unknown(String),
+}
+
+impl Transform {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Transform::Lowercase => "lowercase",
+            Transform::UrlDecode => "urlDecodeUni",
+            Transform::HtmlEntityDecode => "htmlEntityDecode",
+            Transform::CompressWhitespace => "compressWhitespace",
+            Transform::RemoveWhitespace => "removeWhitespace",
+            Transform::RemoveNulls => "removeNulls",
+            Transform::ReplaceComments => "replaceComments",
+            Transform::NormalizePath => "normalizePath",
+            Transform::Trim => "trim",
+            Transform::Unknown(name) => name,
+        }
+    }
+
+    /// Applies the transform to `input`
+    pub fn apply<'a>(&self, input: Cow<'a, str>) -> Cow<'a, str> {
+        match self {
+            Transform::Lowercase => Cow::Owned(input.to_lowercase()),
+            Transform::UrlDecode => Cow::Owned(url_decode(&input)),
+            Transform::HtmlEntityDecode => Cow::Owned(html_entity_decode(&input)),
+            Transform::CompressWhitespace => {
+                Cow::Owned(input.split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            Transform::RemoveWhitespace => {
+                Cow::Owned(input.chars().filter(|c| !c.is_whitespace()).collect())
+            }
+            Transform::RemoveNulls => Cow::Owned(input.replace('\0', "")),
+            Transform::ReplaceComments => Cow::Owned(replace_comments(&input)),
+            Transform::NormalizePath => Cow::Owned(canonical::normalize_path(&input)),
+            Transform::Trim => Cow::Owned(input.trim().to_string()),
+            Transform::Unknown(_) => input,
+        }
+    }
+}
+
+impl From<String> for Transform {
+    fn from(value: String) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "lowercase" => Transform::Lowercase,
+            "urldecode" | "urldecodeuni" => Transform::UrlDecode,
+            "htmlentitydecode" => Transform::HtmlEntityDecode,
+            "compresswhitespace" => Transform::CompressWhitespace,
+            "removewhitespace" => Transform::RemoveWhitespace,
+            "removenulls" => Transform::RemoveNulls,
+            "replacecomments" => Transform::ReplaceComments,
+            "normalizepath" | "normalisepath" => Transform::NormalizePath,
+            "trim" => Transform::Trim,
+            _ => Transform::Unknown(value),
+        }
+    }
+}
+
+impl From<Transform> for String {
+    fn from(value: Transform) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+/// Applies `transforms` in order
+pub fn apply_all<'a>(input: &'a str, transforms: &[Transform]) -> Cow<'a, str> {
+    transforms
+        .iter()
+        .fold(Cow::Borrowed(input), |text, transform| {
+            transform.apply(text)
+        })
+}
+
+/// Percent-decodes `%XX` (and `%uXXXX`) sequences and turns `+` into a space
+fn url_decode(input: &str) -> String {
+    let plus_decoded = input.replace('+', " ");
+    let mut out = String::with_capacity(plus_decoded.len());
+    let mut rest = plus_decoded.as_str();
+
+    // %uXXXX is an IIS-specific encoding that ModSecurity's urlDecodeUni handles
+    while let Some(idx) = rest.find("%u") {
+        let (head, tail) = rest.split_at(idx);
+        out.push_str(&String::from_utf8_lossy(&urlencoding::decode_binary(
+            head.as_bytes(),
+        )));
+        match tail
+            .get(2..6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+        {
+            Some(c) => {
+                out.push(c);
+                rest = &tail[6..];
+            }
+            None => {
+                out.push_str("%u");
+                rest = &tail[2..];
+            }
+        }
+    }
+    out.push_str(&String::from_utf8_lossy(&urlencoding::decode_binary(
+        rest.as_bytes(),
+    )));
+
+    out
+}
+
+/// Decodes numeric entities and the handful of named entities used in attacks
+fn html_entity_decode(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(idx) = rest.find('&') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        let end = rest
+            .char_indices()
+            .take(12)
+            .find(|(_, c)| *c == ';')
+            .map(|(i, _)| i);
+        let decoded = end.and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(num) = entity.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    match entity.to_ascii_lowercase().as_str() {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        "colon" => Some(':'),
+        "lpar" => Some('('),
+        "rpar" => Some(')'),
+        "sol" => Some('/'),
+        "tab" => Some('\t'),
+        "newline" => Some('\n'),
+        _ => None,
+    }
+}
+
+/// Replaces each C-style `/* ... */` comment with a space (an unterminated
+/// comment runs to the end of the input)
+fn replace_comments(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        out.push(' ');
+        match rest[start + 2..].find("*/") {
+            Some(end) => rest = &rest[start + 2 + end + 2..],
+            None => rest = "",
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(transform: Transform, input: &str) -> String {
+        transform.apply(Cow::Borrowed(input)).into_owned()
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for name in [
+            "lowercase",
+            "urlDecodeUni",
+            "htmlEntityDecode",
+            "normalizePath",
+        ] {
+            let transform = Transform::from(name.to_string());
+            assert!(!matches!(transform, Transform::Unknown(_)));
+            assert_eq!(transform.as_str(), name);
+        }
+        assert_eq!(
+            Transform::from("urlDecode".to_string()),
+            Transform::UrlDecode
+        );
+        assert_eq!(
+            Transform::from("base64Decode".to_string()),
+            Transform::Unknown("base64Decode".to_string())
+        );
+    }
+
+    #[test]
+    fn test_url_decode() {
+        assert_eq!(apply(Transform::UrlDecode, "a%20b+c%27"), "a b c'");
+        assert_eq!(apply(Transform::UrlDecode, "%u003cscript"), "<script");
+        assert_eq!(apply(Transform::UrlDecode, "100%"), "100%");
+        assert_eq!(apply(Transform::UrlDecode, "%uZZ"), "%uZZ");
+    }
+
+    #[test]
+    fn test_html_entity_decode() {
+        assert_eq!(
+            apply(Transform::HtmlEntityDecode, "&lt;script&gt;&#97;&#x6C;ert"),
+            "<script>alert"
+        );
+        assert_eq!(
+            apply(Transform::HtmlEntityDecode, "a & b &bogus;"),
+            "a & b &bogus;"
+        );
+    }
+
+    #[test]
+    fn test_whitespace_and_comments() {
+        assert_eq!(
+            apply(Transform::ReplaceComments, "union/**/select/*x*/1/*"),
+            "union select 1 "
+        );
+        assert_eq!(apply(Transform::CompressWhitespace, " a \t\n b "), "a b");
+        assert_eq!(apply(Transform::RemoveWhitespace, " a \t b "), "ab");
+        assert_eq!(apply(Transform::RemoveNulls, "a\0b"), "ab");
+        assert_eq!(apply(Transform::Trim, "  a  "), "a");
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(apply(Transform::NormalizePath, "/a/./b/../c//d"), "/a/c/d");
+        assert_eq!(
+            apply(Transform::NormalizePath, "/../../etc/passwd"),
+            "/etc/passwd"
+        );
+        assert_eq!(apply(Transform::NormalizePath, "/a/b/"), "/a/b/");
+    }
+
+    #[test]
+    fn test_apply_all_in_order() {
+        let transforms = vec![Transform::UrlDecode, Transform::Lowercase];
+        assert_eq!(apply_all("%3CSCRIPT%3E", &transforms), "<script>");
+        assert_eq!(apply_all("unchanged", &[]), "unchanged");
+    }
+}
@@ -0,0 +1,580 @@
+use crate::core::rulebook::{Rule, RuleTarget, Rulebook};
+use crate::core::transform::Transform;
+use chrono::Utc;
+use regex_syntax::ast::{self, Ast, RepetitionKind, RepetitionRange};
+use regex_syntax::hir::{Hir, HirKind};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Ordinary inputs of different shapes: a pattern matching every one of them
+/// matches nearly any request
+const MATCH_EVERYTHING_SAMPLES: &[&str] = &["GET /index.html", "hello", "42", "/", " ", "-"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The rule must not be loaded or applied
+    Error,
+    /// The rule works but is redundant or suspicious
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A problem found in a rule by the validator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleIssue {
+    pub rule_id: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl RuleIssue {
+    fn error(rule: &Rule, message: String) -> Self {
+        Self {
+            rule_id: rule.id.clone(),
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(rule: &Rule, message: String) -> Self {
+        Self {
+            rule_id: rule.id.clone(),
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for RuleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {}",
+            self.severity.as_str(),
+            self.rule_id,
+            self.message
+        )
+    }
+}
+
+pub fn has_errors(issues: &[RuleIssue]) -> bool {
+    issues.iter().any(|i| i.severity == Severity::Error)
+}
+
+/// Checks a single rule on its own: pattern syntax, backtracking risk,
+/// match-everything patterns, confidence range, targets and activation window.
+pub fn validate_rule(rule: &Rule) -> Vec<RuleIssue> {
+    let mut issues = Vec::new();
+
+    if let (Some(not_before), Some(expires_at)) = (rule.not_before, rule.expires_at) {
+        if not_before >= expires_at {
+            issues.push(RuleIssue::error(
+                rule,
+                "not_before is not earlier than expires_at, the rule is never active".to_string(),
+            ));
+        }
+    }
+    if let Some(expires_at) = rule.expires_at {
+        if expires_at <= Utc::now() {
+            issues.push(RuleIssue::warning(
+                rule,
+                format!("expired at {}", expires_at.to_rfc3339()),
+            ));
+        }
+    }
+    if let Some(hours) = &rule.active_hours {
+        if hours.start == hours.end {
+            issues.push(RuleIssue::error(
+                rule,
+                "active_hours start and end are equal".to_string(),
+            ));
+        }
+    }
+
+    if !(0.0..=1.0).contains(&rule.confidence) {
+        issues.push(RuleIssue::error(
+            rule,
+            format!("confidence {} is outside 0..1", rule.confidence),
+        ));
+    }
+
+    for target in &rule.targets {
+        if let RuleTarget::Unknown(name) = target {
+            issues.push(RuleIssue::error(rule, format!("unknown target '{}'", name)));
+        }
+    }
+
+    for transform in &rule.transforms {
+        if let Transform::Unknown(name) = transform {
+            issues.push(RuleIssue::error(
+                rule,
+                format!("unknown transform '{}'", name),
+            ));
+        }
+    }
+
+    if rule.pattern.trim().is_empty() {
+        issues.push(RuleIssue::error(rule, "pattern is empty".to_string()));
+        return issues;
+    }
+
+    let regex = match rule.compile_pattern() {
+        Ok(regex) => regex,
+        Err(e) => {
+            issues.push(RuleIssue::error(rule, format!("{:#}", e)));
+            return issues;
+        }
+    };
+
+    // An unanchored pattern that matches the empty string matches every input
+    if regex.is_match("") {
+        issues.push(RuleIssue::error(
+            rule,
+            "pattern matches everything (it matches the empty string)".to_string(),
+        ));
+    } else if MATCH_EVERYTHING_SAMPLES
+        .iter()
+        .all(|sample| regex.is_match(sample))
+    {
+        issues.push(RuleIssue::error(
+            rule,
+            "pattern matches everything (it matches any character)".to_string(),
+        ));
+    }
+
+    // Safe with the linear-time engine used here, but not once exported
+    if let Ok(ast) = ast::parse::Parser::new().parse(&rule.pattern) {
+        if has_nested_quantifier(&ast, false) {
+            issues.push(RuleIssue::warning(
+                rule,
+                "pattern nests unbounded quantifiers and would backtrack catastrophically \
+                 in PCRE-style engines"
+                    .to_string(),
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Checks a whole rulebook: every rule on its own, plus duplicate ids,
+/// duplicate patterns and rules subsumed by another rule.
+pub fn validate_rulebook(rulebook: &Rulebook) -> Vec<RuleIssue> {
+    let mut issues: Vec<RuleIssue> = rulebook.rules.iter().flat_map(validate_rule).collect();
+
+    let mut seen_ids: HashMap<&str, usize> = HashMap::new();
+    for rule in &rulebook.rules {
+        *seen_ids.entry(rule.id.as_str()).or_default() += 1;
+    }
+    for rule in &rulebook.rules {
+        if seen_ids.get(rule.id.as_str()).copied().unwrap_or_default() > 1 {
+            issues.push(RuleIssue::error(rule, "duplicate rule id".to_string()));
+            seen_ids.remove(rule.id.as_str());
+        }
+    }
+
+    for (idx, rule) in rulebook.rules.iter().enumerate() {
+        if let Some(issue) = overlap_issue(rule, &rulebook.rules[..idx]) {
+            issues.push(issue);
+        } else if let Some(issue) = rulebook.rules[idx + 1..]
+            .iter()
+            .find_map(|other| subsumed_issue(rule, other))
+        {
+            issues.push(issue);
+        }
+    }
+
+    issues
+}
+
+/// Drops the rules that must not be loaded: rules with errors of their own
+/// and rules reusing the id of an earlier rule. Returns the errors of the
+/// dropped rules followed by the warnings about the rules kept.
+pub fn retain_valid_rules(rulebook: &mut Rulebook) -> Vec<RuleIssue> {
+    let mut issues = Vec::new();
+    let mut ids = HashSet::new();
+    rulebook.rules.retain(|rule| {
+        let errors: Vec<RuleIssue> = validate_rule(rule)
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        if !errors.is_empty() {
+            issues.extend(errors);
+            return false;
+        }
+        if !ids.insert(rule.id.clone()) {
+            issues.push(RuleIssue::error(rule, "duplicate rule id".to_string()));
+            return false;
+        }
+        true
+    });
+
+    issues.extend(validate_rulebook(rulebook));
+    issues
+}
+
+/// Checks a rule about to be added to `rulebook`: the rule on its own, plus
+/// duplicates of (or subsumption by) existing rules.
+pub fn validate_addition(rulebook: &Rulebook, rule: &Rule) -> Vec<RuleIssue> {
+    let mut issues = validate_rule(rule);
+    if has_errors(&issues) {
+        return issues;
+    }
+
+    if rulebook.rules.iter().any(|r| r.id == rule.id) {
+        issues.push(RuleIssue::error(rule, "duplicate rule id".to_string()));
+    }
+    if let Some(issue) = overlap_issue(rule, &rulebook.rules) {
+        issues.push(issue);
+    }
+
+    issues
+}
+
+/// Reports `rule` if an earlier rule has the same normalized pattern or subsumes it
+fn overlap_issue(rule: &Rule, others: &[Rule]) -> Option<RuleIssue> {
+    let normalized = normalized_pattern(&rule.pattern)?;
+
+    others.iter().find_map(|other| {
+        if !covers(other, rule) {
+            return None;
+        }
+        if normalized_pattern(&other.pattern).as_deref() == Some(normalized.as_str()) {
+            return Some(RuleIssue::warning(
+                rule,
+                format!("duplicates the pattern of rule {}", other.id),
+            ));
+        }
+        subsumed_issue(rule, other)
+    })
+}
+
+fn subsumed_issue(rule: &Rule, other: &Rule) -> Option<RuleIssue> {
+    if covers(other, rule) && is_subsumed_by(rule, other) {
+        Some(RuleIssue::warning(
+            rule,
+            format!("is subsumed by rule {} ({})", other.id, other.pattern),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Canonical form of a pattern, so `(?:abc)` and `ABC` compare equal
+pub fn normalized_pattern(pattern: &str) -> Option<String> {
+    regex_syntax::ParserBuilder::new()
+        .case_insensitive(true)
+        .build()
+        .parse(pattern)
+        .ok()
+        .map(|hir| hir.to_string())
+}
+
+/// True if every input matched by `rule` is also matched by `other`.
+///
+/// Only decided for literal patterns: when `other` has no anchors or other
+/// assertions, matching the literal means matching every input containing it.
+fn is_subsumed_by(rule: &Rule, other: &Rule) -> bool {
+    let Some(literal) = literal_text(&rule.pattern) else {
+        return false;
+    };
+    let Ok(other_hir) = regex_syntax::parse(&other.pattern) else {
+        return false;
+    };
+    if !other_hir.properties().look_set().is_empty() || other.pattern == rule.pattern {
+        return false;
+    }
+
+    other
+        .compile_pattern()
+        .map(|regex| regex.is_match(literal.as_str()))
+        .unwrap_or(false)
+}
+
+fn literal_text(pattern: &str) -> Option<String> {
+    let hir: Hir = regex_syntax::parse(pattern).ok()?;
+    match hir.kind() {
+        HirKind::Literal(lit) => String::from_utf8(lit.0.to_vec()).ok(),
+        _ => None,
+    }
+}
+
+/// True if `outer` sees the same text as `inner` on every target of `inner`
+fn covers(outer: &Rule, inner: &Rule) -> bool {
+    outer.transforms == inner.transforms && targets_cover(&outer.targets, &inner.targets)
+}
+
+/// True if `outer` applies to every target of `inner` (empty = whole request)
+fn targets_cover(outer: &[RuleTarget], inner: &[RuleTarget]) -> bool {
+    outer.is_empty() || (!inner.is_empty() && inner.iter().all(|t| outer.contains(t)))
+}
+
+/// Detects repetitions of sub-patterns that themselves contain an unbounded
+/// repetition, e.g. `(a+)+` or `(\w*x)*`.
+fn has_nested_quantifier(ast: &Ast, inside_repetition: bool) -> bool {
+    match ast {
+        Ast::Repetition(rep) => {
+            let unbounded = is_unbounded(&rep.op.kind);
+            if unbounded && inside_repetition {
+                return true;
+            }
+            let repeats = !matches!(
+                rep.op.kind,
+                RepetitionKind::ZeroOrOne
+                    | RepetitionKind::Range(RepetitionRange::Exactly(1))
+                    | RepetitionKind::Range(RepetitionRange::Bounded(_, 1))
+            );
+            has_nested_quantifier(&rep.ast, inside_repetition || repeats)
+        }
+        Ast::Group(group) => has_nested_quantifier(&group.ast, inside_repetition),
+        Ast::Alternation(alt) => alt
+            .asts
+            .iter()
+            .any(|a| has_nested_quantifier(a, inside_repetition)),
+        Ast::Concat(concat) => concat
+            .asts
+            .iter()
+            .any(|a| has_nested_quantifier(a, inside_repetition)),
+        _ => false,
+    }
+}
+
+fn is_unbounded(kind: &RepetitionKind) -> bool {
+    matches!(
+        kind,
+        RepetitionKind::ZeroOrMore
+            | RepetitionKind::OneOrMore
+            | RepetitionKind::Range(RepetitionRange::AtLeast(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::decision::RuleAction;
+
+    fn rule(pattern: &str) -> Rule {
+        Rule::new(
+            pattern.to_string(),
+            "sqli".to_string(),
+            0.8,
+            RuleAction::Block,
+            "test".to_string(),
+        )
+    }
+
+    fn messages(issues: &[RuleIssue]) -> String {
+        issues
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_valid_rule() {
+        assert!(validate_rule(&rule(r"union\s+select")).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let issues = validate_rule(&rule("(select"));
+        assert!(has_errors(&issues));
+        assert!(messages(&issues).contains("Invalid pattern"));
+    }
+
+    #[test]
+    fn test_empty_pattern() {
+        let issues = validate_rule(&rule("  "));
+        assert!(has_errors(&issues));
+        assert!(messages(&issues).contains("empty"));
+    }
+
+    #[test]
+    fn test_match_everything_patterns() {
+        for pattern in [".*", "a*", "(foo)?", "x|", ".+", r"[\s\S]", "(?s)."] {
+            let issues = validate_rule(&rule(pattern));
+            assert!(
+                messages(&issues).contains("matches everything"),
+                "{} should be rejected",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_narrow_patterns_do_not_match_everything() {
+        for pattern in [r"\S", r"\w+", "/", "e"] {
+            assert!(
+                validate_rule(&rule(pattern)).is_empty(),
+                "{} should be accepted",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_catastrophic_backtracking() {
+        for pattern in ["(a+)+b", r"(\w*x)*y", "(?:a|b+){2,}c", "((ab)*)+c"] {
+            let issues = validate_rule(&rule(pattern));
+            assert!(
+                messages(&issues).contains("backtrack"),
+                "{} should be reported",
+                pattern
+            );
+            // The linear-time engine runs it safely
+            assert!(!has_errors(&issues), "{} should be loadable", pattern);
+        }
+        for pattern in [r"(ab)+c", r"(\d{1,3}\.){3}\d{1,3}", "(a+)?b"] {
+            assert!(
+                validate_rule(&rule(pattern)).is_empty(),
+                "{} should be accepted",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_confidence_out_of_range() {
+        let mut r = rule("select");
+        r.confidence = 1.5;
+        assert!(messages(&validate_rule(&r)).contains("outside 0..1"));
+
+        r.confidence = f32::NAN;
+        assert!(has_errors(&validate_rule(&r)));
+    }
+
+    #[test]
+    fn test_unknown_target() {
+        let r = rule("select").with_targets(vec![
+            RuleTarget::Query,
+            RuleTarget::Unknown("cookies".to_string()),
+        ]);
+        let issues = validate_rule(&r);
+        assert!(has_errors(&issues));
+        assert!(messages(&issues).contains("unknown target 'cookies'"));
+    }
+
+    #[test]
+    fn test_activation_window() {
+        let now = Utc::now();
+
+        let mut r = rule("select");
+        r.not_before = Some(now + chrono::Duration::days(2));
+        r.expires_at = Some(now + chrono::Duration::days(1));
+        assert!(messages(&validate_rule(&r)).contains("never active"));
+
+        let mut r = rule("select");
+        r.expires_at = Some(now - chrono::Duration::days(1));
+        let issues = validate_rule(&r);
+        assert!(!has_errors(&issues));
+        assert!(messages(&issues).contains("expired"));
+
+        let mut r = rule("select");
+        r.active_hours = Some(crate::core::rulebook::ActiveHours {
+            start: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            days: vec![],
+        });
+        assert!(has_errors(&validate_rule(&r)));
+    }
+
+    #[test]
+    fn test_rulebook_duplicate_patterns() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("UNION.*SELECT"));
+        rulebook.add_rule(rule("(?:union.*select)"));
+
+        let issues = validate_rulebook(&rulebook);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(issues[0].rule_id, rulebook.rules[1].id);
+        assert!(issues[0].message.contains("duplicates"));
+    }
+
+    #[test]
+    fn test_rulebook_subsumed_rule() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("drop table users"));
+        rulebook.add_rule(rule(r"drop\s+table"));
+
+        let issues = validate_rulebook(&rulebook);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule_id, rulebook.rules[0].id);
+        assert!(issues[0].message.contains("subsumed"));
+    }
+
+    #[test]
+    fn test_anchored_rule_does_not_subsume() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("admin"));
+        rulebook.add_rule(rule("^admin$"));
+
+        assert!(validate_rulebook(&rulebook).is_empty());
+    }
+
+    #[test]
+    fn test_narrower_targets_do_not_subsume() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("drop table"));
+        rulebook.add_rule(rule("drop").with_targets(vec![RuleTarget::Query]));
+
+        assert!(validate_rulebook(&rulebook).is_empty());
+    }
+
+    #[test]
+    fn test_rulebook_duplicate_ids() {
+        let mut rulebook = Rulebook::new();
+        let first = rule("select");
+        let mut second = rule("<script");
+        second.id = first.id.clone();
+        rulebook.add_rule(first);
+        rulebook.add_rule(second);
+
+        let issues = validate_rulebook(&rulebook);
+        assert!(has_errors(&issues));
+        assert!(messages(&issues).contains("duplicate rule id"));
+    }
+
+    #[test]
+    fn test_retain_valid_rules() {
+        let mut rulebook = Rulebook::new();
+        let first = rule("select");
+        let mut reused_id = rule("<script");
+        reused_id.id = first.id.clone();
+        rulebook.add_rule(first);
+        rulebook.add_rule(reused_id);
+        rulebook.add_rule(rule("(unclosed"));
+        rulebook.add_rule(rule("(a+)+b"));
+
+        let issues = retain_valid_rules(&mut rulebook);
+        let patterns: Vec<&str> = rulebook.rules.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, vec!["select", "(a+)+b"]);
+        let errors: Vec<&RuleIssue> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert!(messages(&issues).contains("duplicate rule id"));
+        assert!(messages(&issues).contains("backtrack"));
+    }
+
+    #[test]
+    fn test_validate_addition_rejects_existing_pattern() {
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("union.*select"));
+
+        let issues = validate_addition(&rulebook, &rule("UNION.*SELECT"));
+        assert!(messages(&issues).contains("duplicates"));
+
+        assert!(validate_addition(&rulebook, &rule("<script")).is_empty());
+    }
+}
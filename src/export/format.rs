@@ -0,0 +1,281 @@
+use crate::config::ExportFormat;
+use crate::models::decision::{JudgeDecision, ThreatLevel};
+use crate::models::request::{RequestContext, RequestPayload};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// SD-ID of the syslog structured data; 32473 is the private enterprise
+/// number RFC 5424 reserves for examples and documentation
+const SD_ID: &str = "guardix@32473";
+
+const APP_NAME: &str = "guardix";
+
+/// An evaluated request as handed to the sinks
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportEvent {
+    pub timestamp: DateTime<Utc>,
+    pub decision: &'static str,
+    pub confidence: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threat_level: Option<ThreatLevel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub payload_hash: String,
+    pub ip_addr: Option<String>,
+    pub user_agent: Option<String>,
+    /// Redacted, size-capped copy of the request, as stored in the event log
+    pub request: RequestContext,
+}
+
+impl ExportEvent {
+    /// Takes the request context the event log built, rather than building another
+    pub fn new(
+        payload: &RequestPayload,
+        decision: &JudgeDecision,
+        request: RequestContext,
+    ) -> Self {
+        let (reason, threat_level) = match decision {
+            JudgeDecision::Allow { .. } => (None, None),
+            JudgeDecision::Flag { reason, .. } => (Some(reason.clone()), None),
+            JudgeDecision::Block {
+                reason,
+                threat_level,
+                ..
+            } => (Some(reason.clone()), Some(*threat_level)),
+        };
+
+        Self {
+            timestamp: Utc::now(),
+            decision: decision.decision_type(),
+            confidence: decision.confidence(),
+            threat_level,
+            reason,
+            method: payload.method.clone(),
+            path: payload.path.clone(),
+            payload_hash: payload.normalized_hash.clone(),
+            ip_addr: payload.ip_addr.clone(),
+            user_agent: payload.get_user_agent().cloned(),
+            request,
+        }
+    }
+
+    fn summary(&self) -> &str {
+        self.reason.as_deref().unwrap_or("Request allowed")
+    }
+}
+
+/// Formats an event as a single line, without the line break
+pub fn format_event(event: &ExportEvent, format: ExportFormat, facility: u8) -> String {
+    match format {
+        ExportFormat::Jsonl => serde_json::to_string(event).unwrap_or_default(),
+        ExportFormat::Cef => cef(event),
+        ExportFormat::Syslog => syslog(event, facility),
+    }
+}
+
+/// `CEF:Version|Device Vendor|Device Product|Device Version|Signature ID|Name|Severity|Extension`
+fn cef(event: &ExportEvent) -> String {
+    let severity = match (event.decision, event.threat_level) {
+        (_, Some(ThreatLevel::Critical)) => 10,
+        (_, Some(ThreatLevel::High)) => 8,
+        (_, Some(ThreatLevel::Medium)) => 6,
+        (_, Some(ThreatLevel::Low)) => 4,
+        ("flag", None) => 3,
+        _ => 0,
+    };
+
+    let mut extension = vec![
+        ("rt", event.timestamp.timestamp_millis().to_string()),
+        ("act", event.decision.to_string()),
+        ("requestMethod", event.method.clone()),
+        ("request", event.path.clone()),
+        ("cs1Label", "payloadHash".to_string()),
+        ("cs1", event.payload_hash.clone()),
+        ("cfp1Label", "confidence".to_string()),
+        ("cfp1", event.confidence.to_string()),
+    ];
+    if let Some(threat_level) = event.threat_level {
+        extension.push(("cs2Label", "threatLevel".to_string()));
+        extension.push(("cs2", threat_level.as_str().to_string()));
+    }
+    if let Some(ip_addr) = &event.ip_addr {
+        extension.push(("src", ip_addr.clone()));
+    }
+    if let Some(user_agent) = &event.user_agent {
+        extension.push(("requestClientApplication", user_agent.clone()));
+    }
+    if let Some(reason) = &event.reason {
+        extension.push(("msg", reason.clone()));
+    }
+    let extension: Vec<String> = extension
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, cef_extension_escape(&value)))
+        .collect();
+
+    format!(
+        "CEF:0|Guardix|Guardix|{}|{}|{}|{}|{}",
+        env!("CARGO_PKG_VERSION"),
+        event.decision,
+        cef_header_escape(event.summary()),
+        severity,
+        extension.join(" ")
+    )
+}
+
+fn cef_header_escape(value: &str) -> String {
+    one_line(value).replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_extension_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD-ID params] MSG`
+fn syslog(event: &ExportEvent, facility: u8) -> String {
+    let severity = match (event.decision, event.threat_level) {
+        (_, Some(ThreatLevel::Critical)) => 2,
+        (_, Some(ThreatLevel::High)) => 3,
+        (_, Some(_)) => 4,
+        ("flag", None) => 5,
+        _ => 6,
+    };
+
+    let mut params = vec![
+        ("decision", event.decision.to_string()),
+        ("confidence", event.confidence.to_string()),
+        ("method", event.method.clone()),
+        ("path", event.path.clone()),
+        ("payloadHash", event.payload_hash.clone()),
+    ];
+    if let Some(threat_level) = event.threat_level {
+        params.push(("threatLevel", threat_level.as_str().to_string()));
+    }
+    if let Some(ip_addr) = &event.ip_addr {
+        params.push(("src", ip_addr.clone()));
+    }
+    if let Some(user_agent) = &event.user_agent {
+        params.push(("userAgent", user_agent.clone()));
+    }
+    let params: Vec<String> = params
+        .into_iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, sd_escape(&value)))
+        .collect();
+
+    format!(
+        "<{}>1 {} {} {} {} {} [{} {}] {}",
+        facility as u32 * 8 + severity,
+        event.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        hostname(),
+        APP_NAME,
+        std::process::id(),
+        event.decision,
+        SD_ID,
+        params.join(" "),
+        one_line(event.summary())
+    )
+}
+
+/// Structured data values escape `"`, `\` and `]`
+fn sd_escape(value: &str) -> String {
+    one_line(value)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+/// Line breaks would split the event in line-framed outputs
+fn one_line(value: &str) -> String {
+    value.replace(['\n', '\r'], " ")
+}
+
+/// Host name for the syslog header: `HOSTNAME`, then `/etc/hostname`, else `-`
+fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|name| name.trim().replace(|c: char| !c.is_ascii_graphic(), ""))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "-".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn event() -> ExportEvent {
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/users".to_string(),
+            HashMap::from([("user-agent".to_string(), "curl/8.0".to_string())]),
+            None,
+            HashMap::from([("id".to_string(), "1' OR '1'='1".to_string())]),
+            Some("203.0.113.7".to_string()),
+        );
+        let decision = JudgeDecision::Block {
+            confidence: 0.95,
+            reason: "SQL injection | tautology \"1=1\"]".to_string(),
+            threat_level: ThreatLevel::High,
+        };
+        ExportEvent::new(&payload, &decision, RequestContext::from_payload(&payload))
+    }
+
+    #[test]
+    fn test_jsonl_format() {
+        let line = format_event(&event(), ExportFormat::Jsonl, 16);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["decision"], "block");
+        assert_eq!(value["threat_level"], "high");
+        assert_eq!(value["ip_addr"], "203.0.113.7");
+        assert_eq!(value["request"]["query"][0][1], "1' OR '1'='1");
+    }
+
+    #[test]
+    fn test_cef_format_escapes_header_and_extension() {
+        let line = format_event(&event(), ExportFormat::Cef, 16);
+        let prefix = format!("CEF:0|Guardix|Guardix|{}|block|", env!("CARGO_PKG_VERSION"));
+        assert!(line.starts_with(&prefix), "{}", line);
+        assert!(line.contains("|SQL injection \\| tautology \"1=1\"]|8|"));
+        assert!(line.contains(" act=block "));
+        assert!(line.contains(" src=203.0.113.7"));
+        assert!(line.contains(" requestClientApplication=curl/8.0"));
+        assert!(line.contains(" msg=SQL injection | tautology \"1\\=1\"]"));
+    }
+
+    #[test]
+    fn test_syslog_format_has_structured_data() {
+        let line = format_event(&event(), ExportFormat::Syslog, 16);
+        // local0 (16) * 8 + err (3)
+        assert!(line.starts_with("<131>1 "), "{}", line);
+
+        let fields: Vec<&str> = line.splitn(7, ' ').collect();
+        assert!(fields[1].ends_with('Z'));
+        assert_eq!(fields[3], "guardix");
+        assert_eq!(fields[5], "block");
+        assert!(fields[6].starts_with("[guardix@32473 decision=\"block\" confidence=\"0.95\""));
+        assert!(line.contains(" threatLevel=\"high\" src=\"203.0.113.7\""));
+        assert!(line.ends_with("] SQL injection | tautology \"1=1\"]"));
+
+        let allow = ExportEvent {
+            decision: "allow",
+            threat_level: None,
+            reason: None,
+            path: "/a\"]\\".to_string(),
+            ..event()
+        };
+        let line = format_event(&allow, ExportFormat::Syslog, 23);
+        assert!(line.starts_with("<190>1 "));
+        assert!(line.contains(" path=\"/a\\\"\\]\\\\\" "));
+        assert!(line.ends_with("] Request allowed"));
+    }
+}
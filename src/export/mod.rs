@@ -0,0 +1,3 @@
+pub mod format;
+pub mod sink;
+pub mod transport;
@@ -0,0 +1,921 @@
+use crate::config::{ExportConfig, ExportFormat, OverflowPolicy, SinkConfig};
+use crate::export::format::{format_event, ExportEvent};
+use crate::export::transport::{SendError, Transport};
+use crate::models::decision::{JudgeDecision, ThreatLevel};
+use crate::models::request::{RequestContext, RequestPayload};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+/// Longest pause between redeliveries while a sink stays unavailable; the
+/// retry interval doubles after every failed redelivery up to it
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+enum Message {
+    Event(Box<ExportEvent>),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SinkMetrics {
+    /// Events that matched the sink's filters
+    pub exported_events: AtomicU64,
+    pub delivered_events: AtomicU64,
+    /// Events written to the disk buffer after a failed delivery
+    pub buffered_events: AtomicU64,
+    /// Events dropped with the queue or the disk buffer full
+    pub dropped_events: AtomicU64,
+    /// Events the sink rejected on their own, moved to the dead letters
+    pub dead_lettered_events: AtomicU64,
+    /// Size of the events left in the disk buffer
+    pub buffer_bytes: AtomicU64,
+}
+
+/// An export sink: events matching its filters are queued to a task that
+/// formats and delivers them. When the queue is full, events of the types the
+/// overflow policy drops are dropped and the others wait for room. Events that
+/// can't be delivered go to a disk buffer, redelivered in order every retry
+/// interval (and after a restart), backing off while the sink stays
+/// unavailable; new events queue up behind the buffer until it is empty.
+/// Events the sink rejects on their own go to the dead letters instead.
+pub struct Sink {
+    name: String,
+    decisions: Vec<String>,
+    min_threat_level: Option<ThreatLevel>,
+    overflow: OverflowPolicy,
+    tx: mpsc::Sender<Message>,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl Sink {
+    /// Starts the sink's delivery task
+    pub async fn spawn(config: &SinkConfig, buffer_dir: &Path) -> Result<Self> {
+        let transport = Transport::new(&config.target)?;
+        let buffer = DiskBuffer::open(
+            buffer_dir.join(format!("{}.buffer", config.name)),
+            config.max_buffer_bytes(),
+        )
+        .await?;
+        let metrics = Arc::new(SinkMetrics::default());
+        metrics.buffer_bytes.store(buffer.bytes, Ordering::Relaxed);
+
+        let (tx, rx) = mpsc::channel(config.queue_capacity);
+        let delivery = Delivery {
+            name: config.name.clone(),
+            format: config.format,
+            facility: config.facility,
+            transport,
+            buffer,
+            metrics: Arc::clone(&metrics),
+        };
+        tokio::spawn(delivery.run(rx, config.retry_interval()));
+
+        Ok(Self {
+            name: config.name.clone(),
+            decisions: config.decisions.clone(),
+            min_threat_level: config.min_threat_level,
+            overflow: config.overflow,
+            tx,
+            metrics,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn metrics(&self) -> &SinkMetrics {
+        &self.metrics
+    }
+
+    /// Whether the sink's filters let a decision through
+    pub fn matches(&self, decision: &JudgeDecision) -> bool {
+        if !self.decisions.iter().any(|d| d == decision.decision_type()) {
+            return false;
+        }
+        match (decision, self.min_threat_level) {
+            (JudgeDecision::Block { threat_level, .. }, Some(min)) => *threat_level >= min,
+            _ => true,
+        }
+    }
+
+    /// Queues an event, waiting for room unless the overflow policy drops it
+    async fn send(&self, event: ExportEvent) {
+        self.metrics.exported_events.fetch_add(1, Ordering::Relaxed);
+        let drops = self.overflow.drops(event.decision);
+        let message = Message::Event(Box::new(event));
+
+        let sent = if drops {
+            match self.tx.try_send(message) {
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    self.metrics.dropped_events.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                result => result.is_ok(),
+            }
+        } else {
+            self.tx.send(message).await.is_ok()
+        };
+        if !sent {
+            tracing::error!(sink = %self.name, "Export sink stopped, event not exported");
+        }
+    }
+
+    /// Waits until every event queued before the call is delivered or buffered
+    #[allow(dead_code)] // Used in tests
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.tx.send(Message::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    }
+}
+
+/// The export sinks, fed by the event writer with every evaluated request
+pub struct Exporter {
+    sinks: Vec<Sink>,
+}
+
+impl Exporter {
+    pub async fn new(config: &ExportConfig) -> Result<Self> {
+        let mut sinks = Vec::with_capacity(config.sinks.len());
+        for sink in &config.sinks {
+            sinks.push(
+                Sink::spawn(sink, Path::new(&config.buffer_dir))
+                    .await
+                    .with_context(|| format!("Failed to start export sink {}", sink.name))?,
+            );
+        }
+
+        Ok(Self { sinks })
+    }
+
+    pub fn sinks(&self) -> &[Sink] {
+        &self.sinks
+    }
+
+    /// Whether any sink's filters let a decision through
+    pub fn matches(&self, decision: &JudgeDecision) -> bool {
+        self.sinks.iter().any(|sink| sink.matches(decision))
+    }
+
+    /// Hands an evaluated request to the sinks whose filters match it
+    pub async fn export(
+        &self,
+        payload: &RequestPayload,
+        decision: &JudgeDecision,
+        context: &RequestContext,
+    ) {
+        let mut sinks = self.sinks.iter().filter(|sink| sink.matches(decision));
+        let Some(first) = sinks.next() else {
+            return;
+        };
+
+        let event = ExportEvent::new(payload, decision, context.clone());
+        for sink in sinks {
+            sink.send(event.clone()).await;
+        }
+        first.send(event).await;
+    }
+}
+
+struct Delivery {
+    name: String,
+    format: ExportFormat,
+    facility: u8,
+    transport: Transport,
+    buffer: DiskBuffer,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl Delivery {
+    async fn run(mut self, mut rx: mpsc::Receiver<Message>, retry_interval: Duration) {
+        // The buffer left by a previous run is redelivered at startup
+        let retry = tokio::time::sleep(Duration::ZERO);
+        tokio::pin!(retry);
+        let mut backoff = retry_interval;
+
+        loop {
+            tokio::select! {
+                message = rx.recv() => match message {
+                    Some(Message::Event(event)) => {
+                        let line = format_event(&event, self.format, self.facility);
+                        self.deliver(line).await;
+                    }
+                    Some(Message::Flush(done)) => {
+                        let _ = done.send(());
+                    }
+                    None => break,
+                },
+                () = &mut retry => {
+                    backoff = if self.redeliver().await {
+                        retry_interval
+                    } else {
+                        (backoff * 2).min(MAX_RETRY_BACKOFF.max(retry_interval))
+                    };
+                    retry.as_mut().reset(Instant::now() + backoff);
+                }
+            }
+        }
+    }
+
+    async fn deliver(&mut self, line: String) {
+        // Behind a non-empty buffer, events wait their turn to keep the order
+        if self.buffer.is_empty() {
+            match self.transport.send(&line).await {
+                Ok(()) => {
+                    self.metrics
+                        .delivered_events
+                        .fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Err(SendError::Rejected(e)) => {
+                    if let Err(e) = self.dead_letter(&line, &e).await {
+                        tracing::error!(sink = %self.name, error = %e, "Failed to dead-letter export event");
+                    }
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!(sink = %self.name, error = %e, "Export delivery failed, buffering events");
+                }
+            }
+        }
+
+        match self.buffer.push(&line).await {
+            Ok(true) => {
+                self.metrics.buffered_events.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(false) => {
+                self.metrics.dropped_events.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::error!(sink = %self.name, error = %e, "Failed to buffer export event");
+                self.metrics.dropped_events.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.metrics
+            .buffer_bytes
+            .store(self.buffer.pending(), Ordering::Relaxed);
+    }
+
+    /// Delivers the buffered events, oldest first, until the sink fails;
+    /// events it rejects on their own are moved to the dead letters. The
+    /// buffer is read from where the last redelivery stopped and only
+    /// updated when events left it. Returns false when events were left for
+    /// the sink to come back.
+    async fn redeliver(&mut self) -> bool {
+        if self.buffer.is_empty() {
+            return true;
+        }
+
+        let mut delivered = 0;
+        let mut removed = 0;
+        let result = async {
+            let mut reader = self.buffer.reader().await?;
+            let mut bytes = Vec::new();
+            loop {
+                bytes.clear();
+                let read = reader.read_until(b'\n', &mut bytes).await?;
+                if read == 0 {
+                    return Ok(true);
+                }
+                let line = String::from_utf8_lossy(&bytes);
+                let event = line.trim_end_matches('\n');
+
+                let sent = match std::str::from_utf8(&bytes) {
+                    Ok(_) => self.transport.send(event).await,
+                    Err(e) => Err(SendError::Rejected(anyhow::anyhow!(
+                        "Buffered event is not valid UTF-8: {}",
+                        e
+                    ))),
+                };
+                match sent {
+                    Ok(()) => delivered += 1,
+                    Err(SendError::Rejected(e)) => self.dead_letter(event, &e).await?,
+                    Err(SendError::Unavailable(e)) => {
+                        tracing::debug!(sink = %self.name, error = %e, "Export sink still unavailable");
+                        return Ok(false);
+                    }
+                }
+                removed += 1;
+                self.buffer.consume(read as u64);
+            }
+        }
+        .await;
+        let reachable = result.unwrap_or_else(|e: anyhow::Error| {
+            tracing::error!(sink = %self.name, error = %e, "Failed to redeliver export buffer");
+            false
+        });
+
+        if removed > 0 {
+            if let Err(e) = self.buffer.commit().await {
+                tracing::error!(sink = %self.name, error = %e, "Failed to update export buffer");
+            }
+            self.metrics
+                .delivered_events
+                .fetch_add(delivered, Ordering::Relaxed);
+            if self.buffer.is_empty() {
+                tracing::info!(sink = %self.name, delivered, "Export buffer delivered");
+            } else {
+                tracing::warn!(
+                    sink = %self.name,
+                    delivered,
+                    left_bytes = self.buffer.pending(),
+                    "Export buffer partly delivered"
+                );
+            }
+        }
+        self.metrics
+            .buffer_bytes
+            .store(self.buffer.pending(), Ordering::Relaxed);
+        reachable
+    }
+
+    /// Moves an event the sink rejected to the dead letters, or drops it
+    /// when they are full
+    async fn dead_letter(&mut self, line: &str, error: &anyhow::Error) -> Result<()> {
+        if self.buffer.dead_letter(line).await? {
+            tracing::warn!(
+                sink = %self.name,
+                error = %error,
+                "Export event rejected, moved to the dead letters"
+            );
+            self.metrics
+                .dead_lettered_events
+                .fetch_add(1, Ordering::Relaxed);
+        } else {
+            tracing::error!(
+                sink = %self.name,
+                error = %error,
+                "Export event rejected and dead letters full, event dropped"
+            );
+            self.metrics.dropped_events.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+/// Undelivered event lines of a sink, in a file that survives restarts.
+/// Delivered lines are skipped with an offset, saved next to the file, and
+/// only cut from the file once they make up half of it.
+struct DiskBuffer {
+    path: PathBuf,
+    /// File size
+    bytes: u64,
+    /// Bytes at the start of the file already delivered
+    offset: u64,
+    /// Offset saved in the offset file
+    saved_offset: u64,
+    /// Size of the dead letters file
+    dead_bytes: u64,
+    /// Bound on the lines still to deliver, and on the dead letters
+    max_bytes: u64,
+}
+
+impl DiskBuffer {
+    async fn open(path: PathBuf, max_bytes: u64) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.with_context(|| {
+                format!("Failed to create export buffer directory: {:?}", parent)
+            })?;
+        }
+        let bytes = match tokio::fs::metadata(&path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+        let mut buffer = Self {
+            path,
+            bytes,
+            offset: 0,
+            saved_offset: 0,
+            dead_bytes: 0,
+            max_bytes,
+        };
+        if let Ok(metadata) = tokio::fs::metadata(buffer.dead_letter_path()).await {
+            buffer.dead_bytes = metadata.len();
+        }
+        // An offset past the end belongs to a file since removed
+        let offset = match tokio::fs::read_to_string(buffer.offset_path()).await {
+            Ok(offset) => offset.trim().parse().unwrap_or(0),
+            Err(_) => 0,
+        };
+        if offset <= bytes {
+            buffer.offset = offset;
+            buffer.saved_offset = offset;
+        }
+
+        Ok(buffer)
+    }
+
+    fn offset_path(&self) -> PathBuf {
+        self.path.with_extension("buffer.offset")
+    }
+
+    fn dead_letter_path(&self) -> PathBuf {
+        self.path.with_extension("dead")
+    }
+
+    /// Size of the lines still to deliver
+    fn pending(&self) -> u64 {
+        self.bytes - self.offset
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Appends a line, returning false when the buffer is full
+    async fn push(&mut self, line: &str) -> Result<bool> {
+        let size = line.len() as u64 + 1;
+        if self.pending() + size > self.max_bytes {
+            return Ok(false);
+        }
+
+        append_line(&self.path, line)
+            .await
+            .with_context(|| format!("Failed to write export buffer: {:?}", self.path))?;
+        self.bytes += size;
+
+        Ok(true)
+    }
+
+    /// Reads the lines still to deliver, oldest first
+    async fn reader(&self) -> Result<BufReader<tokio::fs::File>> {
+        let mut file = tokio::fs::File::open(&self.path)
+            .await
+            .with_context(|| format!("Failed to read export buffer: {:?}", self.path))?;
+        file.seek(SeekFrom::Start(self.offset)).await?;
+
+        Ok(BufReader::new(file))
+    }
+
+    /// Marks the next `size` bytes delivered; `commit` records it
+    fn consume(&mut self, size: u64) {
+        self.offset += size;
+    }
+
+    /// Appends a rejected line to the dead letters, `<name>.dead`, returning
+    /// false when they are full
+    async fn dead_letter(&mut self, line: &str) -> Result<bool> {
+        let size = line.len() as u64 + 1;
+        if self.dead_bytes + size > self.max_bytes {
+            return Ok(false);
+        }
+
+        let path = self.dead_letter_path();
+        append_line(&path, line)
+            .await
+            .with_context(|| format!("Failed to write export dead letters: {:?}", path))?;
+        self.dead_bytes += size;
+
+        Ok(true)
+    }
+
+    /// Records the delivered lines: removes the buffer once all of them are,
+    /// cuts them from the file once they are half of it, or saves the offset
+    async fn commit(&mut self) -> Result<()> {
+        if self.offset == self.saved_offset {
+            return Ok(());
+        }
+        if !self.is_empty() && self.offset < self.bytes / 2 {
+            tokio::fs::write(self.offset_path(), self.offset.to_string()).await?;
+            self.saved_offset = self.offset;
+            return Ok(());
+        }
+
+        // The offset goes first: a crash in between redelivers lines rather than skipping some
+        match tokio::fs::remove_file(self.offset_path()).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => self.saved_offset = 0,
+        }
+        if self.is_empty() {
+            tokio::fs::remove_file(&self.path).await?;
+            self.bytes = 0;
+        } else {
+            let tmp = self.path.with_extension("buffer.tmp");
+            let mut reader = self.reader().await?;
+            let mut file = tokio::fs::File::create(&tmp).await?;
+            tokio::io::copy_buf(&mut reader, &mut file).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp, &self.path).await?;
+            self.bytes -= self.offset;
+        }
+        self.offset = 0;
+
+        Ok(())
+    }
+}
+
+async fn append_line(path: &Path, line: &str) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(format!("{}\n", line).as_bytes()).await?;
+    file.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::{TcpListener, UdpSocket, UnixDatagram};
+
+    fn payload(path: &str) -> RequestPayload {
+        RequestPayload::new(
+            "GET".to_string(),
+            path.to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            Some("198.51.100.4".to_string()),
+        )
+    }
+
+    fn block(threat_level: ThreatLevel) -> JudgeDecision {
+        JudgeDecision::Block {
+            confidence: 0.9,
+            reason: "SQL injection".to_string(),
+            threat_level,
+        }
+    }
+
+    async fn export(exporter: &Exporter, payload: &RequestPayload, decision: &JudgeDecision) {
+        exporter
+            .export(payload, decision, &RequestContext::from_payload(payload))
+            .await;
+    }
+
+    fn sink_config(name: &str, format: &str, target: String) -> SinkConfig {
+        let yaml = format!(
+            "name: {}\nformat: {}\ntarget: {}\nretry_interval_seconds: 1\n",
+            name, format, target
+        );
+        serde_yaml_ng::from_str(&yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_syslog_over_udp_filtered_by_decision_and_threat_level() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut config = sink_config(
+            "soc",
+            "syslog",
+            format!("udp://{}", listener.local_addr().unwrap()),
+        );
+        config.min_threat_level = Some(ThreatLevel::High);
+        let exporter = Exporter::new(&ExportConfig {
+            sinks: vec![config],
+            buffer_dir: temp_dir.path().to_string_lossy().to_string(),
+        })
+        .await
+        .unwrap();
+
+        export(
+            &exporter,
+            &payload("/allowed"),
+            &JudgeDecision::Allow { confidence: 0.9 },
+        )
+        .await;
+        export(&exporter, &payload("/low"), &block(ThreatLevel::Low)).await;
+        export(
+            &exporter,
+            &payload("/critical"),
+            &block(ThreatLevel::Critical),
+        )
+        .await;
+        let sink = &exporter.sinks()[0];
+        sink.flush().await;
+
+        let mut buf = [0; 2048];
+        let len = listener.recv(&mut buf).await.unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        // local0 (16) * 8 + crit (2)
+        assert!(message.starts_with("<130>1 "), "{}", message);
+        assert!(message.contains("path=\"/critical\""));
+        assert!(message.contains("src=\"198.51.100.4\""));
+
+        assert_eq!(sink.metrics().exported_events.load(Ordering::Relaxed), 1);
+        assert_eq!(sink.metrics().delivered_events.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cef_over_unix_socket() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("siem.sock");
+        let listener = UnixDatagram::bind(&socket_path).unwrap();
+        let exporter = Exporter::new(&ExportConfig {
+            sinks: vec![sink_config(
+                "arcsight",
+                "cef",
+                format!("unix://{}", socket_path.display()),
+            )],
+            buffer_dir: temp_dir.path().join("buffer").to_string_lossy().to_string(),
+        })
+        .await
+        .unwrap();
+
+        export(&exporter, &payload("/users"), &block(ThreatLevel::Medium)).await;
+        exporter.sinks()[0].flush().await;
+
+        let mut buf = [0; 2048];
+        let len = listener.recv(&mut buf).await.unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(message.starts_with("CEF:0|Guardix|Guardix|"));
+        assert!(message.contains("|block|SQL injection|6|"));
+        assert!(message.contains(" request=/users "));
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_to_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("out/events.jsonl");
+        let mut config = sink_config("file", "jsonl", format!("file://{}", path.display()));
+        config.decisions = vec!["allow".to_string()];
+        let exporter = Exporter::new(&ExportConfig {
+            sinks: vec![config],
+            buffer_dir: temp_dir.path().to_string_lossy().to_string(),
+        })
+        .await
+        .unwrap();
+
+        export(
+            &exporter,
+            &payload("/a"),
+            &JudgeDecision::Allow { confidence: 0.9 },
+        )
+        .await;
+        export(&exporter, &payload("/b"), &block(ThreatLevel::High)).await;
+        export(
+            &exporter,
+            &payload("/c"),
+            &JudgeDecision::Allow { confidence: 0.8 },
+        )
+        .await;
+        exporter.sinks()[0].flush().await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let paths: Vec<String> = content
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|event| event["path"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(paths, vec!["/a", "/c"]);
+    }
+
+    #[tokio::test]
+    async fn test_undelivered_events_buffered_on_disk_and_redelivered_in_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        // A free port, nothing listening yet
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let exporter = Exporter::new(&ExportConfig {
+            sinks: vec![sink_config("soc", "jsonl", format!("tcp://{}", addr))],
+            buffer_dir: temp_dir.path().to_string_lossy().to_string(),
+        })
+        .await
+        .unwrap();
+        let sink = &exporter.sinks()[0];
+
+        for path in ["/1", "/2", "/3"] {
+            export(&exporter, &payload(path), &block(ThreatLevel::High)).await;
+        }
+        sink.flush().await;
+        assert_eq!(sink.metrics().buffered_events.load(Ordering::Relaxed), 3);
+        assert!(temp_dir.path().join("soc.buffer").exists());
+
+        let listener = TcpListener::bind(addr).await.unwrap();
+        export(&exporter, &payload("/4"), &block(ThreatLevel::High)).await;
+
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut paths = Vec::new();
+        for _ in 0..4 {
+            let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+            paths.push(event["path"].as_str().unwrap().to_string());
+        }
+        assert_eq!(paths, vec!["/1", "/2", "/3", "/4"]);
+
+        sink.flush().await;
+        assert_eq!(sink.metrics().delivered_events.load(Ordering::Relaxed), 4);
+        assert_eq!(sink.metrics().buffer_bytes.load(Ordering::Relaxed), 0);
+        assert!(!temp_dir.path().join("soc.buffer").exists());
+    }
+
+    #[tokio::test]
+    async fn test_full_disk_buffer_drops_events() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = sink_config(
+            "soc",
+            "jsonl",
+            format!("unix://{}", temp_dir.path().join("missing.sock").display()),
+        );
+        config.max_buffer_mb = 0;
+        let exporter = Exporter::new(&ExportConfig {
+            sinks: vec![config],
+            buffer_dir: temp_dir.path().to_string_lossy().to_string(),
+        })
+        .await
+        .unwrap();
+
+        export(&exporter, &payload("/"), &block(ThreatLevel::High)).await;
+        let sink = &exporter.sinks()[0];
+        sink.flush().await;
+        assert_eq!(sink.metrics().dropped_events.load(Ordering::Relaxed), 1);
+        assert_eq!(sink.metrics().buffered_events.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_allows_and_waits_with_blocks() {
+        let (tx, mut rx) = mpsc::channel(1);
+        // No delivery task: the queue fills up
+        let sink = Arc::new(Sink {
+            name: "soc".to_string(),
+            decisions: vec!["allow".to_string(), "block".to_string()],
+            min_threat_level: None,
+            overflow: OverflowPolicy::DropAllows,
+            tx,
+            metrics: Arc::new(SinkMetrics::default()),
+        });
+        let event = |path: &str, decision: &JudgeDecision| {
+            let payload = payload(path);
+            ExportEvent::new(&payload, decision, RequestContext::from_payload(&payload))
+        };
+
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        sink.send(event("/queued", &allow)).await;
+        sink.send(event("/dropped", &allow)).await;
+        assert_eq!(sink.metrics().dropped_events.load(Ordering::Relaxed), 1);
+
+        let blocked = {
+            let sink = Arc::clone(&sink);
+            let event = event("/blocked", &block(ThreatLevel::High));
+            tokio::spawn(async move { sink.send(event).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!blocked.is_finished());
+
+        let mut paths = Vec::new();
+        for _ in 0..2 {
+            if let Some(Message::Event(event)) = rx.recv().await {
+                paths.push(event.path);
+            }
+        }
+        blocked.await.unwrap();
+        assert_eq!(paths, vec!["/queued", "/blocked"]);
+        assert_eq!(sink.metrics().dropped_events.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_read_from_offset_and_cut_once_half_delivered() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("soc.buffer");
+        let mut buffer = DiskBuffer::open(path.clone(), 1024).await.unwrap();
+        for line in ["one", "two", "three", "four"] {
+            assert!(buffer.push(line).await.unwrap());
+        }
+
+        let mut line = String::new();
+        let read = buffer
+            .reader()
+            .await
+            .unwrap()
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        assert_eq!(line, "one\n");
+        buffer.consume(read as u64);
+        buffer.commit().await.unwrap();
+        // Only the offset is saved
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 19);
+        assert_eq!(std::fs::read_to_string(buffer.offset_path()).unwrap(), "4");
+
+        // Also after a restart
+        let mut buffer = DiskBuffer::open(path.clone(), 1024).await.unwrap();
+        assert_eq!(buffer.pending(), 15);
+        let mut reader = buffer.reader().await.unwrap();
+        for expected in ["two\n", "three\n"] {
+            line.clear();
+            let read = reader.read_line(&mut line).await.unwrap();
+            assert_eq!(line, expected);
+            buffer.consume(read as u64);
+        }
+        buffer.commit().await.unwrap();
+
+        // Half of it delivered: the rest is kept alone
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "four\n");
+        assert!(!buffer.offset_path().exists());
+        assert_eq!(buffer.pending(), 5);
+
+        buffer.consume(5);
+        buffer.commit().await.unwrap();
+        assert!(buffer.is_empty());
+        assert!(!path.exists());
+    }
+
+    fn delivery(target: String, buffer: DiskBuffer) -> Delivery {
+        Delivery {
+            name: "soc".to_string(),
+            format: ExportFormat::Jsonl,
+            facility: 16,
+            transport: Transport::new(&target).unwrap(),
+            buffer,
+            metrics: Arc::new(SinkMetrics::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_sink_keeps_buffered_events_until_it_recovers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("soc.buffer");
+        let socket_path = temp_dir.path().join("siem.sock");
+        let mut buffer = DiskBuffer::open(path.clone(), 1024).await.unwrap();
+        for line in ["first", "second", "third"] {
+            buffer.push(line).await.unwrap();
+        }
+        let mut delivery = delivery(format!("unix://{}", socket_path.display()), buffer);
+
+        // An outage lasting well over 5 retries
+        for _ in 0..10 {
+            assert!(!delivery.redeliver().await);
+        }
+        assert_eq!(delivery.buffer.offset, 0);
+        assert!(!delivery.buffer.offset_path().exists());
+        assert!(!temp_dir.path().join("soc.dead").exists());
+
+        let listener = UnixDatagram::bind(&socket_path).unwrap();
+        assert!(delivery.redeliver().await);
+
+        let mut buf = [0; 64];
+        for expected in ["first", "second", "third"] {
+            let len = listener.recv(&mut buf).await.unwrap();
+            assert_eq!(std::str::from_utf8(&buf[..len]).unwrap(), expected);
+        }
+        let metrics = &delivery.metrics;
+        assert_eq!(metrics.delivered_events.load(Ordering::Relaxed), 3);
+        assert_eq!(metrics.dead_lettered_events.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.buffer_bytes.load(Ordering::Relaxed), 0);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_events_moved_to_bounded_dead_letters() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("soc.buffer");
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        // A line torn by an earlier crash, between two good ones
+        std::fs::write(&path, b"first\n\xffbad\nsecond\n").unwrap();
+        let buffer = DiskBuffer::open(path.clone(), 16).await.unwrap();
+        let mut delivery = delivery(format!("udp://{}", listener.local_addr().unwrap()), buffer);
+
+        // Rejected lines don't hold up the others
+        assert!(delivery.redeliver().await);
+        let mut buf = [0; 64];
+        for expected in ["first", "second"] {
+            let len = listener.recv(&mut buf).await.unwrap();
+            assert_eq!(std::str::from_utf8(&buf[..len]).unwrap(), expected);
+        }
+        let dead_path = temp_dir.path().join("soc.dead");
+        assert_eq!(
+            std::fs::read_to_string(&dead_path).unwrap(),
+            "\u{FFFD}bad\n"
+        );
+        assert!(!path.exists());
+
+        // A datagram too large is rejected as sent, without being buffered
+        delivery.deliver("x".repeat(70_000)).await;
+        delivery.deliver("y".repeat(70_000)).await;
+        let metrics = &delivery.metrics;
+        assert_eq!(metrics.delivered_events.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.buffered_events.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.dead_lettered_events.load(Ordering::Relaxed), 1);
+        // The dead letters are as bounded as the buffer
+        assert_eq!(metrics.dropped_events.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            std::fs::read_to_string(&dead_path).unwrap(),
+            "\u{FFFD}bad\n"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket, UnixDatagram};
+
+/// Time allowed to connect, or to write one event, before the delivery fails
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest payload of a UDP datagram over IPv4
+const MAX_UDP_PAYLOAD: usize = 65_507;
+
+/// Why an event wasn't delivered
+#[derive(Debug)]
+pub enum SendError {
+    /// The target failed (unreachable, refused, timed out): the event is
+    /// worth retrying once it is back
+    Unavailable(anyhow::Error),
+    /// The event can't be delivered on its own (e.g. too large for a
+    /// datagram), whatever the state of the target
+    Rejected(anyhow::Error),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Unavailable(e) | SendError::Rejected(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+/// Where a sink writes its event lines. Datagram transports send one event
+/// per datagram; streams and files end each event with a line break (RFC 6587
+/// non-transparent framing for syslog over TCP). Connections are opened on
+/// first use and reopened after a failure.
+pub enum Transport {
+    Stdout,
+    File {
+        path: PathBuf,
+        file: Option<File>,
+    },
+    Udp {
+        addr: String,
+        socket: Option<UdpSocket>,
+    },
+    Tcp {
+        addr: String,
+        stream: Option<TcpStream>,
+    },
+    /// Unix datagram socket, like the local syslog socket `/dev/log`
+    Unix {
+        path: PathBuf,
+        socket: Option<UnixDatagram>,
+    },
+}
+
+impl Transport {
+    /// Parses a sink target: `stdout`, `file://`, `udp://`, `tcp://` or `unix://`
+    pub fn new(target: &str) -> Result<Self> {
+        if target == "stdout" {
+            return Ok(Self::Stdout);
+        }
+        let (scheme, rest) = target
+            .split_once("://")
+            .with_context(|| format!("Invalid export target: {}", target))?;
+
+        Ok(match scheme {
+            "file" => Self::File {
+                path: PathBuf::from(rest),
+                file: None,
+            },
+            "udp" => Self::Udp {
+                addr: rest.to_string(),
+                socket: None,
+            },
+            "tcp" => Self::Tcp {
+                addr: rest.to_string(),
+                stream: None,
+            },
+            "unix" => Self::Unix {
+                path: PathBuf::from(rest),
+                socket: None,
+            },
+            _ => anyhow::bail!("Unsupported export target: {}", target),
+        })
+    }
+
+    /// Delivers one event line (without its line break)
+    pub async fn send(&mut self, line: &str) -> Result<(), SendError> {
+        self.check(line).map_err(SendError::Rejected)?;
+        let result = tokio::time::timeout(IO_TIMEOUT, self.write(line))
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out")));
+        if result.is_err() {
+            self.disconnect();
+        }
+        result.map_err(SendError::Unavailable)
+    }
+
+    /// Rejects events the target could never take as they are
+    fn check(&self, line: &str) -> Result<()> {
+        match self {
+            Self::Udp { .. } if line.len() > MAX_UDP_PAYLOAD => {
+                anyhow::bail!(
+                    "Event of {} bytes is too large for a UDP datagram",
+                    line.len()
+                )
+            }
+            Self::Stdout | Self::File { .. } | Self::Tcp { .. } if line.contains('\n') => {
+                anyhow::bail!("Event contains a line break, which would split it in two")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn write(&mut self, line: &str) -> Result<()> {
+        match self {
+            Self::Stdout => write_line(&mut tokio::io::stdout(), line).await,
+            Self::File { path, file } => {
+                if file.is_none() {
+                    if let Some(parent) = path.parent() {
+                        tokio::fs::create_dir_all(parent).await?;
+                    }
+                    let opened = OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&path)
+                        .await
+                        .with_context(|| format!("Failed to open export file: {:?}", path))?;
+                    *file = Some(opened);
+                }
+                write_line(file.as_mut().unwrap(), line).await
+            }
+            Self::Udp { addr, socket } => {
+                if socket.is_none() {
+                    let remote = tokio::net::lookup_host(addr.as_str())
+                        .await?
+                        .next()
+                        .with_context(|| format!("No address for {}", addr))?;
+                    let local = if remote.is_ipv4() {
+                        "0.0.0.0:0"
+                    } else {
+                        "[::]:0"
+                    };
+                    let opened = UdpSocket::bind(local).await?;
+                    opened.connect(remote).await?;
+                    *socket = Some(opened);
+                }
+                socket.as_ref().unwrap().send(line.as_bytes()).await?;
+                Ok(())
+            }
+            Self::Tcp { addr, stream } => {
+                if stream.is_none() {
+                    let opened = TcpStream::connect(addr.as_str())
+                        .await
+                        .with_context(|| format!("Failed to connect to {}", addr))?;
+                    *stream = Some(opened);
+                }
+                write_line(stream.as_mut().unwrap(), line).await
+            }
+            Self::Unix { path, socket } => {
+                if socket.is_none() {
+                    *socket = Some(UnixDatagram::unbound()?);
+                }
+                socket
+                    .as_ref()
+                    .unwrap()
+                    .send_to(line.as_bytes(), &path)
+                    .await
+                    .with_context(|| format!("Failed to send to {:?}", path))?;
+                Ok(())
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        match self {
+            Self::Stdout => {}
+            Self::File { file, .. } => *file = None,
+            Self::Udp { socket, .. } => *socket = None,
+            Self::Tcp { stream, .. } => *stream = None,
+            Self::Unix { socket, .. } => *socket = None,
+        }
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(writer: &mut W, line: &str) -> Result<()> {
+    let mut buf = Vec::with_capacity(line.len() + 1);
+    buf.extend_from_slice(line.as_bytes());
+    buf.push(b'\n');
+    writer.write_all(&buf).await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,578 @@
+use crate::core::escalation::ProviderMetrics;
+use crate::core::feedback::Feedback;
+use crate::core::judge::Judge;
+use crate::export::sink::Exporter;
+use crate::models::label::{EventLabel, LabelKind};
+use crate::storage::cache::CacheFlush;
+use crate::storage::logs::EventStore;
+use crate::storage::retention::Retention;
+use crate::storage::writer::EventWriter;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// State of the admin API, served on its own listener (`admin.listen_addr`)
+#[derive(Clone)]
+pub struct AdminState {
+    pub logs: Arc<dyn EventStore>,
+    pub feedback: Arc<Feedback>,
+    pub judge: Arc<Judge>,
+    /// Log retention, when enabled (for `GET /metrics`)
+    pub retention: Option<Arc<Retention>>,
+    /// Event log writer, when the proxy runs (for `GET /metrics`)
+    pub writer: Option<EventWriter>,
+    /// Export sinks, when configured (for `GET /metrics`)
+    pub exporter: Option<Arc<Exporter>>,
+    /// Bearer token required on every request, when set
+    pub token: Option<String>,
+}
+
+/// Body of `POST /labels`
+#[derive(Debug, Deserialize)]
+pub struct LabelRequest {
+    pub event_id: i64,
+    /// `false_positive` (`fp`) or `false_negative` (`fn`)
+    pub label: String,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn api_error(status: StatusCode, message: impl ToString) -> ApiError {
+    (
+        status,
+        Json(serde_json::json!({ "error": message.to_string() })),
+    )
+}
+
+pub fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/labels", post(create_label))
+        .route("/cache/flush", post(flush_cache))
+        .route("/metrics", get(metrics))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+/// Rejects requests without the configured bearer token
+async fn require_token(
+    State(state): State<AdminState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if let Some(ref token) = state.token {
+        let provided = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if !provided.is_some_and(|provided| tokens_match(provided, token)) {
+            return api_error(StatusCode::UNAUTHORIZED, "Missing or invalid admin token")
+                .into_response();
+        }
+    }
+    next.run(req).await
+}
+
+/// Compares the digests of both tokens in constant time, so neither the
+/// position of the first wrong byte nor the token length leaks through timing
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    let provided = Sha256::digest(provided.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    provided
+        .iter()
+        .zip(expected.iter())
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Labels an event as a false positive or false negative, pinning the
+/// corrected verdict for identical requests
+async fn create_label(
+    State(state): State<AdminState>,
+    Json(request): Json<LabelRequest>,
+) -> Result<(StatusCode, Json<EventLabel>), ApiError> {
+    let kind = LabelKind::parse(&request.label).ok_or_else(|| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown label '{}'", request.label),
+        )
+    })?;
+
+    let event = state
+        .logs
+        .get_event(request.event_id)
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                format!("Event {} not found", request.event_id),
+            )
+        })?;
+    kind.check(&event.decision)
+        .map_err(|e| api_error(StatusCode::CONFLICT, e))?;
+
+    let label = state
+        .feedback
+        .label(&event, kind, request.note.as_deref())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, event_id = event.id, "Failed to label event");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e))
+        })?;
+
+    Ok((StatusCode::CREATED, Json(label)))
+}
+
+/// Drops cached verdicts, all of them or only those under a path prefix
+/// and/or of a decision type
+async fn flush_cache(
+    State(state): State<AdminState>,
+    Json(flush): Json<CacheFlush>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if let Some(ref decision) = flush.decision {
+        if !["allow", "flag", "block"].contains(&decision.as_str()) {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown decision '{}'", decision),
+            ));
+        }
+    }
+
+    let flushed = state.judge.flush_cache(&flush).await.map_err(|e| {
+        tracing::error!(error = %e, "Failed to flush verdict cache");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e))
+    })?;
+
+    Ok(Json(serde_json::json!({ "flushed": flushed })))
+}
+
+/// Counters of the judge and the background tasks (`null` for those
+/// disabled)
+async fn metrics(State(state): State<AdminState>) -> Json<serde_json::Value> {
+    let judge = state.judge.metrics();
+    let counter = |counter: &std::sync::atomic::AtomicU64| counter.load(Ordering::Relaxed);
+    let sinks: Option<serde_json::Map<_, _>> = state.exporter.as_ref().map(|exporter| {
+        exporter
+            .sinks()
+            .iter()
+            .map(|sink| (sink.name().to_string(), serde_json::json!(sink.metrics())))
+            .collect()
+    });
+    let second_opinions: serde_json::Map<_, _> = state
+        .judge
+        .second_opinion_metrics()
+        .into_iter()
+        .map(|(name, metrics)| (name.to_string(), provider_metrics(metrics)))
+        .collect();
+
+    Json(serde_json::json!({
+        "judge": {
+            "total_requests": counter(&judge.total_requests),
+            "pinned_hits": counter(&judge.pinned_hits),
+            "local_rule_hits": counter(&judge.local_rule_hits),
+            "steering_detections": counter(&judge.steering_detections),
+            "cache_hits": counter(&judge.cache_hits),
+            "memory_cache_hits": counter(&judge.memory_cache_hits),
+            "backend_cache_hits": counter(&judge.backend_cache_hits),
+            "cache_misses": counter(&judge.cache_misses),
+            "coalesced_requests": counter(&judge.coalesced_requests),
+            "llm_timeouts": counter(&judge.llm_timeouts),
+            "llm_errors": counter(&judge.llm_errors),
+            "fail_open_count": counter(&judge.fail_open_count),
+            "fail_closed_count": counter(&judge.fail_closed_count),
+            "background_evaluations": counter(&judge.background_evaluations),
+            "llm": provider_metrics(&judge.llm),
+            "escalations": counter(&judge.escalations),
+            "escalations_confirmed": counter(&judge.escalations_confirmed),
+            "escalations_overturned": counter(&judge.escalations_overturned),
+            "second_opinions": second_opinions,
+        },
+        "export": sinks,
+        "retention": state.retention.as_ref().map(|retention| retention.metrics()),
+        "writer": state.writer.as_ref().map(|writer| serde_json::json!({
+            "queue_depth": writer.queue_depth(),
+            "metrics": writer.metrics(),
+        })),
+    }))
+}
+
+fn provider_metrics(metrics: &ProviderMetrics) -> serde_json::Value {
+    serde_json::json!({
+        "calls": metrics.calls.load(Ordering::Relaxed),
+        "errors": metrics.errors.load(Ordering::Relaxed),
+        "timeouts": metrics.timeouts.load(Ordering::Relaxed),
+        "avg_latency_ms": metrics.avg_latency_ms(),
+        "max_latency_ms": metrics.max_latency_ms.load(Ordering::Relaxed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FailMode;
+    use crate::core::judge::Judge;
+    use crate::core::rulebook::Rulebook;
+    use crate::llm::client::mock::MockLlmProvider;
+    use crate::models::decision::JudgeDecision;
+    use crate::models::request::RequestPayload;
+    use crate::storage::logs::LogStore;
+    use crate::storage::memory::MemoryCache;
+    use http_body_util::BodyExt;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+
+    async fn state(dir: &std::path::Path) -> AdminState {
+        let logs: Arc<dyn EventStore> = Arc::new(LogStore::new(dir.join("test.db")).await.unwrap());
+        let judge = Arc::new(
+            Judge::new(
+                Arc::new(MockLlmProvider::new()),
+                None,
+                Arc::new(RwLock::new(Rulebook::new())),
+                Duration::from_secs(1),
+                FailMode::Open,
+            )
+            .await
+            .with_memory_cache(MemoryCache::new(100, Duration::from_secs(60))),
+        );
+        AdminState {
+            feedback: Arc::new(Feedback::new(Arc::clone(&logs), Arc::clone(&judge))),
+            judge,
+            logs,
+            retention: None,
+            writer: None,
+            exporter: None,
+            token: Some("secret".to_string()),
+        }
+    }
+
+    fn post_label(body: serde_json::Value, token: Option<&str>) -> Request<Body> {
+        post("/labels", body, token)
+    }
+
+    fn post(uri: &str, body: serde_json::Value, token: Option<&str>) -> Request<Body> {
+        let mut req = Request::post(uri).header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_label() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state = state(temp_dir.path()).await;
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/health".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+        let id = state
+            .logs
+            .log_event(&payload, &JudgeDecision::Allow { confidence: 0.9 })
+            .await
+            .unwrap();
+        let app = router(state);
+
+        let response = app
+            .clone()
+            .oneshot(post_label(
+                serde_json::json!({ "event_id": id, "label": "fn" }),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        for wrong in ["secreT", "secret2", ""] {
+            let response = app
+                .clone()
+                .oneshot(post_label(
+                    serde_json::json!({ "event_id": id, "label": "fn" }),
+                    Some(wrong),
+                ))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let response = app
+            .clone()
+            .oneshot(post_label(
+                serde_json::json!({ "event_id": id, "label": "fp" }),
+                Some("secret"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = app
+            .clone()
+            .oneshot(post_label(
+                serde_json::json!({ "event_id": id + 1, "label": "fn" }),
+                Some("secret"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .oneshot(post_label(
+                serde_json::json!({ "event_id": id, "label": "fn", "note": "SSRF probe" }),
+                Some("secret"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let label: EventLabel = serde_json::from_slice(&body).unwrap();
+        assert_eq!(label.event_id, id);
+        assert_eq!(label.label, "false_negative");
+        assert_eq!(label.note.as_deref(), Some("SSRF probe"));
+    }
+
+    #[tokio::test]
+    async fn test_flush_cache() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state = state(temp_dir.path()).await;
+        for path in ["/api/users", "/api/orders", "/static/app.js"] {
+            let payload = RequestPayload::new(
+                "GET".to_string(),
+                path.to_string(),
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                None,
+            );
+            state.judge.evaluate(payload).await;
+        }
+        let app = router(state);
+
+        let flush = |body: serde_json::Value| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(post("/cache/flush", body, Some("secret")))
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                (status, body)
+            }
+        };
+
+        let (status, _) = flush(serde_json::json!({ "decision": "maybe" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = flush(serde_json::json!({
+            "path_prefix": "/api/",
+            "decision": "allow"
+        }))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["flushed"], 2);
+
+        let (_, body) = flush(serde_json::json!({})).await;
+        assert_eq!(body["flushed"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        use crate::storage::retention::Retention;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut state = state(temp_dir.path()).await;
+        let retention = Arc::new(Retention::new(
+            Arc::clone(&state.logs),
+            Duration::from_secs(3600),
+            vec![("allow", Duration::from_secs(3600))],
+            100,
+        ));
+        retention.run().await.unwrap();
+        state.retention = Some(retention);
+        let writer = EventWriter::spawn(Arc::clone(&state.logs), &Default::default());
+        writer
+            .log(
+                &RequestPayload::new(
+                    "GET".to_string(),
+                    "/".to_string(),
+                    HashMap::new(),
+                    None,
+                    HashMap::new(),
+                    None,
+                ),
+                &JudgeDecision::Allow { confidence: 0.9 },
+                None,
+                None,
+            )
+            .await;
+        writer.flush().await;
+        state.writer = Some(writer);
+        let request = RequestPayload::new(
+            "GET".to_string(),
+            "/cached".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+        state.judge.evaluate(request.clone()).await;
+        state.judge.evaluate(request).await;
+        let app = router(state);
+
+        let get = |token: Option<&str>| {
+            let mut req = Request::get("/metrics");
+            if let Some(token) = token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            req.body(Body::empty()).unwrap()
+        };
+        let response = app.clone().oneshot(get(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.oneshot(get(Some("secret"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["retention"]["runs"], 1);
+        assert_eq!(body["retention"]["deleted_events"], 0);
+        assert_eq!(body["writer"]["metrics"]["written_events"], 1);
+        assert_eq!(body["writer"]["queue_depth"], 0);
+        assert_eq!(body["judge"]["total_requests"], 2);
+        assert_eq!(body["judge"]["cache_hits"], 1);
+        assert_eq!(body["judge"]["memory_cache_hits"], 1);
+        assert_eq!(body["judge"]["backend_cache_hits"], 0);
+        assert_eq!(body["judge"]["llm"]["calls"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_export_sinks() {
+        use crate::config::ExportConfig;
+        use crate::models::decision::ThreatLevel;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut state = state(temp_dir.path()).await;
+        let listener = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let exporter = Arc::new(
+            Exporter::new(&ExportConfig {
+                sinks: vec![serde_yaml_ng::from_str(&format!(
+                    "name: soc\nformat: syslog\ntarget: udp://{}\n",
+                    listener.local_addr().unwrap()
+                ))
+                .unwrap()],
+                buffer_dir: temp_dir.path().to_string_lossy().to_string(),
+            })
+            .await
+            .unwrap(),
+        );
+        let writer = EventWriter::spawn(Arc::clone(&state.logs), &Default::default())
+            .with_exporter(Arc::clone(&exporter));
+        writer
+            .log(
+                &RequestPayload::new(
+                    "GET".to_string(),
+                    "/admin".to_string(),
+                    HashMap::new(),
+                    None,
+                    HashMap::new(),
+                    None,
+                ),
+                &JudgeDecision::Block {
+                    confidence: 0.95,
+                    reason: "Scanner".to_string(),
+                    threat_level: ThreatLevel::High,
+                },
+                None,
+                None,
+            )
+            .await;
+        exporter.sinks()[0].flush().await;
+        state.exporter = Some(exporter);
+
+        let response = router(state)
+            .oneshot(
+                Request::get("/metrics")
+                    .header(header::AUTHORIZATION, "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["export"]["soc"]["exported_events"], 1);
+        assert_eq!(body["export"]["soc"]["delivered_events"], 1);
+        assert_eq!(body["export"]["soc"]["buffer_bytes"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_second_opinions() {
+        use crate::config::{AgreementPolicy, EscalationConfig};
+        use crate::core::escalation::Escalation;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut state = state(temp_dir.path()).await;
+        let escalation = Escalation::new(EscalationConfig {
+            enabled: true,
+            max_confidence: 1.0,
+            policy: AgreementPolicy::Any,
+            ..EscalationConfig::default()
+        })
+        .with_judge("second", Arc::new(MockLlmProvider::new().with_block()));
+        let judge = Judge::new(
+            Arc::new(MockLlmProvider::new().with_block()),
+            None,
+            Arc::new(RwLock::new(Rulebook::new())),
+            Duration::from_secs(1),
+            FailMode::Open,
+        )
+        .await
+        .with_escalation(escalation);
+        judge
+            .evaluate(RequestPayload::new(
+                "GET".to_string(),
+                "/admin".to_string(),
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                None,
+            ))
+            .await;
+        state.judge = Arc::new(judge);
+
+        let response = router(state)
+            .oneshot(
+                Request::get("/metrics")
+                    .header(header::AUTHORIZATION, "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let second = &body["judge"]["second_opinions"]["second"];
+        assert_eq!(second["calls"], 1);
+        assert_eq!(second["errors"], 0);
+        assert_eq!(second["timeouts"], 0);
+        assert!(second["avg_latency_ms"].is_number());
+    }
+}
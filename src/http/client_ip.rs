@@ -0,0 +1,150 @@
+use anyhow::{bail, Context, Result};
+use std::net::IpAddr;
+
+/// A proxy address or network (`10.0.0.1`, `10.0.0.0/8`, `fd00::/8`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Network {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Network {
+    fn parse(value: &str) -> Result<Self> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid proxy address: {}", value))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid prefix length: {}", value))?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            bail!("Prefix length over {}: {}", max_prefix, value);
+        }
+
+        Ok(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                same_prefix(u32::from(net).into(), u32::from(ip).into(), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                same_prefix(u128::from(net), u128::from(ip), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether the first `prefix` of the `bits` low bits of `a` and `b` match
+fn same_prefix(a: u128, b: u128, prefix: u32, bits: u32) -> bool {
+    prefix == 0 || (a ^ b) >> (bits - prefix) == 0
+}
+
+/// Proxies whose `X-Forwarded-For` header is believed (`waf.trusted_proxies`).
+/// Anyone else could put any address there, so requests from other peers
+/// are attributed to the peer itself.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<Network>,
+}
+
+impl TrustedProxies {
+    pub fn parse(entries: &[String]) -> Result<Self> {
+        let networks = entries
+            .iter()
+            .map(|entry| Network::parse(entry))
+            .collect::<Result<_>>()?;
+        Ok(Self { networks })
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Address of the client connected from `peer`: the peer itself, or
+    /// when it is a trusted proxy, the `X-Forwarded-For` hop right before
+    /// the last trusted one. Unknown without a peer address.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let mut client = peer?;
+        if !self.is_trusted(client) {
+            return Some(client);
+        }
+
+        // Hops are appended by each proxy: walk back from the nearest one
+        for hop in forwarded_for
+            .into_iter()
+            .flat_map(|header| header.rsplit(','))
+        {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !self.is_trusted(ip) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    #[test]
+    fn test_client_ip_trusts_only_configured_proxies() {
+        let proxies =
+            TrustedProxies::parse(&["10.0.0.0/8".to_string(), "192.168.1.5".to_string()]).unwrap();
+
+        // Untrusted peers can't pick their address
+        assert_eq!(
+            proxies.client_ip(ip("203.0.113.7"), Some("198.51.100.1")),
+            ip("203.0.113.7")
+        );
+        // The hop before the trusted proxies is the client, whatever it claims
+        assert_eq!(
+            proxies.client_ip(ip("10.1.2.3"), Some("1.2.3.4, 198.51.100.1, 192.168.1.5")),
+            ip("198.51.100.1")
+        );
+        assert_eq!(proxies.client_ip(ip("10.1.2.3"), None), ip("10.1.2.3"));
+        assert_eq!(
+            proxies.client_ip(ip("10.1.2.3"), Some("garbage, 10.0.0.2")),
+            ip("10.0.0.2")
+        );
+        assert_eq!(proxies.client_ip(None, Some("198.51.100.1")), None);
+
+        // Nothing is trusted by default
+        assert_eq!(
+            TrustedProxies::default().client_ip(ip("10.1.2.3"), Some("198.51.100.1")),
+            ip("10.1.2.3")
+        );
+    }
+
+    #[test]
+    fn test_parse_networks() {
+        let proxies =
+            TrustedProxies::parse(&["fd00::/8".to_string(), "0.0.0.0/0".to_string()]).unwrap();
+        assert!(proxies.is_trusted("fd12::1".parse().unwrap()));
+        assert!(!proxies.is_trusted("fe80::1".parse().unwrap()));
+        assert!(proxies.is_trusted("203.0.113.7".parse().unwrap()));
+
+        for invalid in ["10.0.0.0/33", "proxy.local", "10.0.0.0/x"] {
+            assert!(TrustedProxies::parse(&[invalid.to_string()]).is_err());
+        }
+    }
+}
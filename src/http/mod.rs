@@ -1,2 +1,4 @@
+pub mod admin;
+pub mod client_ip;
 pub mod middleware;
 pub mod proxy;
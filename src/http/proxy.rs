@@ -1,9 +1,14 @@
-use crate::core::judge::Judge;
+use crate::config::AsyncJudgeConfig;
+use crate::core::bans::BanList;
+use crate::core::judge::{Evaluation, Judge};
+use crate::http::client_ip::TrustedProxies;
+use crate::models::canonical::{self, Canonicalizer};
+use crate::models::decision::{JudgeDecision, ThreatLevel};
 use crate::models::request::RequestPayload;
-use crate::storage::logs::LogStore;
+use crate::storage::writer::EventWriter;
 use axum::{
     body::Body,
-    extract::State,
+    extract::{ConnectInfo, State},
     http::{Request, Response, StatusCode, Uri},
     response::IntoResponse,
 };
@@ -11,27 +16,57 @@ use http_body_util::BodyExt;
 use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub judge: Arc<Judge>,
-    pub logs: Arc<LogStore>,
+    pub events: EventWriter,
     pub upstream_url: String,
     pub upstream_client: Client<hyper_util::client::legacy::connect::HttpConnector, Body>,
+    pub async_judge: AsyncJudgeConfig,
+    /// Clients banned after a malicious background verdict
+    pub bans: Option<Arc<BanList>>,
+    pub canonicalizer: Canonicalizer,
+    pub trusted_proxies: TrustedProxies,
 }
 
 impl AppState {
-    pub fn new(judge: Arc<Judge>, logs: Arc<LogStore>, upstream_url: String) -> Self {
+    pub fn new(judge: Arc<Judge>, events: EventWriter, upstream_url: String) -> Self {
         let upstream_client = Client::builder(TokioExecutor::new()).build_http();
 
         Self {
             judge,
-            logs,
+            events,
             upstream_url,
             upstream_client,
+            async_judge: AsyncJudgeConfig::default(),
+            bans: None,
+            canonicalizer: Canonicalizer::default(),
+            trusted_proxies: TrustedProxies::default(),
         }
     }
+
+    /// Headers covered by the request hash (`cache.hash_headers`)
+    pub fn with_hash_headers(mut self, headers: &[String]) -> Self {
+        self.canonicalizer = Canonicalizer::new(headers);
+        self
+    }
+
+    /// Forwards requests to the configured routes before judging them, and
+    /// bans the clients of the ones judged malicious
+    pub fn with_async_judge(mut self, config: AsyncJudgeConfig) -> Self {
+        self.bans = BanList::for_async_judge(&config);
+        self.async_judge = config;
+        self
+    }
+
+    /// Proxies whose `X-Forwarded-For` header gives the client address
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
 }
 
 /// Main proxy handler - evaluates requests and forwards them upstream
@@ -42,30 +77,59 @@ pub async fn proxy_handler(
     // Step 1: Extract and normalize the request
     let (parts, body) = req.into_parts();
 
-    let payload = match extract_payload(&parts, body).await {
-        Ok(p) => p,
-        Err(e) => {
-            tracing::error!(error = %e, "Failed to extract payload");
-            return Err(StatusCode::BAD_REQUEST);
+    let payload =
+        match extract_payload(&parts, body, &state.canonicalizer, &state.trusted_proxies).await {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to extract payload");
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        };
+
+    // Step 2: Refuse banned clients without judging them. The refusal is
+    // logged with the ban reason; the client only gets a generic 403.
+    if let Some(reason) = state.bans.as_ref().and_then(|bans| bans.check(&payload)) {
+        tracing::warn!(
+            method = %payload.method,
+            path = %payload.path,
+            ip = ?payload.ip_addr,
+            "Request from banned client refused"
+        );
+        let evaluation = Evaluation {
+            decision: JudgeDecision::Block {
+                confidence: 1.0,
+                reason: format!("Client banned: {}", reason),
+                threat_level: ThreatLevel::High,
+            },
+            score: None,
+            prompt_version: None,
+        };
+        log_evaluation(&state.events, &payload, &evaluation).await;
+        return Ok(blocked_response("Client banned"));
+    }
+
+    // Step 3: Judge evaluation. Latency-sensitive routes are forwarded now
+    // and judged in the background, unless the local rules block them.
+    let evaluation = if state.async_judge.is_async(&payload.path) {
+        match state.judge.evaluate_local(&payload) {
+            Some(evaluation) => evaluation,
+            None => {
+                judge_after_forward(&state, payload.clone());
+                return forward_to_upstream(&state, parts, payload).await;
+            }
         }
+    } else {
+        state.judge.evaluate_scored(payload.clone()).await
     };
+    let decision = evaluation.decision.clone();
 
-    // Step 2: Judge evaluation
-    let decision = state.judge.evaluate(payload.clone()).await;
-
-    // Step 3: Log event asynchronously (non-blocking)
-    let logs = Arc::clone(&state.logs);
-    let payload_for_log = payload.clone();
-    let decision_for_log = decision.clone();
-    tokio::spawn(async move {
-        if let Err(e) = logs.log_event(&payload_for_log, &decision_for_log).await {
-            tracing::error!(error = %e, "Failed to log event");
-        }
-    });
+    // Step 4: Queue the event for the writer (waits only if the queue is
+    // full and the overflow policy keeps this decision type)
+    log_evaluation(&state.events, &payload, &evaluation).await;
 
-    // Step 4: Act on decision
+    // Step 5: Act on decision
     match decision {
-        crate::models::decision::JudgeDecision::Block { reason, .. } => {
+        JudgeDecision::Block { reason, .. } => {
             tracing::warn!(
                 method = %payload.method,
                 path = %payload.path,
@@ -73,17 +137,7 @@ pub async fn proxy_handler(
                 "Request blocked"
             );
 
-            Ok(Response::builder()
-                .status(StatusCode::FORBIDDEN)
-                .header("Content-Type", "application/json; charset=utf-8")
-                .body(Body::from(
-                    serde_json::json!({
-                        "error": "Request blocked by WAF",
-                        "reason": reason
-                    })
-                    .to_string(),
-                ))
-                .unwrap())
+            Ok(blocked_response(&reason))
         }
         _ => {
             // Allow or Flag - forward to upstream
@@ -92,9 +146,73 @@ pub async fn proxy_handler(
     }
 }
 
+/// Judges an already forwarded request: the verdict is cached and logged
+/// like an inline one, and a confident block bans the client
+fn judge_after_forward(state: &AppState, payload: RequestPayload) {
+    let evaluation = state.judge.evaluate_in_background(payload.clone());
+    let events = state.events.clone();
+    let bans = state.bans.clone();
+    let min_confidence = state.async_judge.ban_min_confidence;
+
+    tokio::spawn(async move {
+        let evaluation = match evaluation.await {
+            Ok(evaluation) => evaluation,
+            Err(e) => {
+                tracing::error!(error = %e, "Background evaluation failed");
+                return;
+            }
+        };
+        log_evaluation(&events, &payload, &evaluation).await;
+
+        let decision = &evaluation.decision;
+        if let JudgeDecision::Block { reason, .. } = decision {
+            let banned = match bans {
+                Some(ref bans) if decision.confidence() >= min_confidence => {
+                    bans.ban(&payload, reason)
+                }
+                _ => Vec::new(),
+            };
+            tracing::warn!(
+                method = %payload.method,
+                path = %payload.path,
+                reason = %reason,
+                banned = ?banned,
+                "Forwarded request judged malicious"
+            );
+        }
+    });
+}
+
+async fn log_evaluation(events: &EventWriter, payload: &RequestPayload, evaluation: &Evaluation) {
+    events
+        .log(
+            payload,
+            &evaluation.decision,
+            evaluation.score.as_ref(),
+            evaluation.prompt_version.as_deref(),
+        )
+        .await;
+}
+
+fn blocked_response(reason: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header("Content-Type", "application/json; charset=utf-8")
+        .body(Body::from(
+            serde_json::json!({
+                "error": "Request blocked by WAF",
+                "reason": reason
+            })
+            .to_string(),
+        ))
+        .unwrap()
+}
+
 async fn extract_payload(
     parts: &http::request::Parts,
     body: Body,
+    canonicalizer: &Canonicalizer,
+    trusted_proxies: &TrustedProxies,
 ) -> anyhow::Result<RequestPayload> {
     let method = parts.method.to_string();
     let path = parts.uri.path().to_string();
@@ -107,18 +225,15 @@ async fn extract_payload(
         }
     }
 
-    // Extract query params
-    let mut query_params = HashMap::new();
-    if let Some(query) = parts.uri.query() {
-        for pair in query.split('&') {
-            if let Some((key, value)) = pair.split_once('=') {
-                query_params.insert(
-                    key.to_string(),
-                    urlencoding::decode(value).unwrap_or_default().to_string(),
-                );
-            }
-        }
-    }
+    // Extract query params (the last value of a repeated key; all of them
+    // are kept in `raw_query`, see `RequestPayload::params`)
+    let query_params: HashMap<String, String> = parts
+        .uri
+        .query()
+        .map(canonical::split_query)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
 
     // Extract body
     let body_bytes = body.collect().await?.to_bytes();
@@ -128,20 +243,20 @@ async fn extract_payload(
         Some(String::from_utf8_lossy(&body_bytes).to_string())
     };
 
-    // Extract IP address
-    let ip_addr = headers
-        .get("x-forwarded-for")
-        .or_else(|| headers.get("X-Forwarded-For"))
-        .map(|s| s.split(',').next().unwrap_or(s).trim().to_string());
-
-    Ok(RequestPayload::new(
-        method,
-        path,
-        headers,
-        body_str,
-        query_params,
-        ip_addr,
-    ))
+    // Extract IP address: the peer's, or the one a trusted proxy forwarded
+    let peer = parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let ip_addr = trusted_proxies
+        .client_ip(peer, headers.get("x-forwarded-for").map(String::as_str))
+        .map(|ip| ip.to_string());
+
+    Ok(
+        RequestPayload::new(method, path, headers, body_str, query_params, ip_addr)
+            .with_raw_query(parts.uri.query().map(str::to_string))
+            .hashed_with(canonicalizer),
+    )
 }
 
 async fn forward_to_upstream(
@@ -236,7 +351,14 @@ mod tests {
             .unwrap();
 
         let (parts, body) = request.into_parts();
-        let payload = extract_payload(&parts, body).await.unwrap();
+        let payload = extract_payload(
+            &parts,
+            body,
+            &Canonicalizer::default(),
+            &TrustedProxies::default(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(payload.method, "GET");
         assert_eq!(payload.path, "/test/path");
@@ -254,7 +376,14 @@ mod tests {
             .unwrap();
 
         let (parts, body) = request.into_parts();
-        let payload = extract_payload(&parts, body).await.unwrap();
+        let payload = extract_payload(
+            &parts,
+            body,
+            &Canonicalizer::default(),
+            &TrustedProxies::default(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(payload.method, "GET");
         assert_eq!(payload.path, "/search");
@@ -274,7 +403,14 @@ mod tests {
             .unwrap();
 
         let (parts, body) = request.into_parts();
-        let payload = extract_payload(&parts, body).await.unwrap();
+        let payload = extract_payload(
+            &parts,
+            body,
+            &Canonicalizer::default(),
+            &TrustedProxies::default(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(payload.method, "POST");
         assert_eq!(payload.path, "/api/users");
@@ -297,7 +433,14 @@ mod tests {
             .unwrap();
 
         let (parts, body) = request.into_parts();
-        let payload = extract_payload(&parts, body).await.unwrap();
+        let payload = extract_payload(
+            &parts,
+            body,
+            &Canonicalizer::default(),
+            &TrustedProxies::default(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(
             payload.headers.get("authorization"),
@@ -311,19 +454,32 @@ mod tests {
 
     #[tokio::test]
     async fn test_extract_payload_with_ip_from_x_forwarded_for() {
-        let uri: Uri = "/".parse().unwrap();
-        let request = Request::builder()
-            .method("GET")
-            .uri(uri)
-            .header("x-forwarded-for", "192.168.1.100, 10.0.0.1")
-            .body(Body::empty())
-            .unwrap();
-
-        let (parts, body) = request.into_parts();
-        let payload = extract_payload(&parts, body).await.unwrap();
+        let trusted = TrustedProxies::parse(&["10.0.0.0/8".to_string()]).unwrap();
+        let ip_from = |peer: &str| {
+            let request = Request::builder()
+                .method("GET")
+                .uri("/")
+                .header("x-forwarded-for", "192.168.1.100, 10.0.0.1")
+                .extension(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 4000)))
+                .body(Body::empty())
+                .unwrap();
+            let (parts, body) = request.into_parts();
+            let trusted = trusted.clone();
+            async move {
+                extract_payload(&parts, body, &Canonicalizer::default(), &trusted)
+                    .await
+                    .unwrap()
+                    .ip_addr
+            }
+        };
 
-        // Should extract first IP from X-Forwarded-For
-        assert_eq!(payload.ip_addr, Some("192.168.1.100".to_string()));
+        // A trusted proxy forwards the client address...
+        assert_eq!(ip_from("10.0.0.2").await, Some("192.168.1.100".to_string()));
+        // ...anyone else is taken for the client
+        assert_eq!(
+            ip_from("203.0.113.7").await,
+            Some("203.0.113.7".to_string())
+        );
     }
 
     #[tokio::test]
@@ -336,7 +492,14 @@ mod tests {
             .unwrap();
 
         let (parts, body) = request.into_parts();
-        let payload = extract_payload(&parts, body).await.unwrap();
+        let payload = extract_payload(
+            &parts,
+            body,
+            &Canonicalizer::default(),
+            &TrustedProxies::default(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(payload.query_params.get("q"), Some(&"hello world".to_string()));
         assert_eq!(
@@ -345,6 +508,32 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_extract_payload_hash_covers_repeated_keys_and_headers() {
+        let hash = |uri: &str, cookie: &str| {
+            let request = Request::builder()
+                .uri(uri)
+                .header("cookie", cookie)
+                .header("x-request-id", "1")
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                let (parts, body) = request.into_parts();
+                let canonicalizer = Canonicalizer::new(&["cookie"]);
+                let payload =
+                    extract_payload(&parts, body, &canonicalizer, &TrustedProxies::default())
+                        .await
+                        .unwrap();
+                payload.normalized_hash
+            }
+        };
+
+        let base = hash("/item?id=1", "sid=a").await;
+        assert_eq!(base, hash("//item?id=%31", "sid=a").await);
+        assert_ne!(base, hash("/item?id=1&id=2", "sid=a").await);
+        assert_ne!(base, hash("/item?id=1", "sid=' OR 1=1").await);
+    }
+
     #[tokio::test]
     async fn test_extract_payload_normalized_hash() {
         let uri: Uri = "/test?a=1&b=2".parse().unwrap();
@@ -355,7 +544,14 @@ mod tests {
             .unwrap();
 
         let (parts, body) = request.into_parts();
-        let payload = extract_payload(&parts, body).await.unwrap();
+        let payload = extract_payload(
+            &parts,
+            body,
+            &Canonicalizer::default(),
+            &TrustedProxies::default(),
+        )
+        .await
+        .unwrap();
 
         // Hash should be deterministic and non-empty
         assert!(!payload.normalized_hash.is_empty());
@@ -364,6 +560,7 @@ mod tests {
 
     #[test]
     fn test_app_state_creation() {
+        use crate::config::EventWriterConfig;
         use crate::core::judge::Judge;
         use crate::core::rulebook::Rulebook;
         use crate::llm::client::mock::MockLlmProvider;
@@ -373,26 +570,193 @@ mod tests {
 
         let llm = Arc::new(MockLlmProvider::new());
         let rulebook = Arc::new(RwLock::new(Rulebook::new()));
-        let judge = Arc::new(Judge::new(
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let judge = Arc::new(runtime.block_on(Judge::new(
             llm,
             None,
             rulebook,
             std::time::Duration::from_secs(1),
             crate::config::FailMode::Open,
-        ));
-
-        let runtime = tokio::runtime::Runtime::new().unwrap();
-        let logs = runtime.block_on(async {
+        )));
+        let events = runtime.block_on(async {
             let temp_dir = tempfile::tempdir().unwrap();
             let db_path = temp_dir.path().join("test.db");
-            Arc::new(LogStore::new(&db_path).await.unwrap())
+            let logs = Arc::new(LogStore::new(&db_path).await.unwrap());
+            EventWriter::spawn(logs, &EventWriterConfig::default())
         });
 
-        let state = AppState::new(judge, logs, "http://backend:3000".to_string());
+        let state = AppState::new(judge, events, "http://backend:3000".to_string());
 
         assert_eq!(state.upstream_url, "http://backend:3000");
     }
 
+    #[tokio::test]
+    async fn test_judge_after_forward_bans_client() {
+        use crate::core::rulebook::Rulebook;
+        use crate::llm::client::mock::MockLlmProvider;
+        use crate::storage::logs::{EventStore, LogStore};
+        use axum::Router;
+        use tokio::sync::RwLock;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(
+            LogStore::new(temp_dir.path().join("test.db"))
+                .await
+                .unwrap(),
+        );
+        let judge = Arc::new(
+            Judge::new(
+                Arc::new(MockLlmProvider::new().with_block()),
+                None,
+                Arc::new(RwLock::new(Rulebook::new())),
+                std::time::Duration::from_secs(1),
+                crate::config::FailMode::Open,
+            )
+            .await,
+        );
+        // Nothing listens upstream: a forwarded request gets a 502
+        let events = EventWriter::spawn(
+            Arc::clone(&logs) as _,
+            &crate::config::EventWriterConfig::default(),
+        );
+        let state = AppState::new(
+            Arc::clone(&judge),
+            events.clone(),
+            "http://127.0.0.1:1".to_string(),
+        )
+        .with_async_judge(AsyncJudgeConfig {
+            routes: vec!["/feed".to_string()],
+            ..AsyncJudgeConfig::default()
+        });
+        let bans = Arc::clone(state.bans.as_ref().unwrap());
+        let app = Router::new().fallback(proxy_handler).with_state(state);
+        let request = || {
+            Request::get("/feed?q=1%27%20OR%201=1")
+                .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        for _ in 0..100 {
+            if bans.check(&extract_payload_for(request()).await).is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["reason"], "Client banned");
+
+        // The refusal is logged with the ban reason
+        events.flush().await;
+        let refused = logs
+            .get_events_since(0, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|event| {
+                event
+                    .reason
+                    .as_deref()
+                    .is_some_and(|reason| reason.starts_with("Client banned: "))
+            })
+            .count();
+        assert_eq!(refused, 1);
+        assert_eq!(
+            judge
+                .metrics()
+                .background_evaluations
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_route_checks_local_rules_inline() {
+        use crate::core::rulebook::{Rule, Rulebook};
+        use crate::llm::client::mock::MockLlmProvider;
+        use crate::models::decision::RuleAction;
+        use crate::storage::logs::LogStore;
+        use axum::Router;
+        use tokio::sync::RwLock;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(
+            LogStore::new(temp_dir.path().join("test.db"))
+                .await
+                .unwrap(),
+        );
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(Rule::new(
+            "union select".to_string(),
+            "sqli".to_string(),
+            0.95,
+            RuleAction::Block,
+            "test".to_string(),
+        ));
+        let judge = Arc::new(
+            Judge::new(
+                Arc::new(MockLlmProvider::new()),
+                None,
+                Arc::new(RwLock::new(rulebook)),
+                std::time::Duration::from_secs(1),
+                crate::config::FailMode::Open,
+            )
+            .await,
+        );
+        let events = EventWriter::spawn(
+            Arc::clone(&logs) as _,
+            &crate::config::EventWriterConfig::default(),
+        );
+        let state = AppState::new(
+            Arc::clone(&judge),
+            events.clone(),
+            "http://127.0.0.1:1".to_string(),
+        )
+        .with_async_judge(AsyncJudgeConfig {
+            routes: vec!["/feed".to_string()],
+            ..AsyncJudgeConfig::default()
+        });
+        let app = Router::new().fallback(proxy_handler).with_state(state);
+
+        // Blocked without being forwarded (which would give a 502)
+        let response = app
+            .oneshot(
+                Request::get("/feed?q=1%20union%20select%20password")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            judge
+                .metrics()
+                .background_evaluations
+                .load(std::sync::atomic::Ordering::Relaxed),
+            0
+        );
+    }
+
+    async fn extract_payload_for(request: Request<Body>) -> RequestPayload {
+        let (parts, body) = request.into_parts();
+        extract_payload(
+            &parts,
+            body,
+            &Canonicalizer::default(),
+            &TrustedProxies::default(),
+        )
+        .await
+        .unwrap()
+    }
+
     #[tokio::test]
     async fn test_health_handler() {
         let response = health_handler().await.into_response();
@@ -3,8 +3,10 @@
 // Licensed under the MIT License
 
 // Re-export public API for testing and library usage
+pub mod cli;
 pub mod config;
 pub mod core;
+pub mod export;
 pub mod http;
 pub mod llm;
 pub mod models;
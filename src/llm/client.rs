@@ -1,6 +1,8 @@
+use crate::core::clustering::FlaggedCluster;
+use crate::core::fewshot::FewShotExample;
 use crate::core::rulebook::Rulebook;
 use crate::models::decision::{JudgeDecision, LearnerOutput};
-use crate::models::request::{LogEntry, RequestPayload};
+use crate::models::request::RequestPayload;
 use anyhow::Result;
 use async_trait::async_trait;
 
@@ -8,6 +10,7 @@ use async_trait::async_trait;
 /// This abstraction allows swapping between different LLM providers (Ollama, OpenAI, etc.)
 /// and makes testing easier with mock implementations.
 #[async_trait]
+#[allow(clippy::double_must_use)] // Emitted by async_trait's expansion
 pub trait LlmProvider: Send + Sync {
     /// Evaluate a single request and return a decision.
     /// Used by the Judge service for real-time request evaluation.
@@ -15,6 +18,7 @@ pub trait LlmProvider: Send + Sync {
     /// # Arguments
     /// * `payload` - The normalized request payload
     /// * `rules` - The current rulebook
+    /// * `examples` - Similar past requests with a confirmed verdict
     ///
     /// # Returns
     /// A `JudgeDecision` (Allow, Flag, or Block)
@@ -22,23 +26,30 @@ pub trait LlmProvider: Send + Sync {
         &self,
         payload: &RequestPayload,
         rules: &Rulebook,
+        examples: &[FewShotExample],
     ) -> Result<JudgeDecision>;
 
-    /// Analyze flagged requests and generate new rules or modify existing ones.
-    /// Used by the Learner service in batch mode.
+    /// Analyze a cluster of similar flagged requests and generate new rules or
+    /// modify existing ones. Used by the Learner service in batch mode, once per cluster.
     ///
     /// # Arguments
-    /// * `flagged_logs` - Recent flagged events
+    /// * `cluster` - Structurally similar flagged events with representative samples
     /// * `current_rules` - The current rulebook
     ///
     /// # Returns
     /// A `LearnerOutput` with suggested rule changes
     async fn learn_rules(
         &self,
-        flagged_logs: Vec<LogEntry>,
+        cluster: &FlaggedCluster,
         current_rules: &Rulebook,
     ) -> Result<LearnerOutput>;
 
+    /// Version of the judge prompt templates, recorded with each event so
+    /// prompt revisions can be compared
+    async fn prompt_version(&self) -> Option<String> {
+        None
+    }
+
     /// Health check for the LLM provider
     async fn health_check(&self) -> Result<()>;
 }
@@ -49,12 +60,18 @@ pub trait LlmProvider: Send + Sync {
 pub mod mock {
     use super::*;
     use crate::models::decision::ThreatLevel;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
     /// Mock LLM provider for testing
     #[allow(dead_code)] // Used in tests
     pub struct MockLlmProvider {
         should_block: bool,
         should_error: bool,
+        delay: Option<std::time::Duration>,
+        prompt_version: Option<String>,
+        learn_calls: AtomicUsize,
+        last_examples: Mutex<Vec<FewShotExample>>,
     }
 
     #[allow(dead_code)] // Used in tests
@@ -70,6 +87,10 @@ pub mod mock {
             Self {
                 should_block: false,
                 should_error: false,
+                delay: None,
+                prompt_version: None,
+                learn_calls: AtomicUsize::new(0),
+                last_examples: Mutex::new(Vec::new()),
             }
         }
 
@@ -82,6 +103,27 @@ pub mod mock {
             self.should_error = true;
             self
         }
+
+        /// Answers `judge_request` only after `delay`
+        pub fn with_delay(mut self, delay: std::time::Duration) -> Self {
+            self.delay = Some(delay);
+            self
+        }
+
+        pub fn with_prompt_version(mut self, version: &str) -> Self {
+            self.prompt_version = Some(version.to_string());
+            self
+        }
+
+        /// Number of `learn_rules` calls received so far
+        pub fn learn_call_count(&self) -> usize {
+            self.learn_calls.load(Ordering::Relaxed)
+        }
+
+        /// Few-shot examples given with the last `judge_request` call
+        pub fn last_examples(&self) -> Vec<FewShotExample> {
+            self.last_examples.lock().unwrap().clone()
+        }
     }
 
     #[async_trait]
@@ -90,7 +132,12 @@ pub mod mock {
             &self,
             _payload: &RequestPayload,
             _rules: &Rulebook,
+            examples: &[FewShotExample],
         ) -> Result<JudgeDecision> {
+            *self.last_examples.lock().unwrap() = examples.to_vec();
+            if let Some(delay) = self.delay {
+                tokio::time::sleep(delay).await;
+            }
             if self.should_error {
                 anyhow::bail!("Mock LLM error")
             } else if self.should_block {
@@ -106,9 +153,10 @@ pub mod mock {
 
         async fn learn_rules(
             &self,
-            _flagged_logs: Vec<LogEntry>,
+            _cluster: &FlaggedCluster,
             _current_rules: &Rulebook,
         ) -> Result<LearnerOutput> {
+            self.learn_calls.fetch_add(1, Ordering::Relaxed);
             Ok(LearnerOutput {
                 new_rules: vec![],
                 weaken_rules: vec![],
@@ -117,6 +165,10 @@ pub mod mock {
             })
         }
 
+        async fn prompt_version(&self) -> Option<String> {
+            self.prompt_version.clone()
+        }
+
         async fn health_check(&self) -> Result<()> {
             Ok(())
         }
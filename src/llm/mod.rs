@@ -1,3 +1,6 @@
 pub mod client;
 pub mod ollama;
 pub mod prompts;
+pub mod sampler;
+pub mod summarizer;
+pub mod template;
@@ -1,14 +1,18 @@
 use crate::config::LlmConfig;
+use crate::core::clustering::FlaggedCluster;
+use crate::core::fewshot::FewShotExample;
 use crate::core::rulebook::Rulebook;
 use crate::llm::client::LlmProvider;
-use crate::llm::prompts::{judge_prompt, learner_prompt};
+use crate::llm::prompts::PromptTemplates;
 use crate::models::decision::{JudgeDecision, LearnerOutput, ThreatLevel};
-use crate::models::request::{LogEntry, RequestPayload};
+use crate::models::request::RequestPayload;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::RwLock;
 
 pub struct OllamaProvider {
     client: Client,
@@ -19,6 +23,7 @@ pub struct OllamaProvider {
     judge_temperature: f32,
     learner_max_tokens: u32,
     learner_temperature: f32,
+    prompts: Arc<RwLock<PromptTemplates>>,
 }
 
 impl OllamaProvider {
@@ -37,11 +42,19 @@ impl OllamaProvider {
             judge_temperature: config.judge_temperature,
             learner_max_tokens: config.learner_max_tokens,
             learner_temperature: config.learner_temperature,
+            prompts: Arc::new(RwLock::new(PromptTemplates::builtin())),
         })
     }
 
+    /// Uses (hot-reloadable) prompt templates instead of the built-in ones
+    pub fn with_prompts(mut self, prompts: Arc<RwLock<PromptTemplates>>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
     async fn generate(
         &self,
+        system: Option<&str>,
         prompt: String,
         max_tokens: u32,
         temperature: f32,
@@ -88,12 +101,23 @@ impl OllamaProvider {
             "required": ["decision", "confidence", "reason", "threat_level"]
         });
 
+        // Instructions go in the system message, untrusted request data only
+        // in the user message
+        let mut messages = Vec::new();
+        if let Some(system) = system {
+            messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: system.to_string(),
+            });
+        }
+        messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: prompt,
+        });
+
         let request = ChatRequest {
             model: self.model.clone(),
-            messages: vec![ChatMessage {
-                role: "user".to_string(),
-                content: prompt,
-            }],
+            messages,
             stream: false,
             format: schema,
             options: ChatOptions {
@@ -245,11 +269,19 @@ impl LlmProvider for OllamaProvider {
         &self,
         payload: &RequestPayload,
         rules: &Rulebook,
+        examples: &[FewShotExample],
     ) -> Result<JudgeDecision> {
-        let prompt = judge_prompt(payload, rules);
+        let (system, prompt) = {
+            let prompts = self.prompts.read().await;
+            (
+                prompts.judge_system()?,
+                prompts.judge_prompt(payload, rules, examples)?,
+            )
+        };
 
         let response = self
             .generate(
+                Some(&system),
                 prompt,
                 self.judge_max_tokens,
                 self.judge_temperature,
@@ -262,13 +294,18 @@ impl LlmProvider for OllamaProvider {
 
     async fn learn_rules(
         &self,
-        flagged_logs: Vec<LogEntry>,
+        cluster: &FlaggedCluster,
         current_rules: &Rulebook,
     ) -> Result<LearnerOutput> {
-        let prompt = learner_prompt(&flagged_logs, current_rules);
+        let prompt = self
+            .prompts
+            .read()
+            .await
+            .learner_prompt(cluster, current_rules)?;
 
         let response = self
             .generate(
+                None,
                 prompt,
                 self.learner_max_tokens,
                 self.learner_temperature,
@@ -279,6 +316,10 @@ impl LlmProvider for OllamaProvider {
         self.parse_learner_response(&response)
     }
 
+    async fn prompt_version(&self) -> Option<String> {
+        Some(self.prompts.read().await.judge_version())
+    }
+
     async fn health_check(&self) -> Result<()> {
         let url = format!("{}/api/tags", self.base_url);
 
@@ -359,6 +400,7 @@ mod tests {
             judge_temperature: 0.0,
             learner_max_tokens: 2048,
             learner_temperature: 0.3,
+            prompts_dir: None,
         }
     }
 
@@ -592,7 +634,7 @@ mod tests {
 
         assert_eq!(request.model, "test-model");
         assert_eq!(request.messages.len(), 1);
-        assert_eq!(request.stream, false);
+        assert!(!request.stream);
         assert_eq!(request.options.temperature, 0.0);
     }
 }
@@ -0,0 +1,122 @@
+use crate::models::request::LogEntry;
+use std::collections::HashMap;
+
+/// Rough token estimate (~4 characters per token) used for prompt budgeting
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Selects flagged events for the learner prompt within a token budget.
+///
+/// Events are grouped by method, path and reason, then drawn round-robin across
+/// groups (newest first within each group), so a single noisy endpoint cannot
+/// crowd out rarer patterns. `render` turns an event into its prompt line; lines
+/// that would overflow the budget are skipped so smaller ones can still fit.
+pub fn sample_diverse<F>(logs: &[LogEntry], budget_tokens: usize, render: F) -> Vec<String>
+where
+    F: Fn(&LogEntry) -> String,
+{
+    let mut group_index: HashMap<(&str, &str, &str), usize> = HashMap::new();
+    let mut groups: Vec<Vec<&LogEntry>> = Vec::new();
+
+    for log in logs {
+        let key = (
+            log.method.as_str(),
+            log.path.as_str(),
+            log.reason.as_deref().unwrap_or_default(),
+        );
+        let idx = *group_index.entry(key).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[idx].push(log);
+    }
+
+    let mut selected = Vec::new();
+    let mut used_tokens = 0;
+    let mut round = 0;
+
+    loop {
+        let mut any_left = false;
+
+        for group in &groups {
+            let Some(log) = group.get(round) else {
+                continue;
+            };
+            any_left = true;
+
+            let line = render(log);
+            let cost = estimate_tokens(&line);
+            if used_tokens + cost <= budget_tokens {
+                used_tokens += cost;
+                selected.push(line);
+            }
+        }
+
+        if !any_left {
+            break;
+        }
+        round += 1;
+    }
+
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(id: i64, path: &str, reason: &str) -> LogEntry {
+        LogEntry {
+            id,
+            timestamp: id,
+            method: "GET".to_string(),
+            path: path.to_string(),
+            payload_hash: format!("{:064}", id),
+            decision: "flag".to_string(),
+            confidence: 0.6,
+            reason: Some(reason.to_string()),
+            ip_addr: None,
+            user_agent: None,
+            request_context: None,
+        }
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("éééé"), 1);
+    }
+
+    #[test]
+    fn test_sample_diverse_round_robins_groups() {
+        let mut logs: Vec<LogEntry> = (0..20).map(|i| log(i, "/noisy", "SQLi")).collect();
+        logs.push(log(100, "/rare", "XSS"));
+        logs.push(log(101, "/other", "Traversal"));
+
+        // Each line costs 3 tokens, so the budget fits exactly three lines
+        let lines = sample_diverse(&logs, 9, |l| format!("{:>12}", l.path));
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().any(|l| l.contains("/noisy")));
+        assert!(lines.iter().any(|l| l.contains("/rare")));
+        assert!(lines.iter().any(|l| l.contains("/other")));
+    }
+
+    #[test]
+    fn test_sample_diverse_respects_budget() {
+        let logs: Vec<LogEntry> = (0..50).map(|i| log(i, "/a", "x")).collect();
+
+        let lines = sample_diverse(&logs, 10, |_| "x".repeat(8));
+
+        assert_eq!(lines.len(), 5);
+    }
+
+    #[test]
+    fn test_sample_diverse_empty() {
+        let lines = sample_diverse(&[], 100, |l| l.path.clone());
+        assert!(lines.is_empty());
+    }
+}
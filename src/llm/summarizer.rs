@@ -0,0 +1,674 @@
+use crate::models::canonical;
+use crate::models::request::RequestPayload;
+use regex_automata::meta::Regex;
+use regex_automata::util::syntax;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// Tokens of request data sent to the judge (Ollama runs it with
+/// `num_ctx: 2048`, shared with the instructions, rules and the answer)
+pub const JUDGE_PAYLOAD_TOKEN_BUDGET: usize = 600;
+
+/// Characters per token, matching `sampler::estimate_tokens`
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Share of the budget given to path, query, headers and body. Whatever a
+/// part does not use goes to the others.
+const PART_WEIGHTS: [usize; 4] = [1, 3, 2, 4];
+
+/// Characters kept on each side of a suspicious token
+const FRAGMENT_CONTEXT_CHARS: usize = 24;
+
+/// Smallest value worth sending; parameters beyond what this allows are dropped
+const MIN_VALUE_CHARS: usize = 16;
+
+const ELLIPSIS: &str = "…";
+
+/// Headers that say nothing about an attack (browser plumbing and caching)
+const BORING_HEADERS: &[&str] = &[
+    "accept-encoding",
+    "accept-language",
+    "cache-control",
+    "connection",
+    "content-length",
+    "dnt",
+    "if-modified-since",
+    "if-none-match",
+    "keep-alive",
+    "pragma",
+    "priority",
+    "te",
+    "upgrade-insecure-requests",
+];
+
+/// Header name prefixes dropped like `BORING_HEADERS`
+const BORING_HEADER_PREFIXES: &[&str] = &["sec-fetch-", "sec-ch-"];
+
+/// Punctuation found in ordinary values of boring headers (language and
+/// encoding lists, ETags, dates). Anything else keeps the header.
+const BORING_VALUE_PUNCTUATION: &str = " ,;=.*/-_:+\"";
+
+/// Longest value a boring header can have and still be dropped
+const BORING_VALUE_MAX_CHARS: usize = 128;
+
+/// Tokens that usually show up in attacks: quotes and statement breaks,
+/// markup, template and shell syntax, traversal, encodings and SQL/JS keywords
+const SUSPICIOUS_PATTERN: &str = r#"['"`<>;|]|\$\(|\$\{|\{\{|\.\./|\.\.\\|%[0-9a-f]{2}|\\x[0-9a-f]{2}|--|/\*|\b(union|select|insert|update|delete|drop|sleep|benchmark|exec|script|javascript|onerror|onload|alert|eval|passwd|cmd|wget|curl)\b"#;
+
+fn suspicious_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::builder()
+            .syntax(syntax::Config::new().case_insensitive(true))
+            .build(SUSPICIOUS_PATTERN)
+            .expect("suspicious token pattern is valid")
+    })
+}
+
+/// Request data sent to the judge, cut to a token budget
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PayloadSummary {
+    pub method: String,
+    pub path: String,
+    /// Values of each query key, every one of a repeated key (shown as a list)
+    #[serde(serialize_with = "serialize_query")]
+    pub query: BTreeMap<String, Vec<String>>,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    /// What was cut or dropped (e.g. `body`, `query.q`, `headers: 3 dropped`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub truncated: Vec<String>,
+}
+
+/// Summarizes a request for the LLM within `budget_tokens`.
+///
+/// The budget is split across path, query, headers and body by `PART_WEIGHTS`,
+/// parts needing less than their share giving the rest to the others. Boring
+/// headers with ordinary values are dropped first. Values over their allowance keep the fragments
+/// around suspicious tokens (or their start if there are none), cut on char
+/// boundaries, and are listed in `truncated`.
+pub fn summarize(payload: &RequestPayload, budget_tokens: usize) -> PayloadSummary {
+    let budget_chars = budget_tokens * CHARS_PER_TOKEN;
+    let mut truncated = Vec::new();
+
+    let headers: BTreeMap<&str, &str> = payload
+        .headers
+        .iter()
+        .filter(|(name, value)| !is_boring_header(name, value))
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let headers: Vec<(&str, &str)> = headers.into_iter().collect();
+    let query = canonical::query_pairs(payload);
+    let query: Vec<(&str, &str)> = query
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let body = payload.body.as_deref().unwrap_or_default();
+
+    let needs = [
+        char_len(&payload.path),
+        entries_len(&query),
+        entries_len(&headers),
+        char_len(body),
+    ];
+    let [path_chars, query_chars, header_chars, body_chars] =
+        allocate(budget_chars, &needs, &PART_WEIGHTS)[..]
+    else {
+        unreachable!("one share per part")
+    };
+
+    let (path, cut) = excerpt(&payload.path, path_chars);
+    if cut {
+        truncated.push("path".to_string());
+    }
+
+    let query = summarize_entries("query", &query, query_chars, &mut truncated)
+        .into_iter()
+        .fold(BTreeMap::new(), |mut query, (key, value)| {
+            query.entry(key).or_insert_with(Vec::new).push(value);
+            query
+        });
+    let headers = summarize_entries("headers", &headers, header_chars, &mut truncated)
+        .into_iter()
+        .collect();
+
+    let body = payload.body.as_deref().map(|body| {
+        let (body, cut) = excerpt(body, body_chars);
+        if cut {
+            truncated.push("body".to_string());
+        }
+        body
+    });
+
+    PayloadSummary {
+        method: payload.method.clone(),
+        path,
+        query,
+        headers,
+        body,
+        truncated,
+    }
+}
+
+/// Truncates to at most `max_chars` characters, on a char boundary
+pub fn truncate_chars(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
+/// Cuts `text` to `max_chars` characters. Fragments around suspicious tokens
+/// are kept (joined by `…`); text without any keeps its start. Returns the
+/// excerpt and whether anything was cut.
+pub fn excerpt(text: &str, max_chars: usize) -> (String, bool) {
+    if char_len(text) <= max_chars {
+        return (text.to_string(), false);
+    }
+    let ellipsis_chars = char_len(ELLIPSIS);
+    if max_chars <= ellipsis_chars {
+        return (truncate_chars(text, max_chars).to_string(), true);
+    }
+
+    // Byte ranges around suspicious tokens, widened to whole chars and merged
+    let mut windows: Vec<(usize, usize)> = Vec::new();
+    for found in suspicious_regex().find_iter(text) {
+        let start = back_chars(text, found.start(), FRAGMENT_CONTEXT_CHARS);
+        let end = forward_chars(text, found.end(), FRAGMENT_CONTEXT_CHARS);
+        match windows.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => windows.push((start, end)),
+        }
+    }
+    if windows.is_empty() {
+        windows.push((0, text.len()));
+    }
+
+    // Room is kept for a trailing `…`
+    let mut out = String::new();
+    let mut remaining = max_chars - ellipsis_chars;
+    let mut kept_until = 0;
+    for (start, end) in windows {
+        if start > 0 {
+            if remaining <= ellipsis_chars {
+                break;
+            }
+            out.push_str(ELLIPSIS);
+            remaining -= ellipsis_chars;
+        }
+
+        let fragment = &text[start..end];
+        let kept = truncate_chars(fragment, remaining);
+        out.push_str(kept);
+        remaining -= char_len(kept);
+        kept_until = start + kept.len();
+        if kept.len() < fragment.len() {
+            break;
+        }
+    }
+    if kept_until < text.len() {
+        out.push_str(ELLIPSIS);
+    }
+
+    (out, true)
+}
+
+/// Cuts key/value pairs (sorted by key, a key may repeat) to `max_chars`
+/// (keys included). Entries with suspicious values come first; those beyond
+/// what `MIN_VALUE_CHARS` per value allows are dropped. Long keys are shown
+/// cut to `MIN_VALUE_CHARS`, unless that would collide with another kept key.
+fn summarize_entries(
+    part: &str,
+    entries: &[(&str, &str)],
+    max_chars: usize,
+    truncated: &mut Vec<String>,
+) -> Vec<(String, String)> {
+    if entries_len(entries) <= max_chars {
+        return entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+    }
+
+    let mut ordered = entries.to_vec();
+    ordered.sort_by_key(|(_, value)| !suspicious_regex().is_match(value));
+
+    // The key each kept key is shown as, the same for all its values
+    let mut shown_keys: Vec<(&str, &str)> = Vec::new();
+    let mut kept: Vec<(&str, &str)> = Vec::new();
+    let mut used = 0;
+    for (key, value) in &ordered {
+        let shown = match shown_keys.iter().find(|(k, _)| k == key) {
+            Some((_, shown)) => *shown,
+            None => {
+                let short = truncate_chars(key, MIN_VALUE_CHARS);
+                if shown_keys.iter().any(|(_, shown)| *shown == short) {
+                    key
+                } else {
+                    short
+                }
+            }
+        };
+        let cost = char_len(shown) + char_len(value).min(MIN_VALUE_CHARS);
+        if used + cost > max_chars {
+            break;
+        }
+        used += cost;
+        if !shown_keys.iter().any(|(k, _)| k == key) {
+            shown_keys.push((key, shown));
+        }
+        kept.push((shown, *value));
+    }
+    if kept.len() < ordered.len() {
+        truncated.push(format!("{}: {} dropped", part, ordered.len() - kept.len()));
+    }
+
+    let value_budget = max_chars.saturating_sub(kept.iter().map(|(k, _)| char_len(k)).sum());
+    let needs: Vec<usize> = kept.iter().map(|(_, v)| char_len(v)).collect();
+    let allowances = allocate(value_budget, &needs, &vec![1; kept.len()]);
+
+    kept.into_iter()
+        .zip(allowances)
+        .map(|((key, value), allowance)| {
+            let (value, cut) = excerpt(value, allowance);
+            if cut {
+                truncated.push(format!("{}.{}", part, key));
+            }
+            (key.to_string(), value)
+        })
+        .collect()
+}
+
+/// Splits `budget` by `weights`: parts needing less than their share get
+/// exactly what they need and the rest is shared again among the others.
+fn allocate(budget: usize, needs: &[usize], weights: &[usize]) -> Vec<usize> {
+    let mut shares = vec![0; needs.len()];
+    let mut open: Vec<usize> = (0..needs.len()).collect();
+    let mut remaining = budget;
+
+    loop {
+        let total_weight: usize = open.iter().map(|&i| weights[i]).sum();
+        if open.is_empty() || total_weight == 0 {
+            break;
+        }
+
+        let satisfied: Vec<usize> = open
+            .iter()
+            .copied()
+            .filter(|&i| needs[i] <= remaining * weights[i] / total_weight)
+            .collect();
+        if satisfied.is_empty() {
+            for &i in &open {
+                shares[i] = remaining * weights[i] / total_weight;
+            }
+            break;
+        }
+
+        for &i in &satisfied {
+            shares[i] = needs[i];
+            remaining -= needs[i];
+        }
+        open.retain(|i| !satisfied.contains(i));
+    }
+
+    shares
+}
+
+fn is_boring_header(name: &str, value: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let boring_name = BORING_HEADERS.contains(&name.as_str())
+        || BORING_HEADER_PREFIXES.iter().any(|p| name.starts_with(p));
+    let plain_value = char_len(value) <= BORING_VALUE_MAX_CHARS
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || BORING_VALUE_PUNCTUATION.contains(c));
+
+    boring_name && plain_value
+}
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+fn entries_len(entries: &[(&str, &str)]) -> usize {
+    entries.iter().map(|(k, v)| char_len(k) + char_len(v)).sum()
+}
+
+/// A key's only value as a string, the values of a repeated key as a list
+fn serialize_query<S: Serializer>(
+    query: &BTreeMap<String, Vec<String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(query.iter().map(|(key, values)| match &values[..] {
+        [value] => (key, serde_json::json!(value)),
+        values => (key, serde_json::json!(values)),
+    }))
+}
+
+/// Byte index `n` chars before `idx` (or 0)
+fn back_chars(text: &str, idx: usize, n: usize) -> usize {
+    text[..idx]
+        .char_indices()
+        .rev()
+        .nth(n.saturating_sub(1))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Byte index `n` chars after `idx` (or the end)
+fn forward_chars(text: &str, idx: usize, n: usize) -> usize {
+    text[idx..]
+        .char_indices()
+        .nth(n)
+        .map_or(text.len(), |(i, _)| idx + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::sampler::estimate_tokens;
+    use std::collections::HashMap;
+
+    fn payload(
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: Option<&str>,
+    ) -> RequestPayload {
+        let to_map = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        RequestPayload::new(
+            "POST".to_string(),
+            path.to_string(),
+            to_map(headers),
+            body.map(str::to_string),
+            to_map(query),
+            None,
+        )
+    }
+
+    fn payload_with_query(raw: &str) -> RequestPayload {
+        payload("/search", &[], &[], None).with_raw_query(Some(raw.to_string()))
+    }
+
+    fn summary_chars(summary: &PayloadSummary) -> usize {
+        char_len(&summary.path)
+            + summary
+                .query
+                .iter()
+                .flat_map(|(k, values)| values.iter().map(move |v| (k, v)))
+                .chain(&summary.headers)
+                .map(|(k, v)| char_len(k) + char_len(v))
+                .sum::<usize>()
+            + summary.body.as_deref().map_or(0, char_len)
+    }
+
+    #[test]
+    fn test_truncate_chars_on_boundaries() {
+        assert_eq!(truncate_chars("hello", 10), "hello");
+        assert_eq!(truncate_chars("hello world", 5), "hello");
+        assert_eq!(truncate_chars("héllo", 2), "hé");
+        assert_eq!(truncate_chars("🦀🦀🦀", 1), "🦀");
+        assert_eq!(truncate_chars("", 0), "");
+    }
+
+    #[test]
+    fn test_excerpt_keeps_suspicious_fragments() {
+        let text = format!(
+            "{}id=1 UNION SELECT password FROM users{}",
+            "a".repeat(300),
+            "b".repeat(300)
+        );
+
+        let (excerpt, cut) = excerpt(&text, 100);
+
+        assert!(cut);
+        assert!(excerpt.contains("UNION SELECT password"));
+        assert!(excerpt.starts_with(ELLIPSIS));
+        assert!(char_len(&excerpt) <= 100);
+    }
+
+    #[test]
+    fn test_excerpt_keeps_start_of_plain_text() {
+        let (excerpt, cut) = excerpt(&"é".repeat(50), 10);
+        assert!(cut);
+        assert_eq!(excerpt, format!("{}{}", "é".repeat(9), ELLIPSIS));
+
+        assert_eq!(super::excerpt("short", 10), ("short".to_string(), false));
+    }
+
+    #[test]
+    fn test_summarize_drops_boring_headers() {
+        let summary = summarize(
+            &payload(
+                "/",
+                &[],
+                &[
+                    ("user-agent", "sqlmap/1.7"),
+                    ("accept-language", "fr-FR,fr;q=0.9"),
+                    ("sec-fetch-mode", "navigate"),
+                ],
+                None,
+            ),
+            JUDGE_PAYLOAD_TOKEN_BUDGET,
+        );
+
+        assert_eq!(summary.headers.len(), 1);
+        assert_eq!(summary.headers["user-agent"], "sqlmap/1.7");
+        assert!(summary.truncated.is_empty());
+    }
+
+    #[test]
+    fn test_summarize_keeps_boring_headers_with_payloads() {
+        let summary = summarize(
+            &payload(
+                "/",
+                &[],
+                &[
+                    ("accept-language", "en' OR 1=1--"),
+                    ("cache-control", "<script>alert(1)</script>"),
+                    ("if-none-match", "W/\"5e15153d-120f\""),
+                ],
+                None,
+            ),
+            JUDGE_PAYLOAD_TOKEN_BUDGET,
+        );
+
+        assert_eq!(summary.headers.len(), 2);
+        assert_eq!(summary.headers["accept-language"], "en' OR 1=1--");
+        assert_eq!(
+            summary.headers["cache-control"],
+            "<script>alert(1)</script>"
+        );
+    }
+
+    #[test]
+    fn test_summarize_within_budget_reports_cuts() {
+        let headers: Vec<(String, String)> = (0..100)
+            .map(|i| (format!("x-custom-{}", i), "v".repeat(100)))
+            .collect();
+        let headers: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let body = format!("{}<script>alert(1)</script>", "lorem ipsum ".repeat(500));
+
+        let summary = summarize(
+            &payload("/comments", &[("page", "2")], &headers, Some(&body)),
+            JUDGE_PAYLOAD_TOKEN_BUDGET,
+        );
+
+        assert!(summary_chars(&summary) <= JUDGE_PAYLOAD_TOKEN_BUDGET * CHARS_PER_TOKEN);
+        assert_eq!(summary.path, "/comments");
+        assert_eq!(summary.query["page"], ["2"]);
+        assert!(summary
+            .body
+            .as_deref()
+            .unwrap()
+            .contains("<script>alert(1)"));
+        assert!(summary.truncated.contains(&"body".to_string()));
+        assert!(summary
+            .truncated
+            .iter()
+            .any(|t| t.starts_with("headers: ") && t.ends_with(" dropped")));
+    }
+
+    #[test]
+    fn test_summarize_keeps_suspicious_params_first() {
+        let query: Vec<(String, String)> = (0..50)
+            .map(|i| (format!("p{:02}", i), "x".repeat(40)))
+            .collect();
+        let mut query: Vec<(&str, &str)> = query
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        query.push(("zz", "1' OR '1'='1"));
+
+        let summary = summarize(&payload("/search", &query, &[], None), 100);
+
+        assert_eq!(summary.query["zz"], ["1' OR '1'='1"]);
+        assert!(summary.query.len() < 51);
+    }
+
+    #[test]
+    fn test_summarize_keeps_keys_sharing_a_prefix_apart() {
+        let filler = "x".repeat(400);
+        let query = [
+            ("filter_by_category_id", "1' OR '1'='1"),
+            ("filter_by_category_name", "books"),
+            ("filler", filler.as_str()),
+        ];
+
+        let summary = summarize(&payload("/search", &query, &[], None), 60);
+
+        assert_eq!(summary.query["filter_by_catego"], ["1' OR '1'='1"]);
+        assert_eq!(summary.query["filter_by_category_name"], ["books"]);
+    }
+
+    #[test]
+    fn test_summarize_keeps_every_value_of_a_repeated_key() {
+        let summary = summarize(
+            &payload_with_query("id=1&sort=name&id=2%20OR%201=1"),
+            JUDGE_PAYLOAD_TOKEN_BUDGET,
+        );
+
+        assert_eq!(summary.query["id"], ["1", "2 OR 1=1"]);
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap()["query"],
+            serde_json::json!({"id": ["1", "2 OR 1=1"], "sort": "name"})
+        );
+
+        // Cut to the budget, a repeated long key keeps one name
+        let filler = "x".repeat(400);
+        let summary = summarize(
+            &payload_with_query(&format!(
+                "filter_by_category_id=1&filler={}&filter_by_category_id=1'%20OR%20'1'='1",
+                filler
+            )),
+            60,
+        );
+        assert_eq!(summary.query["filter_by_catego"], ["1' OR '1'='1", "1"]);
+    }
+
+    #[test]
+    fn test_allocate_redistributes_unused_share() {
+        assert_eq!(allocate(100, &[10, 500], &[1, 1]), [10, 90]);
+        assert_eq!(allocate(100, &[500, 500], &[1, 3]), [25, 75]);
+        assert_eq!(allocate(100, &[10, 20], &[1, 1]), [10, 20]);
+    }
+
+    /// xorshift, so the fuzz cases are the same on every run
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+
+        fn text(&mut self, max_len: usize) -> String {
+            const PIECES: &[&str] = &[
+                "a",
+                "Z",
+                " ",
+                "é",
+                "ß",
+                "中文",
+                "🦀",
+                "👩‍💻",
+                "e\u{301}",
+                "\u{200b}",
+                "\0",
+                "'",
+                "\"",
+                "<",
+                "</script>",
+                "%2e%2e/",
+                "union select",
+                "${",
+                "--",
+                "ĳ",
+                "\u{10ffff}",
+            ];
+            let len = self.next() % max_len;
+            (0..len)
+                .map(|_| PIECES[self.next() % PIECES.len()])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_fuzz_excerpt_non_ascii() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+        for _ in 0..2000 {
+            let text = rng.text(200);
+            let max_chars = rng.next() % 120;
+
+            let (excerpt, cut) = excerpt(&text, max_chars);
+
+            assert!(char_len(&excerpt) <= max_chars);
+            assert_eq!(cut, char_len(&text) > max_chars);
+            if !cut {
+                assert_eq!(excerpt, text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fuzz_summarize_non_ascii() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..300 {
+            let query: Vec<(String, String)> = (0..rng.next() % 20)
+                .map(|_| (rng.text(10), rng.text(80)))
+                .collect();
+            let headers: Vec<(String, String)> = (0..rng.next() % 20)
+                .map(|_| (rng.text(10), rng.text(80)))
+                .collect();
+            let path = format!("/{}", rng.text(60));
+            let body = rng.text(400);
+            let budget = rng.next() % 200;
+
+            let query: Vec<(&str, &str)> = query
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            let headers: Vec<(&str, &str)> = headers
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+
+            let summary = summarize(&payload(&path, &query, &headers, Some(&body)), budget);
+
+            assert!(summary_chars(&summary) <= budget * CHARS_PER_TOKEN);
+            assert!(estimate_tokens(&serde_json::to_string(&summary).unwrap()) > 0);
+        }
+    }
+}
@@ -0,0 +1,438 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+/// A parsed prompt template.
+///
+/// The language is deliberately small:
+/// - `{{ a.b.c }}` inserts a variable (strings as-is, other values as JSON)
+/// - `{% if a.b %}...{% else %}...{% endif %}`, optionally `if not a.b`
+/// - `{% for item in a.list %}...{% else %}...{% endfor %}` (the `else`
+///   branch renders for empty lists); `loop.index`, `loop.first` and
+///   `loop.last` are available inside
+/// - `{# ... #}` comments; `{# version: <v> #}` is required and names the
+///   template revision
+///
+/// Tags and comments alone on their line don't leave an empty line behind,
+/// and the final newline of the file is dropped. Unknown variables are
+/// errors, so templates can be checked against sample data when loaded.
+#[derive(Debug, Clone)]
+pub struct Template {
+    name: String,
+    version: String,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Var(Path),
+    If {
+        negated: bool,
+        cond: Path,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        item: String,
+        list: Path,
+        body: Vec<Node>,
+        empty: Vec<Node>,
+    },
+}
+
+/// Dotted variable path, with the line it appears on for error messages
+#[derive(Debug, Clone)]
+struct Path {
+    segments: Vec<String>,
+    line: usize,
+}
+
+impl Path {
+    fn parse(expr: &str, line: usize) -> Result<Self> {
+        let segments: Vec<String> = expr.split('.').map(str::to_string).collect();
+        let valid = segments
+            .iter()
+            .all(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        if !valid {
+            bail!("line {}: invalid variable '{}'", line, expr);
+        }
+        Ok(Self { segments, line })
+    }
+
+    fn display(&self) -> String {
+        self.segments.join(".")
+    }
+}
+
+#[derive(Debug)]
+enum Token<'a> {
+    Text(&'a str),
+    Var(&'a str, usize),
+    Tag(&'a str, usize),
+}
+
+impl Template {
+    pub fn parse(name: &str, source: &str) -> Result<Self> {
+        Self::parse_inner(name, source).with_context(|| format!("Template '{}'", name))
+    }
+
+    fn parse_inner(name: &str, source: &str) -> Result<Self> {
+        let source = source.strip_suffix('\n').unwrap_or(source);
+        let (tokens, comments) = tokenize(source)?;
+
+        let version = comments
+            .iter()
+            .find_map(|c| c.trim().strip_prefix("version:"))
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .context("missing version (add `{# version: <v> #}`)")?;
+
+        let mut tokens = tokens.into_iter();
+        let (nodes, end) = parse_nodes(&mut tokens)?;
+        if let Some((tag, line)) = end {
+            bail!("line {}: unexpected '{{% {} %}}'", line, tag);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            version,
+            nodes,
+        })
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn render(&self, context: &Value) -> Result<String> {
+        let mut out = String::new();
+        let mut scopes = Vec::new();
+        render_nodes(&self.nodes, context, &mut scopes, &mut out)
+            .with_context(|| format!("Failed to render template '{}'", self.name))?;
+        Ok(out)
+    }
+}
+
+/// Splits the source into text, variables and tags, collecting comments.
+/// Tags and comments alone on their line take the line with them.
+fn tokenize(source: &str) -> Result<(Vec<Token<'_>>, Vec<&str>)> {
+    let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = source[pos..].find('{') {
+        let start = pos + offset;
+        let close = match source[start..].get(..2) {
+            Some("{{") => "}}",
+            Some("{%") => "%}",
+            Some("{#") => "#}",
+            _ => {
+                tokens.push(Token::Text(&source[pos..start + 1]));
+                pos = start + 1;
+                continue;
+            }
+        };
+        let line = source[..start].matches('\n').count() + 1;
+        let Some(len) = source[start + 2..].find(close) else {
+            bail!("line {}: unclosed '{}'", line, &source[start..start + 2]);
+        };
+        let inner = source[start + 2..start + 2 + len].trim();
+        let mut end = start + 2 + len + 2;
+
+        let mut text_end = start;
+        if close != "}}" {
+            let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+            let rest = &source[end..];
+            let newline = ["\n", "\r\n", ""]
+                .into_iter()
+                .find(|nl| rest.starts_with(nl) && (!nl.is_empty() || rest.is_empty()));
+            if let Some(newline) = newline {
+                if line_start >= pos && source[line_start..start].trim().is_empty() {
+                    text_end = line_start;
+                    end += newline.len();
+                }
+            }
+        }
+
+        if text_end > pos {
+            tokens.push(Token::Text(&source[pos..text_end]));
+        }
+        match close {
+            "}}" => tokens.push(Token::Var(inner, line)),
+            "%}" => tokens.push(Token::Tag(inner, line)),
+            _ => comments.push(inner),
+        }
+        pos = end;
+    }
+    if pos < source.len() {
+        tokens.push(Token::Text(&source[pos..]));
+    }
+
+    Ok((tokens, comments))
+}
+
+/// A block-ending tag (`else`, `endif` or `endfor`) and its line
+type BlockEnd<'a> = Option<(&'a str, usize)>;
+
+/// Parses nodes up to the next block-ending tag, which is returned
+fn parse_nodes<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+) -> Result<(Vec<Node>, BlockEnd<'a>)> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text.to_string())),
+            Token::Var(expr, line) => nodes.push(Node::Var(Path::parse(expr, line)?)),
+            Token::Tag(tag, line) => {
+                let words: Vec<&str> = tag.split_whitespace().collect();
+                match words.as_slice() {
+                    ["if", "not", cond] => nodes.push(parse_if(tokens, true, cond, line)?),
+                    ["if", cond] => nodes.push(parse_if(tokens, false, cond, line)?),
+                    ["for", item, "in", list] => {
+                        let item = Path::parse(item, line)?;
+                        if item.segments.len() != 1 || item.segments[0] == "loop" {
+                            bail!("line {}: invalid loop variable '{}'", line, item.display());
+                        }
+                        let list = Path::parse(list, line)?;
+                        let (body, empty) = parse_branches(tokens, "endfor", line)?;
+                        nodes.push(Node::For {
+                            item: item.display(),
+                            list,
+                            body,
+                            empty,
+                        });
+                    }
+                    ["else"] | ["endif"] | ["endfor"] => return Ok((nodes, Some((tag, line)))),
+                    _ => bail!("line {}: unknown tag '{{% {} %}}'", line, tag),
+                }
+            }
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+fn parse_if<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+    negated: bool,
+    cond: &str,
+    line: usize,
+) -> Result<Node> {
+    let cond = Path::parse(cond, line)?;
+    let (then, otherwise) = parse_branches(tokens, "endif", line)?;
+    Ok(Node::If {
+        negated,
+        cond,
+        then,
+        otherwise,
+    })
+}
+
+/// Parses a block body and its optional `else` branch up to `closing`
+fn parse_branches<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+    closing: &str,
+    line: usize,
+) -> Result<(Vec<Node>, Vec<Node>)> {
+    let (body, end) = parse_nodes(tokens)?;
+    let (otherwise, end) = match end {
+        Some(("else", _)) => parse_nodes(tokens)?,
+        end => (Vec::new(), end),
+    };
+    match end {
+        Some((tag, _)) if tag == closing => Ok((body, otherwise)),
+        Some((tag, tag_line)) => bail!("line {}: unexpected '{{% {} %}}'", tag_line, tag),
+        None => bail!(
+            "line {}: block is never closed with '{{% {} %}}'",
+            line,
+            closing
+        ),
+    }
+}
+
+/// `scopes` holds the loop variables in scope, innermost last
+fn render_nodes<'t>(
+    nodes: &'t [Node],
+    context: &Value,
+    scopes: &mut Vec<(&'t str, Value)>,
+    out: &mut String,
+) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var(path) => match lookup(path, context, scopes)? {
+                Value::String(s) => out.push_str(s),
+                Value::Null => {}
+                other => out.push_str(&other.to_string()),
+            },
+            Node::If {
+                negated,
+                cond,
+                then,
+                otherwise,
+            } => {
+                let branch = if truthy(lookup(cond, context, scopes)?) != *negated {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, context, scopes, out)?;
+            }
+            Node::For {
+                item,
+                list,
+                body,
+                empty,
+            } => {
+                let Value::Array(items) = lookup(list, context, scopes)?.clone() else {
+                    bail!("line {}: '{}' is not a list", list.line, list.display());
+                };
+                if items.is_empty() {
+                    render_nodes(empty, context, scopes, out)?;
+                }
+                let count = items.len();
+                for (i, value) in items.into_iter().enumerate() {
+                    let loop_info = serde_json::json!({
+                        "index": i + 1,
+                        "first": i == 0,
+                        "last": i + 1 == count,
+                    });
+                    scopes.push(("loop", loop_info));
+                    scopes.push((item.as_str(), value));
+                    let result = render_nodes(body, context, scopes, out);
+                    scopes.truncate(scopes.len() - 2);
+                    result?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'v>(path: &Path, context: &'v Value, scopes: &'v [(&str, Value)]) -> Result<&'v Value> {
+    let (first, rest) = path
+        .segments
+        .split_first()
+        .expect("paths have at least one segment");
+
+    let mut value = match scopes.iter().rev().find(|(name, _)| name == first) {
+        Some((_, value)) => value,
+        None => context.get(first.as_str()).with_context(|| {
+            format!("line {}: unknown variable '{}'", path.line, path.display())
+        })?,
+    };
+    for segment in rest {
+        let next = match value {
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            other => other.get(segment.as_str()),
+        };
+        value = next.with_context(|| {
+            format!("line {}: unknown variable '{}'", path.line, path.display())
+        })?;
+    }
+    Ok(value)
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(source: &str, context: Value) -> Result<String> {
+        Template::parse("test", source)?.render(&context)
+    }
+
+    #[test]
+    fn test_variables_and_version() {
+        let template = Template::parse(
+            "judge",
+            "{# version: 2025-06-a #}\nHello {{ user.name }} ({{ user.age }})!\n",
+        )
+        .unwrap();
+
+        assert_eq!(template.version(), "2025-06-a");
+        assert_eq!(
+            template
+                .render(&json!({"user": {"name": "Ada", "age": 36}}))
+                .unwrap(),
+            "Hello Ada (36)!"
+        );
+    }
+
+    #[test]
+    fn test_loops_and_conditionals() {
+        let source = "{# version: 1 #}
+RULES:
+{% for rule in rules %}
+{{ loop.index }}. {{ rule.id }}{% if rule.block %} [block]{% endif %}
+{% else %}
+none
+{% endfor %}
+params: {% for p in params %}{% if not loop.first %}, {% endif %}{{ p }}{% endfor %}";
+
+        let out = render(
+            source,
+            json!({
+                "rules": [{"id": "a", "block": true}, {"id": "b", "block": false}],
+                "params": ["q", "sort"],
+            }),
+        )
+        .unwrap();
+        assert_eq!(out, "RULES:\n1. a [block]\n2. b\nparams: q, sort");
+
+        let out = render(source, json!({"rules": [], "params": []})).unwrap();
+        assert_eq!(out, "RULES:\nnone\nparams: ");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("Hello", "missing version"),
+            ("{# version: 1 #}{% if a %}", "never closed"),
+            ("{# version: 1 #}{% endfor %}", "unexpected"),
+            ("{# version: 1 #}{% if a %}{% endfor %}", "unexpected"),
+            ("{# version: 1 #}{% while a %}{% endwhile %}", "unknown tag"),
+            ("{# version: 1 #}\n{{ a b }}", "line 2: invalid variable"),
+            ("{# version: 1 #}{{ a", "unclosed"),
+        ];
+
+        for (source, expected) in cases {
+            let err = Template::parse("test", source).unwrap_err();
+            assert!(
+                format!("{:#}", err).contains(expected),
+                "{}: {:#}",
+                source,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_variables_fail_to_render() {
+        let err = render(
+            "{# version: 1 #}\n\n{{ request.boody }}",
+            json!({"request": {}}),
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("line 3: unknown variable 'request.boody'"));
+
+        let err = render(
+            "{# version: 1 #}{% for x in name %}{% endfor %}",
+            json!({"name": "a"}),
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("'name' is not a list"));
+    }
+}
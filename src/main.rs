@@ -2,8 +2,10 @@
 // Copyright (c) 2025 Yoann Vanitou
 // Licensed under the MIT License
 
+mod cli;
 mod config;
 mod core;
+mod export;
 mod http;
 mod llm;
 mod models;
@@ -11,21 +13,51 @@ mod storage;
 
 use anyhow::{Context, Result};
 use axum::{middleware, routing::get, Router};
-use config::Config;
-use core::{judge::Judge, learner::Learner, rulebook::Rulebook};
+use chrono::Utc;
+use config::{CacheBackend, Config, LogBackend};
+use core::{
+    escalation::Escalation,
+    feedback::{self, Feedback},
+    fewshot::ExampleIndex,
+    judge::Judge,
+    learner::Learner,
+    rulebook::ActivationTracker,
+};
+use export::sink::Exporter;
 use http::{
+    admin::{self, AdminState},
+    client_ip::TrustedProxies,
     middleware::tracing_middleware,
     proxy::{health_handler, proxy_handler, AppState},
 };
-use llm::{client::LlmProvider, ollama::OllamaProvider};
+use llm::{client::LlmProvider, ollama::OllamaProvider, prompts::PromptTemplates};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use storage::{cache::RedisCache, logs::LogStore, rules::RulebookStore};
+use storage::{
+    cache::{NoopCache, RedisCache, VerdictCache},
+    logs::{EventStore, LogStore},
+    memory::MemoryCache,
+    pg_logs::PgLogStore,
+    prompts::PromptStore,
+    retention::Retention,
+    rules::RulebookStore,
+    sampling::AllowSampler,
+    sqlite_cache::SqliteCache,
+    writer::EventWriter,
+};
 use tokio::sync::RwLock;
 use tower_http::timeout::TimeoutLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Run a CLI subcommand instead of the server when one is given
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        let code = cli::run(&args).await?;
+        std::process::exit(code);
+    }
+
     // Initialize tracing
     tracing_subscriber::registry()
         .with(
@@ -45,38 +77,80 @@ async fn main() -> Result<()> {
     // Initialize storage components
     tracing::info!("Initializing storage...");
 
-    let logs = Arc::new(
-        LogStore::new(&config.storage.logs_db_path)
-            .await
-            .with_context(|| "Failed to initialize log store")?,
-    );
-    tracing::info!("✓ Log store initialized");
+    let logs: Arc<dyn EventStore> = match config.storage.backend {
+        LogBackend::Sqlite => Arc::new(
+            LogStore::new(&config.storage.logs_db_path)
+                .await
+                .with_context(|| "Failed to initialize log store")?,
+        ),
+        LogBackend::Postgres => Arc::new(
+            PgLogStore::new(config.storage.postgres_url.as_deref().unwrap_or_default())
+                .await
+                .with_context(|| "Failed to initialize PostgreSQL log store")?,
+        ),
+    };
+    tracing::info!("✓ Log store initialized ({})", logs.name());
 
-    let rules_store = Arc::new(
-        RulebookStore::new(&config.storage.rulebook_path)
-            .with_context(|| "Failed to initialize rulebook store")?,
-    );
+    let mut rules_store = RulebookStore::new(&config.storage.rulebook_path)
+        .with_context(|| "Failed to initialize rulebook store")?;
+    if let Some(seed_rules_path) = &config.storage.seed_rules_path {
+        rules_store = rules_store.with_seed_rules(seed_rules_path);
+    }
+    let rules_store = Arc::new(rules_store);
     tracing::info!("✓ Rulebook store initialized");
 
-    let cache = if config.cache.enabled {
-        let cache = RedisCache::new(&config.cache.redis_url, config.cache.ttl())
-            .await
-            .with_context(|| "Failed to connect to Redis")?;
+    let cache: Option<Arc<dyn VerdictCache>> = if config.cache.enabled {
+        let cache: Arc<dyn VerdictCache> = match config.cache.backend {
+            CacheBackend::Redis => {
+                let cache = RedisCache::new(&config.cache.redis_url)
+                    .await
+                    .with_context(|| "Failed to connect to Redis")?;
 
-        // Test connection
-        cache.ping().await.with_context(|| "Redis ping failed")?;
+                // Test connection
+                cache.ping().await.with_context(|| "Redis ping failed")?;
+                Arc::new(cache)
+            }
+            CacheBackend::Sqlite => Arc::new(
+                SqliteCache::new(&config.cache.sqlite_path)
+                    .await
+                    .with_context(|| "Failed to open SQLite cache")?,
+            ),
+            CacheBackend::Memory => Arc::new(MemoryCache::new(
+                config.cache.memory.capacity,
+                config.cache.ttl(),
+            )),
+            CacheBackend::None => Arc::new(NoopCache),
+        };
 
-        tracing::info!("✓ Redis cache initialized");
-        Some(Arc::new(cache))
+        tracing::info!("✓ Verdict cache initialized ({})", cache.name());
+        Some(cache)
     } else {
-        tracing::info!("Cache disabled");
+        tracing::info!("Verdict cache backend disabled");
         None
     };
 
+    // Load prompt templates (built-in unless a prompts directory is set)
+    let prompt_store = config.llm.prompts_dir.as_ref().map(PromptStore::new);
+    let prompts = match &prompt_store {
+        Some(store) => store
+            .load()
+            .await
+            .with_context(|| "Failed to load prompt templates")?,
+        None => PromptTemplates::builtin(),
+    };
+    tracing::info!(
+        "✓ Prompt templates loaded (judge version {}, learner version {})",
+        prompts.judge_version(),
+        prompts.learner_version()
+    );
+    let prompts = Arc::new(RwLock::new(prompts));
+
     // Initialize LLM provider
     tracing::info!("Initializing LLM provider...");
     let llm = Arc::new(
-        OllamaProvider::new(&config.llm).with_context(|| "Failed to create Ollama provider")?,
+        OllamaProvider::new(&config.llm)
+            .with_context(|| "Failed to create Ollama provider")?
+            .with_prompts(Arc::clone(&prompts)),
     );
 
     // Health check LLM
@@ -88,23 +162,85 @@ async fn main() -> Result<()> {
     }
 
     // Load or create initial rulebook
-    let rulebook = rules_store.load().await.unwrap_or_else(|_| {
-        tracing::warn!("Failed to load rulebook, creating new one");
-        Rulebook::default()
-    });
+    // An unreadable rulebook stops startup rather than running without rules
+    let rulebook = rules_store
+        .load()
+        .await
+        .with_context(|| "Failed to load rulebook")?;
     tracing::info!("Loaded rulebook with {} rules", rulebook.rules.len());
 
     let rulebook = Arc::new(RwLock::new(rulebook));
 
+    // Verdicts pinned by human labels
+    let pins = feedback::pinned_verdicts(logs.as_ref())
+        .await
+        .with_context(|| "Failed to load pinned verdicts")?;
+    tracing::info!("Loaded {} pinned verdicts", pins.len());
+
     // Initialize Judge
-    let judge = Arc::new(Judge::new(
+    let mut judge = Judge::new(
         llm.clone(),
         cache,
         Arc::clone(&rulebook),
         config.llm.judge_timeout(),
         config.waf.fail_mode.clone(),
-    ));
-    tracing::info!("✓ Judge service initialized");
+    )
+    .await
+    .with_scoring(config.scoring.clone())
+    .with_verdict_ttls(config.cache.verdict_ttls())
+    .with_pinned_verdicts(pins);
+    if config.cache.memory_tier_enabled() {
+        judge = judge.with_memory_cache(MemoryCache::new(
+            config.cache.memory.capacity,
+            config.cache.memory.ttl(),
+        ));
+        tracing::info!(
+            "✓ In-process cache initialized ({} verdicts)",
+            config.cache.memory.capacity
+        );
+    }
+    let examples = config
+        .fewshot
+        .enabled
+        .then(|| Arc::new(ExampleIndex::new(Arc::clone(&logs), config.fewshot.clone())));
+    if let Some(examples) = &examples {
+        judge = judge.with_examples(Arc::clone(examples));
+        tracing::info!(
+            "✓ Few-shot examples enabled (up to {})",
+            config.fewshot.max_examples
+        );
+    }
+    if config.escalation.enabled {
+        let mut escalation = Escalation::new(config.escalation.clone());
+        for second in &config.escalation.judges {
+            let second_prompts = match &second.prompts_dir {
+                Some(dir) => Arc::new(RwLock::new(
+                    PromptStore::new(dir).load().await.with_context(|| {
+                        format!("Failed to load prompt templates of judge '{}'", second.name)
+                    })?,
+                )),
+                None => Arc::clone(&prompts),
+            };
+            let provider =
+                OllamaProvider::new(&second.llm_config(&config.llm, config.escalation.timeout_ms))
+                    .with_context(|| {
+                        format!("Failed to create second-opinion judge '{}'", second.name)
+                    })?
+                    .with_prompts(second_prompts);
+            escalation = escalation.with_judge(&second.name, Arc::new(provider));
+        }
+        judge = judge.with_escalation(escalation);
+        tracing::info!(
+            "✓ Second-opinion escalation enabled ({} judges, {:?} policy)",
+            config.escalation.judges.len(),
+            config.escalation.policy
+        );
+    }
+    let judge = Arc::new(judge);
+    tracing::info!(
+        "✓ Judge service initialized ({:?} decisions)",
+        config.scoring.mode
+    );
 
     // Initialize Learner
     if config.learner.enabled {
@@ -114,6 +250,7 @@ async fn main() -> Result<()> {
             Arc::clone(&rules_store),
             config.learner.batch_interval(),
             config.learner.min_flagged_requests,
+            config.learner.max_clusters_per_batch,
         ));
         tracing::info!("✓ Learner service initialized");
 
@@ -127,42 +264,224 @@ async fn main() -> Result<()> {
         tracing::info!("Learner disabled");
     }
 
-    // Setup hot-reload watcher
+    // Start log retention
+    let retention_config = &config.storage.retention;
+    let retention = if retention_config.enabled {
+        let mut retention = Retention::new(
+            Arc::clone(&logs),
+            retention_config.interval(),
+            retention_config.max_ages(),
+            retention_config.batch_size,
+        );
+        if let Some(dir) = &retention_config.archive_dir {
+            retention = retention.with_archive_dir(dir);
+        }
+        let retention = Arc::new(retention);
+        tokio::spawn(Arc::clone(&retention).start_scheduler());
+        tracing::info!(
+            "✓ Log retention started (interval: {:?}, archive: {})",
+            retention_config.interval(),
+            retention_config.archive_dir.as_deref().unwrap_or("none")
+        );
+        Some(retention)
+    } else {
+        tracing::info!("Log retention disabled");
+        None
+    };
+
+    // Setup hot-reload watcher. Rule activation changes are reported when
+    // the next window opens or closes, since they come without any file
+    // change (the judge moves its cache keys on its own).
     let rulebook_for_watcher = Arc::clone(&rulebook);
+    let judge_for_watcher = Arc::clone(&judge);
     let rules_store_for_watcher = Arc::clone(&rules_store);
     tokio::spawn(async move {
-        match rules_store_for_watcher.watch() {
-            Ok(mut rx) => {
+        let mut rx = match rules_store_for_watcher.watch() {
+            Ok(rx) => {
                 tracing::info!("✓ Rulebook hot-reload watcher started");
-                while let Some(result) = rx.recv().await {
+                rx
+            }
+            Err(e) => {
+                tracing::error!("Failed to start rulebook watcher: {}", e);
+                return;
+            }
+        };
+
+        let mut activation = ActivationTracker::default();
+        let mut next_change = {
+            let rb = rulebook_for_watcher.read().await;
+            activation.update(&rb, Utc::now());
+            rb.next_activation_change(Utc::now())
+        };
+
+        loop {
+            let activation_change = async {
+                match next_change {
+                    Some(at) => {
+                        tokio::time::sleep((at - Utc::now()).to_std().unwrap_or_default()).await
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                result = rx.recv() => {
+                    let Some(result) = result else { break };
                     match result {
                         Ok(new_rulebook) => {
-                            let mut rb = rulebook_for_watcher.write().await;
-                            *rb = new_rulebook.clone();
                             tracing::info!(
                                 "🔄 Rulebook hot-reloaded: {} rules (version {})",
                                 new_rulebook.rules.len(),
                                 new_rulebook.version
                             );
+                            activation.update(&new_rulebook, Utc::now());
+                            next_change = new_rulebook.next_activation_change(Utc::now());
+                            if let Err(e) = judge_for_watcher.update_rulebook(new_rulebook).await {
+                                tracing::error!("Failed to apply reloaded rulebook: {}", e);
+                            }
                         }
                         Err(e) => {
-                            tracing::error!("Failed to reload rulebook: {}", e);
+                            tracing::error!(
+                                "Failed to reload rulebook, keeping previous rulebook: {:#}",
+                                e
+                            );
                         }
                     }
                 }
-            }
-            Err(e) => {
-                tracing::error!("Failed to start rulebook watcher: {}", e);
+                _ = activation_change => {
+                    let rb = rulebook_for_watcher.read().await;
+                    let (activated, deactivated) = activation.update(&rb, Utc::now());
+                    next_change = rb.next_activation_change(Utc::now());
+                    for id in activated {
+                        tracing::info!("Rule {} is now active", id);
+                    }
+                    for id in deactivated {
+                        tracing::info!("Rule {} is now inactive", id);
+                    }
+                }
             }
         }
     });
 
-    // Build application state
-    let app_state = AppState::new(
-        Arc::clone(&judge),
-        Arc::clone(&logs),
-        config.waf.upstream_url.clone(),
+    // Setup prompt template hot-reload watcher
+    if let Some(store) = prompt_store {
+        let prompts_for_watcher = Arc::clone(&prompts);
+        tokio::spawn(async move {
+            let mut rx = match store.watch() {
+                Ok(rx) => {
+                    tracing::info!("✓ Prompt template hot-reload watcher started");
+                    rx
+                }
+                Err(e) => {
+                    tracing::error!("Failed to start prompt template watcher: {}", e);
+                    return;
+                }
+            };
+
+            while let Some(result) = rx.recv().await {
+                match result {
+                    Ok(new_prompts) => {
+                        tracing::info!(
+                            "🔄 Prompt templates hot-reloaded (judge version {}, learner version {})",
+                            new_prompts.judge_version(),
+                            new_prompts.learner_version()
+                        );
+                        *prompts_for_watcher.write().await = new_prompts;
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to reload prompt templates, keeping previous templates: {:#}",
+                            e
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    // Start the event log writer
+    let mut events = EventWriter::spawn(Arc::clone(&logs), &config.storage.writer);
+    tracing::info!(
+        "✓ Event writer started (queue: {}, overflow: {:?})",
+        config.storage.writer.queue_capacity,
+        config.storage.writer.overflow
     );
+    if config.storage.sampling.is_enabled() {
+        events = events.with_sampler(AllowSampler::new(config.storage.sampling.clone()));
+        tracing::info!(
+            "✓ Allow sampling enabled (default rate: {}, {} route rates)",
+            config.storage.sampling.allow_rate,
+            config.storage.sampling.routes.len()
+        );
+    }
+    let exporter = if !config.export.sinks.is_empty() {
+        let exporter = Arc::new(
+            Exporter::new(&config.export)
+                .await
+                .with_context(|| "Failed to start event export")?,
+        );
+        let names: Vec<&str> = exporter.sinks().iter().map(|sink| sink.name()).collect();
+        tracing::info!("✓ Event export started (sinks: {})", names.join(", "));
+        events = events.with_exporter(Arc::clone(&exporter));
+        Some(exporter)
+    } else {
+        None
+    };
+
+    // Build application state
+    let trusted_proxies = TrustedProxies::parse(&config.waf.trusted_proxies)
+        .with_context(|| "Invalid waf.trusted_proxies")?;
+    let writer = events.clone();
+    let app_state = AppState::new(Arc::clone(&judge), events, config.waf.upstream_url.clone())
+        .with_async_judge(config.async_judge.clone())
+        .with_hash_headers(&config.cache.hash_headers)
+        .with_trusted_proxies(trusted_proxies);
+    if !config.async_judge.routes.is_empty() {
+        tracing::info!(
+            "✓ Judging after forward on {:?} (bans: {}s)",
+            config.async_judge.routes,
+            config.async_judge.ban_ttl_seconds
+        );
+    }
+    if !config.waf.trusted_proxies.is_empty() {
+        tracing::info!(
+            "✓ Trusting X-Forwarded-For from {:?}",
+            config.waf.trusted_proxies
+        );
+    }
+
+    // Start the admin API (event labeling) on its own listener
+    if config.admin.enabled {
+        let mut feedback = Feedback::new(Arc::clone(&logs), Arc::clone(&judge));
+        if let Some(examples) = examples {
+            feedback = feedback.with_examples(examples);
+        }
+        if let Some(ref bans) = app_state.bans {
+            feedback = feedback.with_bans(Arc::clone(bans));
+        }
+        let admin_app = admin::router(AdminState {
+            logs: Arc::clone(&logs),
+            feedback: Arc::new(feedback),
+            judge: Arc::clone(&judge),
+            retention,
+            writer: Some(writer.clone()),
+            exporter,
+            token: config.admin.token.clone(),
+        })
+        .layer(middleware::from_fn(tracing_middleware));
+
+        let admin_listener = tokio::net::TcpListener::bind(&config.admin.listen_addr)
+            .await
+            .with_context(|| format!("Failed to bind admin API to {}", config.admin.listen_addr))?;
+        if config.admin.token.is_none() {
+            tracing::warn!("Admin API has no token, keep it on a private address");
+        }
+        tracing::info!("✓ Admin API listening on {}", config.admin.listen_addr);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(admin_listener, admin_app).await {
+                tracing::error!("Admin API error: {}", e);
+            }
+        });
+    }
 
     // Build Axum router
     let app = Router::new()
@@ -181,9 +500,46 @@ async fn main() -> Result<()> {
     tracing::info!("   Upstream: {}", config.waf.upstream_url);
     tracing::info!("   Health check: http://{}/health", config.waf.listen_addr);
 
-    axum::serve(listener, app)
-        .await
-        .with_context(|| "Server error")?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .with_context(|| "Server error")?;
+
+    // Write the events still queued before exiting
+    writer.shutdown().await;
 
     Ok(())
 }
+
+/// Resolves on ctrl-c or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("Shutting down, draining in-flight requests");
+}
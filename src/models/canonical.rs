@@ -0,0 +1,492 @@
+use crate::models::request::RequestPayload;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Headers covered by the request hash unless `cache.hash_headers` is set
+pub const DEFAULT_HASH_HEADERS: &[&str] = &[
+    "authorization",
+    "content-type",
+    "cookie",
+    "origin",
+    "referer",
+    "user-agent",
+    "x-forwarded-host",
+    "x-original-url",
+    "x-rewrite-url",
+];
+
+/// Computes the request hash (the verdict cache key) from the canonical form
+/// of a request, so that requests a backend would see as the same share a
+/// verdict and requests differing in anything security-relevant don't:
+/// - method, uppercased
+/// - path, with dot-segments and duplicate slashes collapsed, then
+///   percent-decoded except for encoded separators
+/// - query, percent-decoded (as bytes) and sorted by key, repeated and
+///   valueless keys kept
+/// - the configured headers, by lowercase name
+/// - body: JSON re-serialized with sorted keys (unless a key is repeated),
+///   forms canonicalized like the query, anything else as is
+#[derive(Debug, Clone)]
+pub struct Canonicalizer {
+    hash_headers: Vec<String>,
+}
+
+impl Default for Canonicalizer {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_HEADERS)
+    }
+}
+
+impl Canonicalizer {
+    pub fn new<S: AsRef<str>>(hash_headers: &[S]) -> Self {
+        let mut hash_headers: Vec<String> = hash_headers
+            .iter()
+            .map(|h| h.as_ref().trim().to_ascii_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect();
+        hash_headers.sort();
+        hash_headers.dedup();
+        Self { hash_headers }
+    }
+
+    pub fn hash(&self, payload: &RequestPayload) -> String {
+        let mut hasher = Sha256::new();
+
+        field(&mut hasher, &payload.method.to_ascii_uppercase());
+        field(&mut hasher, &canonical_path(&payload.path));
+
+        let query = query_bytes(payload);
+        field(&mut hasher, &query.len().to_string());
+        for (key, value) in &query {
+            field(&mut hasher, key);
+            field(&mut hasher, value);
+        }
+
+        for name in &self.hash_headers {
+            let value = payload
+                .headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim());
+            if let Some(value) = value {
+                field(&mut hasher, name);
+                field(&mut hasher, value);
+            }
+        }
+
+        match &payload.body {
+            Some(body) => {
+                field(&mut hasher, "body");
+                field(
+                    &mut hasher,
+                    &canonical_body(body, payload.content_type().map(|s| s.as_str())),
+                );
+            }
+            None => field(&mut hasher, "no body"),
+        }
+
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Length-prefixed, so that field boundaries can't be forged
+fn field<T: AsRef<[u8]> + ?Sized>(hasher: &mut Sha256, value: &T) {
+    let value = value.as_ref();
+    hasher.update((value.len() as u64).to_le_bytes());
+    hasher.update(value);
+}
+
+/// Percent-decodes `input` to bytes, turning `+` into a space in query
+/// strings and forms
+fn percent_decode_bytes(input: &str, plus_as_space: bool) -> Vec<u8> {
+    if plus_as_space {
+        urlencoding::decode_binary(input.replace('+', " ").as_bytes()).into_owned()
+    } else {
+        urlencoding::decode_binary(input.as_bytes()).into_owned()
+    }
+}
+
+/// `bytes` as text, percent-encoding the bytes that aren't UTF-8 and the
+/// `escaped` characters
+fn decoded_string(bytes: &[u8], escaped: &[char]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if escaped.contains(&c) {
+                out.push_str(&format!("%{:02X}", c as u32));
+            } else {
+                out.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// Collapses `//`, `/./` and `/../` segments (never above the root)
+pub fn normalize_path(input: &str) -> String {
+    let absolute = input.starts_with('/');
+    let trailing = input.ends_with('/') && input.len() > 1;
+    let mut segments: Vec<&str> = Vec::new();
+
+    for segment in input.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    let mut out = segments.join("/");
+    if absolute {
+        out.insert(0, '/');
+    }
+    if trailing && !out.ends_with('/') {
+        out.push('/');
+    }
+
+    out
+}
+
+/// Path with its segments collapsed as sent, then decoded: `%2e%2e` is a
+/// segment named `..`, not a step up, and an encoded `/` (or `%`) stays
+/// encoded so it can't pass for a separator
+pub fn canonical_path(path: &str) -> String {
+    normalize_path(path)
+        .split('/')
+        .map(|segment| decoded_string(&percent_decode_bytes(segment, false), &['%', '/']))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Whether a canonical `path` falls under a route `prefix`: equal to it or
+/// below it on a segment boundary (`/api` covers `/api/users`, not `/apiary`)
+pub fn has_route_prefix(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'))
+}
+
+/// Decoded `key=value` pairs of a query string or form body, in order, as
+/// bytes; `?debug` gives `("debug", "")`
+fn split_query_bytes(raw: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
+    raw.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (
+                percent_decode_bytes(key, true),
+                percent_decode_bytes(value, true),
+            )
+        })
+        .collect()
+}
+
+/// `split_query_bytes`, sorted by key (the order of a repeated key's values
+/// is kept)
+fn parse_query_bytes(raw: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut pairs = split_query_bytes(raw);
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    pairs
+}
+
+/// Decoded `key=value` pairs of a query string or form body, in order;
+/// `?debug` gives `("debug", "")`. Bytes that aren't UTF-8 stay
+/// percent-encoded.
+pub fn split_query(raw: &str) -> Vec<(String, String)> {
+    split_query_bytes(raw)
+        .into_iter()
+        .map(|(key, value)| (decoded_string(&key, &[]), decoded_string(&value, &[])))
+        .collect()
+}
+
+/// `split_query`, sorted by key (the order of a repeated key's values is kept)
+pub fn parse_query(raw: &str) -> Vec<(String, String)> {
+    let mut pairs = split_query(raw);
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    pairs
+}
+
+/// Canonical query of a request: from its raw query string when known, else
+/// from its (single-valued) query params
+pub fn query_pairs(payload: &RequestPayload) -> Vec<(String, String)> {
+    match &payload.raw_query {
+        Some(raw) => parse_query(raw),
+        None => {
+            let mut pairs: Vec<_> = payload
+                .query_params
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            pairs.sort();
+            pairs
+        }
+    }
+}
+
+/// Canonical query of a request as bytes, for hashing
+fn query_bytes(payload: &RequestPayload) -> Vec<(Vec<u8>, Vec<u8>)> {
+    match &payload.raw_query {
+        Some(raw) => parse_query_bytes(raw),
+        None => query_pairs(payload)
+            .into_iter()
+            .map(|(k, v)| (k.into_bytes(), v.into_bytes()))
+            .collect(),
+    }
+}
+
+pub fn canonical_body(body: &str, content_type: Option<&str>) -> String {
+    let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+
+    if content_type.contains("json") {
+        // serde_json maps are sorted by key, but keep only the last value of
+        // a repeated key, which other parsers may not
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(body) {
+            if serde_json::from_str::<UniqueKeys>(body).is_ok() {
+                return value.to_string();
+            }
+        }
+    } else if content_type.contains("x-www-form-urlencoded") {
+        return parse_query_bytes(body)
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "{}={}",
+                    urlencoding::encode_binary(k),
+                    urlencoding::encode_binary(v)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+    }
+
+    body.to_string()
+}
+
+/// A JSON document whose objects don't repeat a key
+struct UniqueKeys;
+
+impl<'de> Deserialize<'de> for UniqueKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UniqueKeysVisitor)
+    }
+}
+
+struct UniqueKeysVisitor;
+
+impl<'de> Visitor<'de> for UniqueKeysVisitor {
+    type Value = UniqueKeys;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value without repeated keys")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_unit<E>(self) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UniqueKeys, A::Error> {
+        while seq.next_element::<UniqueKeys>()?.is_some() {}
+        Ok(UniqueKeys)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UniqueKeys, A::Error> {
+        let mut keys = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if !keys.insert(key) {
+                return Err(de::Error::custom("repeated key"));
+            }
+            map.next_value::<UniqueKeys>()?;
+        }
+        Ok(UniqueKeys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request(
+        path: &str,
+        query: Option<&str>,
+        headers: &[(&str, &str)],
+        body: Option<&str>,
+    ) -> RequestPayload {
+        RequestPayload::new(
+            "POST".to_string(),
+            path.to_string(),
+            headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body.map(str::to_string),
+            HashMap::new(),
+            None,
+        )
+        .with_raw_query(query.map(str::to_string))
+    }
+
+    #[test]
+    fn test_canonical_path() {
+        assert_eq!(canonical_path("/api//users/./1"), "/api/users/1");
+        assert_eq!(canonical_path("/api/%75sers/x/../1"), "/api/users/1");
+        assert_eq!(canonical_path("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(canonical_path("/a/b/"), "/a/b/");
+
+        // Encoded dots and separators are collapsed as sent, not decoded first
+        assert_eq!(canonical_path("/static/%2e%2e/admin"), "/static/../admin");
+        assert_eq!(canonical_path("/a/..%2f..%2fetc"), "/a/..%2F..%2Fetc");
+        assert_ne!(canonical_path("/a%2fb"), canonical_path("/a/b"));
+        assert_ne!(canonical_path("/a%252fb"), canonical_path("/a%2fb"));
+    }
+
+    #[test]
+    fn test_has_route_prefix() {
+        assert!(has_route_prefix("/api", "/api"));
+        assert!(has_route_prefix("/api/users", "/api"));
+        assert!(has_route_prefix("/api/users", "/api/"));
+        assert!(has_route_prefix("/anything", "/"));
+        assert!(!has_route_prefix("/apiary", "/api"));
+        assert!(!has_route_prefix("/api-v2/users", "/api"));
+        assert!(!has_route_prefix("/ap", "/api"));
+    }
+
+    #[test]
+    fn test_percent_decode_keeps_invalid_bytes() {
+        assert_eq!(
+            split_query("q=caf%C3%A9+x&p=%C0%AF..%ff"),
+            vec![
+                ("q".to_string(), "café x".to_string()),
+                ("p".to_string(), "%C0%AF..%FF".to_string()),
+            ]
+        );
+        assert_eq!(canonical_path("/%FF"), "/%FF");
+        assert_ne!(canonical_path("/%FF"), canonical_path("/%25FF"));
+    }
+
+    #[test]
+    fn test_parse_query_keeps_repeated_and_valueless_keys() {
+        assert_eq!(
+            parse_query("b=2&a=1&debug&a=%27x%27&&q=a+b"),
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("a".to_string(), "'x'".to_string()),
+                ("b".to_string(), "2".to_string()),
+                ("debug".to_string(), String::new()),
+                ("q".to_string(), "a b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canonical_body() {
+        assert_eq!(
+            canonical_body(
+                r#"{ "b": 1, "a": {"d": 2, "c": 3} }"#,
+                Some("application/json")
+            ),
+            r#"{"a":{"c":3,"d":2},"b":1}"#
+        );
+        assert_eq!(
+            canonical_body("b=2&a=x+y", Some("application/x-www-form-urlencoded")),
+            "a=x%20y&b=2"
+        );
+        assert_eq!(canonical_body("{ raw }", Some("text/plain")), "{ raw }");
+
+        // Repeated keys are kept as sent
+        let repeated = r#"{"role": "admin", "role": "user"}"#;
+        assert_eq!(canonical_body(repeated, Some("application/json")), repeated);
+        let nested = r#"[{"a": {"b": 1, "\u0062": 2}}]"#;
+        assert_eq!(canonical_body(nested, Some("application/json")), nested);
+    }
+
+    #[test]
+    fn test_hash_equivalent_requests() {
+        let json = [("content-type", "application/json")];
+        let a = request(
+            "/api/users",
+            Some("a=1&b=2"),
+            &json,
+            Some(r#"{"x":1,"y":2}"#),
+        );
+        let b = request(
+            "/api//users/.",
+            Some("b=2&a=%31"),
+            &json,
+            Some(r#"{ "y": 2, "x": 1 }"#),
+        );
+        assert_eq!(a.normalized_hash, b.normalized_hash);
+    }
+
+    #[test]
+    fn test_hash_distinguishes_security_relevant_differences() {
+        let base = request("/search", Some("q=1"), &[("user-agent", "curl")], None);
+
+        let repeated = request("/search", Some("q=1&q=2"), &[("user-agent", "curl")], None);
+        let valueless = request(
+            "/search",
+            Some("q=1&debug"),
+            &[("user-agent", "curl")],
+            None,
+        );
+        let agent = request("/search", Some("q=1"), &[("User-Agent", "sqlmap")], None);
+        let cookie = request(
+            "/search",
+            Some("q=1"),
+            &[("user-agent", "curl"), ("cookie", "id=' OR 1=1")],
+            None,
+        );
+        let swapped = request("/search", Some("q=1"), &[("user-agent", "curl")], Some(""));
+        // Not UTF-8 vs. the replacement character
+        let invalid = request("/search", Some("q=%FF"), &[("user-agent", "curl")], None);
+        let replaced = request(
+            "/search",
+            Some("q=%EF%BF%BD"),
+            &[("user-agent", "curl")],
+            None,
+        );
+        assert_ne!(invalid.normalized_hash, replaced.normalized_hash);
+        for other in [&repeated, &valueless, &agent, &cookie, &swapped] {
+            assert_ne!(base.normalized_hash, other.normalized_hash);
+        }
+
+        // Headers outside the configured set are ignored
+        let traced = request(
+            "/search",
+            Some("q=1"),
+            &[("user-agent", "curl"), ("x-request-id", "42")],
+            None,
+        );
+        assert_eq!(base.normalized_hash, traced.normalized_hash);
+        assert_ne!(
+            Canonicalizer::new(&["x-request-id"]).hash(&base),
+            Canonicalizer::new(&["x-request-id"]).hash(&traced)
+        );
+    }
+}
@@ -40,7 +40,7 @@ impl JudgeDecision {
         matches!(self, JudgeDecision::Flag { .. })
     }
 
-    pub fn decision_type(&self) -> &str {
+    pub fn decision_type(&self) -> &'static str {
         match self {
             JudgeDecision::Allow { .. } => "allow",
             JudgeDecision::Flag { .. } => "flag",
@@ -49,7 +49,7 @@ impl JudgeDecision {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum ThreatLevel {
     Low,
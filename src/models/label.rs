@@ -0,0 +1,114 @@
+use crate::models::decision::{JudgeDecision, ThreatLevel};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Human verdict on a logged event
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelKind {
+    /// Blocked or flagged, but legitimate
+    FalsePositive,
+    /// Allowed or flagged, but an attack
+    FalseNegative,
+}
+
+impl LabelKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LabelKind::FalsePositive => "false_positive",
+            LabelKind::FalseNegative => "false_negative",
+        }
+    }
+
+    /// Parses `false_positive`/`false_negative`, or `fp`/`fn` for short
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "false_positive" | "fp" => Some(LabelKind::FalsePositive),
+            "false_negative" | "fn" => Some(LabelKind::FalseNegative),
+            _ => None,
+        }
+    }
+
+    /// Fails unless an event with `decision` can be labeled this way
+    pub fn check(&self, decision: &str) -> Result<()> {
+        match (self, decision) {
+            (LabelKind::FalsePositive, "block" | "flag")
+            | (LabelKind::FalseNegative, "allow" | "flag") => Ok(()),
+            _ => bail!(
+                "An event decided '{}' cannot be a {}",
+                decision,
+                self.as_str().replace('_', " ")
+            ),
+        }
+    }
+
+    /// Verdict pinned for requests identical to the labeled one
+    pub fn pinned_verdict(&self, note: Option<&str>) -> JudgeDecision {
+        match self {
+            LabelKind::FalsePositive => JudgeDecision::Allow { confidence: 1.0 },
+            LabelKind::FalseNegative => JudgeDecision::Block {
+                confidence: 1.0,
+                reason: match note {
+                    Some(note) => format!("Labeled false negative: {}", note),
+                    None => "Labeled false negative".to_string(),
+                },
+                threat_level: ThreatLevel::High,
+            },
+        }
+    }
+}
+
+/// A label stored in the `labels` table
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EventLabel {
+    pub id: i64,
+    pub created_at: i64,
+    pub event_id: i64,
+    pub payload_hash: String,
+    pub label: String,
+    pub note: Option<String>,
+}
+
+impl EventLabel {
+    pub fn kind(&self) -> Option<LabelKind> {
+        LabelKind::parse(&self.label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_kind_parse() {
+        assert_eq!(LabelKind::parse("fp"), Some(LabelKind::FalsePositive));
+        assert_eq!(
+            LabelKind::parse("false_negative"),
+            Some(LabelKind::FalseNegative)
+        );
+        assert_eq!(LabelKind::parse("maybe"), None);
+    }
+
+    #[test]
+    fn test_label_kind_check() {
+        assert!(LabelKind::FalsePositive.check("block").is_ok());
+        assert!(LabelKind::FalsePositive.check("flag").is_ok());
+        assert!(LabelKind::FalsePositive.check("allow").is_err());
+        assert!(LabelKind::FalseNegative.check("allow").is_ok());
+        assert!(LabelKind::FalseNegative.check("block").is_err());
+    }
+
+    #[test]
+    fn test_pinned_verdict() {
+        assert_eq!(
+            LabelKind::FalsePositive.pinned_verdict(Some("health check")),
+            JudgeDecision::Allow { confidence: 1.0 }
+        );
+        match LabelKind::FalseNegative.pinned_verdict(Some("SSRF")) {
+            JudgeDecision::Block { reason, .. } => {
+                assert_eq!(reason, "Labeled false negative: SSRF")
+            }
+            other => panic!("expected block, got {:?}", other),
+        }
+    }
+}
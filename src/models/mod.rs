@@ -1,2 +1,5 @@
+pub mod canonical;
 pub mod decision;
+pub mod label;
+pub mod params;
 pub mod request;
@@ -0,0 +1,333 @@
+use crate::models::canonical;
+use crate::models::request::RequestPayload;
+use serde::{Deserialize, Serialize};
+
+/// Longest value kept per parameter (multipart file content included)
+pub const MAX_PARAM_VALUE_CHARS: usize = 1024;
+
+/// Where a named request input comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamSource {
+    Query,
+    /// `application/x-www-form-urlencoded` body
+    Form,
+    /// `multipart/form-data` body part
+    Multipart,
+    /// JSON body value, named by its flattened path
+    Json,
+    Cookie,
+}
+
+/// A named request input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub source: ParamSource,
+    /// Name, or the flattened path of a JSON value (`user.roles[0]`)
+    pub name: String,
+    pub value: String,
+    /// Filename of an uploaded file (multipart)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// Declared content type of a multipart part
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Set when the value was cut to `MAX_PARAM_VALUE_CHARS`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl Param {
+    fn new(source: ParamSource, name: String, value: &str) -> Self {
+        let (value, truncated) = cap(value);
+        Self {
+            source,
+            name,
+            value,
+            filename: None,
+            content_type: None,
+            truncated,
+        }
+    }
+}
+
+fn cap(value: &str) -> (String, bool) {
+    match value.char_indices().nth(MAX_PARAM_VALUE_CHARS) {
+        Some((idx, _)) => (value[..idx].to_string(), true),
+        None => (value.to_string(), false),
+    }
+}
+
+/// Named inputs of a request, in order: query (repeated and valueless keys
+/// included), body (form, multipart or JSON, by content type), then cookies
+pub fn extract(payload: &RequestPayload) -> Vec<Param> {
+    let mut params: Vec<Param> = match &payload.raw_query {
+        Some(raw) => canonical::split_query(raw),
+        None => canonical::query_pairs(payload),
+    }
+    .into_iter()
+    .map(|(name, value)| Param::new(ParamSource::Query, name, &value))
+    .collect();
+
+    if let Some(body) = &payload.body {
+        let content_type = payload
+            .content_type()
+            .map(|s| s.to_ascii_lowercase())
+            .unwrap_or_default();
+        if content_type.contains("x-www-form-urlencoded") {
+            params.extend(
+                canonical::split_query(body)
+                    .into_iter()
+                    .map(|(name, value)| Param::new(ParamSource::Form, name, &value)),
+            );
+        } else if content_type.starts_with("multipart/form-data") {
+            if let Some(boundary) = payload.content_type().and_then(|ct| boundary(ct)) {
+                params.extend(multipart(body, &boundary));
+            }
+        } else if content_type.contains("json") {
+            if let Ok(value) = serde_json::from_str::<serde_json::Value>(body) {
+                flatten_json(&value, String::new(), &mut params);
+            }
+        }
+    }
+
+    for (header, value) in &payload.headers {
+        if header.eq_ignore_ascii_case("cookie") {
+            params.extend(
+                value
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|cookie| !cookie.is_empty())
+                    .map(|cookie| {
+                        let (name, value) = cookie.split_once('=').unwrap_or((cookie, ""));
+                        Param::new(ParamSource::Cookie, name.to_string(), value)
+                    }),
+            );
+        }
+    }
+
+    params
+}
+
+/// `boundary` parameter of a multipart content type
+pub(crate) fn boundary(content_type: &str) -> Option<String> {
+    content_type
+        .split(';')
+        .filter_map(|part| part.trim().split_once('='))
+        .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Fields and files of a multipart body; malformed parts are skipped
+fn multipart(body: &str, boundary: &str) -> Vec<Param> {
+    let delimiter = format!("--{}", boundary);
+
+    body.split(delimiter.as_str())
+        .skip(1)
+        .take_while(|part| !part.starts_with("--"))
+        .filter_map(|part| {
+            let part = part
+                .strip_prefix("\r\n")
+                .or_else(|| part.strip_prefix('\n'))?;
+            let (head, content) = part
+                .split_once("\r\n\r\n")
+                .or_else(|| part.split_once("\n\n"))?;
+            let content = content
+                .strip_suffix("\r\n")
+                .or_else(|| content.strip_suffix('\n'))
+                .unwrap_or(content);
+
+            let mut name = None;
+            let mut filename = None;
+            let mut content_type = None;
+            for line in head.lines() {
+                let Some((header, value)) = line.split_once(':') else {
+                    continue;
+                };
+                if header.trim().eq_ignore_ascii_case("content-disposition") {
+                    name = disposition_param(value, "name");
+                    filename = disposition_param(value, "filename");
+                } else if header.trim().eq_ignore_ascii_case("content-type") {
+                    content_type = Some(value.trim().to_string());
+                }
+            }
+
+            let mut param = Param::new(ParamSource::Multipart, name?, content);
+            param.filename = filename;
+            param.content_type = content_type;
+            Some(param)
+        })
+        .collect()
+}
+
+/// Value of `key` in a `Content-Disposition` header (`form-data; name="a"`)
+pub(crate) fn disposition_param(disposition: &str, key: &str) -> Option<String> {
+    disposition
+        .split(';')
+        .filter_map(|part| part.trim().split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+}
+
+/// One param per scalar, named by its path (`a.b[0].c`; the root is `$`)
+fn flatten_json(value: &serde_json::Value, path: String, params: &mut Vec<Param>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten_json(v, path, params);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                flatten_json(v, format!("{}[{}]", path, i), params);
+            }
+        }
+        scalar => {
+            let name = if path.is_empty() {
+                "$".to_string()
+            } else {
+                path
+            };
+            let value = match scalar {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            params.push(Param::new(ParamSource::Json, name, &value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn payload(
+        query: Option<&str>,
+        content_type: Option<&str>,
+        body: Option<&str>,
+    ) -> RequestPayload {
+        let mut headers = HashMap::new();
+        if let Some(content_type) = content_type {
+            headers.insert("content-type".to_string(), content_type.to_string());
+        }
+        RequestPayload::new(
+            "POST".to_string(),
+            "/submit".to_string(),
+            headers,
+            body.map(str::to_string),
+            HashMap::new(),
+            None,
+        )
+        .with_raw_query(query.map(str::to_string))
+    }
+
+    fn names(params: &[Param]) -> Vec<(ParamSource, &str, &str)> {
+        params
+            .iter()
+            .map(|p| (p.source, p.name.as_str(), p.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_query_keeps_repeated_and_valueless_keys() {
+        let params = extract(&payload(Some("a=1&a=2&debug&q=%27x%27"), None, None));
+
+        assert_eq!(
+            names(&params),
+            vec![
+                (ParamSource::Query, "a", "1"),
+                (ParamSource::Query, "a", "2"),
+                (ParamSource::Query, "debug", ""),
+                (ParamSource::Query, "q", "'x'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_form_and_cookies() {
+        let mut request = payload(
+            None,
+            Some("application/x-www-form-urlencoded"),
+            Some("user=admin&pass=a+b"),
+        );
+        request
+            .headers
+            .insert("cookie".to_string(), "sid=1; theme".to_string());
+
+        assert_eq!(
+            names(&extract(&request)),
+            vec![
+                (ParamSource::Form, "user", "admin"),
+                (ParamSource::Form, "pass", "a b"),
+                (ParamSource::Cookie, "sid", "1"),
+                (ParamSource::Cookie, "theme", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_flattened_paths() {
+        let params = extract(&payload(
+            None,
+            Some("application/json; charset=utf-8"),
+            Some(r#"{"user":{"name":"bob","roles":["admin",{"id":1}]},"ok":true,"n":null}"#),
+        ));
+
+        assert_eq!(
+            names(&params),
+            vec![
+                (ParamSource::Json, "n", "null"),
+                (ParamSource::Json, "ok", "true"),
+                (ParamSource::Json, "user.name", "bob"),
+                (ParamSource::Json, "user.roles[0]", "admin"),
+                (ParamSource::Json, "user.roles[1].id", "1"),
+            ]
+        );
+        assert_eq!(
+            names(&extract(&payload(
+                None,
+                Some("application/json"),
+                Some("\"x\"")
+            ))),
+            vec![(ParamSource::Json, "$", "x")]
+        );
+    }
+
+    #[test]
+    fn test_multipart_fields_and_files() {
+        let content = "A".repeat(MAX_PARAM_VALUE_CHARS + 1);
+        let body = format!(
+            "--XyZ\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+             hello\r\n\
+             --XyZ\r\n\
+             Content-Disposition: form-data; name=\"avatar\"; filename=\"shell.php\"\r\n\
+             Content-Type: image/png\r\n\r\n\
+             {}\r\n\
+             --XyZ--\r\n",
+            content
+        );
+        let params = extract(&payload(
+            None,
+            Some("multipart/form-data; boundary=\"XyZ\""),
+            Some(&body),
+        ));
+
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].name, "title");
+        assert_eq!(params[0].value, "hello");
+        assert_eq!(params[1].source, ParamSource::Multipart);
+        assert_eq!(params[1].name, "avatar");
+        assert_eq!(params[1].filename.as_deref(), Some("shell.php"));
+        assert_eq!(params[1].content_type.as_deref(), Some("image/png"));
+        assert!(params[1].truncated);
+        assert_eq!(params[1].value.chars().count(), MAX_PARAM_VALUE_CHARS);
+    }
+}
@@ -1,6 +1,48 @@
+use crate::models::canonical::{self, Canonicalizer};
+use crate::models::params::{self, Param, ParamSource};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+/// Headers kept in the stored request context (everything else is dropped)
+const CONTEXT_HEADERS: &[&str] = &[
+    "user-agent",
+    "content-type",
+    "accept",
+    "referer",
+    "origin",
+    "x-forwarded-for",
+    "x-requested-with",
+    "authorization",
+    "cookie",
+];
+
+/// Name tokens that mark a query/body parameter as carrying a secret. Names
+/// are split into tokens on separators and camelCase (`api_key`, `apiKey` and
+/// `X-Api-Key` all give `api`, `key`), so `keyword` or `author` are kept.
+const SENSITIVE_PARAM_TOKENS: &[&str] = &[
+    "pass",
+    "passwd",
+    "password",
+    "pwd",
+    "passphrase",
+    "token",
+    "accesstoken",
+    "secret",
+    "clientsecret",
+    "key",
+    "apikey",
+    "session",
+    "sessionid",
+    "auth",
+    "authorization",
+    "credentials",
+];
+
+const REDACTED: &str = "[REDACTED]";
+const MAX_CONTEXT_VALUE_CHARS: usize = 256;
+const MAX_CONTEXT_BODY_CHARS: usize = 1024;
+const MAX_CONTEXT_QUERY_PARAMS: usize = 32;
+const MAX_CONTEXT_PARAMS: usize = 64;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequestPayload {
@@ -10,6 +52,11 @@ pub struct RequestPayload {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub body: Option<String>,
     pub query_params: HashMap<String, String>,
+    /// Query string as received, keeping repeated and valueless keys
+    /// (`query_params` holds one value per key)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_query: Option<String>,
+    /// Hash of the canonical request (see `Canonicalizer`), the verdict cache key
     pub normalized_hash: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ip_addr: Option<String>,
@@ -24,43 +71,57 @@ impl RequestPayload {
         query_params: HashMap<String, String>,
         ip_addr: Option<String>,
     ) -> Self {
-        let normalized_hash = Self::compute_hash(&method, &path, &body, &query_params);
-
-        Self {
+        let mut payload = Self {
             method,
             path,
             headers,
             body,
             query_params,
-            normalized_hash,
+            raw_query: None,
+            normalized_hash: String::new(),
             ip_addr,
-        }
+        };
+        payload.normalized_hash = Canonicalizer::default().hash(&payload);
+        payload
+    }
+
+    /// Keeps the raw query string and rehashes with the default headers
+    pub fn with_raw_query(mut self, raw_query: Option<String>) -> Self {
+        self.raw_query = raw_query;
+        self.normalized_hash = Canonicalizer::default().hash(&self);
+        self
     }
 
+    /// Rehashes the request with `canonicalizer` (configured hash headers)
+    pub fn hashed_with(mut self, canonicalizer: &Canonicalizer) -> Self {
+        self.normalized_hash = canonicalizer.hash(&self);
+        self
+    }
+
+    /// Hash of a request without headers
+    #[allow(dead_code)] // Used in tests
     pub fn compute_hash(
         method: &str,
         path: &str,
         body: &Option<String>,
         query_params: &HashMap<String, String>,
     ) -> String {
-        let mut hasher = Sha256::new();
-
-        hasher.update(method.as_bytes());
-        hasher.update(path.as_bytes());
-
-        if let Some(body_content) = body {
-            hasher.update(body_content.as_bytes());
-        }
-
-        // Sort query params for consistent hashing
-        let mut params: Vec<_> = query_params.iter().collect();
-        params.sort_by_key(|(k, _)| *k);
-        for (key, value) in params {
-            hasher.update(key.as_bytes());
-            hasher.update(value.as_bytes());
-        }
+        let payload = Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: HashMap::new(),
+            body: body.clone(),
+            query_params: query_params.clone(),
+            raw_query: None,
+            normalized_hash: String::new(),
+            ip_addr: None,
+        };
+        Canonicalizer::new::<&str>(&[]).hash(&payload)
+    }
 
-        format!("{:x}", hasher.finalize())
+    /// Named inputs: query, form/multipart/JSON body and cookies (see `params::extract`)
+    pub fn params(&self) -> Vec<Param> {
+        params::extract(self)
     }
 
     pub fn get_user_agent(&self) -> Option<&String> {
@@ -78,7 +139,265 @@ impl RequestPayload {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Redacted, size-capped copy of a request, stored with each event so the
+/// Learner can reason about query params, headers and body content.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestContext {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub query: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_excerpt: Option<String>,
+    /// Named inputs (see `params::extract`), so rules targeting them can be
+    /// tested on the whole body rather than its excerpt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Param>,
+    /// Set when any value was cut or dropped to fit the size caps
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+impl RequestContext {
+    pub fn from_payload(payload: &RequestPayload) -> Self {
+        let mut context = Self::default();
+
+        let params = canonical::query_pairs(payload);
+        if params.len() > MAX_CONTEXT_QUERY_PARAMS {
+            context.truncated = true;
+        }
+        for (key, value) in params.into_iter().take(MAX_CONTEXT_QUERY_PARAMS) {
+            let value = if is_sensitive_param(&key) {
+                REDACTED.to_string()
+            } else {
+                context.cap(&value, MAX_CONTEXT_VALUE_CHARS)
+            };
+            context.query.push((key, value));
+        }
+
+        for (name, value) in &payload.headers {
+            let name = name.to_ascii_lowercase();
+            if !CONTEXT_HEADERS.contains(&name.as_str()) {
+                continue;
+            }
+            let value = match name.as_str() {
+                // Keep the scheme, it tells Basic/Bearer/garbage apart
+                "authorization" => match value.split_once(' ') {
+                    Some((scheme, _)) => format!("{} {}", scheme, REDACTED),
+                    None => REDACTED.to_string(),
+                },
+                // Keep cookie names, drop their values
+                "cookie" => value
+                    .split(';')
+                    .map(|c| match c.trim().split_once('=') {
+                        Some((name, _)) => format!("{}={}", name, REDACTED),
+                        None => c.trim().to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                _ => value.clone(),
+            };
+            let value = context.cap(&value, MAX_CONTEXT_VALUE_CHARS);
+            context.headers.insert(name, value);
+        }
+
+        if let Some(body) = &payload.body {
+            let redacted = redact_body(body, payload.content_type().map(|s| s.as_str()));
+            context.body_excerpt = Some(context.cap(&redacted, MAX_CONTEXT_BODY_CHARS));
+        }
+
+        let params = payload.params();
+        if params.len() > MAX_CONTEXT_PARAMS {
+            context.truncated = true;
+        }
+        for mut param in params.into_iter().take(MAX_CONTEXT_PARAMS) {
+            param.value = if param.source == ParamSource::Cookie || is_sensitive_param(&param.name)
+            {
+                REDACTED.to_string()
+            } else {
+                context.cap(&param.value, MAX_CONTEXT_VALUE_CHARS)
+            };
+            if param.truncated {
+                context.truncated = true;
+            }
+            context.params.push(param);
+        }
+
+        context
+    }
+
+    /// Returns `value` cut to `max_chars` characters, recording the truncation
+    fn cap(&mut self, value: &str, max_chars: usize) -> String {
+        match value.char_indices().nth(max_chars) {
+            Some((idx, _)) => {
+                self.truncated = true;
+                value[..idx].to_string()
+            }
+            None => value.to_string(),
+        }
+    }
+}
+
+fn is_sensitive_param(name: &str) -> bool {
+    name_tokens(name).any(|token| SENSITIVE_PARAM_TOKENS.contains(&token.as_str()))
+}
+
+/// Lowercase tokens of a parameter name, split on non-alphanumerics and on
+/// lower-to-upper camelCase boundaries
+fn name_tokens(name: &str) -> impl Iterator<Item = String> + '_ {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .flat_map(|segment| {
+            let mut tokens = Vec::new();
+            let mut start = 0;
+            let bytes = segment.as_bytes();
+            for i in 1..bytes.len() {
+                if bytes[i - 1].is_ascii_lowercase() && bytes[i].is_ascii_uppercase() {
+                    tokens.push(&segment[start..i]);
+                    start = i;
+                }
+            }
+            tokens.push(&segment[start..]);
+            tokens
+        })
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_ascii_lowercase())
+}
+
+/// Masks secret-looking fields in JSON, form and multipart bodies; other
+/// bodies get their `name=value` and `name: value` pairs masked
+fn redact_body(body: &str, content_type: Option<&str>) -> String {
+    let lowercase = content_type.unwrap_or_default().to_ascii_lowercase();
+
+    if lowercase.contains("json") {
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(body) {
+            redact_json(&mut value);
+            return value.to_string();
+        }
+    } else if lowercase.contains("x-www-form-urlencoded") {
+        return body
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if is_sensitive_param(key) => format!("{}={}", key, REDACTED),
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+    } else if lowercase.starts_with("multipart/form-data") {
+        if let Some(boundary) = content_type.and_then(params::boundary) {
+            return redact_multipart(body, &boundary);
+        }
+    }
+
+    redact_raw(body)
+}
+
+/// Replaces the content of multipart parts whose field name is sensitive,
+/// keeping the part headers and the framing
+fn redact_multipart(body: &str, boundary: &str) -> String {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = body.split(delimiter.as_str());
+    let mut redacted = parts.next().unwrap_or_default().to_string();
+
+    for part in parts {
+        redacted.push_str(&delimiter);
+        let split = part
+            .find("\r\n\r\n")
+            .map(|idx| idx + 4)
+            .or_else(|| part.find("\n\n").map(|idx| idx + 2));
+        let Some(split) = split.filter(|_| !part.starts_with("--")) else {
+            redacted.push_str(part);
+            continue;
+        };
+
+        let (head, content) = part.split_at(split);
+        let sensitive = head.lines().any(|line| {
+            line.split_once(':').is_some_and(|(header, value)| {
+                header.trim().eq_ignore_ascii_case("content-disposition")
+                    && params::disposition_param(value, "name")
+                        .is_some_and(|name| is_sensitive_param(&name))
+            })
+        });
+        redacted.push_str(head);
+        if sensitive {
+            let line_break = if content.ends_with("\r\n") {
+                "\r\n"
+            } else if content.ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            redacted.push_str(REDACTED);
+            redacted.push_str(line_break);
+        } else {
+            redacted.push_str(content);
+        }
+    }
+
+    redacted
+}
+
+/// Masks `name=value` / `name: value` pairs with a sensitive name in a body of
+/// unknown type (plain text, missing or wrong content type, XML-ish payloads)
+fn redact_raw(body: &str) -> String {
+    if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(body) {
+        if value.is_object() || value.is_array() {
+            redact_json(&mut value);
+            return value.to_string();
+        }
+    }
+
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+    let is_value_end =
+        |c: char| matches!(c, '&' | ';' | ',' | '"' | '\'' | '<') || c.is_whitespace();
+
+    let mut redacted = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(idx) = rest.find(['=', ':']) {
+        let (before, after) = rest.split_at(idx);
+        let name_start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_name_char(*c))
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let name = before[name_start..].trim_matches(|c| c == '"' || c == '\'');
+        redacted.push_str(before);
+        redacted.push_str(&after[..1]);
+        rest = &after[1..];
+
+        if !name.is_empty() && is_sensitive_param(name) {
+            let value_start = rest.len() - rest.trim_start_matches([' ', '"', '\'']).len();
+            redacted.push_str(&rest[..value_start]);
+            rest = &rest[value_start..];
+            let value_end = rest.find(is_value_end).unwrap_or(rest.len());
+            if value_end > 0 {
+                redacted.push_str(REDACTED);
+                rest = &rest[value_end..];
+            }
+        }
+    }
+    redacted.push_str(rest);
+
+    redacted
+}
+
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if is_sensitive_param(key) {
+                    *v = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(v);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct LogEntry {
     pub id: i64,
     pub timestamp: i64,
@@ -90,6 +409,34 @@ pub struct LogEntry {
     pub reason: Option<String>,
     pub ip_addr: Option<String>,
     pub user_agent: Option<String>,
+    /// JSON-encoded `RequestContext` (absent for events logged before it existed)
+    pub request_context: Option<String>,
+}
+
+impl LogEntry {
+    /// Decodes the stored request context, if any
+    pub fn context(&self) -> Option<RequestContext> {
+        self.request_context
+            .as_deref()
+            .and_then(|json| serde_json::from_str(json).ok())
+    }
+
+    /// The logged request rebuilt from its stored context, keeping the
+    /// event's hash. Redacted and size-capped, so only an approximation.
+    pub fn to_payload(&self) -> RequestPayload {
+        let context = self.context().unwrap_or_default();
+
+        RequestPayload {
+            method: self.method.clone(),
+            path: self.path.clone(),
+            headers: context.headers.into_iter().collect(),
+            body: context.body_excerpt,
+            query_params: context.query.into_iter().collect(),
+            raw_query: None,
+            normalized_hash: self.payload_hash.clone(),
+            ip_addr: self.ip_addr.clone(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -323,6 +670,7 @@ mod tests {
             reason: Some("Legitimate request".to_string()),
             ip_addr: Some("192.168.1.1".to_string()),
             user_agent: Some("Mozilla/5.0".to_string()),
+            request_context: None,
         };
 
         assert_eq!(entry.id, 1);
@@ -330,4 +678,151 @@ mod tests {
         assert_eq!(entry.decision, "allow");
         assert_eq!(entry.confidence, 0.95);
     }
+
+    #[test]
+    fn test_request_context_redacts_secrets() {
+        let mut headers = HashMap::new();
+        headers.insert("authorization".to_string(), "Bearer abc.def".to_string());
+        headers.insert("cookie".to_string(), "sid=123; theme=dark".to_string());
+        headers.insert("user-agent".to_string(), "sqlmap/1.7".to_string());
+        headers.insert("x-internal-id".to_string(), "42".to_string());
+        headers.insert("content-type".to_string(), "application/json".to_string());
+
+        let mut query_params = HashMap::new();
+        query_params.insert("q".to_string(), "' OR 1=1--".to_string());
+        query_params.insert("api_key".to_string(), "s3cr3t".to_string());
+
+        let payload = RequestPayload::new(
+            "POST".to_string(),
+            "/login".to_string(),
+            headers,
+            Some(r#"{"user":"admin","password":"hunter2"}"#.to_string()),
+            query_params,
+            None,
+        );
+
+        let context = RequestContext::from_payload(&payload);
+
+        assert_eq!(
+            context.query,
+            vec![
+                ("api_key".to_string(), "[REDACTED]".to_string()),
+                ("q".to_string(), "' OR 1=1--".to_string()),
+            ]
+        );
+        assert_eq!(context.headers["authorization"], "Bearer [REDACTED]");
+        assert_eq!(
+            context.headers["cookie"],
+            "sid=[REDACTED]; theme=[REDACTED]"
+        );
+        assert_eq!(context.headers["user-agent"], "sqlmap/1.7");
+        assert!(!context.headers.contains_key("x-internal-id"));
+
+        let params: Vec<(&str, &str)> = context
+            .params
+            .iter()
+            .map(|param| (param.name.as_str(), param.value.as_str()))
+            .collect();
+        assert!(params.contains(&("user", "admin")));
+        assert!(params.contains(&("password", "[REDACTED]")));
+        assert!(params.contains(&("theme", "[REDACTED]")));
+
+        let body = context.body_excerpt.unwrap();
+        assert!(body.contains("admin"));
+        assert!(!body.contains("hunter2"));
+        assert!(!context.truncated);
+    }
+
+    #[test]
+    fn test_sensitive_params_match_whole_tokens() {
+        for name in ["api_key", "apiKey", "X-Api-Key", "password", "access_token", "sessionId"] {
+            assert!(is_sensitive_param(name), "{}", name);
+        }
+        for name in ["keyword", "monkey", "author", "passenger", "tokenizer", "q"] {
+            assert!(!is_sensitive_param(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_request_context_redacts_multipart_and_raw_bodies() {
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"user\"\r\n\r\nadmin\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"password\"\r\n\r\nhunter2\r\n\
+            --XyZ--\r\n";
+        let mut headers = HashMap::new();
+        headers.insert(
+            "content-type".to_string(),
+            "multipart/form-data; boundary=XyZ".to_string(),
+        );
+        let payload = RequestPayload::new(
+            "POST".to_string(),
+            "/login".to_string(),
+            headers,
+            Some(body.to_string()),
+            HashMap::new(),
+            None,
+        );
+
+        let excerpt = RequestContext::from_payload(&payload).body_excerpt.unwrap();
+        assert!(excerpt.contains("\r\n\r\nadmin\r\n"));
+        assert!(excerpt.contains("name=\"password\"\r\n\r\n[REDACTED]\r\n--XyZ--"));
+        assert!(!excerpt.contains("hunter2"));
+
+        let raw = "user=admin&password=hunter2\napi_key: s3cr3t\nkeyword=waf";
+        assert_eq!(
+            redact_body(raw, Some("text/plain")),
+            "user=admin&password=[REDACTED]\napi_key: [REDACTED]\nkeyword=waf"
+        );
+        assert_eq!(
+            redact_body(r#"{"token":"abc","q":"1"}"#, None),
+            r#"{"q":"1","token":"[REDACTED]"}"#
+        );
+    }
+
+    #[test]
+    fn test_request_context_caps_sizes_on_char_boundaries() {
+        let body = "é".repeat(MAX_CONTEXT_BODY_CHARS + 10);
+
+        let payload = RequestPayload::new(
+            "POST".to_string(),
+            "/upload".to_string(),
+            HashMap::new(),
+            Some(body),
+            HashMap::new(),
+            None,
+        );
+
+        let context = RequestContext::from_payload(&payload);
+
+        assert!(context.truncated);
+        assert_eq!(
+            context.body_excerpt.unwrap().chars().count(),
+            MAX_CONTEXT_BODY_CHARS
+        );
+    }
+
+    #[test]
+    fn test_log_entry_context_roundtrip() {
+        let context = RequestContext {
+            query: vec![("id".to_string(), "1".to_string())],
+            ..Default::default()
+        };
+
+        let entry = LogEntry {
+            id: 1,
+            timestamp: 0,
+            method: "GET".to_string(),
+            path: "/item".to_string(),
+            payload_hash: "abc".to_string(),
+            decision: "flag".to_string(),
+            confidence: 0.6,
+            reason: None,
+            ip_addr: None,
+            user_agent: None,
+            request_context: Some(serde_json::to_string(&context).unwrap()),
+        };
+
+        assert_eq!(entry.context(), Some(context));
+    }
 }
@@ -1,17 +1,143 @@
 use crate::models::decision::JudgeDecision;
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use redis::aio::ConnectionManager;
 use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+/// A cached verdict with the canonical path of its request, so that
+/// verdicts can be flushed by path, and the version of the judge prompts
+/// that produced it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedVerdict {
+    pub decision: JudgeDecision,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+/// Which cached verdicts to flush; every verdict when empty
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CacheFlush {
+    /// Canonical path prefix (`/api/` matches `/api/users`)
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+    /// `allow`, `flag` or `block`
+    #[serde(default)]
+    pub decision: Option<String>,
+}
+
+impl CacheFlush {
+    /// Whether every verdict is flushed, unreadable ones included
+    pub fn is_everything(&self) -> bool {
+        self.path_prefix.is_none() && self.decision.is_none()
+    }
+
+    pub fn matches(&self, verdict: &CachedVerdict) -> bool {
+        self.path_prefix
+            .as_deref()
+            .is_none_or(|prefix| verdict.path.starts_with(prefix))
+            && self
+                .decision
+                .as_deref()
+                .is_none_or(|decision| verdict.decision.decision_type() == decision)
+    }
+}
+
+/// How long a verdict is cached: long for confident allows and blocks,
+/// short for flags and less confident verdicts. Allows without any
+/// confidence (what fail-open produces) are never cached.
+#[derive(Debug, Clone, Copy)]
+pub struct VerdictTtls {
+    pub confident: Duration,
+    pub short: Duration,
+    /// Minimum confidence of an allow or block for the long TTL
+    pub confident_threshold: f32,
+}
+
+impl Default for VerdictTtls {
+    fn default() -> Self {
+        Self {
+            confident: Duration::from_secs(900),
+            short: Duration::from_secs(60),
+            confident_threshold: 0.8,
+        }
+    }
+}
+
+impl VerdictTtls {
+    /// TTL of `decision`, `None` when it must not be cached
+    pub fn ttl_for(&self, decision: &JudgeDecision) -> Option<Duration> {
+        let ttl = match decision {
+            JudgeDecision::Allow { confidence } if *confidence <= 0.0 => return None,
+            JudgeDecision::Flag { .. } => self.short,
+            other if other.confidence() >= self.confident_threshold => self.confident,
+            _ => self.short,
+        };
+        (!ttl.is_zero()).then_some(ttl)
+    }
+}
+
+/// Shared verdict cache backend, keyed by cache key (see `Judge`).
+/// Implemented by Redis, the in-process LRU, a SQLite file and a no-op cache.
+#[async_trait]
+#[allow(clippy::double_must_use)] // Emitted by async_trait's expansion
+pub trait VerdictCache: Send + Sync {
+    /// Backend name, for logs
+    fn name(&self) -> &'static str;
+
+    /// Cached verdict, unless missing or expired
+    async fn get_verdict(&self, key: &str) -> Result<Option<CachedVerdict>>;
+
+    /// Caches a verdict for `ttl`, replacing any verdict under `key`
+    async fn set_verdict(&self, key: &str, verdict: &CachedVerdict, ttl: Duration) -> Result<()>;
+
+    /// Drops the verdict under `key` (when a label pins a new one)
+    async fn invalidate(&self, key: &str) -> Result<()>;
+
+    /// Drops the verdicts matching `flush`, returning how many
+    async fn flush(&self, flush: &CacheFlush) -> Result<usize>;
+}
+
+/// Caches nothing (`cache.backend: none`)
+pub struct NoopCache;
+
+#[async_trait]
+impl VerdictCache for NoopCache {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    async fn get_verdict(&self, _key: &str) -> Result<Option<CachedVerdict>> {
+        Ok(None)
+    }
+
+    async fn set_verdict(
+        &self,
+        _key: &str,
+        _verdict: &CachedVerdict,
+        _ttl: Duration,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn invalidate(&self, _key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn flush(&self, _flush: &CacheFlush) -> Result<usize> {
+        Ok(0)
+    }
+}
+
 #[derive(Clone)]
 pub struct RedisCache {
     client: ConnectionManager,
-    ttl: Duration,
 }
 
 impl RedisCache {
-    pub async fn new(redis_url: &str, ttl: Duration) -> Result<Self> {
+    pub async fn new(redis_url: &str) -> Result<Self> {
         let client = redis::Client::open(redis_url)
             .with_context(|| format!("Failed to create Redis client: {}", redis_url))?;
 
@@ -19,13 +145,30 @@ impl RedisCache {
             .await
             .with_context(|| "Failed to connect to Redis")?;
 
-        Ok(Self {
-            client: connection,
-            ttl,
-        })
+        Ok(Self { client: connection })
     }
 
-    pub async fn get_verdict(&self, hash: &str) -> Result<Option<JudgeDecision>> {
+    pub async fn ping(&self) -> Result<()> {
+        let mut conn = self.client.clone();
+        redis::cmd("PING")
+            .query_async::<()>(&mut conn)
+            .await
+            .with_context(|| "Redis ping failed")?;
+        Ok(())
+    }
+
+    fn verdict_key(hash: &str) -> String {
+        format!("verdict:{}", hash)
+    }
+}
+
+#[async_trait]
+impl VerdictCache for RedisCache {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get_verdict(&self, hash: &str) -> Result<Option<CachedVerdict>> {
         let key = Self::verdict_key(hash);
         let mut conn = self.client.clone();
 
@@ -36,31 +179,29 @@ impl RedisCache {
 
         match value {
             Some(json) => {
-                let decision = serde_json::from_str(&json)
+                let verdict: CachedVerdict = serde_json::from_str(&json)
                     .with_context(|| "Failed to deserialize verdict from Redis")?;
-                Ok(Some(decision))
+                Ok(Some(verdict))
             }
             None => Ok(None),
         }
     }
 
-    pub async fn set_verdict(&self, hash: &str, decision: &JudgeDecision) -> Result<()> {
+    async fn set_verdict(&self, hash: &str, verdict: &CachedVerdict, ttl: Duration) -> Result<()> {
         let key = Self::verdict_key(hash);
         let mut conn = self.client.clone();
 
-        let json = serde_json::to_string(decision)
+        let json = serde_json::to_string(verdict)
             .with_context(|| "Failed to serialize verdict for Redis")?;
 
-        conn.set_ex::<_, _, ()>(&key, json, self.ttl.as_secs())
+        conn.set_ex::<_, _, ()>(&key, json, ttl.as_secs().max(1))
             .await
             .with_context(|| format!("Failed to set verdict in Redis: {}", key))?;
 
         Ok(())
     }
 
-    /// Invalidates a cached verdict - used for cache warming/invalidation strategies
-    #[allow(dead_code)]
-    pub async fn invalidate(&self, hash: &str) -> Result<()> {
+    async fn invalidate(&self, hash: &str) -> Result<()> {
         let key = Self::verdict_key(hash);
         let mut conn = self.client.clone();
 
@@ -71,17 +212,55 @@ impl RedisCache {
         Ok(())
     }
 
-    pub async fn ping(&self) -> Result<()> {
+    /// Scans every verdict key, so meant for occasional admin use
+    async fn flush(&self, flush: &CacheFlush) -> Result<usize> {
         let mut conn = self.client.clone();
-        redis::cmd("PING")
-            .query_async::<()>(&mut conn)
-            .await
-            .with_context(|| "Redis ping failed")?;
-        Ok(())
-    }
 
-    fn verdict_key(hash: &str) -> String {
-        format!("verdict:{}", hash)
+        let mut keys = Vec::new();
+        {
+            let mut iter = conn
+                .scan_match::<_, String>(Self::verdict_key("*"))
+                .await
+                .with_context(|| "Failed to scan verdicts in Redis")?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+
+        let mut flushed = 0;
+        let mut unreadable = 0;
+        for key in keys {
+            let value: Option<String> = conn
+                .get(&key)
+                .await
+                .with_context(|| format!("Failed to get verdict from Redis: {}", key))?;
+            // Unreadable verdicts only go with everything: nothing says they
+            // match the filter
+            let matches = match value.map(|json| serde_json::from_str::<CachedVerdict>(&json)) {
+                None => continue,
+                Some(Ok(verdict)) => flush.matches(&verdict),
+                Some(Err(_)) if flush.is_everything() => true,
+                Some(Err(_)) => {
+                    unreadable += 1;
+                    false
+                }
+            };
+            if matches {
+                flushed += conn
+                    .del::<_, usize>(&key)
+                    .await
+                    .with_context(|| format!("Failed to delete verdict from Redis: {}", key))?;
+            }
+        }
+        if unreadable > 0 {
+            tracing::warn!(
+                unreadable,
+                "Unreadable verdicts left in Redis by a filtered flush"
+            );
+        }
+
+
+        Ok(flushed)
     }
 }
 
@@ -89,6 +268,197 @@ impl RedisCache {
 mod tests {
     use super::*;
     use crate::models::decision::ThreatLevel;
+    use crate::storage::memory::MemoryCache;
+    use crate::storage::sqlite_cache::SqliteCache;
+
+    fn cached(decision: &JudgeDecision) -> CachedVerdict {
+        CachedVerdict {
+            decision: decision.clone(),
+            path: "/api/users".to_string(),
+            prompt_version: None,
+        }
+    }
+
+    /// Behavior every storing backend must have
+    async fn check_contract(cache: &dyn VerdictCache) {
+        let ttl = Duration::from_secs(60);
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        let block = JudgeDecision::Block {
+            confidence: 0.9,
+            reason: "SQLi".to_string(),
+            threat_level: ThreatLevel::High,
+        };
+        let at = |path: &str, decision: &JudgeDecision| CachedVerdict {
+            decision: decision.clone(),
+            path: path.to_string(),
+            prompt_version: Some("3+5".to_string()),
+        };
+
+        assert_eq!(cache.get_verdict("contract:a").await.unwrap(), None);
+
+        cache
+            .set_verdict("contract:a", &at("/contract/api/users", &allow), ttl)
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.get_verdict("contract:a").await.unwrap(),
+            Some(at("/contract/api/users", &allow))
+        );
+
+        // Overwrite, then invalidate
+        cache
+            .set_verdict("contract:a", &at("/contract/api/users", &block), ttl)
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.get_verdict("contract:a").await.unwrap(),
+            Some(at("/contract/api/users", &block))
+        );
+        cache.invalidate("contract:a").await.unwrap();
+        assert_eq!(cache.get_verdict("contract:a").await.unwrap(), None);
+        cache.invalidate("contract:missing").await.unwrap();
+
+        // Flush by path prefix and decision type
+        cache
+            .set_verdict("contract:a", &at("/contract/api/users", &allow), ttl)
+            .await
+            .unwrap();
+        cache
+            .set_verdict("contract:b", &at("/contract/api/orders", &block), ttl)
+            .await
+            .unwrap();
+        cache
+            .set_verdict("contract:c", &at("/contract/static/app.js", &allow), ttl)
+            .await
+            .unwrap();
+        let flushed = cache
+            .flush(&CacheFlush {
+                path_prefix: Some("/contract/api/".to_string()),
+                decision: Some("allow".to_string()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(flushed, 1);
+        assert_eq!(cache.get_verdict("contract:a").await.unwrap(), None);
+        assert!(cache.get_verdict("contract:b").await.unwrap().is_some());
+        let flushed = cache
+            .flush(&CacheFlush {
+                path_prefix: Some("/contract/".to_string()),
+                decision: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(flushed, 2);
+        assert_eq!(cache.get_verdict("contract:c").await.unwrap(), None);
+
+        // A filter matching nothing leaves everything; an empty one flushes it
+        cache
+            .set_verdict("contract:e", &at("/contract/api/users", &allow), ttl)
+            .await
+            .unwrap();
+        let flushed = cache
+            .flush(&CacheFlush {
+                path_prefix: Some("/contract/api/users".to_string()),
+                decision: Some("block".to_string()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(flushed, 0);
+        assert!(cache.get_verdict("contract:e").await.unwrap().is_some());
+        assert!(cache.flush(&CacheFlush::default()).await.unwrap() >= 1);
+        assert_eq!(cache.get_verdict("contract:e").await.unwrap(), None);
+
+        // Expiry
+        cache
+            .set_verdict(
+                "contract:d",
+                &at("/contract/api/users", &allow),
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert!(cache.get_verdict("contract:d").await.unwrap().is_some());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(cache.get_verdict("contract:d").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_contract() {
+        let cache = MemoryCache::new(100, Duration::from_secs(60));
+        check_contract(&cache).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_cache_contract() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = SqliteCache::new(temp_dir.path().join("cache.db"))
+            .await
+            .unwrap();
+        check_contract(&cache).await;
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires a local redis-server
+    async fn test_redis_cache_contract() {
+        let cache = RedisCache::new("redis://localhost:6379")
+            .await
+            .expect("Redis not running");
+        check_contract(&cache).await;
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires a local redis-server
+    async fn test_redis_filtered_flush_keeps_unreadable_verdicts() {
+        let cache = RedisCache::new("redis://localhost:6379")
+            .await
+            .expect("Redis not running");
+        let mut conn = cache.client.clone();
+        conn.set_ex::<_, _, ()>(RedisCache::verdict_key("unreadable:a"), "{", 60)
+            .await
+            .unwrap();
+        cache
+            .set_verdict(
+                "unreadable:b",
+                &cached(&JudgeDecision::Allow { confidence: 0.9 }),
+                Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+
+        let flushed = cache
+            .flush(&CacheFlush {
+                path_prefix: Some("/api/".to_string()),
+                decision: None,
+            })
+            .await
+            .unwrap();
+        assert!(flushed >= 1);
+        assert_eq!(cache.get_verdict("unreadable:b").await.unwrap(), None);
+        let value: Option<String> = conn
+            .get(RedisCache::verdict_key("unreadable:a"))
+            .await
+            .unwrap();
+        assert_eq!(value.as_deref(), Some("{"));
+
+        cache.flush(&CacheFlush::default()).await.unwrap();
+        let value: Option<String> = conn
+            .get(RedisCache::verdict_key("unreadable:a"))
+            .await
+            .unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[tokio::test]
+    async fn test_noop_cache_stores_nothing() {
+        let verdict = cached(&JudgeDecision::Allow { confidence: 0.9 });
+
+        NoopCache
+            .set_verdict("a", &verdict, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(NoopCache.get_verdict("a").await.unwrap(), None);
+        assert_eq!(NoopCache.flush(&CacheFlush::default()).await.unwrap(), 0);
+    }
 
     #[test]
     fn test_verdict_key_format() {
@@ -111,11 +481,63 @@ mod tests {
         assert_eq!(key, "verdict:");
     }
 
+    #[test]
+    fn test_verdict_ttls() {
+        let ttls = VerdictTtls::default();
+        let block = |confidence| JudgeDecision::Block {
+            confidence,
+            reason: "SQLi".to_string(),
+            threat_level: ThreatLevel::High,
+        };
+
+        assert_eq!(
+            ttls.ttl_for(&JudgeDecision::Allow { confidence: 0.95 }),
+            Some(ttls.confident)
+        );
+        assert_eq!(ttls.ttl_for(&block(0.9)), Some(ttls.confident));
+        assert_eq!(ttls.ttl_for(&block(0.5)), Some(ttls.short));
+        assert_eq!(
+            ttls.ttl_for(&JudgeDecision::Flag {
+                confidence: 1.0,
+                reason: "Odd".to_string(),
+                suggested_rule: None,
+            }),
+            Some(ttls.short)
+        );
+        // Fail-open allows are never cached
+        assert_eq!(
+            ttls.ttl_for(&JudgeDecision::Allow { confidence: 0.0 }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_flush_matches() {
+        let verdict = cached(&JudgeDecision::Allow { confidence: 0.9 });
+
+        assert!(CacheFlush::default().matches(&verdict));
+        let by_path = CacheFlush {
+            path_prefix: Some("/api/".to_string()),
+            decision: None,
+        };
+        assert!(by_path.matches(&verdict));
+        let by_both = CacheFlush {
+            path_prefix: Some("/api/".to_string()),
+            decision: Some("block".to_string()),
+        };
+        assert!(!by_both.matches(&verdict));
+        let other_path = CacheFlush {
+            path_prefix: Some("/admin".to_string()),
+            decision: Some("allow".to_string()),
+        };
+        assert!(!other_path.matches(&verdict));
+    }
+
     #[tokio::test]
     #[ignore] // Requires actual network connection attempt - run with integration tests
     async fn test_redis_connection_failure() {
         // Try to connect to non-existent Redis
-        let result = RedisCache::new("redis://localhost:9999", Duration::from_secs(60)).await;
+        let result = RedisCache::new("redis://localhost:9999").await;
         assert!(result.is_err());
         
         if let Err(err) = result {
@@ -127,14 +549,14 @@ mod tests {
     #[tokio::test]
     async fn test_redis_invalid_url() {
         // Invalid Redis URL format
-        let result = RedisCache::new("invalid://url", Duration::from_secs(60)).await;
+        let result = RedisCache::new("invalid://url").await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_redis_empty_url() {
         // Empty URL should fail
-        let result = RedisCache::new("", Duration::from_secs(60)).await;
+        let result = RedisCache::new("").await;
         assert!(result.is_err());
     }
 
@@ -142,7 +564,7 @@ mod tests {
     #[tokio::test]
     #[ignore]
     async fn test_set_and_get_verdict_with_redis() {
-        let cache = RedisCache::new("redis://localhost:6379", Duration::from_secs(60))
+        let cache = RedisCache::new("redis://localhost:6379")
             .await
             .expect("Failed to create cache - Redis not running?");
 
@@ -155,7 +577,7 @@ mod tests {
         let hash = "test_hash_123";
 
         cache
-            .set_verdict(hash, &decision)
+            .set_verdict(hash, &cached(&decision), Duration::from_secs(60))
             .await
             .expect("Failed to set verdict");
 
@@ -165,13 +587,13 @@ mod tests {
             .expect("Failed to get verdict")
             .expect("Verdict not found");
 
-        assert_eq!(decision, retrieved);
+        assert_eq!(decision, retrieved.decision);
     }
 
     #[tokio::test]
     #[ignore]
     async fn test_get_nonexistent_verdict_with_redis() {
-        let cache = RedisCache::new("redis://localhost:6379", Duration::from_secs(60))
+        let cache = RedisCache::new("redis://localhost:6379")
             .await
             .expect("Redis not running");
 
@@ -182,7 +604,7 @@ mod tests {
     #[tokio::test]
     #[ignore]
     async fn test_invalidate_verdict_with_redis() {
-        let cache = RedisCache::new("redis://localhost:6379", Duration::from_secs(60))
+        let cache = RedisCache::new("redis://localhost:6379")
             .await
             .expect("Redis not running");
 
@@ -190,7 +612,10 @@ mod tests {
         let hash = "test_invalidate";
 
         // Set then invalidate
-        cache.set_verdict(hash, &decision).await.unwrap();
+        cache
+            .set_verdict(hash, &cached(&decision), Duration::from_secs(60))
+            .await
+            .unwrap();
         cache.invalidate(hash).await.unwrap();
 
         // Should be gone
@@ -198,10 +623,48 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[tokio::test]
+    #[ignore]
+    async fn test_flush_verdicts_with_redis() {
+        let cache = RedisCache::new("redis://localhost:6379")
+            .await
+            .expect("Redis not running");
+
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        let api = cached(&allow);
+        let admin = CachedVerdict {
+            decision: allow,
+            path: "/admin".to_string(),
+            prompt_version: None,
+        };
+        let ttl = Duration::from_secs(60);
+        cache
+            .set_verdict("test_flush_api", &api, ttl)
+            .await
+            .unwrap();
+        cache
+            .set_verdict("test_flush_admin", &admin, ttl)
+            .await
+            .unwrap();
+
+        let flush = CacheFlush {
+            path_prefix: Some("/api/".to_string()),
+            decision: Some("allow".to_string()),
+        };
+        assert!(cache.flush(&flush).await.unwrap() >= 1);
+        assert!(cache.get_verdict("test_flush_api").await.unwrap().is_none());
+        assert!(cache
+            .get_verdict("test_flush_admin")
+            .await
+            .unwrap()
+            .is_some());
+        cache.invalidate("test_flush_admin").await.unwrap();
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_ping_with_redis() {
-        let cache = RedisCache::new("redis://localhost:6379", Duration::from_secs(60))
+        let cache = RedisCache::new("redis://localhost:6379")
             .await
             .expect("Redis not running");
 
@@ -212,7 +675,7 @@ mod tests {
     #[tokio::test]
     #[ignore]
     async fn test_cache_ttl_with_redis() {
-        let cache = RedisCache::new("redis://localhost:6379", Duration::from_secs(1))
+        let cache = RedisCache::new("redis://localhost:6379")
             .await
             .expect("Redis not running");
 
@@ -223,7 +686,10 @@ mod tests {
         };
         let hash = "test_ttl";
 
-        cache.set_verdict(hash, &decision).await.unwrap();
+        cache
+            .set_verdict(hash, &cached(&decision), Duration::from_secs(1))
+            .await
+            .unwrap();
 
         // Should exist immediately
         let result1 = cache.get_verdict(hash).await.unwrap();
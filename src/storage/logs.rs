@@ -1,15 +1,226 @@
+use crate::core::fewshot::StoredExample;
+use crate::core::scoring::ScoreBreakdown;
 use crate::models::decision::JudgeDecision;
-use crate::models::request::{LogEntry, RequestPayload};
+use crate::models::label::{EventLabel, LabelKind};
+use crate::models::request::{LogEntry, RequestContext, RequestPayload};
 use anyhow::{Context, Result};
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use async_trait::async_trait;
+use sqlx::sqlite::{
+    SqliteAutoVacuum, SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePool,
+    SqlitePoolOptions, SqliteSynchronous,
+};
 use std::path::Path;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Event log backend: events, labels and few-shot examples. Implemented by
+/// a SQLite file (`LogStore`) and PostgreSQL (`PgLogStore`), which several
+/// replicas can share.
+#[async_trait]
+#[allow(clippy::double_must_use)] // Emitted by async_trait's expansion
+pub trait EventStore: Send + Sync {
+    /// Backend name, for logs
+    fn name(&self) -> &'static str;
+
+    #[allow(dead_code)] // Used in tests
+    async fn log_event(&self, payload: &RequestPayload, decision: &JudgeDecision) -> Result<i64> {
+        self.log_scored_event(payload, decision, None, None).await
+    }
+
+    /// Logs an event together with its anomaly score breakdown (anomaly mode)
+    /// and the version of the judge prompts that were in use
+    async fn log_scored_event(
+        &self,
+        payload: &RequestPayload,
+        decision: &JudgeDecision,
+        score: Option<&ScoreBreakdown>,
+        prompt_version: Option<&str>,
+    ) -> Result<i64>;
+
+    /// Logs events in a single transaction
+    async fn log_batch(&self, events: &[NewEvent]) -> Result<()>;
+
+    /// Flagged requests since timestamp, newest first
+    async fn get_flagged_since(&self, since_timestamp: i64) -> Result<Vec<LogEntry>>;
+
+    /// Retrieves blocked requests since timestamp - used for analytics dashboards
+    #[allow(dead_code)]
+    async fn get_blocked_since(&self, since_timestamp: i64) -> Result<Vec<LogEntry>>;
+
+    /// Retrieves all events since timestamp with limit, newest first
+    async fn get_events_since(&self, since_timestamp: i64, limit: i64) -> Result<Vec<LogEntry>>;
+
+    /// Aggregates event counts by decision type - used for metrics and reporting.
+    /// Sampled events count for their weight, so counts are estimates.
+    #[allow(dead_code)]
+    async fn count_events_by_decision(&self, since_timestamp: i64) -> Result<Vec<(String, i64)>>;
+
+    /// Event by id
+    async fn get_event(&self, id: i64) -> Result<Option<LogEntry>>;
+
+    /// Labels an event as a false positive or false negative
+    async fn add_label(
+        &self,
+        event: &LogEntry,
+        kind: LabelKind,
+        note: Option<&str>,
+    ) -> Result<EventLabel>;
+
+    /// Latest label of each payload hash: the verdicts to pin
+    async fn latest_labels(&self) -> Result<Vec<EventLabel>>;
+
+    /// Labels added since timestamp with their event, oldest first. Labels
+    /// of deleted events are left out.
+    async fn get_labeled_events_since(
+        &self,
+        since_timestamp: i64,
+    ) -> Result<Vec<(EventLabel, LogEntry)>>;
+
+    /// Stores a few-shot example, replacing the example of the same request.
+    /// A corrected example is only ever replaced by another correction.
+    async fn upsert_example(&self, example: &StoredExample) -> Result<()>;
+
+    /// Few-shot examples of an endpoint, newest first
+    async fn examples_for_template(
+        &self,
+        method: &str,
+        path_template: &str,
+        limit: i64,
+    ) -> Result<Vec<StoredExample>>;
+
+    /// Deletes the few-shot examples not corrected by a human created before
+    /// `before_timestamp`, returning how many were deleted
+    async fn delete_unverified_examples(&self, before_timestamp: i64) -> Result<u64>;
+
+    /// Up to `limit` events with the given decision logged before
+    /// `before_timestamp`, oldest first, as `(id, JSON line)` for archiving.
+    /// Labeled events are kept: labels reference them and pin verdicts.
+    async fn expired_events(
+        &self,
+        decision: &str,
+        before_timestamp: i64,
+        limit: i64,
+    ) -> Result<Vec<(i64, String)>>;
+
+    /// Deletes events by id, returning how many were deleted
+    async fn delete_events(&self, ids: &[i64]) -> Result<u64>;
+
+    /// Gives free space back after deletions, returning the number of pages
+    /// reclaimed
+    async fn compact(&self, step_pages: u32) -> Result<u64>;
+}
+
+/// Event log in a SQLite file
 pub struct LogStore {
     pool: SqlitePool,
 }
 
+/// Event row ready to be inserted, timestamped when it was built
+#[derive(Debug, Clone)]
+pub struct NewEvent {
+    pub timestamp: i64,
+    pub method: String,
+    pub path: String,
+    pub payload_hash: String,
+    pub decision: &'static str,
+    pub confidence: f32,
+    pub reason: Option<String>,
+    pub ip_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_context: String,
+    pub score_breakdown: Option<String>,
+    pub prompt_version: Option<String>,
+    /// Requests the event stands for (1 unless sampled)
+    pub weight: f64,
+}
+
+impl NewEvent {
+    pub fn new(
+        payload: &RequestPayload,
+        decision: &JudgeDecision,
+        score: Option<&ScoreBreakdown>,
+        prompt_version: Option<&str>,
+    ) -> Result<Self> {
+        let context = RequestContext::from_payload(payload);
+        Self::from_context(payload, decision, &context, score, prompt_version)
+    }
+
+    /// Event with an already built request context, shared with the export sinks
+    pub fn from_context(
+        payload: &RequestPayload,
+        decision: &JudgeDecision,
+        context: &RequestContext,
+        score: Option<&ScoreBreakdown>,
+        prompt_version: Option<&str>,
+    ) -> Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let reason = match decision {
+            JudgeDecision::Flag { reason, .. } | JudgeDecision::Block { reason, .. } => {
+                Some(reason.clone())
+            }
+            JudgeDecision::Allow { .. } => None,
+        };
+
+        let request_context = serde_json::to_string(context)
+            .with_context(|| "Failed to serialize request context")?;
+
+        let score_breakdown = score
+            .map(serde_json::to_string)
+            .transpose()
+            .with_context(|| "Failed to serialize score breakdown")?;
+
+        Ok(Self {
+            timestamp,
+            method: payload.method.clone(),
+            path: payload.path.clone(),
+            payload_hash: payload.normalized_hash.clone(),
+            decision: decision.decision_type(),
+            confidence: decision.confidence(),
+            reason,
+            ip_addr: payload.ip_addr.clone(),
+            user_agent: payload.get_user_agent().cloned(),
+            request_context,
+            score_breakdown,
+            prompt_version: prompt_version.map(str::to_string),
+            weight: 1.0,
+        })
+    }
+}
+
+async fn insert_event<'e, E>(executor: E, event: &NewEvent) -> Result<i64>
+where
+    E: sqlx::SqliteExecutor<'e>,
+{
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO events (timestamp, method, path, payload_hash, decision, confidence, reason, ip_addr, user_agent, request_context, score_breakdown, prompt_version, weight)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        event.timestamp,
+        event.method,
+        event.path,
+        event.payload_hash,
+        event.decision,
+        event.confidence,
+        event.reason,
+        event.ip_addr,
+        event.user_agent,
+        event.request_context,
+        event.score_breakdown,
+        event.prompt_version,
+        event.weight,
+    )
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to insert event into database")?;
+
+    Ok(result.last_insert_rowid())
+}
+
 impl LogStore {
     pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
         let path_str = db_path.as_ref().to_string_lossy().to_string();
@@ -20,8 +231,13 @@ impl LogStore {
                 .with_context(|| format!("Failed to create database directory: {:?}", parent))?;
         }
 
+        // Incremental auto-vacuum lets retention give pages back to the file
+        // system. WAL lets readers (learner, admin) run while events are written.
         let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", path_str))?
-            .create_if_missing(true);
+            .create_if_missing(true)
+            .auto_vacuum(SqliteAutoVacuum::Incremental)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal);
 
         let pool = SqlitePoolOptions::new()
             .max_connections(5)
@@ -29,6 +245,14 @@ impl LogStore {
             .await
             .with_context(|| format!("Failed to connect to SQLite database: {}", path_str))?;
 
+        if !incremental_vacuum_enabled(&pool).await? {
+            tracing::warn!(
+                "Logs database predates incremental auto-vacuum: retention can't give space back \
+                 until `guardix logs vacuum {}` is run (full VACUUM, with the WAF stopped)",
+                path_str
+            );
+        }
+
         // Run migrations
         sqlx::migrate!("./migrations")
             .run(&pool)
@@ -38,54 +262,69 @@ impl LogStore {
         Ok(Self { pool })
     }
 
-    pub async fn log_event(
+    /// Switches a database created before incremental auto-vacuum to it
+    /// with one full VACUUM, which locks the file for as long as it takes
+    /// (`guardix logs vacuum`). Returns whether it was needed.
+    pub async fn enable_incremental_vacuum(&self) -> Result<bool> {
+        // On one connection: others would keep seeing the old mode
+        let mut conn = self.pool.acquire().await?;
+        if incremental_vacuum_enabled_on(&mut conn).await? {
+            return Ok(false);
+        }
+
+        sqlx::query("PRAGMA auto_vacuum = INCREMENTAL")
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("VACUUM")
+            .execute(&mut *conn)
+            .await
+            .with_context(|| "Failed to vacuum logs database")?;
+
+        Ok(true)
+    }
+
+    async fn free_pages(&self) -> Result<u64> {
+        let free: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+            .fetch_one(&self.pool)
+            .await
+            .with_context(|| "Failed to read free page count")?;
+
+        Ok(free as u64)
+    }
+}
+
+#[async_trait]
+impl EventStore for LogStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn log_scored_event(
         &self,
         payload: &RequestPayload,
         decision: &JudgeDecision,
+        score: Option<&ScoreBreakdown>,
+        prompt_version: Option<&str>,
     ) -> Result<i64> {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-
-        let decision_type = decision.decision_type();
-        let confidence = decision.confidence();
-        let reason = match decision {
-            JudgeDecision::Flag { reason, .. } | JudgeDecision::Block { reason, .. } => {
-                Some(reason.as_str())
-            }
-            JudgeDecision::Allow { .. } => None,
-        };
-
-        let user_agent = payload.get_user_agent().map(|s| s.as_str());
-
-        let result = sqlx::query!(
-            r#"
-            INSERT INTO events (timestamp, method, path, payload_hash, decision, confidence, reason, ip_addr, user_agent)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-            "#,
-            timestamp,
-            payload.method,
-            payload.path,
-            payload.normalized_hash,
-            decision_type,
-            confidence,
-            reason,
-            payload.ip_addr,
-            user_agent,
-        )
-        .execute(&self.pool)
-        .await
-        .with_context(|| "Failed to insert event into database")?;
+        let event = NewEvent::new(payload, decision, score, prompt_version)?;
+        insert_event(&self.pool, &event).await
+    }
 
-        Ok(result.last_insert_rowid())
+    async fn log_batch(&self, events: &[NewEvent]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for event in events {
+            insert_event(&mut *tx, event).await?;
+        }
+        tx.commit()
+            .await
+            .with_context(|| "Failed to commit event batch")
     }
 
-    pub async fn get_flagged_since(&self, since_timestamp: i64) -> Result<Vec<LogEntry>> {
+    async fn get_flagged_since(&self, since_timestamp: i64) -> Result<Vec<LogEntry>> {
         let entries = sqlx::query_as!(
             LogEntry,
             r#"
-            SELECT id as "id!", timestamp as "timestamp!", method, path, payload_hash, decision, confidence as "confidence: f32", reason, ip_addr, user_agent
+            SELECT id as "id!", timestamp as "timestamp!", method, path, payload_hash, decision, confidence as "confidence: f32", reason, ip_addr, user_agent, request_context
             FROM events
             WHERE decision = 'flag' AND timestamp >= ?
             ORDER BY timestamp DESC
@@ -99,13 +338,11 @@ impl LogStore {
         Ok(entries)
     }
 
-    /// Retrieves blocked requests since timestamp - used for analytics dashboards
-    #[allow(dead_code)]
-    pub async fn get_blocked_since(&self, since_timestamp: i64) -> Result<Vec<LogEntry>> {
+    async fn get_blocked_since(&self, since_timestamp: i64) -> Result<Vec<LogEntry>> {
         let entries = sqlx::query_as!(
             LogEntry,
             r#"
-            SELECT id as "id!", timestamp as "timestamp!", method, path, payload_hash, decision, confidence as "confidence: f32", reason, ip_addr, user_agent
+            SELECT id as "id!", timestamp as "timestamp!", method, path, payload_hash, decision, confidence as "confidence: f32", reason, ip_addr, user_agent, request_context
             FROM events
             WHERE decision = 'block' AND timestamp >= ?
             ORDER BY timestamp DESC
@@ -119,17 +356,11 @@ impl LogStore {
         Ok(entries)
     }
 
-    /// Retrieves all events since timestamp with limit - used for general log viewing
-    #[allow(dead_code)]
-    pub async fn get_events_since(
-        &self,
-        since_timestamp: i64,
-        limit: i64,
-    ) -> Result<Vec<LogEntry>> {
+    async fn get_events_since(&self, since_timestamp: i64, limit: i64) -> Result<Vec<LogEntry>> {
         let entries = sqlx::query_as!(
             LogEntry,
             r#"
-            SELECT id as "id!", timestamp as "timestamp!", method, path, payload_hash, decision, confidence as "confidence: f32", reason, ip_addr, user_agent
+            SELECT id as "id!", timestamp as "timestamp!", method, path, payload_hash, decision, confidence as "confidence: f32", reason, ip_addr, user_agent, request_context
             FROM events
             WHERE timestamp >= ?
             ORDER BY timestamp DESC
@@ -145,15 +376,10 @@ impl LogStore {
         Ok(entries)
     }
 
-    /// Aggregates event counts by decision type - used for metrics and reporting
-    #[allow(dead_code)]
-    pub async fn count_events_by_decision(
-        &self,
-        since_timestamp: i64,
-    ) -> Result<Vec<(String, i64)>> {
+    async fn count_events_by_decision(&self, since_timestamp: i64) -> Result<Vec<(String, i64)>> {
         let counts = sqlx::query!(
             r#"
-            SELECT decision, COUNT(*) as count
+            SELECT decision, CAST(ROUND(SUM(weight)) AS INTEGER) as "count!: i64"
             FROM events
             WHERE timestamp >= ?
             GROUP BY decision
@@ -169,6 +395,277 @@ impl LogStore {
             .map(|row| (row.decision, row.count))
             .collect())
     }
+
+    async fn get_event(&self, id: i64) -> Result<Option<LogEntry>> {
+        let entry = sqlx::query_as!(
+            LogEntry,
+            r#"
+            SELECT id as "id!", timestamp as "timestamp!", method, path, payload_hash, decision, confidence as "confidence: f32", reason, ip_addr, user_agent, request_context
+            FROM events
+            WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| format!("Failed to fetch event {}", id))?;
+
+        Ok(entry)
+    }
+
+    async fn add_label(
+        &self,
+        event: &LogEntry,
+        kind: LabelKind,
+        note: Option<&str>,
+    ) -> Result<EventLabel> {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let label = kind.as_str();
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO labels (created_at, event_id, payload_hash, label, note)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            created_at,
+            event.id,
+            event.payload_hash,
+            label,
+            note,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to label event {}", event.id))?;
+
+        Ok(EventLabel {
+            id: result.last_insert_rowid(),
+            created_at,
+            event_id: event.id,
+            payload_hash: event.payload_hash.clone(),
+            label: label.to_string(),
+            note: note.map(str::to_string),
+        })
+    }
+
+    async fn latest_labels(&self) -> Result<Vec<EventLabel>> {
+        let labels = sqlx::query_as!(
+            EventLabel,
+            r#"
+            SELECT id as "id!", created_at, event_id, payload_hash, label, note
+            FROM labels
+            WHERE id IN (SELECT MAX(id) FROM labels GROUP BY payload_hash)
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "Failed to fetch labels")?;
+
+        Ok(labels)
+    }
+
+    async fn get_labeled_events_since(
+        &self,
+        since_timestamp: i64,
+    ) -> Result<Vec<(EventLabel, LogEntry)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT l.id as "label_id!", l.created_at as label_created_at, l.label, l.note,
+                e.id as "id!", e.timestamp as "timestamp!", e.method, e.path, e.payload_hash, e.decision,
+                e.confidence as "confidence: f32", e.reason, e.ip_addr, e.user_agent, e.request_context
+            FROM labels l
+            JOIN events e ON e.id = l.event_id
+            WHERE l.created_at >= ?
+            ORDER BY l.id
+            "#,
+            since_timestamp
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "Failed to fetch labeled events")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let label = EventLabel {
+                    id: row.label_id,
+                    created_at: row.label_created_at,
+                    event_id: row.id,
+                    payload_hash: row.payload_hash.clone(),
+                    label: row.label,
+                    note: row.note,
+                };
+                let event = LogEntry {
+                    id: row.id,
+                    timestamp: row.timestamp,
+                    method: row.method,
+                    path: row.path,
+                    payload_hash: row.payload_hash,
+                    decision: row.decision,
+                    confidence: row.confidence,
+                    reason: row.reason,
+                    ip_addr: row.ip_addr,
+                    user_agent: row.user_agent,
+                    request_context: row.request_context,
+                };
+                (label, event)
+            })
+            .collect())
+    }
+
+    async fn upsert_example(&self, example: &StoredExample) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO few_shot_examples (created_at, method, path_template, payload_hash, request, minhash, decision, reason, source)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(payload_hash) DO UPDATE SET
+                created_at = excluded.created_at,
+                request = excluded.request,
+                minhash = excluded.minhash,
+                decision = excluded.decision,
+                reason = excluded.reason,
+                source = excluded.source
+            WHERE excluded.source = 'corrected' OR few_shot_examples.source != 'corrected'
+            "#,
+            example.created_at,
+            example.method,
+            example.path_template,
+            example.payload_hash,
+            example.request,
+            example.minhash,
+            example.decision,
+            example.reason,
+            example.source,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "Failed to store few-shot example")?;
+
+        Ok(())
+    }
+
+    async fn examples_for_template(
+        &self,
+        method: &str,
+        path_template: &str,
+        limit: i64,
+    ) -> Result<Vec<StoredExample>> {
+        let examples = sqlx::query_as!(
+            StoredExample,
+            r#"
+            SELECT method, path_template, payload_hash, request, minhash, decision, reason, source, created_at
+            FROM few_shot_examples
+            WHERE method = ? AND path_template = ?
+            ORDER BY created_at DESC, id DESC
+            LIMIT ?
+            "#,
+            method,
+            path_template,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "Failed to fetch few-shot examples")?;
+
+        Ok(examples)
+    }
+
+    async fn delete_unverified_examples(&self, before_timestamp: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM few_shot_examples WHERE source != 'corrected' AND created_at < ?",
+            before_timestamp
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "Failed to delete expired few-shot examples")?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn expired_events(
+        &self,
+        decision: &str,
+        before_timestamp: i64,
+        limit: i64,
+    ) -> Result<Vec<(i64, String)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id as "id!", json_object(
+                'id', id, 'timestamp', timestamp, 'method', method, 'path', path,
+                'payload_hash', payload_hash, 'decision', decision, 'confidence', confidence,
+                'reason', reason, 'ip_addr', ip_addr, 'user_agent', user_agent,
+                'request_context', json(request_context), 'score_breakdown', json(score_breakdown),
+                'prompt_version', prompt_version, 'weight', weight
+            ) as "line!: String"
+            FROM events
+            WHERE decision = ? AND timestamp < ?
+                AND id NOT IN (SELECT event_id FROM labels)
+            ORDER BY timestamp
+            LIMIT ?
+            "#,
+            decision,
+            before_timestamp,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("Failed to fetch expired {} events", decision))?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.line)).collect())
+    }
+
+    async fn delete_events(&self, ids: &[i64]) -> Result<u64> {
+        let ids = serde_json::to_string(ids)?;
+        let result = sqlx::query!(
+            "DELETE FROM events WHERE id IN (SELECT value FROM json_each(?))",
+            ids
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "Failed to delete events")?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Incremental VACUUM, `step_pages` at a time so writers aren't locked out for long
+    async fn compact(&self, step_pages: u32) -> Result<u64> {
+        let mut reclaimed = 0;
+        let mut free = self.free_pages().await?;
+        while free > 0 {
+            sqlx::query(&format!("PRAGMA incremental_vacuum({})", step_pages))
+                .execute(&self.pool)
+                .await
+                .with_context(|| "Failed to run incremental vacuum")?;
+
+            let left = self.free_pages().await?;
+            if left >= free {
+                break;
+            }
+            reclaimed += free - left;
+            free = left;
+            tokio::task::yield_now().await;
+        }
+
+        Ok(reclaimed)
+    }
+}
+
+/// Databases created before incremental auto-vacuum keep their mode until
+/// converted (see `LogStore::enable_incremental_vacuum`)
+async fn incremental_vacuum_enabled(pool: &SqlitePool) -> Result<bool> {
+    incremental_vacuum_enabled_on(&mut *pool.acquire().await?).await
+}
+
+async fn incremental_vacuum_enabled_on(conn: &mut SqliteConnection) -> Result<bool> {
+    let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum")
+        .fetch_one(&mut *conn)
+        .await
+        .with_context(|| "Failed to read auto_vacuum mode")?;
+    // 2 = incremental
+    Ok(auto_vacuum == 2)
 }
 
 #[cfg(test)]
@@ -203,6 +700,56 @@ mod tests {
         assert!(id > 0);
     }
 
+    #[tokio::test]
+    async fn test_log_scored_event_stores_breakdown_and_prompt_version() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = LogStore::new(&db_path).await.unwrap();
+
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/test".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+        let decision = JudgeDecision::Allow { confidence: 1.0 };
+        let score = ScoreBreakdown::new(5.0, 3.0);
+
+        let scored = store
+            .log_scored_event(&payload, &decision, Some(&score), Some("2+3"))
+            .await
+            .unwrap();
+        let unscored = store.log_event(&payload, &decision).await.unwrap();
+
+        let stored: Option<String> =
+            sqlx::query_scalar("SELECT score_breakdown FROM events WHERE id = ?")
+                .bind(scored)
+                .fetch_one(&store.pool)
+                .await
+                .unwrap();
+        let stored: ScoreBreakdown = serde_json::from_str(&stored.unwrap()).unwrap();
+        assert_eq!(stored, score);
+
+        let version: Option<String> =
+            sqlx::query_scalar("SELECT prompt_version FROM events WHERE id = ?")
+                .bind(scored)
+                .fetch_one(&store.pool)
+                .await
+                .unwrap();
+        assert_eq!(version.as_deref(), Some("2+3"));
+
+        let stored: Option<String> =
+            sqlx::query_scalar("SELECT score_breakdown FROM events WHERE id = ?")
+                .bind(unscored)
+                .fetch_one(&store.pool)
+                .await
+                .unwrap();
+        assert!(stored.is_none());
+    }
+
     #[tokio::test]
     async fn test_get_flagged_since() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -234,6 +781,43 @@ mod tests {
         assert_eq!(flagged[0].path, "/suspicious");
     }
 
+    #[tokio::test]
+    async fn test_log_event_stores_request_context() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let store = LogStore::new(&db_path).await.unwrap();
+
+        let mut query_params = HashMap::new();
+        query_params.insert("id".to_string(), "1 UNION SELECT password".to_string());
+        query_params.insert("token".to_string(), "abc".to_string());
+
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/items".to_string(),
+            HashMap::new(),
+            None,
+            query_params,
+            None,
+        );
+
+        let decision = JudgeDecision::Flag {
+            confidence: 0.7,
+            reason: "Possible SQLi".to_string(),
+            suggested_rule: None,
+        };
+
+        store.log_event(&payload, &decision).await.unwrap();
+
+        let flagged = store.get_flagged_since(0).await.unwrap();
+        let context = flagged[0].context().expect("context should be stored");
+        assert!(context
+            .query
+            .contains(&("id".to_string(), "1 UNION SELECT password".to_string())));
+        assert!(context
+            .query
+            .contains(&("token".to_string(), "[REDACTED]".to_string())));
+    }
+
     #[tokio::test]
     async fn test_get_blocked_since() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -299,7 +883,7 @@ mod tests {
         let store = LogStore::new(&db_path).await.unwrap();
 
         // Log different decision types
-        let decisions = vec![
+        let decisions = [
             JudgeDecision::Allow { confidence: 0.9 },
             JudgeDecision::Allow { confidence: 0.85 },
             JudgeDecision::Flag {
@@ -338,6 +922,34 @@ mod tests {
         assert_eq!(block_count, Some(1));
     }
 
+    #[tokio::test]
+    async fn test_count_events_by_decision_weighs_sampled_events() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = LogStore::new(temp_dir.path().join("test.db"))
+            .await
+            .unwrap();
+
+        let payload = RequestPayload::new(
+            "GET".to_string(),
+            "/feed".to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        let mut sampled = NewEvent::new(&payload, &allow, None, None).unwrap();
+        sampled.weight = 10.0;
+        let kept = NewEvent::new(&payload, &allow, None, None).unwrap();
+        store
+            .log_batch(&[sampled.clone(), sampled, kept])
+            .await
+            .unwrap();
+
+        let counts = store.count_events_by_decision(0).await.unwrap();
+        assert_eq!(counts, vec![("allow".to_string(), 21)]);
+    }
+
     #[tokio::test]
     async fn test_log_event_with_allow_decision() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -403,4 +1015,32 @@ mod tests {
         let flagged = store.get_flagged_since(0).await.unwrap();
         assert_eq!(flagged.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_existing_database_converted_on_request() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        // A database created without incremental auto-vacuum
+        let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", db_path.display()))
+            .unwrap()
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await.unwrap();
+        sqlx::query("CREATE TABLE legacy (id INTEGER)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        // Opening it doesn't VACUUM
+        let store = LogStore::new(&db_path).await.unwrap();
+        assert!(!incremental_vacuum_enabled(&store.pool).await.unwrap());
+        assert_eq!(store.compact(100).await.unwrap(), 0);
+
+        assert!(store.enable_incremental_vacuum().await.unwrap());
+        store.pool.close().await;
+        let store = LogStore::new(&db_path).await.unwrap();
+        assert!(incremental_vacuum_enabled(&store.pool).await.unwrap());
+        assert!(!store.enable_incremental_vacuum().await.unwrap());
+    }
 }
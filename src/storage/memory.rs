@@ -0,0 +1,250 @@
+use crate::storage::cache::{CacheFlush, CachedVerdict, VerdictCache};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    verdict: CachedVerdict,
+    expires_at: Instant,
+    /// Position in the recency order
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    /// Hashes by last use, oldest first
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl Lru {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, hash: &str) {
+        if let Some(entry) = self.entries.remove(hash) {
+            self.order.remove(&entry.tick);
+        }
+    }
+}
+
+/// In-process verdict cache, checked before Redis (or used alone when
+/// Redis is disabled). Holds at most `capacity` verdicts, evicting the least
+/// recently used; entries expire after their own TTL capped to `ttl`, which
+/// also bounds how long an instance can serve a verdict another instance has
+/// invalidated. As a `cache.backend`, it is a per-instance, non-persistent
+/// shared tier.
+pub struct MemoryCache {
+    capacity: usize,
+    ttl: Duration,
+    lru: Mutex<Lru>,
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    pub fn get(&self, hash: &str) -> Option<CachedVerdict> {
+        let mut lru = self.lru.lock().unwrap();
+        let now = Instant::now();
+
+        let old_tick = match lru.entries.get(hash) {
+            Some(entry) if entry.expires_at > now => entry.tick,
+            Some(_) => {
+                lru.remove(hash);
+                return None;
+            }
+            None => return None,
+        };
+
+        let tick = lru.next_tick();
+        lru.order.remove(&old_tick);
+        lru.order.insert(tick, hash.to_string());
+        let entry = lru.entries.get_mut(hash)?;
+        entry.tick = tick;
+        Some(entry.verdict.clone())
+    }
+
+    pub fn set(&self, hash: &str, verdict: &CachedVerdict, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        lru.remove(hash);
+        let tick = lru.next_tick();
+        lru.order.insert(tick, hash.to_string());
+        lru.entries.insert(
+            hash.to_string(),
+            Entry {
+                verdict: verdict.clone(),
+                expires_at: Instant::now() + ttl.min(self.ttl),
+                tick,
+            },
+        );
+
+        while lru.entries.len() > self.capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+    }
+
+    pub fn invalidate(&self, hash: &str) {
+        self.lru.lock().unwrap().remove(hash);
+    }
+
+    /// Drops the verdicts matching `flush`, returning how many
+    pub fn flush(&self, flush: &CacheFlush) -> usize {
+        let mut lru = self.lru.lock().unwrap();
+        let hashes: Vec<String> = lru
+            .entries
+            .iter()
+            .filter(|(_, entry)| flush.matches(&entry.verdict))
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        for hash in &hashes {
+            lru.remove(hash);
+        }
+        hashes.len()
+    }
+
+    /// Verdicts held, expired ones included until they are looked up or evicted
+    #[allow(dead_code)] // Used in tests
+    pub fn entry_count(&self) -> usize {
+        self.lru.lock().unwrap().entries.len()
+    }
+}
+
+#[async_trait]
+impl VerdictCache for MemoryCache {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get_verdict(&self, key: &str) -> Result<Option<CachedVerdict>> {
+        Ok(self.get(key))
+    }
+
+    async fn set_verdict(&self, key: &str, verdict: &CachedVerdict, ttl: Duration) -> Result<()> {
+        self.set(key, verdict, ttl);
+        Ok(())
+    }
+
+    async fn invalidate(&self, key: &str) -> Result<()> {
+        MemoryCache::invalidate(self, key);
+        Ok(())
+    }
+
+    async fn flush(&self, flush: &CacheFlush) -> Result<usize> {
+        Ok(MemoryCache::flush(self, flush))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::decision::JudgeDecision;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn allow(confidence: f32) -> JudgeDecision {
+        JudgeDecision::Allow { confidence }
+    }
+
+    fn verdict(path: &str, decision: JudgeDecision) -> CachedVerdict {
+        CachedVerdict {
+            decision,
+            path: path.to_string(),
+            prompt_version: None,
+        }
+    }
+
+    fn set(cache: &MemoryCache, hash: &str, decision: JudgeDecision) {
+        cache.set(hash, &verdict("/", decision), TTL);
+    }
+
+    fn get(cache: &MemoryCache, hash: &str) -> Option<JudgeDecision> {
+        cache.get(hash).map(|verdict| verdict.decision)
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2, TTL);
+        set(&cache, "a", allow(0.1));
+        set(&cache, "b", allow(0.2));
+
+        // "a" is now more recent than "b"
+        assert_eq!(get(&cache, "a"), Some(allow(0.1)));
+        set(&cache, "c", allow(0.3));
+
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(get(&cache, "b"), None);
+        assert_eq!(get(&cache, "a"), Some(allow(0.1)));
+        assert_eq!(get(&cache, "c"), Some(allow(0.3)));
+
+        // Overwriting doesn't grow the cache
+        set(&cache, "c", allow(0.4));
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(get(&cache, "c"), Some(allow(0.4)));
+    }
+
+    #[test]
+    fn test_expiry_and_invalidation() {
+        let expired = MemoryCache::new(10, Duration::ZERO);
+        set(&expired, "a", allow(0.9));
+        assert_eq!(get(&expired, "a"), None);
+        assert_eq!(expired.entry_count(), 0);
+
+        let cache = MemoryCache::new(10, TTL);
+        set(&cache, "a", allow(0.9));
+        cache.invalidate("a");
+        assert_eq!(get(&cache, "a"), None);
+
+        let disabled = MemoryCache::new(0, TTL);
+        set(&disabled, "a", allow(0.9));
+        assert_eq!(disabled.entry_count(), 0);
+    }
+
+    #[test]
+    fn test_entry_ttl_below_cache_ttl() {
+        let cache = MemoryCache::new(10, TTL);
+        cache.set("a", &verdict("/", allow(0.9)), Duration::ZERO);
+        assert_eq!(get(&cache, "a"), None);
+    }
+
+    #[test]
+    fn test_flush_by_path_and_decision() {
+        let cache = MemoryCache::new(10, TTL);
+        let block = JudgeDecision::Block {
+            confidence: 0.9,
+            reason: "SQLi".to_string(),
+            threat_level: crate::models::decision::ThreatLevel::High,
+        };
+        cache.set("a", &verdict("/api/users", allow(0.9)), TTL);
+        cache.set("b", &verdict("/api/orders", block), TTL);
+        cache.set("c", &verdict("/static/app.js", allow(0.9)), TTL);
+
+        let flushed = cache.flush(&CacheFlush {
+            path_prefix: Some("/api/".to_string()),
+            decision: Some("allow".to_string()),
+        });
+        assert_eq!(flushed, 1);
+        assert_eq!(get(&cache, "a"), None);
+        assert!(get(&cache, "b").is_some());
+
+        assert_eq!(cache.flush(&CacheFlush::default()), 2);
+        assert_eq!(cache.entry_count(), 0);
+    }
+}
@@ -1,3 +1,10 @@
 pub mod cache;
 pub mod logs;
+pub mod memory;
+pub mod pg_logs;
+pub mod prompts;
+pub mod retention;
 pub mod rules;
+pub mod sampling;
+pub mod sqlite_cache;
+pub mod writer;
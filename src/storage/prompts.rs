@@ -0,0 +1,132 @@
+use crate::llm::prompts::{PromptTemplates, TEMPLATE_NAMES};
+use anyhow::{Context, Result};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+/// Extension of prompt template files (`judge.tmpl`, ...)
+const TEMPLATE_EXTENSION: &str = "tmpl";
+
+/// Prompt templates loaded from a directory. Each `<name>.tmpl` file found
+/// replaces the built-in template of that name.
+pub struct PromptStore {
+    dir: PathBuf,
+}
+
+impl PromptStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Loads and validates the templates
+    pub async fn load(&self) -> Result<PromptTemplates> {
+        let mut templates = PromptTemplates::builtin();
+
+        for name in TEMPLATE_NAMES {
+            let path = self.dir.join(format!("{}.{}", name, TEMPLATE_EXTENSION));
+            if !path.exists() {
+                continue;
+            }
+
+            let source = tokio::fs::read_to_string(&path)
+                .await
+                .with_context(|| format!("Failed to read prompt template: {:?}", path))?;
+            templates
+                .set(name, &source)
+                .with_context(|| format!("Invalid prompt template {:?}", path))?;
+        }
+
+        templates
+            .validate()
+            .with_context(|| format!("Invalid prompt templates in {:?}", self.dir))?;
+
+        Ok(templates)
+    }
+
+    pub fn watch(&self) -> Result<mpsc::Receiver<Result<PromptTemplates>>> {
+        let (tx, rx) = mpsc::channel(10);
+        let dir = self.dir.clone();
+
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+
+            runtime.block_on(async move {
+                let (notify_tx, mut notify_rx) = mpsc::channel(10);
+
+                let mut watcher = RecommendedWatcher::new(
+                    move |res: Result<Event, notify::Error>| {
+                        if let Ok(event) = res {
+                            let _ = notify_tx.blocking_send(event);
+                        }
+                    },
+                    Config::default(),
+                )
+                .unwrap();
+
+                watcher.watch(&dir, RecursiveMode::NonRecursive).unwrap();
+
+                while let Some(event) = notify_rx.recv().await {
+                    let is_template_event = matches!(
+                        event.kind,
+                        EventKind::Modify(_) | EventKind::Create(_) | EventKind::Remove(_)
+                    ) && event.paths.iter().any(|p| {
+                        p.extension().and_then(|e| e.to_str()) == Some(TEMPLATE_EXTENSION)
+                    });
+
+                    if is_template_event {
+                        // Small delay to ensure file write is complete
+                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+                        let store = PromptStore::new(&dir);
+                        let result = store.load().await;
+                        let _ = tx.send(result).await;
+                    }
+                }
+            });
+        });
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_overrides_builtin_templates() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp_dir.path().join("judge.tmpl"),
+            "{# version: 7 #}\n{% for rule in rules %}{{ rule.id }} {% endfor %}\n{{ request }}\n",
+        )
+        .unwrap();
+
+        let templates = PromptStore::new(temp_dir.path()).load().await.unwrap();
+
+        let builtin = PromptTemplates::builtin();
+        assert_eq!(templates.judge_version(), "3+7");
+        assert_eq!(templates.learner_version(), builtin.learner_version());
+    }
+
+    #[tokio::test]
+    async fn test_load_rejects_invalid_templates() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = PromptStore::new(temp_dir.path());
+
+        // Unknown variables are caught when loading, not on the first request
+        std::fs::write(
+            temp_dir.path().join("learner.tmpl"),
+            "{# version: 2 #}\n{{ cluster.endpoint }}\n",
+        )
+        .unwrap();
+        let err = store.load().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown variable 'cluster.endpoint'"));
+
+        std::fs::write(temp_dir.path().join("learner.tmpl"), "no version\n").unwrap();
+        let err = store.load().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("missing version"));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::storage::logs::EventStore;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::interval;
+
+/// Free pages given back per incremental VACUUM step
+const COMPACT_STEP_PAGES: u32 = 1000;
+
+/// Outcome of one retention run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RetentionReport {
+    pub deleted: u64,
+    pub archived: u64,
+    pub pages_reclaimed: u64,
+    /// Archive file written by the run, if any event was archived
+    pub archive: Option<PathBuf>,
+}
+
+/// Totals over all retention runs
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionMetrics {
+    pub runs: AtomicU64,
+    pub failures: AtomicU64,
+    pub deleted_events: AtomicU64,
+    pub archived_events: AtomicU64,
+    pub pages_reclaimed: AtomicU64,
+    pub last_run_ms: AtomicU64,
+}
+
+/// Deletes events older than the maximum age of their decision type,
+/// optionally archiving them first, then compacts the logs database.
+/// Labeled events are never deleted.
+pub struct Retention {
+    logs: Arc<dyn EventStore>,
+    run_interval: Duration,
+    max_ages: Vec<(&'static str, Duration)>,
+    batch_size: usize,
+    archive_dir: Option<PathBuf>,
+    metrics: RetentionMetrics,
+}
+
+impl Retention {
+    pub fn new(
+        logs: Arc<dyn EventStore>,
+        run_interval: Duration,
+        max_ages: Vec<(&'static str, Duration)>,
+        batch_size: usize,
+    ) -> Self {
+        Self {
+            logs,
+            run_interval,
+            max_ages,
+            batch_size,
+            archive_dir: None,
+            metrics: RetentionMetrics::default(),
+        }
+    }
+
+    /// Archives expired events to gzip-compressed JSONL files in `dir`
+    pub fn with_archive_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.archive_dir = Some(dir.into());
+        self
+    }
+
+    pub fn metrics(&self) -> &RetentionMetrics {
+        &self.metrics
+    }
+
+    /// Runs retention once, recording the outcome in the metrics
+    pub async fn run(&self) -> Result<RetentionReport> {
+        let started = Instant::now();
+        let result = self.purge().await;
+
+        let metrics = &self.metrics;
+        metrics.runs.fetch_add(1, Ordering::Relaxed);
+        metrics
+            .last_run_ms
+            .store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+        match &result {
+            Ok(report) => {
+                metrics
+                    .deleted_events
+                    .fetch_add(report.deleted, Ordering::Relaxed);
+                metrics
+                    .archived_events
+                    .fetch_add(report.archived, Ordering::Relaxed);
+                metrics
+                    .pages_reclaimed
+                    .fetch_add(report.pages_reclaimed, Ordering::Relaxed);
+            }
+            Err(_) => {
+                metrics.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        result
+    }
+
+    async fn purge(&self) -> Result<RetentionReport> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let archive = self.archive_dir.as_ref().map(|dir| {
+            dir.join(format!(
+                "events-{}.jsonl.gz",
+                Utc::now().format("%Y%m%dT%H%M%SZ")
+            ))
+        });
+        let mut report = RetentionReport::default();
+
+        for (decision, max_age) in &self.max_ages {
+            let before = now - max_age.as_secs() as i64;
+            loop {
+                let batch = self
+                    .logs
+                    .expired_events(decision, before, self.batch_size as i64)
+                    .await?;
+                if batch.is_empty() {
+                    break;
+                }
+
+                let (ids, lines): (Vec<i64>, Vec<String>) = batch.into_iter().unzip();
+                // Archived before deleting, so a failed write loses nothing
+                if let Some(path) = &archive {
+                    append_archive(path.clone(), lines).await?;
+                    report.archived += ids.len() as u64;
+                    report.archive = Some(path.clone());
+                }
+                report.deleted += self.logs.delete_events(&ids).await?;
+
+                if ids.len() < self.batch_size {
+                    break;
+                }
+            }
+        }
+
+        report.pages_reclaimed = self.logs.compact(COMPACT_STEP_PAGES).await?;
+        Ok(report)
+    }
+
+    /// Start the retention scheduler (runs in background)
+    pub async fn start_scheduler(self: Arc<Self>) {
+        let mut ticker = interval(self.run_interval);
+
+        loop {
+            ticker.tick().await;
+
+            match self.run().await {
+                Ok(report) => tracing::info!(
+                    deleted = report.deleted,
+                    archived = report.archived,
+                    pages_reclaimed = report.pages_reclaimed,
+                    archive = ?report.archive,
+                    total_deleted = self.metrics.deleted_events.load(Ordering::Relaxed),
+                    duration_ms = self.metrics.last_run_ms.load(Ordering::Relaxed),
+                    "Log retention run complete"
+                ),
+                Err(e) => tracing::error!(error = %e, "Log retention run failed"),
+            }
+        }
+    }
+}
+
+/// Appends lines to a gzip archive as a new gzip member; multi-member files
+/// read back as one stream with `zcat` or `MultiGzDecoder`
+async fn append_archive(path: PathBuf, lines: Vec<String>) -> Result<()> {
+    tokio::task::spawn_blocking(move || write_archive(&path, &lines))
+        .await
+        .with_context(|| "Archive writer panicked")?
+}
+
+fn write_archive(path: &Path, lines: &[String]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create archive directory: {:?}", parent))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open archive: {:?}", path))?;
+
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    for line in lines {
+        encoder.write_all(line.as_bytes())?;
+        encoder.write_all(b"\n")?;
+    }
+    encoder
+        .finish()
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("Failed to write archive: {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::decision::{JudgeDecision, ThreatLevel};
+    use crate::models::label::LabelKind;
+    use crate::models::request::RequestPayload;
+    use crate::storage::logs::LogStore;
+    use flate2::read::MultiGzDecoder;
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    fn payload(path: &str) -> RequestPayload {
+        RequestPayload::new(
+            "GET".to_string(),
+            path.to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_expired_events_are_archived_then_deleted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        let block = JudgeDecision::Block {
+            confidence: 0.9,
+            reason: "SQL injection".to_string(),
+            threat_level: ThreatLevel::High,
+        };
+        for i in 0..5 {
+            logs.log_event(&payload(&format!("/old/{}", i)), &allow)
+                .await
+                .unwrap();
+        }
+        let labeled = logs.log_event(&payload("/labeled"), &allow).await.unwrap();
+        let event = logs.get_event(labeled).await.unwrap().unwrap();
+        logs.add_label(&event, LabelKind::FalseNegative, None)
+            .await
+            .unwrap();
+        logs.log_event(&payload("/blocked"), &block).await.unwrap();
+
+        // Allows expire at once, blocks are kept for a day
+        let archive_dir = temp_dir.path().join("archive");
+        let retention = Retention::new(
+            Arc::clone(&logs),
+            Duration::from_secs(60),
+            vec![
+                ("allow", Duration::ZERO),
+                ("block", Duration::from_secs(86400)),
+            ],
+            2,
+        )
+        .with_archive_dir(&archive_dir);
+        // Events logged this second aren't older than a zero max age yet
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let report = retention.run().await.unwrap();
+        assert_eq!(report.deleted, 5);
+        assert_eq!(report.archived, 5);
+
+        let remaining = logs.get_events_since(0, 100).await.unwrap();
+        let mut paths: Vec<_> = remaining.iter().map(|e| e.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/blocked", "/labeled"]);
+
+        let mut archived = String::new();
+        MultiGzDecoder::new(std::fs::File::open(report.archive.unwrap()).unwrap())
+            .read_to_string(&mut archived)
+            .unwrap();
+        let lines: Vec<serde_json::Value> = archived
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0]["path"], "/old/0");
+        assert_eq!(lines[0]["decision"], "allow");
+        assert!(lines[0]["request_context"].is_object());
+
+        let metrics = retention.metrics();
+        assert_eq!(metrics.runs.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.deleted_events.load(Ordering::Relaxed), 5);
+
+        // Nothing left to expire
+        let report = retention.run().await.unwrap();
+        assert_eq!(report, RetentionReport::default());
+    }
+
+    #[tokio::test]
+    async fn test_compaction_reclaims_deleted_pages() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("logs.db");
+        let logs: Arc<dyn EventStore> = Arc::new(LogStore::new(&db_path).await.unwrap());
+
+        let mut large = payload("/upload");
+        large.body = Some("x".repeat(2048));
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        for _ in 0..200 {
+            logs.log_event(&large, &allow).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let retention = Retention::new(
+            Arc::clone(&logs),
+            Duration::from_secs(60),
+            vec![("allow", Duration::ZERO)],
+            50,
+        );
+        let report = retention.run().await.unwrap();
+        assert_eq!(report.deleted, 200);
+        assert!(report.pages_reclaimed > 0);
+        assert_eq!(report.archive, None);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::core::modsecurity;
 use crate::core::rulebook::Rulebook;
+use crate::core::validator::{self, Severity};
 use anyhow::{Context, Result};
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::path::{Path, PathBuf};
@@ -6,6 +8,7 @@ use tokio::sync::mpsc;
 
 pub struct RulebookStore {
     path: PathBuf,
+    seed_rules_path: Option<PathBuf>,
 }
 
 impl RulebookStore {
@@ -18,13 +21,59 @@ impl RulebookStore {
                 .with_context(|| format!("Failed to create rulebook directory: {:?}", parent))?;
         }
 
-        Ok(Self { path })
+        Ok(Self {
+            path,
+            seed_rules_path: None,
+        })
     }
 
+    /// Seeds a missing rulebook with the ModSecurity/CRS rules at `path`
+    pub fn with_seed_rules<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.seed_rules_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Loads the rulebook at startup: invalid rules are skipped, not the
+    /// whole rulebook, and the file keeps them
     pub async fn load(&self) -> Result<Rulebook> {
+        let mut rulebook = self.read().await?;
+
+        for issue in validator::retain_valid_rules(&mut rulebook) {
+            match issue.severity {
+                Severity::Error => tracing::error!("Rulebook {}, rule skipped", issue),
+                Severity::Warning => tracing::warn!("Rulebook {}", issue),
+            }
+        }
+
+        Ok(rulebook)
+    }
+
+    /// Loads the rulebook, failing if any rule is invalid: used on reload,
+    /// so the previous rulebook stays in force, and by the learner, so it
+    /// never saves a rulebook stripped of the invalid rules
+    pub async fn load_strict(&self) -> Result<Rulebook> {
+        let rulebook = self.read().await?;
+
+        let issues = validator::validate_rulebook(&rulebook);
+        if validator::has_errors(&issues) {
+            let errors: Vec<String> = issues
+                .iter()
+                .filter(|i| i.severity == Severity::Error)
+                .map(|i| i.to_string())
+                .collect();
+            anyhow::bail!("Invalid rules in rulebook: {}", errors.join("; "));
+        }
+        for issue in issues {
+            tracing::warn!("Rulebook {}", issue);
+        }
+
+        Ok(rulebook)
+    }
+
+    /// Reads the rulebook file, creating it (empty or seeded) if missing
+    async fn read(&self) -> Result<Rulebook> {
         if !self.path.exists() {
-            // Create default empty rulebook if it doesn't exist
-            let rulebook = Rulebook::default();
+            let rulebook = self.seed_rulebook()?;
             self.save(&rulebook).await?;
             return Ok(rulebook);
         }
@@ -33,9 +82,32 @@ impl RulebookStore {
             .await
             .with_context(|| format!("Failed to read rulebook file: {:?}", self.path))?;
 
-        let rulebook: Rulebook =
-            serde_json::from_str(&content).with_context(|| "Failed to parse rulebook JSON")?;
+        serde_json::from_str(&content).with_context(|| "Failed to parse rulebook JSON")
+    }
+
+    fn seed_rulebook(&self) -> Result<Rulebook> {
+        let mut rulebook = Rulebook::default();
+        let Some(seed_path) = &self.seed_rules_path else {
+            return Ok(rulebook);
+        };
 
+        let report = modsecurity::import_path(seed_path)
+            .with_context(|| format!("Failed to import seed rules from {:?}", seed_path))?;
+        for skipped in &report.skipped {
+            tracing::debug!("Seed rules: skipped {}", skipped);
+        }
+        tracing::info!(
+            "Seeded rulebook with {} rules from {:?} ({} skipped)",
+            report.rules.len(),
+            seed_path,
+            report.skipped.len()
+        );
+
+        for rule in report.rules {
+            if !rulebook.rules.iter().any(|r| r.id == rule.id) {
+                rulebook.rules.push(rule);
+            }
+        }
         Ok(rulebook)
     }
 
@@ -98,8 +170,11 @@ impl RulebookStore {
                                     tokio::time::sleep(tokio::time::Duration::from_millis(100))
                                         .await;
 
-                                    let store = RulebookStore { path: path.clone() };
-                                    let result = store.load().await;
+                                    let store = RulebookStore {
+                                        path: path.clone(),
+                                        seed_rules_path: None,
+                                    };
+                                    let result = store.load_strict().await;
                                     let _ = tx.send(result).await;
                                 }
                             }
@@ -149,6 +224,63 @@ mod tests {
         assert_eq!(loaded.rules[0].threat_type, "sqli");
     }
 
+    #[tokio::test]
+    async fn test_load_skips_invalid_rules() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rulebook_path = temp_dir.path().join("rulebook.json");
+
+        let store = RulebookStore::new(&rulebook_path).unwrap();
+
+        let mut rulebook = Rulebook::new();
+        for pattern in ["(unclosed", "union.*select", "(a+)+$"] {
+            rulebook.add_rule(Rule::new(
+                pattern.to_string(),
+                "sqli".to_string(),
+                0.8,
+                RuleAction::Block,
+                "test".to_string(),
+            ));
+        }
+        store.save(&rulebook).await.unwrap();
+
+        // Nested quantifiers only warn: the regex engine is linear-time
+        let loaded = store.load().await.unwrap();
+        let patterns: Vec<&str> = loaded.rules.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, vec!["union.*select", "(a+)+$"]);
+
+        // The file is left as it was
+        let content = std::fs::read_to_string(&rulebook_path).unwrap();
+        assert!(content.contains("(unclosed"));
+
+        // ...and a strict load (reload, learner) refuses it
+        let err = store.load_strict().await.unwrap_err();
+        assert!(err.to_string().contains("Invalid rules"));
+    }
+
+    #[tokio::test]
+    async fn test_seed_rules_only_for_missing_rulebook() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rulebook_path = temp_dir.path().join("rulebook.json");
+        let seed_path = temp_dir.path().join("seed.conf");
+        std::fs::write(
+            &seed_path,
+            r#"SecRule ARGS "@pm sqlmap nikto" "id:913100,block,msg:'Scanner'""#,
+        )
+        .unwrap();
+
+        let store = RulebookStore::new(&rulebook_path)
+            .unwrap()
+            .with_seed_rules(&seed_path);
+
+        let seeded = store.load().await.unwrap();
+        assert_eq!(seeded.rules.len(), 1);
+        assert_eq!(seeded.rules[0].created_by, "import");
+
+        // An existing rulebook is never re-seeded
+        store.save(&Rulebook::new()).await.unwrap();
+        assert!(store.load().await.unwrap().rules.is_empty());
+    }
+
     #[tokio::test]
     async fn test_watch_changes() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -186,4 +318,54 @@ mod tests {
             assert_eq!(updated_rulebook.rules.len(), 1);
         }
     }
+
+    #[tokio::test]
+    async fn test_watch_keeps_previous_rulebook_on_invalid_edit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rulebook_path = temp_dir.path().join("rulebook.json");
+        let store = RulebookStore::new(&rulebook_path).unwrap();
+        let rule = |pattern: &str| {
+            Rule::new(
+                pattern.to_string(),
+                "sqli".to_string(),
+                0.8,
+                RuleAction::Block,
+                "test".to_string(),
+            )
+        };
+
+        let mut rulebook = Rulebook::new();
+        rulebook.add_rule(rule("union.*select"));
+        store.save(&rulebook).await.unwrap();
+        let mut in_force = store.load().await.unwrap();
+        let mut rx = store.watch().unwrap();
+
+        // An edit adding an invalid rule next to a new valid one
+        let writer = RulebookStore::new(&rulebook_path).unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+            let mut edited = Rulebook::new();
+            edited.add_rule(rule("union.*select"));
+            edited.add_rule(rule("(unclosed"));
+            edited.add_rule(rule("drop\\s+table"));
+            writer.save(&edited).await.unwrap();
+        });
+
+        // Applied as the hot-reload watcher does: only successful loads
+        let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(2);
+        let mut rejected = false;
+        while let Ok(Some(result)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            match result {
+                Ok(reloaded) => in_force = reloaded,
+                Err(_) => {
+                    rejected = true;
+                    break;
+                }
+            }
+        }
+
+        assert!(rejected);
+        let patterns: Vec<&str> = in_force.rules.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, vec!["union.*select"]);
+    }
 }
@@ -0,0 +1,258 @@
+use crate::config::SamplingConfig;
+use crate::models::canonical;
+use crate::models::decision::JudgeDecision;
+use crate::models::request::RequestPayload;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Sampling rates are applied in parts per million
+const RATE_SCALE: u64 = 1_000_000;
+
+/// Request hashes seen recently, in two generations: when the current one is
+/// full it becomes the previous one, forgetting the oldest hashes
+struct SeenHashes {
+    current: HashSet<String>,
+    previous: HashSet<String>,
+    generation_size: usize,
+}
+
+impl SeenHashes {
+    /// Records a hash, returning whether it wasn't seen before
+    fn insert(&mut self, hash: &str) -> bool {
+        if self.current.contains(hash) {
+            return false;
+        }
+        let new = !self.previous.remove(hash);
+        if self.current.len() >= self.generation_size {
+            self.previous = std::mem::take(&mut self.current);
+        }
+        self.current.insert(hash.to_string());
+        new
+    }
+}
+
+/// Decides which allowed requests are logged, and the weight of the logged
+/// ones. Blocks, flags and allows below the confidence threshold are always
+/// logged with weight 1; other allows are sampled at the rate of their route
+/// and weigh 1/rate. The first allow of a new request hash is always logged
+/// too, weighing 1/rate when it falls on a sampled place and 1 otherwise.
+///
+/// Sampling is systematic (every 1/rate-th allow of a route), so the
+/// weighted counts stay within one sampling interval of the real ones.
+pub struct AllowSampler {
+    config: SamplingConfig,
+    /// Allows sampled so far per route, the default rate last
+    counters: Vec<AtomicU64>,
+    seen: Option<Mutex<SeenHashes>>,
+}
+
+impl AllowSampler {
+    pub fn new(config: SamplingConfig) -> Self {
+        let counters = (0..=config.routes.len())
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        let seen = (config.seen_hashes_capacity > 0).then(|| {
+            Mutex::new(SeenHashes {
+                current: HashSet::new(),
+                previous: HashSet::new(),
+                generation_size: config.seen_hashes_capacity.div_ceil(2),
+            })
+        });
+
+        Self {
+            config,
+            counters,
+            seen,
+        }
+    }
+
+    /// Weight of the event if it is logged, `None` if it is sampled out
+    pub fn weight(&self, payload: &RequestPayload, decision: &JudgeDecision) -> Option<f64> {
+        if !matches!(decision, JudgeDecision::Allow { .. })
+            || decision.confidence() < self.config.always_log_below_confidence
+        {
+            return Some(1.0);
+        }
+        let new_hash = self
+            .seen
+            .as_ref()
+            .is_some_and(|seen| seen.lock().unwrap().insert(&payload.normalized_hash));
+
+        let (index, rate) = self.rate_for(&payload.path);
+        if rate >= 1.0 {
+            return Some(1.0);
+        }
+        if rate <= 0.0 {
+            return new_hash.then_some(1.0);
+        }
+        // The n-th allow is kept when [n * rate, (n + 1) * rate) holds an integer
+        let step = ((rate as f64 * RATE_SCALE as f64).round() as u64).max(1);
+        let kept = |n: u64| (n % RATE_SCALE) * step % RATE_SCALE < step;
+        let sample_weight = RATE_SCALE as f64 / step as f64;
+        let counter = &self.counters[index];
+
+        if new_hash {
+            // Logged either way, but only takes a place in the sampled stream
+            // when it lands on a kept one, standing in for the allows after it
+            let mut n = counter.load(Ordering::Relaxed);
+            while kept(n) {
+                match counter.compare_exchange_weak(n, n + 1, Ordering::Relaxed, Ordering::Relaxed)
+                {
+                    Ok(_) => return Some(sample_weight),
+                    Err(current) => n = current,
+                }
+            }
+            return Some(1.0);
+        }
+        kept(counter.fetch_add(1, Ordering::Relaxed)).then_some(sample_weight)
+    }
+
+    /// Counter index and sampling rate of a path, matched in canonical form
+    /// on segment boundaries
+    fn rate_for(&self, path: &str) -> (usize, f32) {
+        let path = canonical::canonical_path(path);
+        self.config
+            .routes
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| canonical::has_route_prefix(&path, &route.path_prefix))
+            .max_by_key(|(_, route)| route.path_prefix.len())
+            .map(|(index, route)| (index, route.allow_rate))
+            .unwrap_or((self.config.routes.len(), self.config.allow_rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouteSampling;
+    use crate::models::decision::ThreatLevel;
+    use std::collections::HashMap;
+
+    fn payload(path: &str, hash: &str) -> RequestPayload {
+        let mut payload = RequestPayload::new(
+            "GET".to_string(),
+            path.to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        );
+        payload.normalized_hash = hash.to_string();
+        payload
+    }
+
+    fn sampler() -> AllowSampler {
+        AllowSampler::new(SamplingConfig {
+            allow_rate: 0.25,
+            routes: vec![RouteSampling {
+                path_prefix: "/health".to_string(),
+                allow_rate: 0.0,
+            }],
+            seen_hashes_capacity: 0,
+            ..SamplingConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_allows_sampled_per_route_with_weights() {
+        let sampler = sampler();
+        let allow = JudgeDecision::Allow { confidence: 0.95 };
+
+        let weights: Vec<f64> = (0..100)
+            .filter_map(|_| sampler.weight(&payload("/api", "h"), &allow))
+            .collect();
+        assert_eq!(weights.len(), 25);
+        assert_eq!(weights.iter().sum::<f64>(), 100.0);
+
+        assert_eq!(sampler.weight(&payload("/health/live", "h"), &allow), None);
+        assert_eq!(sampler.weight(&payload("//health", "h"), &allow), None);
+        assert_eq!(sampler.weight(&payload("/%68ealth", "h"), &allow), None);
+    }
+
+    #[test]
+    fn test_routes_match_on_segment_boundaries() {
+        let sampler = AllowSampler::new(SamplingConfig {
+            allow_rate: 1.0,
+            routes: vec![RouteSampling {
+                path_prefix: "/api".to_string(),
+                allow_rate: 0.0,
+            }],
+            ..SamplingConfig::default()
+        });
+
+        assert_eq!(sampler.rate_for("/api"), (0, 0.0));
+        assert_eq!(sampler.rate_for("/api/users"), (0, 0.0));
+        assert_eq!(sampler.rate_for("/apiary"), (1, 1.0));
+        assert_eq!(sampler.rate_for("/api-docs"), (1, 1.0));
+    }
+
+    #[test]
+    fn test_blocks_flags_and_unsure_allows_always_logged() {
+        let sampler = sampler();
+        let block = JudgeDecision::Block {
+            confidence: 0.95,
+            reason: "SQL injection".to_string(),
+            threat_level: ThreatLevel::High,
+        };
+        let flag = JudgeDecision::Flag {
+            confidence: 0.5,
+            reason: "Suspicious".to_string(),
+            suggested_rule: None,
+        };
+        let unsure = JudgeDecision::Allow { confidence: 0.5 };
+
+        for decision in [block, flag, unsure] {
+            assert_eq!(
+                sampler.weight(&payload("/health", "h"), &decision),
+                Some(1.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_first_allow_of_new_hash_always_logged() {
+        let sampler = AllowSampler::new(SamplingConfig {
+            allow_rate: 0.0,
+            seen_hashes_capacity: 4,
+            ..SamplingConfig::default()
+        });
+        let allow = JudgeDecision::Allow { confidence: 0.95 };
+
+        assert_eq!(sampler.weight(&payload("/", "a"), &allow), Some(1.0));
+        assert_eq!(sampler.weight(&payload("/", "a"), &allow), None);
+
+        // Hashes are forgotten two generations later
+        for hash in ["b", "c", "d", "e"] {
+            assert_eq!(sampler.weight(&payload("/", hash), &allow), Some(1.0));
+        }
+        assert_eq!(sampler.weight(&payload("/", "a"), &allow), Some(1.0));
+    }
+
+    #[test]
+    fn test_first_allow_of_new_hash_takes_its_place_in_the_sample() {
+        let sampler = AllowSampler::new(SamplingConfig {
+            allow_rate: 0.25,
+            seen_hashes_capacity: 16,
+            ..SamplingConfig::default()
+        });
+        let allow = JudgeDecision::Allow { confidence: 0.95 };
+
+        // Lands on a sampled place: stands in for the next three allows
+        assert_eq!(sampler.weight(&payload("/", "a"), &allow), Some(4.0));
+        let weights: Vec<Option<f64>> = (0..3)
+            .map(|_| sampler.weight(&payload("/", "a"), &allow))
+            .collect();
+        assert_eq!(weights, vec![None, None, None]);
+
+        // Off a sampled place: weighs only itself, the stream is unchanged
+        assert_eq!(sampler.weight(&payload("/", "a"), &allow), Some(4.0));
+        assert_eq!(sampler.weight(&payload("/", "b"), &allow), Some(1.0));
+        let weights: Vec<Option<f64>> = (0..3)
+            .map(|_| sampler.weight(&payload("/", "a"), &allow))
+            .collect();
+        assert_eq!(weights, vec![None, None, None]);
+        assert_eq!(sampler.weight(&payload("/", "a"), &allow), Some(4.0));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::storage::cache::{CacheFlush, CachedVerdict, VerdictCache};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Expired verdicts are purged every this many writes
+const PURGE_EVERY_WRITES: u64 = 1000;
+
+/// Verdict cache in a SQLite file (`cache.backend: sqlite`): persistent
+/// caching for single-instance deployments without Redis. Kept apart from
+/// the event logs so it can be deleted at any time.
+pub struct SqliteCache {
+    pool: SqlitePool,
+    writes: AtomicU64,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+impl SqliteCache {
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        let path_str = db_path.as_ref().to_string_lossy().to_string();
+
+        // Ensure parent directory exists
+        if let Some(parent) = db_path.as_ref().parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create cache directory: {:?}", parent))?;
+        }
+
+        let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", path_str))?
+            .create_if_missing(true);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("Failed to connect to SQLite cache: {}", path_str))?;
+
+        sqlx::migrate!("./migrations/cache")
+            .run(&pool)
+            .await
+            .with_context(|| "Failed to run cache migrations")?;
+
+        let cache = Self {
+            pool,
+            writes: AtomicU64::new(0),
+        };
+        cache.purge_expired().await?;
+        Ok(cache)
+    }
+
+    /// Deletes expired verdicts, returning how many
+    pub async fn purge_expired(&self) -> Result<u64> {
+        let now = now_ms();
+        let result = sqlx::query!("DELETE FROM verdicts WHERE expires_at <= ?", now)
+            .execute(&self.pool)
+            .await
+            .with_context(|| "Failed to purge expired verdicts")?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl VerdictCache for SqliteCache {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn get_verdict(&self, key: &str) -> Result<Option<CachedVerdict>> {
+        let now = now_ms();
+        let row = sqlx::query!(
+            "SELECT verdict, path, prompt_version FROM verdicts WHERE cache_key = ? AND expires_at > ?",
+            key,
+            now
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| format!("Failed to get verdict from SQLite cache: {}", key))?;
+
+        row.map(|row| {
+            Ok(CachedVerdict {
+                decision: serde_json::from_str(&row.verdict)
+                    .with_context(|| "Failed to deserialize verdict from SQLite cache")?,
+                path: row.path,
+                prompt_version: row.prompt_version,
+            })
+        })
+        .transpose()
+    }
+
+    async fn set_verdict(&self, key: &str, verdict: &CachedVerdict, ttl: Duration) -> Result<()> {
+        let json = serde_json::to_string(&verdict.decision)
+            .with_context(|| "Failed to serialize verdict for SQLite cache")?;
+        let expires_at = now_ms() + ttl.as_millis() as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO verdicts (cache_key, verdict, path, prompt_version, expires_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(cache_key) DO UPDATE SET
+                verdict = excluded.verdict,
+                path = excluded.path,
+                prompt_version = excluded.prompt_version,
+                expires_at = excluded.expires_at
+            "#,
+            key,
+            json,
+            verdict.path,
+            verdict.prompt_version,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to set verdict in SQLite cache: {}", key))?;
+
+        // The verdict is stored: a failed purge is retried on the next round
+        let writes = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+        if writes.is_multiple_of(PURGE_EVERY_WRITES) {
+            if let Err(e) = self.purge_expired().await {
+                tracing::warn!(error = %e, "Failed to purge expired verdicts");
+            }
+        }
+        Ok(())
+    }
+
+    async fn invalidate(&self, key: &str) -> Result<()> {
+        sqlx::query!("DELETE FROM verdicts WHERE cache_key = ?", key)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to delete verdict from SQLite cache: {}", key))?;
+
+        Ok(())
+    }
+
+    async fn flush(&self, flush: &CacheFlush) -> Result<usize> {
+        self.purge_expired().await?;
+
+        // Same filter as `CacheFlush::matches`; unreadable verdicts only go
+        // with everything
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM verdicts
+            WHERE CASE WHEN json_valid(verdict) THEN
+                (?1 IS NULL OR substr(path, 1, length(?1)) = ?1)
+                AND (?2 IS NULL OR json_extract(verdict, '$.decision') = ?2)
+            ELSE ?1 IS NULL AND ?2 IS NULL END
+            "#,
+            flush.path_prefix,
+            flush.decision
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "Failed to flush verdicts in SQLite cache")?;
+
+        Ok(result.rows_affected() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::decision::JudgeDecision;
+
+    #[tokio::test]
+    async fn test_verdicts_survive_reopening() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("cache.db");
+        let verdict = CachedVerdict {
+            decision: JudgeDecision::Allow { confidence: 0.9 },
+            path: "/api/users".to_string(),
+            prompt_version: Some("3+5".to_string()),
+        };
+
+        let cache = SqliteCache::new(&path).await.unwrap();
+        cache
+            .set_verdict("kept", &verdict, Duration::from_secs(60))
+            .await
+            .unwrap();
+        cache
+            .set_verdict("expired", &verdict, Duration::ZERO)
+            .await
+            .unwrap();
+        drop(cache);
+
+        // Expired verdicts are purged on open
+        let cache = SqliteCache::new(&path).await.unwrap();
+        assert_eq!(cache.get_verdict("kept").await.unwrap(), Some(verdict));
+        assert_eq!(cache.purge_expired().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_flush_in_one_statement() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = SqliteCache::new(temp_dir.path().join("cache.db"))
+            .await
+            .unwrap();
+        let ttl = Duration::from_secs(60);
+        for (key, path) in [("a", "/api_v1/users"), ("b", "/apixv1/users")] {
+            let verdict = CachedVerdict {
+                decision: JudgeDecision::Allow { confidence: 0.9 },
+                path: path.to_string(),
+                prompt_version: None,
+            };
+            cache.set_verdict(key, &verdict, ttl).await.unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO verdicts (cache_key, verdict, path, expires_at) VALUES ('c', '{', '/', ?)",
+        )
+        .bind(now_ms() + 60_000)
+        .execute(&cache.pool)
+        .await
+        .unwrap();
+
+        // Prefixes are literal; unreadable verdicts are left to a full flush
+        let flushed = cache
+            .flush(&CacheFlush {
+                path_prefix: Some("/api_".to_string()),
+                decision: Some("allow".to_string()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(flushed, 1);
+        assert!(cache.get_verdict("a").await.unwrap().is_none());
+        assert!(cache.get_verdict("b").await.unwrap().is_some());
+        assert_eq!(cache.flush(&CacheFlush::default()).await.unwrap(), 2);
+    }
+}
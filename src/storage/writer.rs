@@ -0,0 +1,413 @@
+use crate::config::{EventWriterConfig, OverflowPolicy};
+use crate::core::scoring::ScoreBreakdown;
+use crate::export::sink::Exporter;
+use crate::models::decision::JudgeDecision;
+use crate::models::request::{RequestContext, RequestPayload};
+use crate::storage::logs::{EventStore, NewEvent};
+use crate::storage::sampling::AllowSampler;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Attempts to write a batch before its events are given up
+const WRITE_ATTEMPTS: u32 = 3;
+
+/// Pause before retrying a failed batch, times the attempt number
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+enum Message {
+    Event(Box<NewEvent>),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct EventWriterMetrics {
+    pub written_events: AtomicU64,
+    pub batches: AtomicU64,
+    /// Events dropped by the overflow policy while the queue was full
+    pub dropped_events: AtomicU64,
+    /// Allows left out by the sampler
+    pub sampled_out_events: AtomicU64,
+    /// Events lost after every write attempt failed
+    pub failed_events: AtomicU64,
+    /// Highest queue depth seen
+    pub peak_queue_depth: AtomicU64,
+}
+
+/// Bounded queue in front of the event log, drained by a single writer task
+/// that inserts events in batches, one transaction each. When the queue is
+/// full, events of the types the overflow policy drops are dropped and the
+/// others wait for room.
+#[derive(Clone)]
+pub struct EventWriter {
+    tx: mpsc::Sender<Message>,
+    overflow: OverflowPolicy,
+    sampler: Option<Arc<AllowSampler>>,
+    exporter: Option<Arc<Exporter>>,
+    metrics: Arc<EventWriterMetrics>,
+}
+
+impl EventWriter {
+    /// Starts the writer task
+    pub fn spawn(logs: Arc<dyn EventStore>, config: &EventWriterConfig) -> Self {
+        let (writer, rx) = Self::channel(config);
+        tokio::spawn(run(
+            logs,
+            rx,
+            config.batch_size,
+            Arc::clone(&writer.metrics),
+        ));
+        writer
+    }
+
+    fn channel(config: &EventWriterConfig) -> (Self, mpsc::Receiver<Message>) {
+        let (tx, rx) = mpsc::channel(config.queue_capacity);
+        let writer = Self {
+            tx,
+            overflow: config.overflow,
+            sampler: None,
+            exporter: None,
+            metrics: Arc::new(EventWriterMetrics::default()),
+        };
+        (writer, rx)
+    }
+
+    /// Logs only the allows the sampler keeps, weighted
+    pub fn with_sampler(mut self, sampler: AllowSampler) -> Self {
+        self.sampler = Some(Arc::new(sampler));
+        self
+    }
+
+    /// Also hands every event to the export sinks, before sampling
+    pub fn with_exporter(mut self, exporter: Arc<Exporter>) -> Self {
+        self.exporter = Some(exporter);
+        self
+    }
+
+    /// Queues an event, waiting for room unless the overflow policy drops it
+    pub async fn log(
+        &self,
+        payload: &RequestPayload,
+        decision: &JudgeDecision,
+        score: Option<&ScoreBreakdown>,
+        prompt_version: Option<&str>,
+    ) {
+        // Built at most once, for the sinks and the event log alike
+        let mut context = None;
+        if let Some(exporter) = &self.exporter {
+            if exporter.matches(decision) {
+                let context = context.insert(RequestContext::from_payload(payload));
+                exporter.export(payload, decision, context).await;
+            }
+        }
+
+        let weight = match &self.sampler {
+            Some(sampler) => match sampler.weight(payload, decision) {
+                Some(weight) => weight,
+                None => {
+                    self.metrics
+                        .sampled_out_events
+                        .fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            },
+            None => 1.0,
+        };
+
+        let context = context.unwrap_or_else(|| RequestContext::from_payload(payload));
+        let event = match NewEvent::from_context(payload, decision, &context, score, prompt_version)
+        {
+            Ok(event) => NewEvent { weight, ..event },
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to prepare event");
+                return;
+            }
+        };
+
+        let message = if self.overflow.drops(event.decision) {
+            match self.tx.try_send(Message::Event(Box::new(event))) {
+                Ok(()) => None,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    self.metrics.dropped_events.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    tracing::error!("Event writer stopped, event not logged");
+                    return;
+                }
+            }
+        } else {
+            Some(Message::Event(Box::new(event)))
+        };
+        if let Some(message) = message {
+            if self.tx.send(message).await.is_err() {
+                tracing::error!("Event writer stopped, event not logged");
+            }
+        }
+
+        self.metrics
+            .peak_queue_depth
+            .fetch_max(self.queue_depth() as u64, Ordering::Relaxed);
+    }
+
+    /// Events waiting to be written
+    pub fn queue_depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    pub fn metrics(&self) -> &EventWriterMetrics {
+        &self.metrics
+    }
+
+    /// Waits until every event queued before the call is written
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.tx.send(Message::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    /// Writes the queued events before the server exits
+    pub async fn shutdown(&self) {
+        let queued = self.queue_depth();
+        self.flush().await;
+        tracing::info!(
+            queued,
+            written = self.metrics.written_events.load(Ordering::Relaxed),
+            failed = self.metrics.failed_events.load(Ordering::Relaxed),
+            "Event writer flushed"
+        );
+    }
+}
+
+async fn run(
+    logs: Arc<dyn EventStore>,
+    mut rx: mpsc::Receiver<Message>,
+    batch_size: usize,
+    metrics: Arc<EventWriterMetrics>,
+) {
+    let mut messages = Vec::with_capacity(batch_size);
+    let mut reported_drops = 0;
+
+    // Takes whatever is queued, so batches grow with the load
+    while rx.recv_many(&mut messages, batch_size).await > 0 {
+        let mut events = Vec::with_capacity(messages.len());
+        let mut flushes = Vec::new();
+        for message in messages.drain(..) {
+            match message {
+                Message::Event(event) => events.push(*event),
+                Message::Flush(done) => flushes.push(done),
+            }
+        }
+
+        if !events.is_empty() {
+            write_batch(logs.as_ref(), &events, &metrics).await;
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+
+        let dropped = metrics.dropped_events.load(Ordering::Relaxed);
+        if dropped > reported_drops {
+            tracing::warn!(
+                dropped = dropped - reported_drops,
+                queue_depth = rx.len(),
+                "Event log queue full, events dropped"
+            );
+            reported_drops = dropped;
+        }
+    }
+}
+
+async fn write_batch(logs: &dyn EventStore, events: &[NewEvent], metrics: &EventWriterMetrics) {
+    for attempt in 1..=WRITE_ATTEMPTS {
+        match logs.log_batch(events).await {
+            Ok(()) => {
+                metrics
+                    .written_events
+                    .fetch_add(events.len() as u64, Ordering::Relaxed);
+                metrics.batches.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(e) if attempt < WRITE_ATTEMPTS => {
+                tracing::warn!(error = %e, attempt, "Failed to write event batch, retrying");
+                tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, events = events.len(), "Failed to write event batch");
+                metrics
+                    .failed_events
+                    .fetch_add(events.len() as u64, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SamplingConfig;
+    use crate::models::decision::ThreatLevel;
+    use crate::storage::logs::LogStore;
+    use std::collections::HashMap;
+
+    fn payload(path: &str) -> RequestPayload {
+        RequestPayload::new(
+            "GET".to_string(),
+            path.to_string(),
+            HashMap::new(),
+            None,
+            HashMap::new(),
+            None,
+        )
+    }
+
+    fn block() -> JudgeDecision {
+        JudgeDecision::Block {
+            confidence: 0.9,
+            reason: "SQL injection".to_string(),
+            threat_level: ThreatLevel::High,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_written_in_batches() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let writer = EventWriter::spawn(Arc::clone(&logs), &EventWriterConfig::default());
+
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        for i in 0..100 {
+            writer
+                .log(&payload(&format!("/{}", i)), &allow, None, None)
+                .await;
+        }
+        writer.flush().await;
+
+        assert_eq!(logs.get_events_since(0, 1000).await.unwrap().len(), 100);
+        let metrics = writer.metrics();
+        assert_eq!(metrics.written_events.load(Ordering::Relaxed), 100);
+        assert!(metrics.batches.load(Ordering::Relaxed) < 100);
+        assert_eq!(writer.queue_depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_sampled_allows_logged_with_weight() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let writer = EventWriter::spawn(Arc::clone(&logs), &EventWriterConfig::default())
+            .with_sampler(AllowSampler::new(SamplingConfig {
+                allow_rate: 0.1,
+                ..SamplingConfig::default()
+            }));
+
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        for _ in 0..100 {
+            writer.log(&payload("/feed"), &allow, None, None).await;
+        }
+        writer.log(&payload("/feed"), &block(), None, None).await;
+        writer.flush().await;
+
+        // One allow in ten, the first of the hash standing in for the next nine
+        assert_eq!(logs.get_events_since(0, 1000).await.unwrap().len(), 11);
+        assert_eq!(
+            writer.metrics().sampled_out_events.load(Ordering::Relaxed),
+            90
+        );
+        let mut counts = logs.count_events_by_decision(0).await.unwrap();
+        counts.sort();
+        assert_eq!(
+            counts,
+            vec![("allow".to_string(), 100), ("block".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_allows_and_waits_with_blocks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let config = EventWriterConfig {
+            queue_capacity: 2,
+            ..EventWriterConfig::default()
+        };
+        // No writer yet: the queue fills up
+        let (writer, rx) = EventWriter::channel(&config);
+
+        let allow = JudgeDecision::Allow { confidence: 0.9 };
+        for path in ["/a", "/b", "/dropped"] {
+            writer.log(&payload(path), &allow, None, None).await;
+        }
+        assert_eq!(writer.queue_depth(), 2);
+        assert_eq!(writer.metrics().dropped_events.load(Ordering::Relaxed), 1);
+
+        let blocked = {
+            let writer = writer.clone();
+            tokio::spawn(
+                async move { writer.log(&payload("/blocked"), &block(), None, None).await },
+            )
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!blocked.is_finished());
+
+        tokio::spawn(run(
+            Arc::clone(&logs),
+            rx,
+            config.batch_size,
+            Arc::clone(&writer.metrics),
+        ));
+        blocked.await.unwrap();
+        writer.flush().await;
+
+        let mut paths: Vec<_> = logs
+            .get_events_since(0, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/a", "/b", "/blocked"]);
+        assert_eq!(writer.metrics().peak_queue_depth.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_writes_queued_events() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let logs: Arc<dyn EventStore> = Arc::new(
+            LogStore::new(temp_dir.path().join("logs.db"))
+                .await
+                .unwrap(),
+        );
+        let config = EventWriterConfig::default();
+        let (writer, rx) = EventWriter::channel(&config);
+
+        writer.log(&payload("/a"), &block(), None, None).await;
+        writer.log(&payload("/b"), &block(), None, None).await;
+        assert_eq!(writer.queue_depth(), 2);
+
+        tokio::spawn(run(
+            Arc::clone(&logs),
+            rx,
+            config.batch_size,
+            Arc::clone(&writer.metrics),
+        ));
+        writer.shutdown().await;
+
+        assert_eq!(logs.get_events_since(0, 10).await.unwrap().len(), 2);
+        assert_eq!(writer.queue_depth(), 0);
+    }
+}
@@ -10,7 +10,6 @@
 /// 4. Command injection attacks → should be blocked (403)
 /// 5. Legitimate requests → should pass (200)
 /// 6. Edge cases → should handle gracefully
-
 use std::time::Duration;
 use tokio::process::{Child, Command};
 use tokio::time::sleep;